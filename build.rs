@@ -1,6 +1,8 @@
 /// Build function for compiling protocol buffers.
 ///
-/// This function is used to compile the protocol buffers defined in the `authentication.proto` file.
+/// This function is used to compile the protocol buffers defined in the `authentication.proto` file,
+/// along with the `google.rpc` messages used to attach error details to failed calls. It also writes
+/// their file descriptor set, which the auth service serves through gRPC reflection.
 ///
 /// # Returns
 ///
@@ -10,6 +12,17 @@
 ///
 /// This function returns an error if there are issues with compiling the protocol buffers.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/authentication.proto")?;
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("authentication_descriptor.bin"))
+        .compile(
+            &[
+                "proto/authentication.proto",
+                "proto/google/rpc/status.proto",
+                "proto/google/rpc/error_details.proto",
+            ],
+            &["proto"],
+        )?;
     Ok(())
-}
\ No newline at end of file
+}
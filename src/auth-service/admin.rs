@@ -0,0 +1,1836 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{debug, info};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditFilter, AuditOutcome, AuditQueryError},
+    auth::{
+        authentication::{
+            admin_server::Admin, AccountStatus as ApiAccountStatus, AuditAction as ApiAuditAction, AuditEventInfo,
+            AuditOutcome as ApiAuditOutcome, DeleteUserRequest, DeleteUserResponse, DisableUserRequest,
+            DisableUserResponse, EnableUserRequest, EnableUserResponse, ExportUsersRequest,
+            ExportUsersResponse, ForcePasswordResetRequest, ForcePasswordResetResponse, GetUserRequest,
+            GetUserResponse, ImportUsersRequest, ImportUsersResponse, ListAuditEventsRequest,
+            ListAuditEventsResponse, ListDeletedUsersRequest,
+            ListDeletedUsersResponse, ListUsersRequest, ListUsersResponse, RejectedUser,
+            RestoreUserRequest, RestoreUserResponse, RevokeUserSessionsRequest,
+            RevokeUserSessionsResponse, SetUserStatusRequest, SetUserStatusResponse, StatusCode,
+            TailAuditEventsRequest, TailAuditEventsResponse, UserInfo,
+        },
+        api_profile, role_names, unix_seconds, AuthService,
+    },
+    bulk,
+    logging::Redacted,
+    rbac::{self, Permission},
+    users::{AccountStatus, User, UsersError},
+};
+
+/// The number of users or audit events a list returns when the request does not set a limit.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// The largest number of users or audit events a list returns in one page.
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// How many audit events can wait to be sent to a `TailAuditEvents` client.
+const TAIL_BUFFER: usize = 64;
+
+/// Converts a stored user into its API representation, leaving out every credential.
+///
+/// # Arguments
+///
+/// * `user` - The stored user.
+///
+/// # Returns
+///
+/// The `UserInfo` message for the user. `purge_at` is left unset, as it depends on the service's
+/// grace period.
+fn user_info(user: User) -> UserInfo {
+    UserInfo {
+        deleted_at: unix_seconds(user.deleted_at),
+        purge_at: 0,
+        user_uuid: user.user_uuid,
+        username: user.username,
+        roles: role_names(&user.roles),
+        status: api_account_status(user.status).into(),
+        password_reset_required: user.password_reset_required,
+        mfa_enabled: user.totp_secret.is_some(),
+        profile: Some(api_profile(user.profile)),
+    }
+}
+
+/// Converts an account status into its API representation.
+///
+/// # Arguments
+///
+/// * `status` - The stored status.
+///
+/// # Returns
+///
+/// The matching `AccountStatus` of the API.
+fn api_account_status(status: AccountStatus) -> ApiAccountStatus {
+    match status {
+        AccountStatus::Active => ApiAccountStatus::Active,
+        AccountStatus::Disabled => ApiAccountStatus::Disabled,
+        AccountStatus::PendingVerification => ApiAccountStatus::PendingVerification,
+        AccountStatus::Locked => ApiAccountStatus::Locked,
+    }
+}
+
+/// Converts an account status received from the API into the stored representation.
+///
+/// # Arguments
+///
+/// * `status` - The status from the request.
+///
+/// # Returns
+///
+/// The matching stored `AccountStatus`.
+fn account_status(status: ApiAccountStatus) -> AccountStatus {
+    match status {
+        ApiAccountStatus::Active => AccountStatus::Active,
+        ApiAccountStatus::Disabled => AccountStatus::Disabled,
+        ApiAccountStatus::PendingVerification => AccountStatus::PendingVerification,
+        ApiAccountStatus::Locked => AccountStatus::Locked,
+    }
+}
+
+/// Converts an audit action into its API representation.
+///
+/// # Arguments
+///
+/// * `action` - The recorded action.
+///
+/// # Returns
+///
+/// The matching `AuditAction` of the API.
+fn api_audit_action(action: AuditAction) -> ApiAuditAction {
+    match action {
+        AuditAction::SignUp => ApiAuditAction::SignUp,
+        AuditAction::SignIn => ApiAuditAction::SignIn,
+        AuditAction::VerifyMfa => ApiAuditAction::VerifyMfa,
+        AuditAction::PasskeySignIn => ApiAuditAction::PasskeySignIn,
+        AuditAction::SignOut => ApiAuditAction::SignOut,
+        AuditAction::DeleteAccount => ApiAuditAction::DeleteAccount,
+        AuditAction::GrantRole => ApiAuditAction::GrantRole,
+        AuditAction::RevokeRole => ApiAuditAction::RevokeRole,
+        AuditAction::AddGroupMember => ApiAuditAction::AddGroupMember,
+        AuditAction::RemoveGroupMember => ApiAuditAction::RemoveGroupMember,
+        AuditAction::ChangeAccountStatus => ApiAuditAction::ChangeAccountStatus,
+        AuditAction::ForcePasswordReset => ApiAuditAction::ForcePasswordReset,
+        AuditAction::ResetPassword => ApiAuditAction::ResetPassword,
+        AuditAction::RevokeSessions => ApiAuditAction::RevokeSessions,
+        AuditAction::RestoreAccount => ApiAuditAction::RestoreAccount,
+        AuditAction::PurgeAccount => ApiAuditAction::PurgeAccount,
+        AuditAction::ChangeUsername => ApiAuditAction::ChangeUsername,
+        AuditAction::ImportUser => ApiAuditAction::ImportUser,
+        AuditAction::ExportUsers => ApiAuditAction::ExportUsers,
+        AuditAction::CreateGroup => ApiAuditAction::CreateGroup,
+        AuditAction::UpdateGroup => ApiAuditAction::UpdateGroup,
+        AuditAction::DeleteGroup => ApiAuditAction::DeleteGroup,
+        AuditAction::GrantGroupRole => ApiAuditAction::GrantGroupRole,
+        AuditAction::RevokeGroupRole => ApiAuditAction::RevokeGroupRole,
+        AuditAction::CreateApiKey => ApiAuditAction::CreateApiKey,
+        AuditAction::RevokeApiKey => ApiAuditAction::RevokeApiKey,
+    }
+}
+
+/// Converts an audit action received from the API into the recorded representation.
+///
+/// # Arguments
+///
+/// * `action` - The action from the request.
+///
+/// # Returns
+///
+/// The matching recorded `AuditAction`, or `None` for `Unspecified`, which matches every action.
+fn audit_action(action: ApiAuditAction) -> Option<AuditAction> {
+    match action {
+        ApiAuditAction::Unspecified => None,
+        ApiAuditAction::SignUp => Some(AuditAction::SignUp),
+        ApiAuditAction::SignIn => Some(AuditAction::SignIn),
+        ApiAuditAction::VerifyMfa => Some(AuditAction::VerifyMfa),
+        ApiAuditAction::PasskeySignIn => Some(AuditAction::PasskeySignIn),
+        ApiAuditAction::SignOut => Some(AuditAction::SignOut),
+        ApiAuditAction::DeleteAccount => Some(AuditAction::DeleteAccount),
+        ApiAuditAction::GrantRole => Some(AuditAction::GrantRole),
+        ApiAuditAction::RevokeRole => Some(AuditAction::RevokeRole),
+        ApiAuditAction::AddGroupMember => Some(AuditAction::AddGroupMember),
+        ApiAuditAction::RemoveGroupMember => Some(AuditAction::RemoveGroupMember),
+        ApiAuditAction::ChangeAccountStatus => Some(AuditAction::ChangeAccountStatus),
+        ApiAuditAction::ForcePasswordReset => Some(AuditAction::ForcePasswordReset),
+        ApiAuditAction::ResetPassword => Some(AuditAction::ResetPassword),
+        ApiAuditAction::RevokeSessions => Some(AuditAction::RevokeSessions),
+        ApiAuditAction::RestoreAccount => Some(AuditAction::RestoreAccount),
+        ApiAuditAction::PurgeAccount => Some(AuditAction::PurgeAccount),
+        ApiAuditAction::ChangeUsername => Some(AuditAction::ChangeUsername),
+        ApiAuditAction::ImportUser => Some(AuditAction::ImportUser),
+        ApiAuditAction::ExportUsers => Some(AuditAction::ExportUsers),
+        ApiAuditAction::CreateGroup => Some(AuditAction::CreateGroup),
+        ApiAuditAction::UpdateGroup => Some(AuditAction::UpdateGroup),
+        ApiAuditAction::DeleteGroup => Some(AuditAction::DeleteGroup),
+        ApiAuditAction::GrantGroupRole => Some(AuditAction::GrantGroupRole),
+        ApiAuditAction::RevokeGroupRole => Some(AuditAction::RevokeGroupRole),
+        ApiAuditAction::CreateApiKey => Some(AuditAction::CreateApiKey),
+        ApiAuditAction::RevokeApiKey => Some(AuditAction::RevokeApiKey),
+    }
+}
+
+/// Converts an audit outcome into its API representation.
+///
+/// # Arguments
+///
+/// * `outcome` - The recorded outcome.
+///
+/// # Returns
+///
+/// The matching `AuditOutcome` of the API.
+fn api_audit_outcome(outcome: AuditOutcome) -> ApiAuditOutcome {
+    match outcome {
+        AuditOutcome::Success => ApiAuditOutcome::Success,
+        AuditOutcome::MfaRequired => ApiAuditOutcome::MfaRequired,
+        AuditOutcome::Failure => ApiAuditOutcome::Failure,
+    }
+}
+
+/// Converts an audit outcome received from the API into the recorded representation.
+///
+/// # Arguments
+///
+/// * `outcome` - The outcome from the request.
+///
+/// # Returns
+///
+/// The matching recorded `AuditOutcome`, or `None` for `Unspecified`, which matches every outcome.
+fn audit_outcome(outcome: ApiAuditOutcome) -> Option<AuditOutcome> {
+    match outcome {
+        ApiAuditOutcome::Unspecified => None,
+        ApiAuditOutcome::Success => Some(AuditOutcome::Success),
+        ApiAuditOutcome::MfaRequired => Some(AuditOutcome::MfaRequired),
+        ApiAuditOutcome::Failure => Some(AuditOutcome::Failure),
+    }
+}
+
+/// Converts a recorded audit event into its API representation.
+///
+/// # Arguments
+///
+/// * `event` - The recorded event.
+///
+/// # Returns
+///
+/// The `AuditEventInfo` message for the event. Unset fields are left empty.
+fn audit_event_info(event: AuditEvent) -> AuditEventInfo {
+    AuditEventInfo {
+        id: event.id,
+        action: api_audit_action(event.action).into(),
+        actor_uuid: event.actor,
+        user_uuid: event.user_uuid,
+        outcome: api_audit_outcome(event.outcome).into(),
+        reason: event.reason.unwrap_or_default(),
+        peer_ip: event.peer_ip.map(|peer_ip| peer_ip.to_string()).unwrap_or_default(),
+        timestamp: unix_seconds(Some(event.timestamp)),
+    }
+}
+
+/// Builds the filter of an audit request.
+///
+/// # Arguments
+///
+/// * `user_uuid` - Only events involving this user, or every user if empty.
+/// * `action` - Only events of this action, or every action if `Unspecified`.
+/// * `outcome` - Only events with this outcome, or every outcome if `Unspecified`.
+/// * `since` - Only events recorded at or after this many seconds since the Unix epoch, or every
+///   event if not positive.
+/// * `until` - Only events recorded before this many seconds since the Unix epoch, or every event
+///   if not positive.
+///
+/// # Returns
+///
+/// The `AuditFilter` selecting the requested events.
+fn audit_filter(user_uuid: &str, action: ApiAuditAction, outcome: ApiAuditOutcome, since: i64, until: i64) -> AuditFilter {
+    let time = |seconds: i64| (seconds > 0).then(|| UNIX_EPOCH + Duration::from_secs(seconds as u64));
+
+    AuditFilter {
+        user_uuid: (!user_uuid.is_empty()).then(|| user_uuid.to_owned()),
+        action: audit_action(action),
+        outcome: audit_outcome(outcome),
+        since: time(since),
+        until: time(until),
+    }
+}
+
+/// Reads the cursor of a `ListAuditEvents` request, which is the ID of the last event of the
+/// previous page.
+///
+/// # Arguments
+///
+/// * `cursor` - The cursor from the request, empty for the first page.
+///
+/// # Returns
+///
+/// An `Ok` result containing the ID to continue before, or `None` for the first page, otherwise an
+/// `AuditQueryError` if the cursor is not one the service returned.
+fn parse_cursor(cursor: &str) -> Result<Option<u64>, AuditQueryError> {
+    if cursor.is_empty() {
+        return Ok(None);
+    }
+
+    match cursor.parse() {
+        Ok(id) if id > 0 => Ok(Some(id)),
+        _ => Err(AuditQueryError::InvalidCursor(cursor.to_owned())),
+    }
+}
+
+impl AuthService {
+
+    /// Changes the status of a user's account on behalf of an operator.
+    ///
+    /// Moving an account out of `AccountStatus::Active` signs the user out. Their API keys are kept
+    /// but stop validating until the account is active again.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - The session token of the caller.
+    /// * `user_uuid` - The UUID of the user.
+    /// * `status` - The new status of the account.
+    /// * `peer_ip` - The IP address of the caller, recorded in the audit trail.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the updated user, otherwise an `Unauthenticated`,
+    /// `PermissionDenied`, `NotFound` or `FailedPrecondition` status, the latter if the change
+    /// would leave no active administrator. The caller must hold every permission the user holds.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn change_account_status(
+        &self,
+        session_token: &str,
+        user_uuid: &str,
+        status: AccountStatus,
+        peer_ip: Option<IpAddr>,
+    ) -> Result<User, Status> {
+        let caller_uuid = self.authorize_over(session_token, Permission::ManageUsers, user_uuid)?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        self.ensure_admin_remains(&*users_service, |users, _| {
+            for user in users.iter_mut().filter(|user| user.user_uuid == user_uuid) {
+                user.status = status;
+            }
+        })?;
+
+        user.status = status;
+        users_service.update_user(user.clone())?;
+        drop(users_service);
+
+        if status != AccountStatus::Active {
+            self.sessions_service.lock().expect("lock should not be tampered").delete_session(user_uuid);
+        }
+
+        if status == AccountStatus::Locked {
+            self.metrics.record_lockout();
+        }
+
+        info!("User {} set the status of {} to {:?}", caller_uuid, user_uuid, status);
+        self.audit(
+            AuditEvent::new(AuditAction::ChangeAccountStatus, user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        Ok(user)
+    }
+}
+
+/// The `Admin` service shares its stores with the `Auth` service, so it is implemented on the same
+/// `AuthService` and served from the same instance.
+#[tonic::async_trait]
+impl Admin for AuthService {
+
+    /// Handles requests to list users, optionally filtered by username.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the search term and the
+    ///   offset and limit of the page.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the page of users, sorted by username, and the total number of
+    /// matching users.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot read users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ListUsersRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     query: "smith".to_string(),
+    ///     offset: 0,
+    ///     limit: 50,
+    /// };
+    /// let response = auth_service.list_users(Request::new(request)).await;
+    /// ```
+    async fn list_users(&self, request: Request<ListUsersRequest>) -> Result<Response<ListUsersResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadUsers)?;
+
+        let limit = match req.limit as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => limit.min(MAX_PAGE_SIZE),
+        };
+
+        let (users, total) = self.users_service.lock()
+                                               .expect("lock should not be tampered")
+                                               .search_users(&req.query, req.offset as usize, limit);
+
+        let reply = ListUsersResponse {
+            status_code: StatusCode::Success.into(),
+            users: users.into_iter().map(user_info).collect(),
+            total_count: total as u32,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to look up a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the user.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot read users, or a `NotFound` status if the user
+    /// does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = GetUserRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.get_user(Request::new(request)).await;
+    /// ```
+    async fn get_user(&self, request: Request<GetUserRequest>) -> Result<Response<GetUserResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadUsers)?;
+
+        let user = self.users_service.lock()
+                                     .expect("lock should not be tampered")
+                                     .get_user(&req.user_uuid)
+                                     .ok_or(UsersError::UserNotFound)?;
+
+        let reply = GetUserResponse {
+            status_code: StatusCode::Success.into(),
+            user: Some(user_info(user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to disable a user's account.
+    ///
+    /// The user is signed out and cannot sign in again until the account is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated user.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, a `NotFound` status if the user does not exist, or a `FailedPrecondition` status if it would disable the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = DisableUserRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.disable_user(Request::new(request)).await;
+    /// ```
+    async fn disable_user(&self, request: Request<DisableUserRequest>) -> Result<Response<DisableUserResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user = self.change_account_status(&req.session_token, &req.user_uuid, AccountStatus::Disabled, peer_ip)?;
+
+        let reply = DisableUserResponse {
+            status_code: StatusCode::Success.into(),
+            user: Some(user_info(user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to enable an account, whatever its current status.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated user. Enabling an active account succeeds without
+    /// changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users, or a `NotFound` status if the
+    /// user does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = EnableUserRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.enable_user(Request::new(request)).await;
+    /// ```
+    async fn enable_user(&self, request: Request<EnableUserRequest>) -> Result<Response<EnableUserResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user = self.change_account_status(&req.session_token, &req.user_uuid, AccountStatus::Active, peer_ip)?;
+
+        let reply = EnableUserResponse {
+            status_code: StatusCode::Success.into(),
+            user: Some(user_info(user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to set the status of a user's account.
+    ///
+    /// Moving an account out of `ACTIVE` signs the user out.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the user and
+    ///   the new status.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated user.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, a `NotFound` status if the user does not exist, or a `FailedPrecondition` status if it would leave no active administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = SetUserStatusRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    ///     status: AccountStatus::Locked.into(),
+    /// };
+    /// let response = auth_service.set_user_status(Request::new(request)).await;
+    /// ```
+    async fn set_user_status(&self, request: Request<SetUserStatusRequest>) -> Result<Response<SetUserStatusResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let status = account_status(req.status());
+        let user = self.change_account_status(&req.session_token, &req.user_uuid, status, peer_ip)?;
+
+        let reply = SetUserStatusResponse {
+            status_code: StatusCode::Success.into(),
+            user: Some(user_info(user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to force a user to choose a new password.
+    ///
+    /// The user is signed out, their API keys are revoked, and they cannot sign in with their password until they redeem the returned
+    /// reset token with `Auth.ResetPassword`. The operator hands the token to the user out of band.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the reset token, valid for the configured password reset TTL.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, or a `NotFound` status if the user does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ForcePasswordResetRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.force_password_reset(Request::new(request)).await;
+    /// ```
+    async fn force_password_reset(
+        &self,
+        request: Request<ForcePasswordResetRequest>,
+    ) -> Result<Response<ForcePasswordResetResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize_over(&req.session_token, Permission::ManageUsers, &req.user_uuid)?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        user.password_reset_required = true;
+        users_service.update_user(user)?;
+        drop(users_service);
+
+        let mut sessions_service = self.sessions_service.lock().expect("lock should not be tampered");
+
+        sessions_service.delete_session(&req.user_uuid);
+        let reset_token = sessions_service.create_password_reset(&req.user_uuid);
+
+        drop(sessions_service);
+
+        // The account is presumed compromised, so keys created with the old password go too.
+        self.api_keys_service.lock()
+                             .expect("lock should not be tampered")
+                             .revoke_user_api_keys(&req.user_uuid);
+
+        info!("User {} forced a password reset for {}", caller_uuid, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::ForcePasswordReset, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = ForcePasswordResetResponse {
+            status_code: StatusCode::Success.into(),
+            reset_token,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to delete a user's account on their behalf.
+    ///
+    /// The user is signed out straight away. The account, its API keys and its group memberships are
+    /// kept until the end of the grace period so that it can be restored with `restore_user`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, a `NotFound` status if the user does not exist, or a `FailedPrecondition` status if it would delete the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = DeleteUserRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.delete_user(Request::new(request)).await;
+    /// ```
+    async fn delete_user(&self, request: Request<DeleteUserRequest>) -> Result<Response<DeleteUserResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize_over(&req.session_token, Permission::ManageUsers, &req.user_uuid)?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        self.ensure_admin_remains(&*users_service, |users, _| {
+            users.retain(|user| user.user_uuid != req.user_uuid);
+        })?;
+
+        users_service.delete_user(req.user_uuid.clone());
+        drop(users_service);
+
+        self.sessions_service.lock().expect("lock should not be tampered").delete_session(&req.user_uuid);
+
+        info!("User {} deleted {}", caller_uuid, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::DeleteAccount, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = DeleteUserResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to sign a user out.
+    ///
+    /// The user can sign in again straight away; disable the account to keep them out.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, or a `NotFound` status if the user does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RevokeUserSessionsRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.revoke_user_sessions(Request::new(request)).await;
+    /// ```
+    async fn revoke_user_sessions(
+        &self,
+        request: Request<RevokeUserSessionsRequest>,
+    ) -> Result<Response<RevokeUserSessionsResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize_over(&req.session_token, Permission::ManageUsers, &req.user_uuid)?;
+
+        self.sessions_service.lock().expect("lock should not be tampered").delete_session(&req.user_uuid);
+
+        info!("User {} revoked the sessions of {}", caller_uuid, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::RevokeSessions, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RevokeUserSessionsResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to list deleted users that have not been purged yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the deleted users, sorted by username, with the times they were
+    /// deleted and are due to be purged.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot read users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ListDeletedUsersRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.list_deleted_users(Request::new(request)).await;
+    /// ```
+    async fn list_deleted_users(&self, request: Request<ListDeletedUsersRequest>) -> Result<Response<ListDeletedUsersResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadUsers)?;
+
+        let mut users = self.users_service.lock()
+                                          .expect("lock should not be tampered")
+                                          .list_deleted_users();
+
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+
+        let reply = ListDeletedUsersResponse {
+            status_code: StatusCode::Success.into(),
+            users: users
+                .into_iter()
+                .map(|user| UserInfo {
+                    purge_at: unix_seconds(self.purge_time(&user)),
+                    ..user_info(user)
+                })
+                .collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to restore a deleted user's account.
+    ///
+    /// The account comes back with its roles, API keys and group memberships. The user is not
+    /// signed back in.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the restored user.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage users or lacks a permission the user
+    /// holds, or a `NotFound` status if no deleted user has the UUID or its grace period has ended.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RestoreUserRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.restore_user(Request::new(request)).await;
+    /// ```
+    async fn restore_user(&self, request: Request<RestoreUserRequest>) -> Result<Response<RestoreUserResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageUsers)?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        // An account past its grace period is only waiting for the purge job and cannot come back.
+        let now = SystemTime::now();
+        let deleted_user = users_service
+            .list_deleted_users()
+            .into_iter()
+            .find(|user| user.user_uuid == req.user_uuid)
+            .filter(|user| self.purge_time(user).is_some_and(|purge_time| purge_time > now))
+            .ok_or(UsersError::UserNotFound)?;
+
+        // `authorize_over` cannot see deleted users, so the same check is made on the deleted record.
+        let caller = users_service.get_user(&caller_uuid).ok_or(UsersError::UserNotFound)?;
+        rbac::require_all(&self.effective_roles(&caller), &self.effective_roles(&deleted_user))?;
+
+        users_service.restore_user(&req.user_uuid)?;
+
+        let user = users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+        drop(users_service);
+
+        info!("User {} restored {}", caller_uuid, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::RestoreAccount, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RestoreUserResponse {
+            status_code: StatusCode::Success.into(),
+            user: Some(user_info(user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to import users from another system.
+    ///
+    /// Each line is imported or rejected on its own, so one bad line does not stop the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the users as JSON Lines
+    ///   and whether this is a dry run.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the number of users imported, or that would be imported in a dry
+    /// run, and the lines that were rejected with the reason for each. Lines granting roles are
+    /// rejected unless the caller can manage roles.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot manage users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ImportUsersRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     users: std::fs::read_to_string("users.jsonl")?,
+    ///     dry_run: true,
+    /// };
+    /// let response = auth_service.import_users(Request::new(request)).await;
+    /// ```
+    async fn import_users(&self, request: Request<ImportUsersRequest>) -> Result<Response<ImportUsersResponse>, Status> {
+        // The request holds password hashes, which are not redacted, so only the metadata is logged.
+        debug!(metadata = ?request.metadata(), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageUsers)?;
+
+        // Importing a user with roles grants them, so it needs the same permission as `grant_role`.
+        let allow_roles = self.authorize(&req.session_token, Permission::ManageRoles).is_ok();
+
+        let report = {
+            let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+            bulk::import_users(&mut *users_service, &req.users, req.dry_run, allow_roles)
+        };
+
+        if !req.dry_run {
+            for user_uuid in &report.imported {
+                self.audit(
+                    AuditEvent::new(AuditAction::ImportUser, user_uuid, AuditOutcome::Success)
+                        .with_actor(&caller_uuid)
+                        .with_peer_ip(peer_ip),
+                );
+            }
+        }
+
+        info!(
+            "User {} imported {} users and rejected {}{}",
+            caller_uuid,
+            report.imported.len(),
+            report.rejected.len(),
+            if req.dry_run { " (dry run)" } else { "" },
+        );
+
+        let reply = ImportUsersResponse {
+            status_code: StatusCode::Success.into(),
+            imported_count: report.imported.len() as u32,
+            rejected: report.rejected
+                .into_iter()
+                .map(|rejected| RejectedUser {
+                    line: rejected.line as u32,
+                    username: rejected.username,
+                    reason: rejected.reason,
+                })
+                .collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to export every user.
+    ///
+    /// The export contains the password hashes of every account, administrators included, so it
+    /// needs the permission to manage roles, which only administrators hold. Deleted users are left
+    /// out.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the users as JSON Lines in the format `import_users` accepts.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot manage roles.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ExportUsersRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.export_users(Request::new(request)).await;
+    /// ```
+    async fn export_users(&self, request: Request<ExportUsersRequest>) -> Result<Response<ExportUsersResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageRoles)?;
+
+        let users = self.users_service.lock()
+                                      .expect("lock should not be tampered")
+                                      .list_users();
+
+        self.audit(
+            AuditEvent::new(AuditAction::ExportUsers, &caller_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = ExportUsersResponse {
+            status_code: StatusCode::Success.into(),
+            users: bulk::export_users(users),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to search the audit trail.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the user, action,
+    ///   outcome and time range to filter by, and the cursor and limit of the page.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the page of matching events, newest first, and the cursor of the
+    /// next page, which is empty on the last page.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot read the audit trail, or an `InvalidArgument`
+    /// status if the cursor was not returned by a previous page.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ListAuditEventsRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     outcome: AuditOutcome::Failure.into(),
+    ///     limit: 50,
+    ///     ..Default::default()
+    /// };
+    /// let response = auth_service.list_audit_events(Request::new(request)).await;
+    /// ```
+    async fn list_audit_events(
+        &self,
+        request: Request<ListAuditEventsRequest>,
+    ) -> Result<Response<ListAuditEventsResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadAudit)?;
+
+        let limit = match req.limit as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => limit.min(MAX_PAGE_SIZE),
+        };
+
+        let before_id = parse_cursor(&req.cursor)?;
+        let filter = audit_filter(&req.user_uuid, req.action(), req.outcome(), req.since, req.until);
+
+        // One more event than the page holds tells whether there is a next page.
+        let mut events = self.audit_service.lock()
+                                           .expect("lock should not be tampered")
+                                           .query(&filter, before_id, limit + 1);
+
+        let next_cursor = match events.len() > limit {
+            true => {
+                events.truncate(limit);
+                events.last().map(|event| event.id.to_string()).unwrap_or_default()
+            }
+            false => String::new(),
+        };
+
+        let reply = ListAuditEventsResponse {
+            status_code: StatusCode::Success.into(),
+            events: events.into_iter().map(audit_event_info).collect(),
+            next_cursor,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    type TailAuditEventsStream = ReceiverStream<Result<TailAuditEventsResponse, Status>>;
+
+    /// Handles requests to follow the audit trail as events are recorded.
+    ///
+    /// The caller is authorized once, when the stream starts. The stream ends when the client
+    /// cancels it or the service shuts down, or with a `ResourceExhausted` status if the client
+    /// falls too far behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the user, action and
+    ///   outcome to filter by.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response streaming each matching event recorded after the call.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot read the audit trail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = TailAuditEventsRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     action: AuditAction::SignIn.into(),
+    ///     ..Default::default()
+    /// };
+    /// let mut stream = auth_service.tail_audit_events(Request::new(request)).await?.into_inner();
+    /// ```
+    async fn tail_audit_events(
+        &self,
+        request: Request<TailAuditEventsRequest>,
+    ) -> Result<Response<Self::TailAuditEventsStream>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadAudit)?;
+
+        let filter = audit_filter(&req.user_uuid, req.action(), req.outcome(), 0, 0);
+        let mut events = self.audit_service.lock().expect("lock should not be tampered").subscribe();
+
+        let shutdown = self.shutdown.clone();
+        let (sender, receiver) = mpsc::channel(TAIL_BUFFER);
+
+        tokio::spawn(async move {
+            let shutdown = async move {
+                match shutdown {
+                    Some(shutdown) => shutdown.wait().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::pin!(shutdown);
+
+            loop {
+                let received = tokio::select! {
+                    received = events.recv() => received,
+                    _ = sender.closed() => return,
+                    _ = &mut shutdown => return,
+                };
+
+                let reply = match received {
+                    Ok(event) if !filter.matches(&event) => continue,
+                    Ok(event) => Ok(TailAuditEventsResponse {
+                        status_code: StatusCode::Success.into(),
+                        event: Some(audit_event_info(event)),
+                    }),
+                    Err(RecvError::Lagged(missed)) => Err(Status::from(AuditQueryError::Lagged(missed))),
+                    Err(RecvError::Closed) => return,
+                };
+
+                // A lagging client is told once, then the stream ends.
+                let lagged = reply.is_err();
+                if sender.send(reply).await.is_err() || lagged {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use tokio_stream::StreamExt;
+
+    use crate::{
+        auth::{
+            authentication::{auth_server::Auth, ErrorReason, ResetPasswordRequest, SignInRequest, ValidateSessionRequest},
+            tests::{grant, sign_in_user},
+        },
+        errors::error_reason,
+        rbac::Role,
+        sessions::SessionsImpl,
+        shutdown,
+        users::{UsersImpl, MIN_PBKDF2_ROUNDS},
+    };
+
+    use super::*;
+
+    /// Creates a service with an administrator and a regular user, returning the administrator's
+    /// session token and the regular user's UUID and session token.
+    fn service_with_admin() -> (AuthService, String, String, String) {
+        service_with_admin_in(UsersImpl::for_tests())
+    }
+
+    /// Like `service_with_admin`, but stores the users in `users_service`, e.g. one that hashes
+    /// with enough rounds for its hashes to be imported.
+    fn service_with_admin_in(users_service: UsersImpl) -> (AuthService, String, String, String) {
+        let auth_service = AuthService::new(Box::new(Mutex::new(users_service)), Box::new(Mutex::new(SessionsImpl::default())));
+
+        let (admin_uuid, admin_session) = sign_in_user(&auth_service, "admin", "password");
+        grant(&auth_service, &admin_uuid, Role::Admin);
+
+        let (user_uuid, user_session) = sign_in_user(&auth_service, "user", "password");
+
+        (auth_service, admin_session, user_uuid, user_session)
+    }
+
+    fn sign_in_request(username: &str, password: &str) -> Request<SignInRequest> {
+        Request::new(SignInRequest {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+
+    #[tokio::test]
+    async fn list_users_should_require_permission() {
+        let (auth_service, _, _, user_session) = service_with_admin();
+
+        let request = Request::new(ListUsersRequest {
+            session_token: user_session,
+            query: "".to_owned(),
+            offset: 0,
+            limit: 0,
+        });
+
+        let status = auth_service.list_users(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn list_users_should_page_results() {
+        let (auth_service, admin_session, _, _) = service_with_admin();
+
+        let request = Request::new(ListUsersRequest {
+            session_token: admin_session.clone(),
+            query: "".to_owned(),
+            offset: 1,
+            limit: 1,
+        });
+
+        let result = auth_service.list_users(request).await.unwrap().into_inner();
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.users.len(), 1);
+        assert_eq!(result.users[0].username, "user");
+
+        let request = Request::new(ListUsersRequest {
+            session_token: admin_session,
+            query: "ADM".to_owned(),
+            offset: 0,
+            limit: 0,
+        });
+
+        let result = auth_service.list_users(request).await.unwrap().into_inner();
+
+        assert_eq!(result.total_count, 1);
+        assert_eq!(result.users[0].roles, vec!["admin".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn get_user_should_fail_for_unknown_user() {
+        let (auth_service, admin_session, _, _) = service_with_admin();
+
+        let request = Request::new(GetUserRequest {
+            session_token: admin_session,
+            user_uuid: "unknown".to_owned(),
+        });
+
+        let status = auth_service.get_user(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn disable_user_should_block_sign_in_until_enabled() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+
+        let request = Request::new(DisableUserRequest {
+            session_token: admin_session.clone(),
+            user_uuid: user_uuid.clone(),
+        });
+
+        let result = auth_service.disable_user(request).await.unwrap().into_inner();
+        assert_eq!(result.user.unwrap().status(), ApiAccountStatus::Disabled);
+
+        let request = Request::new(ValidateSessionRequest { token: user_session });
+        assert!(auth_service.validate_session(request).await.is_err());
+
+        let status = auth_service.sign_in(sign_in_request("user", "password")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountDisabled));
+
+        let request = Request::new(EnableUserRequest {
+            session_token: admin_session,
+            user_uuid,
+        });
+
+        auth_service.enable_user(request).await.unwrap();
+
+        assert!(auth_service.sign_in(sign_in_request("user", "password")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn disable_user_should_keep_last_admin() {
+        let (auth_service, admin_session, _, _) = service_with_admin();
+        let admin_uuid = auth_service.sessions_service.lock().unwrap().get_user_uuid(&admin_session).unwrap();
+
+        let request = Request::new(DisableUserRequest {
+            session_token: admin_session,
+            user_uuid: admin_uuid,
+        });
+
+        let status = auth_service.disable_user(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::LastAdmin));
+    }
+
+    #[tokio::test]
+    async fn user_manager_should_not_act_on_admin() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+        let admin_uuid = auth_service.sessions_service.lock().unwrap().get_user_uuid(&admin_session).unwrap();
+
+        grant(&auth_service, &user_uuid, Role::UserManager);
+
+        let request = Request::new(ForcePasswordResetRequest {
+            session_token: user_session.clone(),
+            user_uuid: admin_uuid.clone(),
+        });
+        let status = auth_service.force_password_reset(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let request = Request::new(DisableUserRequest {
+            session_token: user_session.clone(),
+            user_uuid: admin_uuid.clone(),
+        });
+        let status = auth_service.disable_user(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        let request = Request::new(SetUserStatusRequest {
+            session_token: user_session.clone(),
+            user_uuid: admin_uuid.clone(),
+            status: ApiAccountStatus::Locked.into(),
+        });
+        let status = auth_service.set_user_status(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        let request = Request::new(DeleteUserRequest {
+            session_token: user_session.clone(),
+            user_uuid: admin_uuid.clone(),
+        });
+        let status = auth_service.delete_user(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        let request = Request::new(RevokeUserSessionsRequest {
+            session_token: user_session,
+            user_uuid: admin_uuid.clone(),
+        });
+        let status = auth_service.revoke_user_sessions(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        let admin = auth_service.users_service.lock().unwrap().get_user(&admin_uuid).unwrap();
+        assert_eq!(admin.status, AccountStatus::Active);
+        assert!(!admin.password_reset_required);
+        assert!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&admin_session).is_ok());
+    }
+
+    #[tokio::test]
+    async fn force_password_reset_should_require_new_password() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        let (api_key, _) = auth_service.api_keys_service
+                                       .lock()
+                                       .unwrap()
+                                       .create_api_key(&user_uuid, "batch", vec![], None);
+
+        let request = Request::new(ForcePasswordResetRequest {
+            session_token: admin_session,
+            user_uuid,
+        });
+
+        let reset_token = auth_service.force_password_reset(request).await.unwrap().into_inner().reset_token;
+
+        let request = Request::new(ValidateSessionRequest { token: api_key });
+        let status = auth_service.validate_session(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidApiKey));
+
+        let status = auth_service.sign_in(sign_in_request("user", "password")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasswordResetRequired));
+
+        let request = Request::new(ResetPasswordRequest {
+            reset_token: reset_token.clone(),
+            new_password: "new password".to_owned(),
+        });
+
+        auth_service.reset_password(request).await.unwrap();
+
+        assert!(auth_service.sign_in(sign_in_request("user", "password")).await.is_err());
+        assert!(auth_service.sign_in(sign_in_request("user", "new password")).await.is_ok());
+
+        let request = Request::new(ResetPasswordRequest {
+            reset_token,
+            new_password: "another password".to_owned(),
+        });
+
+        let status = auth_service.reset_password(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasswordResetInvalid));
+    }
+
+    #[tokio::test]
+    async fn delete_user_should_remove_account() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+
+        let request = Request::new(DeleteUserRequest {
+            session_token: admin_session,
+            user_uuid: user_uuid.clone(),
+        });
+
+        auth_service.delete_user(request).await.unwrap();
+
+        assert!(auth_service.users_service.lock().unwrap().get_user(&user_uuid).is_none());
+        assert!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&user_session).is_err());
+    }
+
+    #[tokio::test]
+    async fn restore_user_should_bring_back_deleted_account() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        let request = Request::new(DeleteUserRequest {
+            session_token: admin_session.clone(),
+            user_uuid: user_uuid.clone(),
+        });
+        auth_service.delete_user(request).await.unwrap();
+
+        let request = Request::new(ListDeletedUsersRequest { session_token: admin_session.clone() });
+        let deleted = auth_service.list_deleted_users(request).await.unwrap().into_inner().users;
+
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].user_uuid, user_uuid);
+        assert!(deleted[0].purge_at > deleted[0].deleted_at);
+
+        let request = Request::new(RestoreUserRequest {
+            session_token: admin_session,
+            user_uuid: user_uuid.clone(),
+        });
+        let restored = auth_service.restore_user(request).await.unwrap().into_inner().user.unwrap();
+
+        assert_eq!(restored.deleted_at, 0);
+        assert!(auth_service.sign_in(sign_in_request("user", "password")).await.is_ok());
+
+        // The sign-in after the restore is audited too.
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events[events.len() - 2].action, AuditAction::RestoreAccount);
+        assert_eq!(events[events.len() - 1].action, AuditAction::SignIn);
+    }
+
+    #[tokio::test]
+    async fn user_manager_should_not_restore_admin() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+        grant(&auth_service, &user_uuid, Role::UserManager);
+
+        let (other_admin_uuid, _) = sign_in_user(&auth_service, "other_admin", "password");
+        grant(&auth_service, &other_admin_uuid, Role::Admin);
+
+        let request = Request::new(DeleteUserRequest {
+            session_token: admin_session,
+            user_uuid: other_admin_uuid.clone(),
+        });
+        auth_service.delete_user(request).await.unwrap();
+
+        let request = Request::new(RestoreUserRequest {
+            session_token: user_session,
+            user_uuid: other_admin_uuid.clone(),
+        });
+
+        let status = auth_service.restore_user(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+        assert!(auth_service.users_service.lock().unwrap().get_user(&other_admin_uuid).is_none());
+    }
+
+    #[tokio::test]
+    async fn restore_user_should_fail_after_grace_period() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+        let auth_service = auth_service.with_deletion_grace_period(Duration::ZERO);
+
+        let request = Request::new(DeleteUserRequest {
+            session_token: admin_session.clone(),
+            user_uuid: user_uuid.clone(),
+        });
+        auth_service.delete_user(request).await.unwrap();
+
+        let request = Request::new(RestoreUserRequest {
+            session_token: admin_session,
+            user_uuid,
+        });
+
+        let status = auth_service.restore_user(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn restore_user_should_fail_for_active_account() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        let request = Request::new(RestoreUserRequest {
+            session_token: admin_session,
+            user_uuid,
+        });
+
+        let status = auth_service.restore_user(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn import_users_should_require_permission() {
+        let (auth_service, _, _, user_session) = service_with_admin();
+
+        let request = Request::new(ImportUsersRequest {
+            session_token: user_session,
+            users: String::new(),
+            dry_run: true,
+        });
+
+        let status = auth_service.import_users(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn export_users_should_require_roles_permission() {
+        let (auth_service, _, user_uuid, user_session) = service_with_admin();
+        grant(&auth_service, &user_uuid, Role::UserManager);
+
+        let request = Request::new(ExportUsersRequest { session_token: user_session });
+
+        let status = auth_service.export_users(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn export_then_import_should_recreate_users() {
+        let (auth_service, admin_session, _, _) = service_with_admin_in(UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS));
+
+        let request = Request::new(ExportUsersRequest { session_token: admin_session.clone() });
+        let exported = auth_service.export_users(request).await.unwrap().into_inner().users;
+
+        assert_eq!(exported.lines().count(), 2);
+
+        // Renaming the exported users lets them be imported alongside the originals.
+        let renamed = exported
+            .lines()
+            .map(|line| {
+                let mut record: serde_json::Value = serde_json::from_str(line).unwrap();
+                let username = format!("migrated_{}", record["username"].as_str().unwrap());
+                record["username"] = username.into();
+                record.as_object_mut().unwrap().remove("user_uuid");
+                record.to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let request = Request::new(ImportUsersRequest {
+            session_token: admin_session.clone(),
+            users: format!("{}\n{}", renamed, exported),
+            dry_run: false,
+        });
+        let result = auth_service.import_users(request).await.unwrap().into_inner();
+
+        assert_eq!(result.imported_count, 2);
+        assert_eq!(result.rejected.iter().map(|rejected| rejected.line).collect::<Vec<u32>>(), vec![3, 4]);
+        assert!(auth_service.sign_in(sign_in_request("migrated_user", "password")).await.is_ok());
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events.iter().filter(|event| event.action == AuditAction::ImportUser).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn import_users_should_reject_roles_without_permission() {
+        let (auth_service, _, user_uuid, user_session) = service_with_admin_in(UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS));
+        grant(&auth_service, &user_uuid, Role::UserManager);
+        let hash = auth_service.users_service.lock().unwrap().get_user_by_username("user").unwrap().password;
+
+        let request = Request::new(ImportUsersRequest {
+            session_token: user_session,
+            users: [
+                format!(r#"{{"username":"root","password_hash":"{}","roles":["admin"]}}"#, hash),
+                format!(r#"{{"username":"new","password_hash":"{}"}}"#, hash),
+            ]
+            .join("\n"),
+            dry_run: false,
+        });
+        let result = auth_service.import_users(request).await.unwrap().into_inner();
+
+        assert_eq!(result.imported_count, 1);
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].username, "root");
+        assert!(auth_service.users_service.lock().unwrap().get_user_by_username("root").is_none());
+    }
+
+    #[tokio::test]
+    async fn import_users_dry_run_should_not_store_users() {
+        let (auth_service, admin_session, _, _) = service_with_admin_in(UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS));
+        let hash = auth_service.users_service.lock().unwrap().get_user_by_username("user").unwrap().password;
+
+        let request = Request::new(ImportUsersRequest {
+            session_token: admin_session,
+            users: format!(r#"{{"username":"new","password_hash":"{}"}}"#, hash),
+            dry_run: true,
+        });
+        let result = auth_service.import_users(request).await.unwrap().into_inner();
+
+        assert_eq!(result.imported_count, 1);
+        assert!(auth_service.users_service.lock().unwrap().get_user_by_username("new").is_none());
+    }
+
+    #[tokio::test]
+    async fn revoke_user_sessions_should_sign_user_out() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+
+        let request = Request::new(RevokeUserSessionsRequest {
+            session_token: admin_session,
+            user_uuid,
+        });
+
+        auth_service.revoke_user_sessions(request).await.unwrap();
+
+        assert!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&user_session).is_err());
+    }
+
+    #[tokio::test]
+    async fn set_user_status_should_reject_api_keys_until_active() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+        let (api_key, _) = auth_service.api_keys_service
+                                       .lock()
+                                       .unwrap()
+                                       .create_api_key(&user_uuid, "ci", Vec::new(), None);
+
+        let request = Request::new(SetUserStatusRequest {
+            session_token: admin_session.clone(),
+            user_uuid: user_uuid.clone(),
+            status: ApiAccountStatus::Locked.into(),
+        });
+
+        auth_service.set_user_status(request).await.unwrap();
+
+        let request = Request::new(ValidateSessionRequest { token: api_key.clone() });
+        let status = auth_service.validate_session(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
+
+        let status = auth_service.sign_in(sign_in_request("user", "password")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
+
+        let request = Request::new(SetUserStatusRequest {
+            session_token: admin_session,
+            user_uuid,
+            status: ApiAccountStatus::Active.into(),
+        });
+
+        auth_service.set_user_status(request).await.unwrap();
+
+        let request = Request::new(ValidateSessionRequest { token: api_key });
+        assert!(auth_service.validate_session(request).await.is_ok());
+    }
+
+    fn list_audit_events_request(session_token: &str, cursor: &str, limit: u32) -> Request<ListAuditEventsRequest> {
+        Request::new(ListAuditEventsRequest {
+            session_token: session_token.to_owned(),
+            cursor: cursor.to_owned(),
+            limit,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn list_audit_events_should_require_permission() {
+        let (auth_service, _, user_uuid, user_session) = service_with_admin();
+
+        let status = auth_service.list_audit_events(list_audit_events_request(&user_session, "", 0)).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        // Auditors can read the audit trail but not change users.
+        grant(&auth_service, &user_uuid, Role::Auditor);
+
+        assert!(auth_service.list_audit_events(list_audit_events_request(&user_session, "", 0)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn list_audit_events_should_page_newest_first() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        for _ in 0..3 {
+            auth_service.audit(AuditEvent::new(AuditAction::SignIn, &user_uuid, AuditOutcome::Failure).with_reason("invalid_credentials"));
+        }
+        auth_service.audit(AuditEvent::new(AuditAction::SignIn, &user_uuid, AuditOutcome::Success).with_actor(&user_uuid));
+
+        let page = auth_service.list_audit_events(list_audit_events_request(&admin_session, "", 3)).await.unwrap().into_inner();
+        let ids: Vec<u64> = page.events.iter().map(|event| event.id).collect();
+
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(page.events[0].outcome(), ApiAuditOutcome::Success);
+        assert_eq!(page.events[0].actor_uuid, user_uuid);
+        assert_eq!(page.events[1].action(), ApiAuditAction::SignIn);
+        assert_eq!(page.events[1].reason, "invalid_credentials");
+        assert!(page.events[1].timestamp > 0);
+        assert_eq!(page.next_cursor, "2");
+
+        let page = auth_service.list_audit_events(list_audit_events_request(&admin_session, &page.next_cursor, 3)).await.unwrap().into_inner();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].id, 1);
+        assert!(page.next_cursor.is_empty());
+
+        let status = auth_service.list_audit_events(list_audit_events_request(&admin_session, "abc", 3)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCursor));
+    }
+
+    #[tokio::test]
+    async fn list_audit_events_should_filter() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        let request = Request::new(DisableUserRequest {
+            session_token: admin_session.clone(),
+            user_uuid: user_uuid.clone(),
+        });
+        auth_service.disable_user(request).await.unwrap();
+        assert!(auth_service.sign_in(sign_in_request("user", "password")).await.is_err());
+
+        // Signing in replaces the administrator's session.
+        let admin_session = auth_service.sign_in(sign_in_request("admin", "password")).await.unwrap().into_inner().session_token;
+
+        let list = |request: ListAuditEventsRequest| {
+            let request = Request::new(ListAuditEventsRequest { session_token: admin_session.clone(), ..request });
+            auth_service.list_audit_events(request)
+        };
+
+        let events = list(ListAuditEventsRequest { user_uuid: user_uuid.clone(), ..Default::default() }).await.unwrap().into_inner().events;
+        let actions: Vec<_> = events.iter().map(|event| event.action()).collect();
+        assert_eq!(actions, vec![ApiAuditAction::SignIn, ApiAuditAction::ChangeAccountStatus]);
+
+        let request = ListAuditEventsRequest {
+            action: ApiAuditAction::SignIn.into(),
+            outcome: ApiAuditOutcome::Failure.into(),
+            ..Default::default()
+        };
+        let events = list(request).await.unwrap().into_inner().events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].user_uuid, user_uuid);
+        assert_eq!(events[0].reason, "account_disabled");
+
+        let until = unix_seconds(Some(SystemTime::now() - Duration::from_secs(60)));
+        let events = list(ListAuditEventsRequest { until, ..Default::default() }).await.unwrap().into_inner().events;
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tail_audit_events_should_stream_matching_events() {
+        let (auth_service, admin_session, user_uuid, user_session) = service_with_admin();
+        let (shutdown_trigger, shutdown) = shutdown::channel();
+        let auth_service = auth_service.with_shutdown(shutdown);
+
+        let request = Request::new(TailAuditEventsRequest {
+            session_token: user_session,
+            ..Default::default()
+        });
+        let status = auth_service.tail_audit_events(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+
+        let request = Request::new(TailAuditEventsRequest {
+            session_token: admin_session,
+            action: ApiAuditAction::SignIn.into(),
+            ..Default::default()
+        });
+        let mut stream = auth_service.tail_audit_events(request).await.unwrap().into_inner();
+
+        auth_service.audit(AuditEvent::new(AuditAction::SignOut, &user_uuid, AuditOutcome::Success));
+        auth_service.audit(AuditEvent::new(AuditAction::SignIn, &user_uuid, AuditOutcome::Failure));
+
+        let event = stream.next().await.unwrap().unwrap().event.unwrap();
+        assert_eq!(event.id, 2);
+        assert_eq!(event.action(), ApiAuditAction::SignIn);
+        assert_eq!(event.user_uuid, user_uuid);
+
+        shutdown_trigger.trigger();
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn tail_audit_events_should_end_when_client_lags() {
+        let (auth_service, admin_session, user_uuid, _) = service_with_admin();
+
+        let request = Request::new(TailAuditEventsRequest {
+            session_token: admin_session,
+            ..Default::default()
+        });
+        let mut stream = auth_service.tail_audit_events(request).await.unwrap().into_inner();
+
+        // The test runtime runs one task at a time, so none of these are sent before the stream is
+        // polled.
+        for _ in 0..2000 {
+            auth_service.audit(AuditEvent::new(AuditAction::SignIn, &user_uuid, AuditOutcome::Failure));
+        }
+
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(error_reason(&status), Some(ErrorReason::AuditTailLagged));
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,469 @@
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use tracing::instrument;
+use uuid::Uuid;
+
+use std::{collections::HashMap, fmt, time::SystemTime};
+
+/// The marker every API key starts with, so keys can be told apart from session tokens and spotted
+/// by secret scanners.
+pub const API_KEY_MARKER: &str = "rak_";
+
+/// The number of random bytes in the visible part of an API key (40 bits, eight base32 characters).
+const PREFIX_BYTES: usize = 5;
+
+/// The number of random bytes in the secret part of an API key (256 bits).
+const SECRET_BYTES: usize = 32;
+
+/// `ApiKeysError` enumerates the ways an operation on the `ApiKeys` store can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApiKeysError {
+    /// No key with the requested ID belongs to the user.
+    KeyNotFound,
+
+    /// The presented key is malformed, unknown or revoked.
+    InvalidKey,
+
+    /// The presented key has passed its expiry time.
+    Expired,
+}
+
+impl fmt::Display for ApiKeysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeysError::KeyNotFound => write!(f, "API key not found."),
+            ApiKeysError::InvalidKey => write!(f, "Invalid API key."),
+            ApiKeysError::Expired => write!(f, "API key has expired."),
+        }
+    }
+}
+
+impl std::error::Error for ApiKeysError {}
+
+/// `ApiKey` struct represents the stored record of an API key. The secret itself is never stored.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    /// A string representing the UUID of the key, used to list and revoke it.
+    pub key_id: String,
+
+    /// A string representing the UUID of the user the key authenticates as.
+    pub user_uuid: String,
+
+    /// A user-chosen label for the key.
+    pub name: String,
+
+    /// The non-secret start of the key, e.g. `rak_abcd2345`, shown so users can recognise it.
+    pub prefix: String,
+
+    /// The lowercase hex SHA-256 digest of the full key.
+    secret_hash: String,
+
+    /// The scopes the key is limited to.
+    pub scopes: Vec<String>,
+
+    /// The time at which the key was created.
+    pub created_at: SystemTime,
+
+    /// The time after which the key stops working, if any.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// `ApiKeys` trait defines methods for managing API keys used for service-to-service authentication.
+pub trait ApiKeys {
+
+    /// Creates a new API key for the specified user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user the key authenticates as.
+    /// * `name` - A label for the key.
+    /// * `scopes` - The scopes the key is limited to.
+    /// * `expires_at` - The time after which the key stops working, or `None` for no expiry.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of the full key, to be shown to the user once, and the stored record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_service` implements `ApiKeys` trait
+    /// let (api_key, record) = api_keys_service.create_api_key("user_uuid", "batch job", vec![], None);
+    /// println!("Created {} ({})", record.prefix, record.key_id);
+    /// ```
+    fn create_api_key(
+        &mut self,
+        user_uuid: &str,
+        name: &str,
+        scopes: Vec<String>,
+        expires_at: Option<SystemTime>,
+    ) -> (String, ApiKey);
+
+    /// Lists the API keys of the specified user, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose keys are listed.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the user's keys, including expired ones.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_service` implements `ApiKeys` trait
+    /// for key in api_keys_service.list_api_keys("user_uuid") {
+    ///     println!("{} {}", key.prefix, key.name);
+    /// }
+    /// ```
+    fn list_api_keys(&self, user_uuid: &str) -> Vec<ApiKey>;
+
+    /// Revokes one of the specified user's API keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user who owns the key.
+    /// * `key_id` - A string representing the UUID of the key to revoke.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the key was revoked, otherwise `ApiKeysError::KeyNotFound` if the user
+    /// has no such key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_service` implements `ApiKeys` trait
+    /// api_keys_service.revoke_api_key("user_uuid", "key_id")?;
+    /// ```
+    fn revoke_api_key(&mut self, user_uuid: &str, key_id: &str) -> Result<(), ApiKeysError>;
+
+    /// Revokes every API key of the specified user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose keys are revoked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_service` implements `ApiKeys` trait
+    /// api_keys_service.revoke_user_api_keys("user_uuid");
+    /// ```
+    fn revoke_user_api_keys(&mut self, user_uuid: &str);
+
+    /// Checks a presented API key.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The full key presented by the caller.
+    /// * `now` - The current time, used to check expiry.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the key's record, otherwise `ApiKeysError::InvalidKey` or
+    /// `ApiKeysError::Expired`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_service` implements `ApiKeys` trait
+    /// let key = api_keys_service.verify_api_key("rak_...", SystemTime::now())?;
+    /// println!("Authenticated as {}", key.user_uuid);
+    /// ```
+    fn verify_api_key(&self, api_key: &str, now: SystemTime) -> Result<ApiKey, ApiKeysError>;
+}
+
+/// `ApiKeysImpl` represents an implementation of the `ApiKeys` trait.
+///
+/// This implementation stores key records in memory, indexed by key ID and by visible prefix.
+#[derive(Default)]
+pub struct ApiKeysImpl {
+
+    /// A HashMap that maps key IDs to key records.
+    id_to_key: HashMap<String, ApiKey>,
+
+    /// A HashMap that maps visible prefixes to key IDs.
+    prefix_to_id: HashMap<String, String>,
+}
+
+impl ApiKeys for ApiKeysImpl {
+
+    /// Creates a new API key for the specified user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user the key authenticates as.
+    /// * `name` - A label for the key.
+    /// * `scopes` - The scopes the key is limited to.
+    /// * `expires_at` - The time after which the key stops working, or `None` for no expiry.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of the full key and the stored record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_impl` is an instance of `ApiKeysImpl`
+    /// let (api_key, record) = api_keys_impl.create_api_key("user_uuid", "batch job", vec![], None);
+    /// ```
+    #[instrument(skip_all, fields(store = "api_keys"))]
+    fn create_api_key(
+        &mut self,
+        user_uuid: &str,
+        name: &str,
+        scopes: Vec<String>,
+        expires_at: Option<SystemTime>,
+    ) -> (String, ApiKey) {
+        // Prefixes are short enough to collide occasionally, so draw again until one is free.
+        let prefix = loop {
+            let prefix = format!("{}{}", API_KEY_MARKER, random_base32(PREFIX_BYTES));
+            if !self.prefix_to_id.contains_key(&prefix) {
+                break prefix;
+            }
+        };
+
+        let api_key = format!("{}_{}", prefix, random_base32(SECRET_BYTES));
+
+        let record = ApiKey {
+            key_id: Uuid::new_v4().to_string(),
+            user_uuid: user_uuid.to_owned(),
+            name: name.to_owned(),
+            prefix: prefix.clone(),
+            secret_hash: hash_api_key(&api_key),
+            scopes,
+            created_at: SystemTime::now(),
+            expires_at,
+        };
+
+        self.prefix_to_id.insert(prefix, record.key_id.clone());
+        self.id_to_key.insert(record.key_id.clone(), record.clone());
+
+        (api_key, record)
+    }
+
+    /// Lists the API keys of the specified user, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose keys are listed.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the user's keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_impl` is an instance of `ApiKeysImpl`
+    /// let keys = api_keys_impl.list_api_keys("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "api_keys"))]
+    fn list_api_keys(&self, user_uuid: &str) -> Vec<ApiKey> {
+        let mut keys: Vec<ApiKey> = self.id_to_key
+            .values()
+            .filter(|key| key.user_uuid == user_uuid)
+            .cloned()
+            .collect();
+
+        keys.sort_by_key(|key| key.created_at);
+        keys
+    }
+
+    /// Revokes one of the specified user's API keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user who owns the key.
+    /// * `key_id` - A string representing the UUID of the key to revoke.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the key was revoked, otherwise `ApiKeysError::KeyNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_impl` is an instance of `ApiKeysImpl`
+    /// api_keys_impl.revoke_api_key("user_uuid", "key_id")?;
+    /// ```
+    #[instrument(skip_all, fields(store = "api_keys"))]
+    fn revoke_api_key(&mut self, user_uuid: &str, key_id: &str) -> Result<(), ApiKeysError> {
+        // Keys of other users are reported as missing rather than forbidden so IDs cannot be probed.
+        match self.id_to_key.get(key_id) {
+            Some(key) if key.user_uuid == user_uuid => {
+                self.prefix_to_id.remove(&key.prefix);
+                self.id_to_key.remove(key_id);
+                Ok(())
+            }
+            _ => Err(ApiKeysError::KeyNotFound),
+        }
+    }
+
+    /// Revokes every API key of the specified user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose keys are revoked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_impl` is an instance of `ApiKeysImpl`
+    /// api_keys_impl.revoke_user_api_keys("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "api_keys"))]
+    fn revoke_user_api_keys(&mut self, user_uuid: &str) {
+        let prefix_to_id = &mut self.prefix_to_id;
+
+        self.id_to_key.retain(|_, key| {
+            if key.user_uuid == user_uuid {
+                prefix_to_id.remove(&key.prefix);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Checks a presented API key.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The full key presented by the caller.
+    /// * `now` - The current time, used to check expiry.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the key's record, otherwise `ApiKeysError::InvalidKey` or
+    /// `ApiKeysError::Expired`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `api_keys_impl` is an instance of `ApiKeysImpl`
+    /// let key = api_keys_impl.verify_api_key("rak_...", SystemTime::now())?;
+    /// ```
+    #[instrument(skip_all, fields(store = "api_keys"))]
+    fn verify_api_key(&self, api_key: &str, now: SystemTime) -> Result<ApiKey, ApiKeysError> {
+        let (prefix, _) = api_key.rsplit_once('_').ok_or(ApiKeysError::InvalidKey)?;
+
+        let key = self.prefix_to_id
+            .get(prefix)
+            .and_then(|key_id| self.id_to_key.get(key_id))
+            .filter(|key| key.secret_hash == hash_api_key(api_key))
+            .ok_or(ApiKeysError::InvalidKey)?;
+
+        if key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(ApiKeysError::Expired);
+        }
+
+        Ok(key.clone())
+    }
+}
+
+/// Tells whether a bearer token is an API key rather than a session token.
+///
+/// # Arguments
+///
+/// * `token` - The token presented by the caller.
+///
+/// # Returns
+///
+/// `true` if the token has the API key marker.
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_MARKER)
+}
+
+/// Hashes an API key for storage.
+///
+/// API keys are random and high-entropy, so a single SHA-256 is sufficient.
+fn hash_api_key(api_key: &str) -> String {
+    Sha256::digest(api_key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Generates random bytes encoded as lowercase base32.
+fn random_base32(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn should_create_and_verify_api_key() {
+        let mut api_keys_service = ApiKeysImpl::default();
+        let (api_key, record) = api_keys_service.create_api_key("123456", "batch", vec!["read".to_owned()], None);
+
+        assert!(is_api_key(&api_key));
+        assert!(api_key.starts_with(&record.prefix));
+        assert_ne!(record.secret_hash, api_key);
+
+        let verified = api_keys_service.verify_api_key(&api_key, SystemTime::now()).unwrap();
+        assert_eq!(verified.user_uuid, "123456");
+        assert_eq!(verified.scopes, vec!["read".to_owned()]);
+    }
+
+    #[test]
+    fn should_reject_tampered_api_key() {
+        let mut api_keys_service = ApiKeysImpl::default();
+        let (api_key, record) = api_keys_service.create_api_key("123456", "batch", vec![], None);
+
+        let tampered = format!("{}_{}", record.prefix, "a".repeat(api_key.len() - record.prefix.len() - 1));
+
+        assert_eq!(api_keys_service.verify_api_key(&tampered, SystemTime::now()).unwrap_err(), ApiKeysError::InvalidKey);
+        assert_eq!(api_keys_service.verify_api_key("rak_nonsense", SystemTime::now()).unwrap_err(), ApiKeysError::InvalidKey);
+    }
+
+    #[test]
+    fn should_reject_expired_api_key() {
+        let mut api_keys_service = ApiKeysImpl::default();
+        let now = SystemTime::now();
+        let (api_key, _) = api_keys_service.create_api_key("123456", "batch", vec![], Some(now + Duration::from_secs(60)));
+
+        assert!(api_keys_service.verify_api_key(&api_key, now).is_ok());
+        assert_eq!(
+            api_keys_service.verify_api_key(&api_key, now + Duration::from_secs(60)).unwrap_err(),
+            ApiKeysError::Expired
+        );
+    }
+
+    #[test]
+    fn should_revoke_api_key() {
+        let mut api_keys_service = ApiKeysImpl::default();
+        let (api_key, record) = api_keys_service.create_api_key("123456", "batch", vec![], None);
+
+        assert_eq!(api_keys_service.revoke_api_key("other", &record.key_id), Err(ApiKeysError::KeyNotFound));
+
+        api_keys_service.revoke_api_key("123456", &record.key_id).unwrap();
+
+        assert_eq!(api_keys_service.verify_api_key(&api_key, SystemTime::now()).unwrap_err(), ApiKeysError::InvalidKey);
+        assert!(api_keys_service.list_api_keys("123456").is_empty());
+    }
+
+    #[test]
+    fn should_list_only_users_api_keys() {
+        let mut api_keys_service = ApiKeysImpl::default();
+        api_keys_service.create_api_key("123456", "first", vec![], None);
+        api_keys_service.create_api_key("123456", "second", vec![], None);
+        api_keys_service.create_api_key("other", "third", vec![], None);
+
+        assert_eq!(api_keys_service.list_api_keys("123456").len(), 2);
+
+        api_keys_service.revoke_user_api_keys("123456");
+
+        assert!(api_keys_service.list_api_keys("123456").is_empty());
+        assert_eq!(api_keys_service.list_api_keys("other").len(), 1);
+        assert_eq!(api_keys_service.prefix_to_id.len(), 1);
+    }
+}
@@ -0,0 +1,1070 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+use tracing::{error, info, instrument};
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The `prev_hash` of the first event in an audit file.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What separates the hash of an audit file line from the rest of the line.
+const HASH_FIELD: &str = ",\"hash\":\"";
+
+/// How many events a subscriber may fall behind before it misses some.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// How many of the newest events are kept in memory to be queried. Older events remain in the
+/// sinks.
+const MAX_RETAINED_EVENTS: usize = 100_000;
+
+/// `AuditError` enumerates the ways opening an audit file can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditError {
+    /// The file could not be read or opened for appending. Carries the path and the cause.
+    Io(PathBuf, String),
+
+    /// A line does not follow from the one before it, so the file was modified after it was
+    /// written. Carries the path and the line number, counting from 1.
+    Tampered(PathBuf, usize),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Io(path, cause) => write!(f, "Could not open audit file {}: {}", path.display(), cause),
+            AuditError::Tampered(path, line) => {
+                write!(f, "Audit file {} was modified: the hash chain breaks at line {}.", path.display(), line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+/// `AuditQueryError` enumerates the ways reading the audit trail through the API can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditQueryError {
+    /// The cursor was not returned by a previous page. Carries the cursor.
+    InvalidCursor(String),
+
+    /// A subscriber fell too far behind. Carries the number of events it missed.
+    Lagged(u64),
+}
+
+impl fmt::Display for AuditQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditQueryError::InvalidCursor(cursor) => write!(f, "Invalid cursor: {}.", cursor),
+            AuditQueryError::Lagged(missed) => write!(f, "Fell behind and missed {} audit events.", missed),
+        }
+    }
+}
+
+impl std::error::Error for AuditQueryError {}
+
+/// `AuditAction` enumerates the security-relevant actions that are recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A user created an account.
+    SignUp,
+
+    /// A user signed in with their password.
+    SignIn,
+
+    /// A user completed sign-in with a TOTP or recovery code.
+    VerifyMfa,
+
+    /// A user signed in with a passkey.
+    PasskeySignIn,
+
+    /// A user signed out.
+    SignOut,
+
+    /// A user deleted their own account.
+    DeleteAccount,
+
+    /// A role was granted to a user.
+    GrantRole,
+
+    /// A role was revoked from a user.
+    RevokeRole,
+
+    /// A user was added to a group.
+    AddGroupMember,
+
+    /// A user was removed from a group.
+    RemoveGroupMember,
+
+    /// An administrator changed the status of an account, for example to disable it.
+    ChangeAccountStatus,
+
+    /// An administrator required a user to reset their password.
+    ForcePasswordReset,
+
+    /// A user chose a new password with a reset token.
+    ResetPassword,
+
+    /// An administrator signed a user out.
+    RevokeSessions,
+
+    /// An administrator restored a deleted account.
+    RestoreAccount,
+
+    /// A deleted account was permanently removed once its grace period ended.
+    PurgeAccount,
+
+    /// A user changed their username.
+    ChangeUsername,
+
+    /// An administrator imported a user from another system.
+    ImportUser,
+
+    /// An administrator exported every user, recorded against the administrator.
+    ExportUsers,
+
+    /// A group was created.
+    CreateGroup,
+
+    /// A group was renamed or its description changed.
+    UpdateGroup,
+
+    /// A group was deleted.
+    DeleteGroup,
+
+    /// A role was granted to a group.
+    GrantGroupRole,
+
+    /// A role was revoked from a group.
+    RevokeGroupRole,
+
+    /// A user created an API key.
+    CreateApiKey,
+
+    /// A user revoked one of their API keys.
+    RevokeApiKey,
+}
+
+/// `AuditOutcome` describes whether an audited action succeeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The action completed.
+    Success,
+
+    /// The password was correct, and the user must still verify a second factor.
+    MfaRequired,
+
+    /// The action was attempted but rejected.
+    Failure,
+}
+
+/// `AuditEvent` struct represents a single entry in the audit trail.
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    /// The position of the event in the audit trail, counting from 1. Zero until it is recorded.
+    /// With an audit file, the position continues from the events already in the file.
+    pub id: u64,
+
+    /// The action that was performed.
+    pub action: AuditAction,
+
+    /// The UUID of the user who performed the action. Empty when the service acted on its own,
+    /// e.g. to purge an account, or the caller was not signed in, e.g. a failed sign-in.
+    pub actor: String,
+
+    /// The UUID of the user the action was performed on, or of the group for group actions. Empty
+    /// when no such user exists, e.g. a sign-in with an unknown username.
+    pub user_uuid: String,
+
+    /// Whether the action succeeded.
+    pub outcome: AuditOutcome,
+
+    /// Why the action failed, as the lower case `ErrorReason`, e.g. `invalid_credentials`.
+    pub reason: Option<String>,
+
+    /// The IP address of the client that sent the request, if known.
+    pub peer_ip: Option<IpAddr>,
+
+    /// The time at which the event was recorded.
+    pub timestamp: SystemTime,
+}
+
+impl AuditEvent {
+
+    /// Constructs a new `AuditEvent` timestamped with the current time, without an actor, reason
+    /// or peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action that was performed.
+    /// * `user_uuid` - A string representing the UUID of the user the action was performed on.
+    /// * `outcome` - Whether the action succeeded.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `AuditEvent`.
+    pub fn new(action: AuditAction, user_uuid: &str, outcome: AuditOutcome) -> Self {
+        Self {
+            id: 0,
+            action,
+            actor: String::new(),
+            user_uuid: user_uuid.to_owned(),
+            outcome,
+            reason: None,
+            peer_ip: None,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// Sets the user who performed the action.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor` - A string representing the UUID of the user who performed the action.
+    ///
+    /// # Returns
+    ///
+    /// The updated `AuditEvent`.
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_owned();
+        self
+    }
+
+    /// Sets why the action failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - The lower case `ErrorReason` of the failure.
+    ///
+    /// # Returns
+    ///
+    /// The updated `AuditEvent`.
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_owned());
+        self
+    }
+
+    /// Sets the IP address of the client that sent the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_ip` - The address, if known.
+    ///
+    /// # Returns
+    ///
+    /// The updated `AuditEvent`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let event = AuditEvent::new(AuditAction::SignOut, &user_uuid, AuditOutcome::Success)
+    ///     .with_actor(&user_uuid)
+    ///     .with_peer_ip(request.remote_addr().map(|addr| addr.ip()));
+    /// ```
+    pub fn with_peer_ip(mut self, peer_ip: Option<IpAddr>) -> Self {
+        self.peer_ip = peer_ip;
+        self
+    }
+
+    /// Returns when the event was recorded, in seconds since the Unix epoch.
+    pub fn unix_timestamp(&self) -> u64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {:?} actor={} user={} outcome={:?}",
+            self.unix_timestamp(),
+            self.action,
+            self.actor,
+            self.user_uuid,
+            self.outcome
+        )?;
+
+        if let Some(reason) = &self.reason {
+            write!(f, " reason={}", reason)?;
+        }
+
+        if let Some(peer_ip) = &self.peer_ip {
+            write!(f, " peer={}", peer_ip)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `AuditFilter` struct selects audit events. Unset fields match every event.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    /// Only events the user performed or that were performed on them.
+    pub user_uuid: Option<String>,
+
+    /// Only events of this action.
+    pub action: Option<AuditAction>,
+
+    /// Only events with this outcome.
+    pub outcome: Option<AuditOutcome>,
+
+    /// Only events recorded at or after this time.
+    pub since: Option<SystemTime>,
+
+    /// Only events recorded before this time.
+    pub until: Option<SystemTime>,
+}
+
+impl AuditFilter {
+
+    /// Checks whether an event is selected by the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to check.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the event matches every field that is set.
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.user_uuid.as_ref().is_none_or(|user_uuid| event.user_uuid == *user_uuid || event.actor == *user_uuid)
+            && self.action.is_none_or(|action| event.action == action)
+            && self.outcome.is_none_or(|outcome| event.outcome == outcome)
+            && self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+    }
+}
+
+/// `AuditSink` trait defines where recorded audit events are written, so that the audit trail can
+/// be sent to a file, the log or another system.
+pub trait AuditSink {
+
+    /// Writes an audit event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to write.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the event was written, otherwise the `io::Error` that stopped it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sink` implements `AuditSink` trait
+    /// sink.write(&AuditEvent::new(AuditAction::SignIn, "user_uuid", AuditOutcome::Success))?;
+    /// ```
+    fn write(&mut self, event: &AuditEvent) -> io::Result<()>;
+
+    /// Makes sure every event written so far is stored durably. Sinks that do not buffer need not
+    /// do anything.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the events are stored, otherwise the `io::Error` that stopped it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sink` implements `AuditSink` trait
+    /// sink.flush()?;
+    /// ```
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `LogSink` writes audit events to the service's log, as `info` lines with the `audit` target.
+#[derive(Default)]
+pub struct LogSink;
+
+impl AuditSink for LogSink {
+
+    /// Writes an audit event to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to write.
+    fn write(&mut self, event: &AuditEvent) -> io::Result<()> {
+        info!(
+            target: "audit",
+            action = ?event.action,
+            actor = event.actor,
+            user_uuid = event.user_uuid,
+            outcome = ?event.outcome,
+            reason = event.reason,
+            peer_ip = event.peer_ip.map(|peer_ip| peer_ip.to_string()),
+            "Audit event"
+        );
+
+        Ok(())
+    }
+}
+
+/// `ChainedRecord` struct is the form an audit event takes in an audit file, before its hash is
+/// appended.
+#[derive(Serialize)]
+struct ChainedRecord<'a> {
+    /// When the event was recorded, in seconds since the Unix epoch.
+    timestamp: u64,
+
+    /// The action that was performed.
+    action: AuditAction,
+
+    /// Whether the action succeeded.
+    outcome: AuditOutcome,
+
+    /// The UUID of the user who performed the action.
+    actor: &'a str,
+
+    /// The UUID of the user the action was performed on.
+    user_uuid: &'a str,
+
+    /// Why the action failed.
+    reason: Option<&'a str>,
+
+    /// The IP address of the client that sent the request.
+    peer_ip: Option<IpAddr>,
+
+    /// The hash of the previous line, or `GENESIS_HASH` for the first one.
+    prev_hash: &'a str,
+}
+
+/// `ChainLink` struct holds the part of an audit file line that links it to the previous one.
+#[derive(Deserialize)]
+struct ChainLink {
+    /// The hash of the previous line.
+    prev_hash: String,
+}
+
+/// `ChainHead` struct describes the end of an audit file's hash chain.
+///
+/// Removing lines from the end of a file leaves a valid, shorter chain, so the head is logged when
+/// the file is opened and flushed. A head that falls behind the one logged before shows the file
+/// was truncated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainHead {
+    /// The number of lines in the file.
+    pub lines: u64,
+
+    /// The hash of the last line, or `GENESIS_HASH` for an empty file.
+    pub hash: String,
+}
+
+impl fmt::Display for ChainHead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} lines, last hash {}", self.lines, self.hash)
+    }
+}
+
+/// Returns the lower case hex SHA-256 hash of an audit file line without its hash.
+fn hash(record: &str) -> String {
+    format!("{:x}", Sha256::digest(record.as_bytes()))
+}
+
+/// Checks that a line of an audit file follows from the previous one.
+///
+/// A line is the JSON object of a `ChainedRecord` with a final `hash` field, which is the SHA-256
+/// hash of the line without that field.
+///
+/// # Arguments
+///
+/// * `line` - The line, without its line break.
+/// * `prev_hash` - The hash of the previous line.
+///
+/// # Returns
+///
+/// The hash of the line, or `None` if the line was modified or does not follow `prev_hash`.
+fn verify_line(line: &str, prev_hash: &str) -> Option<String> {
+    let (record, rest) = line.rsplit_once(HASH_FIELD)?;
+    let line_hash = rest.strip_suffix("\"}")?;
+    let record = format!("{}}}", record);
+
+    let link: ChainLink = serde_json::from_str(&record).ok()?;
+
+    match link.prev_hash == prev_hash && hash(&record) == line_hash {
+        true => Some(line_hash.to_owned()),
+        false => None,
+    }
+}
+
+/// Checks the hash chain of an audit file.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file. A missing file is an empty, valid audit trail.
+///
+/// # Returns
+///
+/// An `Ok` result containing the head of the chain, otherwise an `AuditError` if the file cannot
+/// be read or was modified.
+///
+/// # Example
+///
+/// ```
+/// match audit::verify(Path::new("audit.log")) {
+///     Ok(head) => println!("The audit trail is intact: {}.", head),
+///     Err(e) => eprintln!("{}", e),
+/// }
+/// ```
+pub fn verify(path: &Path) -> Result<ChainHead, AuditError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ChainHead { lines: 0, hash: GENESIS_HASH.to_owned() }),
+        Err(e) => return Err(AuditError::Io(path.to_owned(), e.to_string())),
+    };
+
+    let mut head = ChainHead { lines: 0, hash: GENESIS_HASH.to_owned() };
+
+    for line in contents.lines() {
+        head.lines += 1;
+        head.hash = verify_line(line, &head.hash).ok_or_else(|| AuditError::Tampered(path.to_owned(), head.lines as usize))?;
+    }
+
+    // Every line is written whole, with its line break.
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        return Err(AuditError::Tampered(path.to_owned(), contents.lines().count()));
+    }
+
+    Ok(head)
+}
+
+/// `FileSink` appends audit events to a file as JSON Lines, chained by hashes.
+///
+/// Each line holds the hash of the line before it and ends with its own hash, so editing or
+/// reordering lines, or removing any but the last ones, breaks the chain from that line on. The
+/// chain is checked when the file is opened. Removing the last lines leaves a valid chain, so the
+/// `ChainHead` is logged when the file is opened and flushed, to be compared across restarts.
+pub struct FileSink {
+    /// The path to the file.
+    path: PathBuf,
+
+    /// The file, opened for appending.
+    file: File,
+
+    /// The end of the chain written so far.
+    head: ChainHead,
+}
+
+impl FileSink {
+
+    /// Opens an audit file for appending, creating it if it does not exist, and checks its hash
+    /// chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the sink, otherwise an `AuditError` if the file cannot be opened
+    /// or was modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let file_sink = FileSink::open(Path::new("audit.log"))?;
+    /// let audit_service = AuditImpl::with_sinks(vec![Box::new(LogSink), Box::new(file_sink)]);
+    /// ```
+    pub fn open(path: &Path) -> Result<Self, AuditError> {
+        let head = verify(path)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AuditError::Io(path.to_owned(), e.to_string()))?;
+
+        info!("Opened audit file {}: {}", path.display(), head);
+
+        Ok(Self { path: path.to_owned(), file, head })
+    }
+
+    /// Returns the end of the chain written so far, e.g. to continue the event IDs after it.
+    pub fn head(&self) -> &ChainHead {
+        &self.head
+    }
+}
+
+impl AuditSink for FileSink {
+
+    /// Appends an audit event to the file, chained to the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to write.
+    fn write(&mut self, event: &AuditEvent) -> io::Result<()> {
+        let record = serde_json::to_string(&ChainedRecord {
+            timestamp: event.unix_timestamp(),
+            action: event.action,
+            outcome: event.outcome,
+            actor: &event.actor,
+            user_uuid: &event.user_uuid,
+            reason: event.reason.as_deref(),
+            peer_ip: event.peer_ip,
+            prev_hash: &self.head.hash,
+        })?;
+
+        let line_hash = hash(&record);
+
+        // The record always ends with the closing brace of its object.
+        let line = format!("{}{}{}\"}}\n", &record[..record.len() - 1], HASH_FIELD, line_hash);
+
+        // One write per line, so lines from a crash are never interleaved.
+        self.file.write_all(line.as_bytes())?;
+        self.head.lines += 1;
+        self.head.hash = line_hash;
+
+        Ok(())
+    }
+
+    /// Waits until the lines written so far have reached the disk, then logs the head of the chain.
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        info!("Flushed audit file {}: {}", self.path.display(), self.head);
+
+        Ok(())
+    }
+}
+
+/// `Audit` trait defines methods for recording security-relevant events.
+pub trait Audit {
+
+    /// Records an audit event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `audit_service` implements `Audit` trait
+    /// audit_service.record(AuditEvent::new(AuditAction::DeleteAccount, "user_uuid", AuditOutcome::Success));
+    /// ```
+    fn record(&mut self, event: AuditEvent);
+
+    /// Retrieves the retained audit events, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the recorded events, at most `MAX_RETAINED_EVENTS` of them.
+    #[allow(dead_code)] // Only read by tests, the API pages through `query`.
+    fn events(&self) -> Vec<AuditEvent>;
+
+    /// Retrieves a page of the recorded audit events that match a filter, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Selects the events.
+    /// * `before_id` - Only events recorded before the event with this ID, to continue from the
+    ///   last event of the previous page. `None` starts from the newest event.
+    /// * `limit` - The largest number of events to return.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the matching events.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `audit_service` implements `Audit` trait
+    /// let filter = AuditFilter { outcome: Some(AuditOutcome::Failure), ..AuditFilter::default() };
+    /// let page = audit_service.query(&filter, None, 50);
+    /// let next_page = audit_service.query(&filter, page.last().map(|event| event.id), 50);
+    /// ```
+    fn query(&self, filter: &AuditFilter, before_id: Option<u64>, limit: usize) -> Vec<AuditEvent>;
+
+    /// Subscribes to the events recorded from now on.
+    ///
+    /// # Returns
+    ///
+    /// A receiver of every event recorded after the call. A receiver that falls too far behind
+    /// misses events, and is told how many.
+    fn subscribe(&self) -> broadcast::Receiver<AuditEvent>;
+
+    /// Makes sure every event recorded so far is stored durably, e.g. before the service exits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `audit_service` implements `Audit` trait
+    /// audit_service.flush();
+    /// ```
+    fn flush(&mut self);
+}
+
+/// `AuditImpl` represents an implementation of the `Audit` trait.
+///
+/// This implementation keeps the newest `MAX_RETAINED_EVENTS` events in memory and writes each one
+/// to its sinks, the log unless configured otherwise.
+pub struct AuditImpl {
+
+    /// The newest events recorded so far, oldest first, with consecutive IDs.
+    events: VecDeque<AuditEvent>,
+
+    /// The ID given to the next recorded event.
+    next_id: u64,
+
+    /// Where events are written as they are recorded.
+    sinks: Vec<Box<dyn AuditSink + Send + Sync>>,
+
+    /// Sends recorded events to subscribers.
+    subscribers: broadcast::Sender<AuditEvent>,
+}
+
+impl Default for AuditImpl {
+    fn default() -> Self {
+        Self::with_sinks(vec![Box::new(LogSink)])
+    }
+}
+
+impl AuditImpl {
+
+    /// Constructs a new `AuditImpl` that writes events to the given sinks.
+    ///
+    /// # Arguments
+    ///
+    /// * `sinks` - Where events are written as they are recorded.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `AuditImpl`.
+    pub fn with_sinks(sinks: Vec<Box<dyn AuditSink + Send + Sync>>) -> Self {
+        Self {
+            events: VecDeque::new(),
+            next_id: 1,
+            sinks,
+            subscribers: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        }
+    }
+
+    /// Sets the ID of the first event recorded, so that IDs continue from a previous process.
+    ///
+    /// # Arguments
+    ///
+    /// * `next_id` - The ID of the first event, counting from 1.
+    ///
+    /// # Returns
+    ///
+    /// The `AuditImpl` starting at the given ID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let file_sink = FileSink::open(Path::new("audit.log"))?;
+    /// let next_id = file_sink.head().lines + 1;
+    /// let audit_service = AuditImpl::with_sinks(vec![Box::new(file_sink)]).with_next_id(next_id);
+    /// ```
+    pub fn with_next_id(mut self, next_id: u64) -> Self {
+        self.next_id = next_id;
+        self
+    }
+}
+
+impl Audit for AuditImpl {
+
+    /// Records an audit event.
+    ///
+    /// The event is given the next ID. A sink that fails to write the event is logged, and the
+    /// other sinks and subscribers still receive it. Once `MAX_RETAINED_EVENTS` are kept, the
+    /// oldest is dropped from memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to record.
+    #[instrument(skip_all, fields(store = "audit"))]
+    fn record(&mut self, mut event: AuditEvent) {
+        event.id = self.next_id;
+        self.next_id += 1;
+
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write(&event) {
+                error!("Failed to write audit event {}: {}", event, e);
+            }
+        }
+
+        // Sending only fails when nobody is subscribed.
+        let _ = self.subscribers.send(event.clone());
+
+        if self.events.len() == MAX_RETAINED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Retrieves the retained audit events, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the recorded events.
+    #[instrument(skip_all, fields(store = "audit"))]
+    fn events(&self) -> Vec<AuditEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    /// Retrieves a page of the recorded audit events that match a filter, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Selects the events.
+    /// * `before_id` - Only events recorded before the event with this ID.
+    /// * `limit` - The largest number of events to return.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the matching events.
+    #[instrument(skip_all, fields(store = "audit"))]
+    fn query(&self, filter: &AuditFilter, before_id: Option<u64>, limit: usize) -> Vec<AuditEvent> {
+        // The retained IDs are consecutive, so the events before an ID are a prefix.
+        let first_id = self.next_id - self.events.len() as u64;
+        let end = before_id.map_or(self.events.len(), |id| (id.saturating_sub(first_id) as usize).min(self.events.len()));
+
+        self.events
+            .range(..end)
+            .rev()
+            .filter(|event| filter.matches(event))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Subscribes to the events recorded from now on.
+    ///
+    /// # Returns
+    ///
+    /// A receiver of every event recorded after the call.
+    fn subscribe(&self) -> broadcast::Receiver<AuditEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// Flushes every sink. A sink that fails to flush is logged, and the others are still flushed.
+    #[instrument(skip_all, fields(store = "audit"))]
+    fn flush(&mut self) {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.flush() {
+                error!("Failed to flush audit events: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_record_event() {
+        let mut audit_service = AuditImpl::default();
+        audit_service.record(AuditEvent::new(AuditAction::DeleteAccount, "123456", AuditOutcome::Success));
+
+        let events = audit_service.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, AuditAction::DeleteAccount);
+        assert_eq!(events[0].user_uuid, "123456");
+        assert_eq!(events[0].outcome, AuditOutcome::Success);
+    }
+
+    /// `FailingSink` fails every write and flush.
+    struct FailingSink;
+
+    impl AuditSink for FailingSink {
+        fn write(&mut self, _event: &AuditEvent) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    /// `FlushCountingSink` counts how often it is flushed.
+    struct FlushCountingSink(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl AuditSink for FlushCountingSink {
+        fn write(&mut self, _event: &AuditEvent) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn audit_path() -> PathBuf {
+        std::env::temp_dir().join(format!("audit-{}.log", uuid::Uuid::new_v4()))
+    }
+
+    fn sign_in_event() -> AuditEvent {
+        AuditEvent::new(AuditAction::SignIn, "123456", AuditOutcome::Failure)
+            .with_reason("invalid_credentials")
+            .with_peer_ip(Some("192.0.2.1".parse().unwrap()))
+    }
+
+    #[test]
+    fn should_query_events_newest_first() {
+        let mut audit_service = AuditImpl::with_sinks(Vec::new());
+        let since = SystemTime::now();
+
+        for user_uuid in ["1", "2", "1", "1"] {
+            audit_service.record(AuditEvent::new(AuditAction::SignIn, user_uuid, AuditOutcome::Failure));
+        }
+        audit_service.record(AuditEvent::new(AuditAction::GrantRole, "2", AuditOutcome::Success).with_actor("1"));
+
+        let ids = |events: Vec<AuditEvent>| events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(audit_service.query(&AuditFilter::default(), None, 10)), vec![5, 4, 3, 2, 1]);
+
+        let filter = AuditFilter { user_uuid: Some("1".to_owned()), ..AuditFilter::default() };
+        assert_eq!(ids(audit_service.query(&filter, None, 2)), vec![5, 4]);
+        assert_eq!(ids(audit_service.query(&filter, Some(4), 2)), vec![3, 1]);
+        assert_eq!(ids(audit_service.query(&filter, Some(1), 2)), Vec::<u64>::new());
+
+        let filter = AuditFilter {
+            action: Some(AuditAction::SignIn),
+            outcome: Some(AuditOutcome::Failure),
+            since: Some(since),
+            ..AuditFilter::default()
+        };
+        assert_eq!(ids(audit_service.query(&filter, None, 10)), vec![4, 3, 2, 1]);
+
+        let filter = AuditFilter { until: Some(since), ..AuditFilter::default() };
+        assert!(audit_service.query(&filter, None, 10).is_empty());
+    }
+
+    #[test]
+    fn should_retain_newest_events() {
+        let mut audit_service = AuditImpl::with_sinks(Vec::new()).with_next_id(11);
+        for _ in 0..MAX_RETAINED_EVENTS + 2 {
+            audit_service.record(sign_in_event());
+        }
+
+        let events = audit_service.events();
+        assert_eq!(events.len(), MAX_RETAINED_EVENTS);
+        assert_eq!(events[0].id, 13);
+
+        let newest_id = MAX_RETAINED_EVENTS as u64 + 12;
+        let ids = |events: Vec<AuditEvent>| events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(audit_service.query(&AuditFilter::default(), None, 2)), vec![newest_id, newest_id - 1]);
+        assert_eq!(ids(audit_service.query(&AuditFilter::default(), Some(newest_id), 2)), vec![newest_id - 1, newest_id - 2]);
+        assert_eq!(ids(audit_service.query(&AuditFilter::default(), Some(14), 10)), vec![13]);
+        assert!(audit_service.query(&AuditFilter::default(), Some(13), 10).is_empty());
+        assert!(audit_service.query(&AuditFilter::default(), Some(5), 10).is_empty());
+    }
+
+    #[test]
+    fn should_send_events_to_subscribers() {
+        let mut audit_service = AuditImpl::with_sinks(Vec::new());
+        audit_service.record(sign_in_event());
+
+        let mut receiver = audit_service.subscribe();
+        audit_service.record(AuditEvent::new(AuditAction::SignOut, "123456", AuditOutcome::Success));
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.id, 2);
+        assert_eq!(event.action, AuditAction::SignOut);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn should_record_despite_failing_sink() {
+        let mut audit_service = AuditImpl::with_sinks(vec![Box::new(FailingSink)]);
+        audit_service.record(sign_in_event());
+
+        assert_eq!(audit_service.events().len(), 1);
+    }
+
+    #[test]
+    fn should_flush_every_sink() {
+        let flushes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let path = audit_path();
+
+        let mut audit_service = AuditImpl::with_sinks(vec![
+            Box::new(FailingSink),
+            Box::new(FileSink::open(&path).unwrap()),
+            Box::new(FlushCountingSink(flushes.clone())),
+        ]);
+        audit_service.record(sign_in_event());
+        audit_service.flush();
+
+        assert_eq!(flushes.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_chain_file_events() {
+        let path = audit_path();
+
+        let mut file_sink = FileSink::open(&path).unwrap();
+        file_sink.write(&sign_in_event()).unwrap();
+        file_sink.write(&AuditEvent::new(AuditAction::SignOut, "123456", AuditOutcome::Success).with_actor("123456")).unwrap();
+        drop(file_sink);
+
+        // Reopening continues the chain.
+        let mut file_sink = FileSink::open(&path).unwrap();
+        file_sink.write(&AuditEvent::new(AuditAction::PurgeAccount, "123456", AuditOutcome::Success)).unwrap();
+        drop(file_sink);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains(r#""action":"sign_in","outcome":"failure","actor":"","user_uuid":"123456","reason":"invalid_credentials","peer_ip":"192.0.2.1""#));
+        assert!(lines[0].contains(&format!(r#""prev_hash":"{}""#, GENESIS_HASH)));
+        let head = verify(&path).unwrap();
+        assert_eq!(head.lines, 3);
+        assert_eq!(FileSink::open(&path).unwrap().head(), &head);
+        assert_eq!(head.hash, lines[2][lines[2].len() - 66..lines[2].len() - 2]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_detect_tampering() {
+        let path = audit_path();
+
+        let mut file_sink = FileSink::open(&path).unwrap();
+        for _ in 0..3 {
+            file_sink.write(&sign_in_event()).unwrap();
+        }
+        drop(file_sink);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        let edited = contents.replacen("invalid_credentials", "mfa_required", 2).replacen("mfa_required", "invalid_credentials", 1);
+        fs::write(&path, edited).unwrap();
+        assert_eq!(verify(&path), Err(AuditError::Tampered(path.clone(), 2)));
+        assert!(matches!(FileSink::open(&path), Err(AuditError::Tampered(_, 2))));
+
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert_eq!(verify(&path), Err(AuditError::Tampered(path.clone(), 2)));
+
+        fs::write(&path, format!("{}\n{}", lines[0], lines[1])).unwrap();
+        assert_eq!(verify(&path), Err(AuditError::Tampered(path.clone(), 2)));
+
+        // Removing the last line leaves a valid chain, but a shorter head shows the truncation.
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert_eq!(verify(&path).unwrap().lines, 2);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,13 +1,45 @@
-use std::sync::Mutex;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use crate::{sessions::Sessions, users::Users};
+use crate::{
+    api_keys::{self, ApiKey, ApiKeys, ApiKeysImpl},
+    audit::{Audit, AuditAction, AuditEvent, AuditImpl, AuditOutcome},
+    groups::{self, Group, Groups, GroupsError, GroupsImpl},
+    logging::Redacted,
+    metrics::{self, Metrics},
+    mfa::{self, MfaError, SecretCipher},
+    ratelimit::{RateLimiter, RateLimits},
+    rbac::{self, Permission, RbacError, Role},
+    sessions::{Sessions, SessionsError, Ttls},
+    shutdown::Shutdown,
+    users::{Profile, User, Users, UsersError},
+    webauthn::{self, WebauthnCeremony, WebauthnConfig, WebauthnError},
+};
 
 use tonic::{Request, Response, Status};
+use tracing::{debug, info};
 
 use authentication::auth_server::Auth;
 use authentication::{
+    AddGroupMemberRequest, AddGroupMemberResponse, ApiKeyInfo, BeginPasskeyLoginRequest,
+    BeginPasskeyLoginResponse, BeginPasskeyRegistrationRequest, BeginPasskeyRegistrationResponse,
+    BeginTotpEnrollmentRequest, BeginTotpEnrollmentResponse, ChangeUsernameRequest,
+    ChangeUsernameResponse, ConfirmTotpEnrollmentRequest,
+    ConfirmTotpEnrollmentResponse, CreateApiKeyRequest, CreateApiKeyResponse, CreateGroupRequest,
+    CreateGroupResponse, CredentialType, DeleteAccountRequest, DeleteAccountResponse,
+    DeleteGroupRequest, DeleteGroupResponse, FinishPasskeyLoginRequest, FinishPasskeyLoginResponse,
+    FinishPasskeyRegistrationRequest, FinishPasskeyRegistrationResponse, GetGroupRequest,
+    GetGroupResponse, GetProfileRequest, GetProfileResponse, Profile as ApiProfile, GrantGroupRoleRequest, GrantGroupRoleResponse, GrantRoleRequest,
+    GrantRoleResponse, GroupInfo, ListApiKeysRequest, ListApiKeysResponse, ListGroupsRequest,
+    ListGroupsResponse, RegenerateRecoveryCodesRequest, RegenerateRecoveryCodesResponse,
+    RemoveGroupMemberRequest, RemoveGroupMemberResponse, RevokeApiKeyRequest, RevokeApiKeyResponse,
+    RevokeGroupRoleRequest, RevokeGroupRoleResponse, RevokeRoleRequest, RevokeRoleResponse,
     SignInRequest, SignInResponse, SignOutRequest, SignOutResponse, SignUpRequest, SignUpResponse,
-    StatusCode,
+    ResetPasswordRequest, ResetPasswordResponse, StatusCode, UpdateGroupRequest, UpdateGroupResponse,
+    UpdateProfileRequest, UpdateProfileResponse, ValidateSessionRequest,
+    ValidateSessionResponse, VerifyMfaRequest, VerifyMfaResponse,
 };
 
 pub mod authentication {
@@ -15,17 +47,56 @@ pub mod authentication {
 }
 
 // Re-exporting
+pub use authentication::admin_server::AdminServer;
 pub use authentication::auth_server::AuthServer;
 pub use tonic::transport::Server;
 
+/// How long a deleted account can be restored before it is purged, unless configured otherwise.
+pub const DEFAULT_DELETION_GRACE_PERIOD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
 /// `AuthService` struct represents the authentication service.
 pub struct AuthService {
 
     /// `users_service` represents the service for managing users.
-    users_service: Box<Mutex<dyn Users + Send + Sync>>,
+    pub(crate) users_service: Box<Mutex<dyn Users + Send + Sync>>,
 
     /// `sessions_service` represents the service for managing sessions.
-    sessions_service: Box<Mutex<dyn Sessions + Send + Sync>>,
+    pub(crate) sessions_service: Box<Mutex<dyn Sessions + Send + Sync>>,
+
+    /// `audit_service` represents the service recording security-relevant events.
+    pub(crate) audit_service: Box<Mutex<dyn Audit + Send + Sync>>,
+
+    /// `api_keys_service` represents the service for managing API keys.
+    pub(crate) api_keys_service: Box<Mutex<dyn ApiKeys + Send + Sync>>,
+
+    /// `groups_service` represents the service for managing groups and their members.
+    pub(crate) groups_service: Box<Mutex<dyn Groups + Send + Sync>>,
+
+    /// `secret_cipher` encrypts TOTP secrets before they are stored on a user.
+    secret_cipher: SecretCipher,
+
+    /// `webauthn_config` identifies the relying party passkeys are registered with.
+    webauthn_config: WebauthnConfig,
+
+    /// `deletion_grace_period` is how long a deleted account can be restored before it is purged.
+    deletion_grace_period: Mutex<Duration>,
+
+    /// `username_reservation_period` is how long a former username stays reserved for the user who
+    /// gave it up. Zero disables reservations.
+    username_reservation_period: Mutex<Duration>,
+
+    /// `sign_in_limiter` limits how often sign-in fails for each username and client address.
+    sign_in_limiter: Mutex<RateLimiter>,
+
+    /// `sign_up_limiter` limits how often each client address signs up.
+    sign_up_limiter: Mutex<RateLimiter>,
+
+    /// `metrics` counts requests and their outcomes for Prometheus.
+    pub(crate) metrics: Arc<Metrics>,
+
+    /// `shutdown` ends streaming responses, which would otherwise hold up shutdown, once the
+    /// service shuts down.
+    pub(crate) shutdown: Option<Shutdown>,
 }
 
 impl AuthService {
@@ -59,225 +130,3954 @@ impl AuthService {
         Self {
             users_service,
             sessions_service,
+            audit_service: Box::new(Mutex::new(AuditImpl::default())),
+            api_keys_service: Box::new(Mutex::new(ApiKeysImpl::default())),
+            groups_service: Box::new(Mutex::new(GroupsImpl::default())),
+            secret_cipher: SecretCipher::default(),
+            webauthn_config: WebauthnConfig::default(),
+            deletion_grace_period: Mutex::new(DEFAULT_DELETION_GRACE_PERIOD),
+            username_reservation_period: Mutex::new(Duration::ZERO),
+            sign_in_limiter: Mutex::new(RateLimiter::new(0)),
+            sign_up_limiter: Mutex::new(RateLimiter::new(0)),
+            metrics: Arc::new(Metrics::default()),
+            shutdown: None,
         }
     }
-}
-
-#[tonic::async_trait]
-impl Auth for AuthService {
 
-    /// Handles user sign-in requests.
+    /// Sets the service recording security-relevant events.
+    ///
+    /// Without this events are kept in memory and written to the log.
     ///
     /// # Arguments
     ///
-    /// * `request` - A gRPC request containing sign-in credentials.
+    /// * `audit_service` - A boxed trait object representing the service recording events.
     ///
     /// # Returns
     ///
-    /// A gRPC response containing the sign-in status and session information.
+    /// The `AuthService` using the given audit service.
     ///
-    /// # Errors
+    /// # Example
+    ///
+    /// ```
+    /// let file_sink = FileSink::open(Path::new("audit.log"))?;
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_audit_service(Box::new(Mutex::new(AuditImpl::with_sinks(vec![Box::new(LogSink), Box::new(file_sink)]))));
+    /// ```
+    pub fn with_audit_service(mut self, audit_service: Box<Mutex<dyn Audit + Send + Sync>>) -> Self {
+        self.audit_service = audit_service;
+        self
+    }
+
+    /// Sets the cipher used to encrypt TOTP secrets.
+    ///
+    /// Without this a random key is used, so secrets stored by a previous process cannot be read.
+    ///
+    /// # Arguments
     ///
-    /// This method returns an error if there are issues with authentication or session creation.
+    /// * `secret_cipher` - The cipher keyed with the service's encryption key.
+    ///
+    /// # Returns
+    ///
+    /// The `AuthService` using the given cipher.
     ///
     /// # Example
     ///
     /// ```
-    /// // Assuming `auth_service` is an instance of AuthService
-    /// let request = SignInRequest {
-    ///     username: "example_user".to_string(),
-    ///     password: "example_password".to_string(),
-    /// };
-    /// let response = auth_service.sign_in(Request::new(request)).await;
-    /// assert!(response.is_ok());
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_secret_cipher(SecretCipher::from_base64(&key).unwrap());
     /// ```
-    async fn sign_in(
-        &self,
-        request: Request<SignInRequest>,
-    ) -> Result<Response<SignInResponse>, Status> {
-        println!("Got a request: {:?}", request);
-
-        let req = request.into_inner();
-
-        let result = self.users_service.lock()
-                                                       .expect("lock should not be tampered")
-                                                       .get_user_uuid(req.username, req.password);
-
-        let user_uuid = match result {
-            Some(uuid) => uuid,
-            None => {
-                let reply = SignInResponse {
-                    status_code: StatusCode::Failure.into(),
-                    user_uuid: "".to_owned(),
-                    session_token: "".to_owned(),
-                };
+    pub fn with_secret_cipher(mut self, secret_cipher: SecretCipher) -> Self {
+        self.secret_cipher = secret_cipher;
+        self
+    }
 
-                return Ok(Response::new(reply));
-            }
-        };
+    /// Sets the relying party passkeys are registered with.
+    ///
+    /// Without this passkeys are scoped to `localhost`, which only suits local development.
+    ///
+    /// # Arguments
+    ///
+    /// * `webauthn_config` - The relying party ID, name and origin.
+    ///
+    /// # Returns
+    ///
+    /// The `AuthService` using the given relying party.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_webauthn_config(WebauthnConfig {
+    ///         rp_id: "example.com".to_string(),
+    ///         rp_name: "Example".to_string(),
+    ///         origin: "https://example.com".to_string(),
+    ///     });
+    /// ```
+    pub fn with_webauthn_config(mut self, webauthn_config: WebauthnConfig) -> Self {
+        self.webauthn_config = webauthn_config;
+        self
+    }
 
-        let session_token = self.sessions_service.lock()
-                                                         .expect("lock should not be tampered")
-                                                         .create_session(&user_uuid);
+    /// Sets how long a deleted account can be restored before it is purged.
+    ///
+    /// Without this `DEFAULT_DELETION_GRACE_PERIOD` is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `deletion_grace_period` - The time between an account's deletion and its purge.
+    ///
+    /// # Returns
+    ///
+    /// The `AuthService` using the given grace period.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_deletion_grace_period(Duration::from_secs(7 * 24 * 60 * 60));
+    /// ```
+    pub fn with_deletion_grace_period(mut self, deletion_grace_period: Duration) -> Self {
+        self.deletion_grace_period = Mutex::new(deletion_grace_period);
+        self
+    }
 
-        let reply = SignInResponse {
-            status_code: StatusCode::Success.into(),
-            user_uuid,
-            session_token
-        };
+    /// Sets how long a former username stays reserved after a user changes it.
+    ///
+    /// Without this old usernames are released straight away, so another user could take one and
+    /// pass themselves off as its former owner.
+    ///
+    /// # Arguments
+    ///
+    /// * `username_reservation_period` - How long old usernames are reserved. Zero disables
+    ///   reservations.
+    ///
+    /// # Returns
+    ///
+    /// The `AuthService` using the given reservation period.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_username_reservation_period(Duration::from_secs(90 * 24 * 60 * 60));
+    /// ```
+    pub fn with_username_reservation_period(mut self, username_reservation_period: Duration) -> Self {
+        self.username_reservation_period = Mutex::new(username_reservation_period);
+        self
+    }
 
-        Ok(Response::new(reply))
+    /// Sets how often `SignIn` and `SignUp` may be called.
+    ///
+    /// Without this neither RPC is limited, leaving passwords open to guessing and the user store
+    /// open to mass sign-ups.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate_limits` - The allowance per minute of each RPC.
+    ///
+    /// # Returns
+    ///
+    /// The `AuthService` using the given rate limits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let auth_service = AuthService::new(users_service, sessions_service)
+    ///     .with_rate_limits(RateLimits { sign_in_per_minute: 10, sign_up_per_minute: 5 });
+    /// ```
+    pub fn with_rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.sign_in_limiter = Mutex::new(RateLimiter::new(rate_limits.sign_in_per_minute));
+        self.sign_up_limiter = Mutex::new(RateLimiter::new(rate_limits.sign_up_per_minute));
+        self
     }
 
-    /// Handles user sign-up requests.
+    /// Sets the shutdown that ends streaming responses, such as `TailAuditEvents`.
+    ///
+    /// Without this streams only end when the client cancels them, so shutdown waits for its
+    /// timeout while any are open.
     ///
     /// # Arguments
     ///
-    /// * `request` - A gRPC request containing sign-up credentials.
+    /// * `shutdown` - A handle to the shutdown of the service.
     ///
     /// # Returns
     ///
-    /// A gRPC response containing the sign-up status.
+    /// The `AuthService` ending its streams on the given shutdown.
     ///
-    /// # Errors
+    /// # Example
+    ///
+    /// ```
+    /// let (shutdown_trigger, shutdown) = shutdown::channel();
+    /// let auth_service = AuthService::new(users_service, sessions_service).with_shutdown(shutdown);
+    /// ```
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Changes the lifetimes and rate limits of a running service.
+    ///
+    /// Tokens already issued keep their expiry and callers keep the requests they have already
+    /// used, while deleted accounts are purged according to the new grace period.
+    ///
+    /// # Arguments
     ///
-    /// This method returns an error if there are issues with user creation.
+    /// * `ttls` - How long each kind of token stays valid.
+    /// * `deletion_grace_period` - The time between an account's deletion and its purge.
+    /// * `username_reservation_period` - How long old usernames are reserved. Zero disables
+    ///   reservations.
+    /// * `rate_limits` - The allowance per minute of each rate limited RPC.
     ///
     /// # Example
     ///
     /// ```
     /// // Assuming `auth_service` is an instance of AuthService
-    /// let request = SignUpRequest {
-    ///     username: "new_user".to_string(),
-    ///     password: "new_password".to_string(),
-    /// };
-    /// let response = auth_service.sign_up(Request::new(request)).await;
-    /// assert!(response.is_ok());
+    /// auth_service.reconfigure(Ttls::default(), DEFAULT_DELETION_GRACE_PERIOD, Duration::ZERO, RateLimits::default());
     /// ```
-    async fn sign_up(&self, request: Request<SignUpRequest>) -> Result<Response<SignUpResponse>, Status> {
-        println!("Got a request: {:?}", request);
+    pub fn reconfigure(
+        &self,
+        ttls: Ttls,
+        deletion_grace_period: Duration,
+        username_reservation_period: Duration,
+        rate_limits: RateLimits,
+    ) {
+        self.sessions_service.lock().expect("lock should not be tampered").set_ttls(ttls);
+        *self.deletion_grace_period.lock().expect("lock should not be tampered") = deletion_grace_period;
+        *self.username_reservation_period.lock().expect("lock should not be tampered") = username_reservation_period;
+        self.sign_in_limiter.lock()
+                            .expect("lock should not be tampered")
+                            .set_per_minute(rate_limits.sign_in_per_minute);
+        self.sign_up_limiter.lock()
+                            .expect("lock should not be tampered")
+                            .set_per_minute(rate_limits.sign_up_per_minute);
+    }
 
-        let req = request.into_inner();
+    /// Returns whether the stores can serve requests.
+    ///
+    /// A request that panics while holding a store's lock poisons it, after which every request
+    /// using that store fails, so the service is only ready while no store is poisoned.
+    ///
+    /// # Returns
+    ///
+    /// `true` if every store is usable.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// if !auth_service.storage_ready() {
+    ///     println!("The auth service needs a restart");
+    /// }
+    /// ```
+    pub fn storage_ready(&self) -> bool {
+        !self.users_service.is_poisoned()
+            && !self.sessions_service.is_poisoned()
+            && !self.audit_service.is_poisoned()
+            && !self.api_keys_service.is_poisoned()
+            && !self.groups_service.is_poisoned()
+    }
 
-        let result = self.users_service.lock()
-                                                           .expect("lock should not be tampered")
-                                                           .create_user(req.username, req.password);
+    /// Makes sure every audit event recorded so far is stored durably. Called once the servers
+    /// have stopped, before the process exits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// auth_service.flush();
+    /// ```
+    pub fn flush(&self) {
+        self.audit_service.lock().expect("lock should not be tampered").flush();
+    }
 
-        match result {
-            Ok(_) => {
-                let reply = SignUpResponse {
-                    status_code: StatusCode::Success.into(),
-                };
+    /// Returns the metrics the service records, so the RPC layer can add to them.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
 
-                Ok(Response::new(reply))
-            }
-            Err(_) => {
-                let reply = SignUpResponse {
-                    status_code: StatusCode::Failure.into(),
-                };
+    /// Encodes the service's metrics in the Prometheus text format, counting the active sessions
+    /// and users first.
+    ///
+    /// # Returns
+    ///
+    /// The encoded metrics.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let body = auth_service.encode_metrics();
+    /// ```
+    pub fn encode_metrics(&self) -> String {
+        let active_sessions = self.sessions_service.lock().expect("lock should not be tampered").count_sessions();
+        let users = self.users_service.lock().expect("lock should not be tampered").count_users();
 
-                Ok(Response::new(reply))
-            }
-        }
+        self.metrics.encode(active_sessions, users)
     }
 
-    /// Handles user sign-out requests.
+    /// Returns when a deleted user is due to be purged.
     ///
     /// # Arguments
     ///
-    /// * `request` - A gRPC request containing the session token to be invalidated.
+    /// * `user` - The user.
     ///
     /// # Returns
     ///
-    /// A gRPC response containing the sign-out status.
+    /// The end of the user's grace period, or `None` if the user has not been deleted.
+    pub(crate) fn purge_time(&self, user: &User) -> Option<SystemTime> {
+        let deletion_grace_period = *self.deletion_grace_period.lock().expect("lock should not be tampered");
+
+        user.deleted_at.map(|deleted_at| deleted_at + deletion_grace_period)
+    }
+
+    /// Permanently removes every deleted user whose grace period has ended.
     ///
-    /// # Errors
+    /// Along with the account this removes its session, pending password resets, API keys and group
+    /// memberships, and records an audit event for each purged user.
     ///
-    /// This method returns an error if there are issues with session deletion.
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The number of users purged.
     ///
     /// # Example
     ///
     /// ```
     /// // Assuming `auth_service` is an instance of AuthService
-    /// let request = SignOutRequest {
-    ///     session_token: "example_session_token".to_string(),
-    /// };
-    /// let response = auth_service.sign_out(Request::new(request)).await;
-    /// assert!(response.is_ok());
+    /// let purged = auth_service.purge_deleted_users(SystemTime::now());
+    /// println!("Purged {} deleted accounts", purged);
     /// ```
-    async fn sign_out(&self, request: Request<SignOutRequest>) -> Result<Response<SignOutResponse>, Status> {
-        println!("Got a request: {:?}", request);
-
-        let req = request.into_inner();
+    pub fn purge_deleted_users(&self, now: SystemTime) -> usize {
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
 
-        self.sessions_service.lock().expect("lock should not be tampered").delete_session(&req.session_token);
+        let due: Vec<String> = users_service
+            .list_deleted_users()
+            .into_iter()
+            .filter(|user| self.purge_time(user).is_some_and(|purge_time| purge_time <= now))
+            .map(|user| user.user_uuid)
+            .collect();
 
-        let reply = SignOutResponse {
-            status_code: StatusCode::Success.into(),
-        };
+        for user_uuid in &due {
+            users_service.purge_user(user_uuid);
+        }
+        drop(users_service);
 
-        Ok(Response::new(reply))
-    }
-}
+        for user_uuid in &due {
+            self.sessions_service.lock().expect("lock should not be tampered").delete_session(user_uuid);
+            self.api_keys_service.lock().expect("lock should not be tampered").revoke_user_api_keys(user_uuid);
+            self.groups_service.lock().expect("lock should not be tampered").remove_member_from_all(user_uuid);
 
-#[cfg(test)]
-mod tests {
-    use crate::{users::UsersImpl, sessions::SessionsImpl};
+            self.audit(AuditEvent::new(AuditAction::PurgeAccount, user_uuid, AuditOutcome::Success));
+        }
 
-    use super::*;
+        due.len()
+    }
 
-    #[tokio::test]
-    async fn sign_in_should_fail_if_user_not_found() {
-        let users_service = Box::new(Mutex::new(UsersImpl::default()));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+    /// Records a security-relevant event in the audit trail.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to record.
+    pub(crate) fn audit(&self, event: AuditEvent) {
+        self.audit_service.lock()
+                          .expect("lock should not be tampered")
+                          .record(event);
+    }
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+    /// Resolves the holder of a session, checking that their account is still active.
+    ///
+    /// Every handler acting for the signed-in user goes through this, so that a session that
+    /// outlived a status change cannot be used.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - The session token supplied with the request.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the caller, otherwise an `Unauthenticated` status if the session is
+    /// invalid, or a `PermissionDenied` status if the caller's account is not active.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    pub(crate) fn authenticate(&self, session_token: &str) -> Result<User, Status> {
+        let user_uuid = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .get_user_uuid(session_token)?;
 
-        let request = tonic::Request::new(SignInRequest {
-            username: "123456".to_owned(),
-            password: "654321".to_owned(),
-        });
+        let user = self.users_service.lock()
+                                     .expect("lock should not be tampered")
+                                     .get_user(&user_uuid)
+                                     .ok_or(UsersError::UserNotFound)?;
 
-        let result = auth_service.sign_in(request).await.unwrap().into_inner();
+        user.status.ensure_active()?;
 
-        assert_eq!(result.status_code, StatusCode::Failure.into());
-        assert_eq!(result.user_uuid.is_empty(), true);
-        assert_eq!(result.session_token.is_empty(), true);
+        Ok(user)
     }
 
-    #[tokio::test]
-    async fn sign_in_should_fail_if_incorrect_password() {
-        let mut users_service = UsersImpl::default();
+    /// Checks that the holder of a session has a permission.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - The session token supplied with the request.
+    /// * `permission` - The permission the operation requires.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the caller's UUID, otherwise an `Unauthenticated` status if the
+    /// session is invalid, or a `PermissionDenied` status if the caller's account is not active or
+    /// none of the caller's roles grants the permission.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    pub(crate) fn authorize(&self, session_token: &str, permission: Permission) -> Result<String, Status> {
+        let user = self.authenticate(session_token)?;
 
-        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        rbac::require(&self.effective_roles(&user), permission)?;
 
-        let users_service = Box::new(Mutex::new(users_service));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+        Ok(user.user_uuid)
+    }
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+    /// Checks that the holder of a session has a permission and is at least as privileged as the
+    /// user the operation acts on.
+    ///
+    /// Without this, a user manager could take over or lock out an administrator, for instance by
+    /// forcing a password reset and redeeming the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - The session token supplied with the request.
+    /// * `permission` - The permission the operation requires.
+    /// * `user_uuid` - The UUID of the user the operation acts on.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the caller's UUID, otherwise the statuses `authorize` returns, a
+    /// `NotFound` status if the user does not exist, or a `PermissionDenied` status if the user's
+    /// roles grant a permission the caller lacks.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    pub(crate) fn authorize_over(&self, session_token: &str, permission: Permission, user_uuid: &str) -> Result<String, Status> {
+        let caller_uuid = self.authorize(session_token, permission)?;
 
-        let request = tonic::Request::new(SignInRequest {
-            username: "123456".to_owned(),
-            password: "wrong password".to_owned(),
-        });
+        let (caller, user) = {
+            let users_service = self.users_service.lock().expect("lock should not be tampered");
 
-        let result = auth_service.sign_in(request).await.unwrap().into_inner();
+            let caller = users_service.get_user(&caller_uuid).ok_or(UsersError::UserNotFound)?;
+            let user = users_service.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
 
-        assert_eq!(result.status_code, StatusCode::Failure.into());
-        assert_eq!(result.user_uuid.is_empty(), true);
-        assert_eq!(result.session_token.is_empty(), true);
-    }
+            (caller, user)
+        };
 
-    #[tokio::test]
-    async fn sign_in_should_succeed() {
-        let mut users_service = UsersImpl::default();
+        rbac::require_all(&self.effective_roles(&caller), &self.effective_roles(&user))?;
 
-        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        Ok(caller_uuid)
+    }
 
-        let users_service = Box::new(Mutex::new(users_service));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+    /// Resolves the roles a user holds directly or through their groups.
+    ///
+    /// Callers that also hold the users lock must take it before this takes the groups lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user whose roles to resolve.
+    ///
+    /// # Returns
+    ///
+    /// The user's effective roles, sorted and without duplicates.
+    pub(crate) fn effective_roles(&self, user: &User) -> Vec<Role> {
+        let user_groups = self.groups_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .user_groups(&user.user_uuid);
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+        groups::effective_roles(user, &user_groups)
+    }
+
+    /// Checks that a change to users or groups keeps at least one administrator.
+    ///
+    /// The change is tried on copies of every user and group, so nothing is modified. A service that
+    /// has no administrator to begin with is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `users_service` - The locked users store.
+    /// * `change` - Applies the change to the copies.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if an administrator remains, otherwise `RbacError::LastAdmin`.
+    pub(crate) fn ensure_admin_remains(
+        &self,
+        users_service: &dyn Users,
+        change: impl FnOnce(&mut Vec<User>, &mut Vec<Group>),
+    ) -> Result<(), RbacError> {
+        let mut users = users_service.list_users();
+        let mut groups = self.groups_service.lock()
+                                            .expect("lock should not be tampered")
+                                            .list_groups();
+
+        let before = groups::count_admins(&users, &groups);
+        change(&mut users, &mut groups);
+
+        if before > 0 && groups::count_admins(&users, &groups) == 0 {
+            return Err(RbacError::LastAdmin);
+        }
+
+        Ok(())
+    }
+
+    /// Checks a user's credentials and starts a session, or an MFA challenge if the user has TOTP
+    /// enabled. See `Auth::sign_in`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The gRPC request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send, or the status the handler fails with.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn attempt_sign_in(&self, request: Request<SignInRequest>) -> Result<Response<SignInResponse>, Status> {
+        // Requests without a known peer address share one allowance per username.
+        let client_address = request.remote_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+        let req = request.into_inner();
+
+        // Limited per username and address, so that guessing from one address is slowed without
+        // letting anyone lock the user out from everywhere else. Only failures use up the allowance,
+        // so a user who keeps signing in successfully is never limited.
+        let limit_key = format!("{}|{}", client_address, req.username);
+
+        self.sign_in_limiter.lock()
+                            .expect("lock should not be tampered")
+                            .ensure_allowed(&limit_key, Instant::now())
+                            .inspect_err(|_| self.metrics.record_rate_limit_rejection("sign_in"))?;
+
+        let users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let Some(user_uuid) = users_service.get_user_uuid(req.username, req.password) else {
+            drop(users_service);
+            self.sign_in_limiter.lock()
+                                .expect("lock should not be tampered")
+                                .charge(&limit_key, Instant::now());
+
+            return Err(UsersError::InvalidCredentials.into());
+        };
+
+        let user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        drop(users_service);
+
+        // Only reported once the password has been verified, so the account status is not leaked.
+        check_sign_in_allowed(&user, true)?;
+
+        let mut sessions_service = self.sessions_service.lock().expect("lock should not be tampered");
+
+        // Roles are only revealed once the user is fully signed in.
+        if user.totp_secret.is_some() {
+            // Each challenge allows one guess at the code, so no challenges are issued while the
+            // user's codes are being guessed.
+            self.sign_in_limiter.lock()
+                                .expect("lock should not be tampered")
+                                .ensure_allowed(&mfa_limit_key(&user_uuid), Instant::now())
+                                .inspect_err(|_| self.metrics.record_rate_limit_rejection("sign_in"))?;
+
+            let reply = SignInResponse {
+                status_code: StatusCode::MfaRequired.into(),
+                user_uuid: "".to_owned(),
+                session_token: "".to_owned(),
+                mfa_challenge_token: sessions_service.create_mfa_challenge(&user_uuid),
+                roles: Vec::new(),
+            };
+
+            return Ok(Response::new(reply));
+        }
+
+        let session_token = sessions_service.create_session(&user_uuid);
+
+        let reply = SignInResponse {
+            status_code: StatusCode::Success.into(),
+            user_uuid,
+            session_token,
+            mfa_challenge_token: "".to_owned(),
+            roles: role_names(&self.effective_roles(&user)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Creates a user from the requested credentials. See `Auth::sign_up`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The gRPC request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send, or the status the handler fails with.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn attempt_sign_up(&self, request: Request<SignUpRequest>) -> Result<Response<SignUpResponse>, Status> {
+        // Requests without a known peer address share one allowance.
+        let client_address = request.remote_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+
+        self.sign_up_limiter.lock()
+                            .expect("lock should not be tampered")
+                            .check(&client_address, Instant::now())
+                            .inspect_err(|_| self.metrics.record_rate_limit_rejection("sign_up"))?;
+
+        let req = request.into_inner();
+
+        self.users_service.lock()
+                          .expect("lock should not be tampered")
+                          .create_user(req.username, req.password)?;
+
+        let reply = SignUpResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Ends the session a token belongs to, if any. See `Auth::sign_out`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The gRPC request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send, or the status the handler fails with.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn attempt_sign_out(&self, request: Request<SignOutRequest>) -> Result<Response<SignOutResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut sessions_service = self.sessions_service.lock().expect("lock should not be tampered");
+
+        if let Ok(user_uuid) = sessions_service.get_user_uuid(&req.session_token) {
+            sessions_service.delete_session(&user_uuid);
+        }
+
+        let reply = SignOutResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Checks the second factor of a user whose password was accepted and starts a session. See
+    /// `Auth::verify_mfa`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - The UUID of the user the challenge was issued to.
+    /// * `req` - The request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send, or the status the handler fails with.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn attempt_verify_mfa(&self, user_uuid: &str, req: VerifyMfaRequest) -> Result<Response<VerifyMfaResponse>, Status> {
+        // Challenges issued before the allowance ran out are refused too.
+        self.sign_in_limiter.lock()
+                            .expect("lock should not be tampered")
+                            .ensure_allowed(&mfa_limit_key(user_uuid), Instant::now())
+                            .inspect_err(|_| self.metrics.record_rate_limit_rejection("verify_mfa"))?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        // The account may have changed since the password step.
+        check_sign_in_allowed(&user, true)?;
+
+        let stored = user.totp_secret.as_deref().ok_or(MfaError::NotEnrolled)?;
+
+        if req.recovery_code.is_empty() {
+            let secret = self.secret_cipher.decrypt(stored)?;
+            let step = mfa::verify_totp(&secret, &req.code, SystemTime::now(), user.totp_last_used_step)?;
+
+            user.totp_last_used_step = Some(step);
+        } else {
+            mfa::consume_recovery_code(&mut user.recovery_code_hashes, &req.recovery_code)?;
+        }
+
+        let remaining_recovery_codes = user.recovery_code_hashes.len() as u32;
+        let roles = role_names(&self.effective_roles(&user));
+
+        users_service.update_user(user)?;
+        drop(users_service);
+
+        let session_token = self.sessions_service.lock()
+                                                 .expect("lock should not be tampered")
+                                                 .create_session(user_uuid);
+
+        let reply = VerifyMfaResponse {
+            status_code: StatusCode::Success.into(),
+            user_uuid: user_uuid.to_owned(),
+            session_token,
+            remaining_recovery_codes,
+            roles,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Checks the passkey assertion of a user whose login challenge was issued and starts a
+    /// session. See `Auth::finish_passkey_login`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - The UUID of the user the challenge was issued to.
+    /// * `req` - The request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send, or the status the handler fails with.
+    #[allow(clippy::result_large_err)] // Returns the same `Status` the handlers do.
+    fn attempt_passkey_sign_in(&self, user_uuid: &str, req: FinishPasskeyLoginRequest) -> Result<Response<FinishPasskeyLoginResponse>, Status> {
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        check_sign_in_allowed(&user, false)?;
+
+        let credential_id = webauthn::encode(&req.credential_id);
+        let passkey = user.passkeys
+                          .iter_mut()
+                          .find(|passkey| passkey.credential_id == credential_id)
+                          .ok_or(WebauthnError::CredentialNotFound)?;
+
+        passkey.sign_count = webauthn::verify_assertion(
+            &self.webauthn_config,
+            passkey,
+            &req.client_data_json,
+            &req.authenticator_data,
+            &req.signature,
+        )?;
+
+        let roles = role_names(&self.effective_roles(&user));
+
+        users_service.update_user(user)?;
+        drop(users_service);
+
+        let session_token = self.sessions_service.lock()
+                                                 .expect("lock should not be tampered")
+                                                 .create_session(user_uuid);
+
+        let reply = FinishPasskeyLoginResponse {
+            status_code: StatusCode::Success.into(),
+            user_uuid: user_uuid.to_owned(),
+            session_token,
+            roles,
+        };
+
+        Ok(Response::new(reply))
+    }
+}
+
+/// Returns the sign-in limiter key that counts a user's failed MFA attempts.
+///
+/// # Arguments
+///
+/// * `user_uuid` - The UUID of the user.
+///
+/// # Returns
+///
+/// The key, which cannot collide with the `address|username` keys of failed passwords.
+fn mfa_limit_key(user_uuid: &str) -> String {
+    format!("mfa:{}", user_uuid)
+}
+
+/// Builds the audit event of a request a user makes for themselves, such as signing in, from its
+/// result. The user is only recorded as the actor once the request succeeded, and failures carry
+/// their reason.
+///
+/// # Arguments
+///
+/// * `action` - The action that was attempted.
+/// * `user_uuid` - The UUID of the user the action was attempted on, empty if unknown.
+/// * `result` - The result of the request.
+///
+/// # Returns
+///
+/// The `AuditEvent` to record.
+fn audit_event<T>(action: AuditAction, user_uuid: &str, result: &Result<T, Status>) -> AuditEvent {
+    match result {
+        Ok(_) => AuditEvent::new(action, user_uuid, AuditOutcome::Success).with_actor(user_uuid),
+        Err(_) => AuditEvent::new(action, user_uuid, AuditOutcome::Failure).with_reason(&metrics::outcome(result)),
+    }
+}
+
+/// Converts a stored group into its API representation.
+///
+/// # Arguments
+///
+/// * `group` - The stored group.
+///
+/// # Returns
+///
+/// The `GroupInfo` message for the group.
+fn group_info(group: Group) -> GroupInfo {
+    GroupInfo {
+        group_uuid: group.group_uuid,
+        name: group.name,
+        description: group.description,
+        roles: role_names(&group.roles),
+        member_uuids: group.member_uuids,
+    }
+}
+
+/// Lists role names for the API.
+///
+/// # Arguments
+///
+/// * `roles` - The roles to name.
+///
+/// # Returns
+///
+/// The name of each role.
+pub(crate) fn role_names(roles: &[Role]) -> Vec<String> {
+    roles.iter().map(|role| role.as_str().to_owned()).collect()
+}
+
+/// Converts a stored profile into its API representation, using empty strings for unset fields.
+///
+/// # Arguments
+///
+/// * `profile` - The stored profile.
+///
+/// # Returns
+///
+/// The `Profile` message for the profile.
+pub(crate) fn api_profile(profile: Profile) -> ApiProfile {
+    ApiProfile {
+        display_name: profile.display_name.unwrap_or_default(),
+        email: profile.email.unwrap_or_default(),
+        locale: profile.locale.unwrap_or_default(),
+        metadata: profile.metadata,
+    }
+}
+
+/// Converts a profile received from the API into the stored representation, treating empty
+/// strings as unset fields.
+///
+/// # Arguments
+///
+/// * `profile` - The profile from the request.
+///
+/// # Returns
+///
+/// The matching stored `Profile`.
+fn profile(profile: ApiProfile) -> Profile {
+    let non_empty = |value: String| (!value.is_empty()).then_some(value);
+
+    Profile {
+        display_name: non_empty(profile.display_name),
+        email: non_empty(profile.email),
+        locale: non_empty(profile.locale),
+        metadata: profile.metadata,
+    }
+}
+
+/// Checks that a user may be issued a session.
+///
+/// # Arguments
+///
+/// * `user` - The user signing in.
+/// * `with_password` - Whether the user is signing in with their password, which is blocked while a
+///   password reset is pending.
+///
+/// # Returns
+///
+/// An `Ok(())` result if the user may sign in, otherwise a `UsersError` describing the account's
+/// status or `UsersError::PasswordResetRequired`.
+fn check_sign_in_allowed(user: &User, with_password: bool) -> Result<(), UsersError> {
+    user.status.ensure_active()?;
+
+    if with_password && user.password_reset_required {
+        return Err(UsersError::PasswordResetRequired);
+    }
+
+    Ok(())
+}
+
+/// Re-confirms the password of an already identified user.
+///
+/// Sensitive operations call this so that a stolen session alone is not enough to perform them.
+///
+/// # Arguments
+///
+/// * `users_service` - The users store.
+/// * `user` - The user whose password is being confirmed.
+/// * `password` - The password supplied with the request.
+///
+/// # Returns
+///
+/// `true` if the password is the user's current password.
+fn confirm_password(users_service: &dyn Users, user: &User, password: String) -> bool {
+    users_service
+        .get_user_uuid(user.username.clone(), password)
+        .is_some_and(|uuid| uuid == user.user_uuid)
+}
+
+/// Issues a new set of recovery codes to a user, replacing any existing ones.
+///
+/// # Arguments
+///
+/// * `user` - The user receiving the codes. Only the hashes are stored on it.
+///
+/// # Returns
+///
+/// The plaintext recovery codes, to be shown to the user once.
+fn issue_recovery_codes(user: &mut User) -> Vec<String> {
+    let codes = mfa::generate_recovery_codes();
+    user.recovery_code_hashes = codes.iter().map(|code| mfa::hash_recovery_code(code)).collect();
+    codes
+}
+
+/// Converts an optional time to Unix seconds for the API, using 0 for `None`.
+///
+/// # Arguments
+///
+/// * `time` - The time to convert.
+///
+/// # Returns
+///
+/// The number of whole seconds since the Unix epoch, or 0.
+pub(crate) fn unix_seconds(time: Option<SystemTime>) -> i64 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Describes a stored API key for the API, leaving out its hash.
+///
+/// # Arguments
+///
+/// * `key` - The stored key record.
+///
+/// # Returns
+///
+/// The `ApiKeyInfo` message for the key.
+fn api_key_info(key: ApiKey) -> ApiKeyInfo {
+    ApiKeyInfo {
+        key_id: key.key_id,
+        name: key.name,
+        prefix: key.prefix,
+        scopes: key.scopes,
+        created_at: unix_seconds(Some(key.created_at)),
+        expires_at: unix_seconds(key.expires_at),
+    }
+}
+
+#[tonic::async_trait]
+impl Auth for AuthService {
+
+    /// Handles user sign-in requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing sign-in credentials.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the sign-in status and session information.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status with the `INVALID_CREDENTIALS` reason if the
+    /// username and password do not match a user, or a `ResourceExhausted` status with the
+    /// `RATE_LIMITED` reason if sign-in has failed too often for the username from the caller's
+    /// address.
+    ///
+    /// If the user has TOTP enabled no session is created; instead the response carries the
+    /// `MFA_REQUIRED` status code and a challenge token to be completed with `verify_mfa`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = SignInRequest {
+    ///     username: "example_user".to_string(),
+    ///     password: "example_password".to_string(),
+    /// };
+    /// let response = auth_service.sign_in(Request::new(request)).await;
+    /// assert!(response.is_ok());
+    /// ```
+    async fn sign_in(&self, request: Request<SignInRequest>) -> Result<Response<SignInResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let username = request.get_ref().username.clone();
+
+        let result = self.attempt_sign_in(request);
+
+        // A correct password without the second factor is not yet a successful sign-in.
+        let mfa_required = matches!(&result, Ok(response) if response.get_ref().status_code == i32::from(StatusCode::MfaRequired));
+        let outcome = match mfa_required {
+            true => "mfa_required".to_owned(),
+            false => metrics::outcome(&result),
+        };
+        self.metrics.record_sign_in(&outcome);
+
+        // Failed attempts are recorded against the account they targeted, if it exists.
+        let user_uuid = self.users_service.lock()
+                                          .expect("lock should not be tampered")
+                                          .get_user_by_username(&username)
+                                          .map(|user| user.user_uuid)
+                                          .unwrap_or_default();
+
+        let event = match mfa_required {
+            true => AuditEvent::new(AuditAction::SignIn, &user_uuid, AuditOutcome::MfaRequired),
+            false => audit_event(AuditAction::SignIn, &user_uuid, &result),
+        };
+        self.audit(event.with_peer_ip(peer_ip));
+
+        result
+    }
+
+    /// Handles user sign-up requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing sign-up credentials.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the sign-up status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `AlreadyExists` status with the `USERNAME_TAKEN` reason if the username
+    /// is taken, a `ResourceExhausted` status with the `RATE_LIMITED` reason if the client address has
+    /// signed up too often, or an `Internal` status if the password could not be hashed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = SignUpRequest {
+    ///     username: "new_user".to_string(),
+    ///     password: "new_password".to_string(),
+    /// };
+    /// let response = auth_service.sign_up(Request::new(request)).await;
+    /// assert!(response.is_ok());
+    /// ```
+    async fn sign_up(&self, request: Request<SignUpRequest>) -> Result<Response<SignUpResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let username = request.get_ref().username.clone();
+
+        let result = self.attempt_sign_up(request);
+        self.metrics.record_sign_up(&metrics::outcome(&result));
+
+        // A failed sign-up creates no account, so there is no user to record it against.
+        let user_uuid = match &result {
+            Ok(_) => self.users_service.lock()
+                                       .expect("lock should not be tampered")
+                                       .get_user_by_username(&username)
+                                       .map(|user| user.user_uuid)
+                                       .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        self.audit(audit_event(AuditAction::SignUp, &user_uuid, &result).with_peer_ip(peer_ip));
+
+        result
+    }
+
+    /// Handles user sign-out requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token to be invalidated.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the sign-out status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if there are issues with session deletion.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = SignOutRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.sign_out(Request::new(request)).await;
+    /// assert!(response.is_ok());
+    /// ```
+    async fn sign_out(&self, request: Request<SignOutRequest>) -> Result<Response<SignOutResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+
+        // Signing out with a token that has no session changes nothing, so it is not audited.
+        let user_uuid = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .get_user_uuid(&request.get_ref().session_token)
+                                             .ok();
+
+        let result = self.attempt_sign_out(request);
+        self.metrics.record_sign_out(&metrics::outcome(&result));
+
+        if let Some(user_uuid) = user_uuid {
+            self.audit(audit_event(AuditAction::SignOut, &user_uuid, &result).with_peer_ip(peer_ip));
+        }
+
+        result
+    }
+
+    /// Handles account deletion requests.
+    ///
+    /// The caller must hold a valid session and re-confirm their password. On success the user is
+    /// deleted, their session is revoked and an audit event is recorded. The account can be
+    /// restored by an administrator until `purge_deleted_users` removes it with its API keys and
+    /// group memberships at the end of the grace period.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token and the user's password.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the deletion status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid or the password is
+    /// incorrect.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = DeleteAccountRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     password: "example_password".to_string(),
+    /// };
+    /// let response = auth_service.delete_account(Request::new(request)).await;
+    /// assert!(response.is_ok());
+    /// ```
+    async fn delete_account(
+        &self,
+        request: Request<DeleteAccountRequest>,
+    ) -> Result<Response<DeleteAccountResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if !confirm_password(&*users_service, &user, req.password) {
+            drop(users_service);
+            self.audit(
+                AuditEvent::new(AuditAction::DeleteAccount, &user_uuid, AuditOutcome::Failure)
+                    .with_actor(&user_uuid)
+                    .with_reason("invalid_credentials")
+                    .with_peer_ip(peer_ip),
+            );
+
+            return Err(UsersError::InvalidCredentials.into());
+        }
+
+        users_service.delete_user(user_uuid.clone());
+        drop(users_service);
+
+        self.sessions_service.lock().expect("lock should not be tampered").delete_session(&user_uuid);
+
+        self.audit(
+            AuditEvent::new(AuditAction::DeleteAccount, &user_uuid, AuditOutcome::Success)
+                .with_actor(&user_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = DeleteAccountResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to start TOTP enrollment for the signed-in user.
+    ///
+    /// A new secret is generated and stored as pending until it is confirmed with
+    /// `confirm_totp_enrollment`. Starting again before confirming replaces the pending secret.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing an `otpauth://` URI and the base32 secret for manual entry.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `FailedPrecondition` status if TOTP is already enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = BeginTotpEnrollmentRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.begin_totp_enrollment(Request::new(request)).await;
+    /// ```
+    async fn begin_totp_enrollment(
+        &self,
+        request: Request<BeginTotpEnrollmentRequest>,
+    ) -> Result<Response<BeginTotpEnrollmentResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if user.totp_secret.is_some() {
+            return Err(MfaError::AlreadyEnabled.into());
+        }
+
+        let secret = mfa::generate_totp_secret();
+        let otpauth_uri = mfa::otpauth_uri(&user.username, &secret);
+
+        user.pending_totp_secret = Some(self.secret_cipher.encrypt(&secret)?);
+        users_service.update_user(user)?;
+
+        let reply = BeginTotpEnrollmentResponse {
+            status_code: StatusCode::Success.into(),
+            otpauth_uri,
+            secret: mfa::encode_totp_secret(&secret),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to finish TOTP enrollment for the signed-in user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token and a code from the authenticator app.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the enrollment status and a set of single-use recovery codes. From
+    /// now on sign-in requires a TOTP code or one of the recovery codes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid or the code is wrong,
+    /// or a `FailedPrecondition` status if enrollment was not started.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ConfirmTotpEnrollmentRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     code: "123456".to_string(),
+    /// };
+    /// let response = auth_service.confirm_totp_enrollment(Request::new(request)).await;
+    /// ```
+    async fn confirm_totp_enrollment(
+        &self,
+        request: Request<ConfirmTotpEnrollmentRequest>,
+    ) -> Result<Response<ConfirmTotpEnrollmentResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        let pending = user.pending_totp_secret.take().ok_or(MfaError::NotEnrolled)?;
+        let secret = self.secret_cipher.decrypt(&pending)?;
+        let step = mfa::verify_totp(&secret, &req.code, SystemTime::now(), None)?;
+
+        user.totp_secret = Some(pending);
+        user.totp_last_used_step = Some(step);
+        let recovery_codes = issue_recovery_codes(&mut user);
+        users_service.update_user(user)?;
+
+        let reply = ConfirmTotpEnrollmentResponse {
+            status_code: StatusCode::Success.into(),
+            recovery_codes,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles the second step of sign-in for users with TOTP enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the challenge token from `sign_in` and either a TOTP
+    ///   code or a recovery code. A recovery code is used up once accepted.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the user's UUID, a new session token and the number of recovery
+    /// codes left.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the challenge is unknown, expired or already
+    /// used, or if the code is wrong. A challenge can only be attempted once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = VerifyMfaRequest {
+    ///     mfa_challenge_token: "example_challenge_token".to_string(),
+    ///     code: "123456".to_string(),
+    /// };
+    /// let response = auth_service.verify_mfa(Request::new(request)).await;
+    /// ```
+    async fn verify_mfa(
+        &self,
+        request: Request<VerifyMfaRequest>,
+    ) -> Result<Response<VerifyMfaResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .take_mfa_challenge(&req.mfa_challenge_token)?;
+
+        let result = self.attempt_verify_mfa(&user_uuid, req);
+        self.audit(audit_event(AuditAction::VerifyMfa, &user_uuid, &result).with_peer_ip(peer_ip));
+
+        // Limited per user rather than per address, since the challenge can be answered from anywhere.
+        if result.is_err() {
+            self.sign_in_limiter.lock()
+                                .expect("lock should not be tampered")
+                                .charge(&mfa_limit_key(&user_uuid), Instant::now());
+        }
+
+        result
+    }
+
+    /// Handles requests to replace the signed-in user's recovery codes.
+    ///
+    /// All previously issued recovery codes stop working.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token and the user's password.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the new recovery codes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid or the password is
+    /// incorrect, or a `FailedPrecondition` status if TOTP is not enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RegenerateRecoveryCodesRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     password: "example_password".to_string(),
+    /// };
+    /// let response = auth_service.regenerate_recovery_codes(Request::new(request)).await;
+    /// ```
+    async fn regenerate_recovery_codes(
+        &self,
+        request: Request<RegenerateRecoveryCodesRequest>,
+    ) -> Result<Response<RegenerateRecoveryCodesResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if !confirm_password(&*users_service, &user, req.password) {
+            return Err(UsersError::InvalidCredentials.into());
+        }
+
+        if user.totp_secret.is_none() {
+            return Err(MfaError::NotEnrolled.into());
+        }
+
+        let recovery_codes = issue_recovery_codes(&mut user);
+        users_service.update_user(user)?;
+
+        let reply = RegenerateRecoveryCodesResponse {
+            status_code: StatusCode::Success.into(),
+            recovery_codes,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to start registering a passkey for the signed-in user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the options to pass to `navigator.credentials.create`: a fresh
+    /// challenge, the relying party, the user handle and the credentials already registered.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = BeginPasskeyRegistrationRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.begin_passkey_registration(Request::new(request)).await;
+    /// ```
+    async fn begin_passkey_registration(
+        &self,
+        request: Request<BeginPasskeyRegistrationRequest>,
+    ) -> Result<Response<BeginPasskeyRegistrationResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user = self.authenticate(&req.session_token)?;
+
+        let challenge = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .create_webauthn_challenge(&user.user_uuid, WebauthnCeremony::Registration);
+
+        let reply = BeginPasskeyRegistrationResponse {
+            status_code: StatusCode::Success.into(),
+            challenge,
+            rp_id: self.webauthn_config.rp_id.clone(),
+            rp_name: self.webauthn_config.rp_name.clone(),
+            user_id: user.user_uuid.into_bytes(),
+            username: user.username,
+            exclude_credential_ids: user.passkeys.iter().map(|passkey| webauthn::decode(&passkey.credential_id)).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to finish registering a passkey for the signed-in user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token, the new credential's ID, the
+    ///   `clientDataJSON` and attestation object returned by the browser, and a label for the passkey.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the registration status. The passkey can be used with
+    /// `begin_passkey_login` from now on.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid or the challenge is
+    /// unknown, expired or was issued to another user, an `InvalidArgument` status if the response
+    /// fails verification, or an `AlreadyExists` status if the passkey is already registered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = FinishPasskeyRegistrationRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     credential_id: credential.raw_id,
+    ///     client_data_json: credential.response.client_data_json,
+    ///     attestation_object: credential.response.attestation_object,
+    ///     name: "laptop".to_string(),
+    /// };
+    /// let response = auth_service.finish_passkey_registration(Request::new(request)).await;
+    /// ```
+    async fn finish_passkey_registration(
+        &self,
+        request: Request<FinishPasskeyRegistrationRequest>,
+    ) -> Result<Response<FinishPasskeyRegistrationResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let challenge = webauthn::client_data_challenge(&req.client_data_json)?;
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut sessions_service = self.sessions_service.lock().expect("lock should not be tampered");
+
+        // The challenge must have been issued to the same user, otherwise one user could attach a
+        // passkey to another user's account.
+        if sessions_service.take_webauthn_challenge(&challenge, WebauthnCeremony::Registration)? != user_uuid {
+            return Err(SessionsError::WebauthnChallengeNotFound.into());
+        }
+
+        drop(sessions_service);
+
+        let passkey = webauthn::verify_registration(
+            &self.webauthn_config,
+            &req.credential_id,
+            &req.client_data_json,
+            &req.attestation_object,
+            &req.name,
+        )?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if user.passkeys.iter().any(|existing| existing.credential_id == passkey.credential_id) {
+            return Err(WebauthnError::CredentialAlreadyRegistered.into());
+        }
+
+        user.passkeys.push(passkey);
+        users_service.update_user(user)?;
+
+        let reply = FinishPasskeyRegistrationResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to start signing in with a passkey.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the username.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the options to pass to `navigator.credentials.get`: a fresh
+    /// challenge, the relying party ID and the user's registered credentials.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the user does not exist or has no passkeys.
+    /// Both cases return the same error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = BeginPasskeyLoginRequest {
+    ///     username: "example_username".to_string(),
+    /// };
+    /// let response = auth_service.begin_passkey_login(Request::new(request)).await;
+    /// ```
+    async fn begin_passkey_login(
+        &self,
+        request: Request<BeginPasskeyLoginRequest>,
+    ) -> Result<Response<BeginPasskeyLoginResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user = self.users_service.lock()
+                                     .expect("lock should not be tampered")
+                                     .get_user_by_username(&req.username)
+                                     .filter(|user| !user.passkeys.is_empty())
+                                     .ok_or(WebauthnError::CredentialNotFound)?;
+
+        let challenge = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .create_webauthn_challenge(&user.user_uuid, WebauthnCeremony::Authentication);
+
+        let reply = BeginPasskeyLoginResponse {
+            status_code: StatusCode::Success.into(),
+            challenge,
+            rp_id: self.webauthn_config.rp_id.clone(),
+            allow_credential_ids: user.passkeys.iter().map(|passkey| webauthn::decode(&passkey.credential_id)).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to finish signing in with a passkey.
+    ///
+    /// A passkey proves possession of a device-bound key, so a successful assertion creates a
+    /// session directly, even for users with TOTP enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the credential ID and the `clientDataJSON`,
+    ///   authenticator data and signature returned by the browser.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the user's UUID and a new session token.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the challenge is unknown, expired or already
+    /// used, if the credential is not registered to the user, or if the signature or signature counter
+    /// is invalid, and an `InvalidArgument` status if the response is malformed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = FinishPasskeyLoginRequest {
+    ///     credential_id: credential.raw_id,
+    ///     client_data_json: credential.response.client_data_json,
+    ///     authenticator_data: credential.response.authenticator_data,
+    ///     signature: credential.response.signature,
+    /// };
+    /// let response = auth_service.finish_passkey_login(Request::new(request)).await;
+    /// ```
+    async fn finish_passkey_login(
+        &self,
+        request: Request<FinishPasskeyLoginRequest>,
+    ) -> Result<Response<FinishPasskeyLoginResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let challenge = webauthn::client_data_challenge(&req.client_data_json)?;
+
+        let user_uuid = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .take_webauthn_challenge(&challenge, WebauthnCeremony::Authentication)?;
+
+        let result = self.attempt_passkey_sign_in(&user_uuid, req);
+        self.audit(audit_event(AuditAction::PasskeySignIn, &user_uuid, &result).with_peer_ip(peer_ip));
+
+        result
+    }
+
+    /// Handles requests to create an API key for the signed-in user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token, a label for the key, the scopes it
+    ///   is limited to and an optional lifetime in seconds.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the full API key, which is not shown again, and its details.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or an
+    /// `InvalidArgument` status if a scope is not a known permission.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = CreateApiKeyRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     name: "nightly export".to_string(),
+    ///     scopes: vec!["users:read".to_string()],
+    ///     expires_in_seconds: 86400,
+    /// };
+    /// let response = auth_service.create_api_key(Request::new(request)).await;
+    /// ```
+    async fn create_api_key(
+        &self,
+        request: Request<CreateApiKeyRequest>,
+    ) -> Result<Response<CreateApiKeyResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        // A mistyped scope would silently leave the key without that permission.
+        for scope in &req.scopes {
+            scope.parse::<Permission>()?;
+        }
+
+        let expires_at = (req.expires_in_seconds > 0)
+            .then(|| SystemTime::now() + Duration::from_secs(req.expires_in_seconds));
+
+        let (api_key, record) = self.api_keys_service.lock()
+                                                     .expect("lock should not be tampered")
+                                                     .create_api_key(&user_uuid, &req.name, req.scopes, expires_at);
+
+        self.audit(
+            AuditEvent::new(AuditAction::CreateApiKey, &user_uuid, AuditOutcome::Success)
+                .with_actor(&user_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = CreateApiKeyResponse {
+            status_code: StatusCode::Success.into(),
+            api_key,
+            info: Some(api_key_info(record)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to list the signed-in user's API keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the details of each key, oldest first. The keys themselves are not
+    /// returned.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ListApiKeysRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.list_api_keys(Request::new(request)).await;
+    /// ```
+    async fn list_api_keys(
+        &self,
+        request: Request<ListApiKeysRequest>,
+    ) -> Result<Response<ListApiKeysResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let api_keys = self.api_keys_service.lock()
+                                            .expect("lock should not be tampered")
+                                            .list_api_keys(&user_uuid);
+
+        let reply = ListApiKeysResponse {
+            status_code: StatusCode::Success.into(),
+            api_keys: api_keys.into_iter().map(api_key_info).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to revoke one of the signed-in user's API keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token and the ID of the key to revoke.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the revocation status. The key stops working immediately.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a `NotFound`
+    /// status if the user has no key with that ID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RevokeApiKeyRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     key_id: "example_key_id".to_string(),
+    /// };
+    /// let response = auth_service.revoke_api_key(Request::new(request)).await;
+    /// ```
+    async fn revoke_api_key(
+        &self,
+        request: Request<RevokeApiKeyRequest>,
+    ) -> Result<Response<RevokeApiKeyResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        self.api_keys_service.lock()
+                             .expect("lock should not be tampered")
+                             .revoke_api_key(&user_uuid, &req.key_id)?;
+
+        self.audit(
+            AuditEvent::new(AuditAction::RevokeApiKey, &user_uuid, AuditOutcome::Success)
+                .with_actor(&user_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RevokeApiKeyResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests from other services to check a caller's credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing either a session token or an API key.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the UUID of the user the credential belongs to, the kind of
+    /// credential and, for API keys, the scopes the key is limited to.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or if the API key is
+    /// unknown, revoked or expired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ValidateSessionRequest {
+    ///     token: "rak_abcd2345_...".to_string(),
+    /// };
+    /// let response = auth_service.validate_session(Request::new(request)).await;
+    /// ```
+    async fn validate_session(
+        &self,
+        request: Request<ValidateSessionRequest>,
+    ) -> Result<Response<ValidateSessionResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let (user_uuid, credential_type, scopes) = if api_keys::is_api_key(&req.token) {
+            let key = self.api_keys_service.lock()
+                                           .expect("lock should not be tampered")
+                                           .verify_api_key(&req.token, SystemTime::now())?;
+
+            (key.user_uuid, CredentialType::ApiKey, key.scopes)
+        } else {
+            let user_uuid = self.sessions_service.lock()
+                                                 .expect("lock should not be tampered")
+                                                 .get_user_uuid(&req.token)?;
+
+            (user_uuid, CredentialType::Session, Vec::new())
+        };
+
+        let user = self.users_service.lock()
+                                     .expect("lock should not be tampered")
+                                     .get_user(&user_uuid)
+                                     .ok_or(UsersError::UserNotFound)?;
+
+        // API keys outlive the sessions revoked when an account stops being active, so the status is
+        // checked on every validation.
+        user.status.ensure_active()?;
+
+        let user_groups = self.groups_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .user_groups(&user_uuid);
+        let roles = groups::effective_roles(&user, &user_groups);
+
+        // A key limited to scopes only carries the permissions it was scoped to. Its roles are left out
+        // too, since a caller checking roles would otherwise see more than the key may do.
+        let permissions = rbac::permissions(&roles)
+            .into_iter()
+            .map(|permission| permission.as_str().to_owned())
+            .filter(|permission| scopes.is_empty() || scopes.contains(permission))
+            .collect();
+
+        let reply = ValidateSessionResponse {
+            status_code: StatusCode::Success.into(),
+            user_uuid,
+            credential_type: credential_type.into(),
+            roles: if scopes.is_empty() { role_names(&roles) } else { Vec::new() },
+            scopes,
+            permissions,
+            groups: user_groups.into_iter().map(|group| group.name).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to grant a role to a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the user and
+    ///   the name of the role.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status. Granting a role the user already holds succeeds without
+    /// changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage roles, an `InvalidArgument` status if
+    /// the role is unknown, or a `NotFound` status if the user does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = GrantRoleRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    ///     role: "auditor".to_string(),
+    /// };
+    /// let response = auth_service.grant_role(Request::new(request)).await;
+    /// ```
+    async fn grant_role(&self, request: Request<GrantRoleRequest>) -> Result<Response<GrantRoleResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageRoles)?;
+        let role: Role = req.role.parse()?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if !user.roles.contains(&role) {
+            user.roles.push(role);
+            users_service.update_user(user)?;
+        }
+
+        drop(users_service);
+
+        info!("User {} granted role {} to {}", caller_uuid, role, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::GrantRole, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = GrantRoleResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to revoke a role from a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the user and
+    ///   the name of the role.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status. Revoking a role the user does not hold succeeds without
+    /// changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage roles, an `InvalidArgument` status if
+    /// the role is unknown, a `NotFound` status if the user does not exist, or a `FailedPrecondition`
+    /// status if it would remove the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RevokeRoleRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    ///     role: "auditor".to_string(),
+    /// };
+    /// let response = auth_service.revoke_role(Request::new(request)).await;
+    /// ```
+    async fn revoke_role(&self, request: Request<RevokeRoleRequest>) -> Result<Response<RevokeRoleResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageRoles)?;
+        let role: Role = req.role.parse()?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let mut user = users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if user.roles.contains(&role) {
+            self.ensure_admin_remains(&*users_service, |users, _| {
+                for user in users.iter_mut().filter(|user| user.user_uuid == req.user_uuid) {
+                    user.roles.retain(|held| *held != role);
+                }
+            })?;
+
+            user.roles.retain(|held| *held != role);
+            users_service.update_user(user)?;
+        }
+
+        drop(users_service);
+
+        info!("User {} revoked role {} from {}", caller_uuid, role, req.user_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::RevokeRole, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RevokeRoleResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to create a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the name and
+    ///   description of the group.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the new group, without roles or members.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage groups, or an `AlreadyExists` status if
+    /// another group has the name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = CreateGroupRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     name: "platform".to_string(),
+    ///     description: "Platform team".to_string(),
+    /// };
+    /// let response = auth_service.create_group(Request::new(request)).await;
+    /// ```
+    async fn create_group(&self, request: Request<CreateGroupRequest>) -> Result<Response<CreateGroupResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageGroups)?;
+
+        let group = self.groups_service.lock()
+                                       .expect("lock should not be tampered")
+                                       .create_group(&req.name, &req.description)?;
+
+        info!("User {} created group {}", caller_uuid, group.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::CreateGroup, &group.group_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = CreateGroupResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to look up a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the group.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the group with its roles and members.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot read users, or a `NotFound` status if the group
+    /// does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = GetGroupRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    /// };
+    /// let response = auth_service.get_group(Request::new(request)).await;
+    /// ```
+    async fn get_group(&self, request: Request<GetGroupRequest>) -> Result<Response<GetGroupResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadUsers)?;
+
+        let group = self.groups_service.lock()
+                                       .expect("lock should not be tampered")
+                                       .get_group(&req.group_uuid)
+                                       .ok_or(GroupsError::GroupNotFound)?;
+
+        let reply = GetGroupResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to list every group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the groups, sorted by name.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a
+    /// `PermissionDenied` status if the caller cannot read users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ListGroupsRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.list_groups(Request::new(request)).await;
+    /// ```
+    async fn list_groups(&self, request: Request<ListGroupsRequest>) -> Result<Response<ListGroupsResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        self.authorize(&req.session_token, Permission::ReadUsers)?;
+
+        let groups = self.groups_service.lock()
+                                        .expect("lock should not be tampered")
+                                        .list_groups();
+
+        let reply = ListGroupsResponse {
+            status_code: StatusCode::Success.into(),
+            groups: groups.into_iter().map(group_info).collect(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to rename a group or change its description.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the group and
+    ///   its new name and description.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated group.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage groups, a `NotFound` status if the group
+    /// does not exist, or an `AlreadyExists` status if another group has the name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = UpdateGroupRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    ///     name: "infrastructure".to_string(),
+    ///     description: "Infrastructure team".to_string(),
+    /// };
+    /// let response = auth_service.update_group(Request::new(request)).await;
+    /// ```
+    async fn update_group(&self, request: Request<UpdateGroupRequest>) -> Result<Response<UpdateGroupResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageGroups)?;
+
+        let mut groups_service = self.groups_service.lock().expect("lock should not be tampered");
+
+        let mut group = groups_service.get_group(&req.group_uuid).ok_or(GroupsError::GroupNotFound)?;
+
+        group.name = req.name;
+        group.description = req.description;
+        groups_service.update_group(group.clone())?;
+
+        drop(groups_service);
+
+        info!("User {} updated group {}", caller_uuid, group.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::UpdateGroup, &group.group_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = UpdateGroupResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to delete a group.
+    ///
+    /// Members lose the roles they held through the group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token and the UUID of the group.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage groups, a `NotFound` status if the group
+    /// does not exist, or a `FailedPrecondition` status if it would remove the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = DeleteGroupRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    /// };
+    /// let response = auth_service.delete_group(Request::new(request)).await;
+    /// ```
+    async fn delete_group(&self, request: Request<DeleteGroupRequest>) -> Result<Response<DeleteGroupResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageGroups)?;
+
+        let users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        self.ensure_admin_remains(&*users_service, |_, groups| {
+            groups.retain(|group| group.group_uuid != req.group_uuid);
+        })?;
+
+        self.groups_service.lock()
+                           .expect("lock should not be tampered")
+                           .delete_group(&req.group_uuid)?;
+
+        drop(users_service);
+
+        info!("User {} deleted group {}", caller_uuid, req.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::DeleteGroup, &req.group_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = DeleteGroupResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to add a user to a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the group and
+    ///   the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated group. Adding a user who is already a member succeeds
+    /// without changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage groups, or a `NotFound` status if the
+    /// group or the user does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = AddGroupMemberRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.add_group_member(Request::new(request)).await;
+    /// ```
+    async fn add_group_member(
+        &self,
+        request: Request<AddGroupMemberRequest>,
+    ) -> Result<Response<AddGroupMemberResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageGroups)?;
+
+        let users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        users_service.get_user(&req.user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        let mut groups_service = self.groups_service.lock().expect("lock should not be tampered");
+
+        let mut group = groups_service.get_group(&req.group_uuid).ok_or(GroupsError::GroupNotFound)?;
+
+        if !group.member_uuids.contains(&req.user_uuid) {
+            group.member_uuids.push(req.user_uuid.clone());
+            groups_service.update_group(group.clone())?;
+        }
+
+        drop(groups_service);
+        drop(users_service);
+
+        info!("User {} added {} to group {}", caller_uuid, req.user_uuid, req.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::AddGroupMember, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = AddGroupMemberResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to remove a user from a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the group and
+    ///   the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated group. Removing a user who is not a member succeeds
+    /// without changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage groups, a `NotFound` status if the group
+    /// does not exist, or a `FailedPrecondition` status if it would remove the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RemoveGroupMemberRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    ///     user_uuid: "example_user_uuid".to_string(),
+    /// };
+    /// let response = auth_service.remove_group_member(Request::new(request)).await;
+    /// ```
+    async fn remove_group_member(
+        &self,
+        request: Request<RemoveGroupMemberRequest>,
+    ) -> Result<Response<RemoveGroupMemberResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageGroups)?;
+
+        let users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        self.ensure_admin_remains(&*users_service, |_, groups| {
+            for group in groups.iter_mut().filter(|group| group.group_uuid == req.group_uuid) {
+                group.member_uuids.retain(|member| *member != req.user_uuid);
+            }
+        })?;
+
+        let mut groups_service = self.groups_service.lock().expect("lock should not be tampered");
+
+        let mut group = groups_service.get_group(&req.group_uuid).ok_or(GroupsError::GroupNotFound)?;
+
+        if group.member_uuids.contains(&req.user_uuid) {
+            group.member_uuids.retain(|member| *member != req.user_uuid);
+            groups_service.update_group(group.clone())?;
+        }
+
+        drop(groups_service);
+        drop(users_service);
+
+        info!("User {} removed {} from group {}", caller_uuid, req.user_uuid, req.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::RemoveGroupMember, &req.user_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RemoveGroupMemberResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to grant a role to a group, and so to each of its members.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the group and
+    ///   the name of the role.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated group. Granting a role the group already holds succeeds
+    /// without changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage roles, an `InvalidArgument` status if
+    /// the role is unknown, or a `NotFound` status if the group does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = GrantGroupRoleRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    ///     role: "auditor".to_string(),
+    /// };
+    /// let response = auth_service.grant_group_role(Request::new(request)).await;
+    /// ```
+    async fn grant_group_role(
+        &self,
+        request: Request<GrantGroupRoleRequest>,
+    ) -> Result<Response<GrantGroupRoleResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageRoles)?;
+        let role: Role = req.role.parse()?;
+
+        let mut groups_service = self.groups_service.lock().expect("lock should not be tampered");
+
+        let mut group = groups_service.get_group(&req.group_uuid).ok_or(GroupsError::GroupNotFound)?;
+
+        if !group.roles.contains(&role) {
+            group.roles.push(role);
+            groups_service.update_group(group.clone())?;
+        }
+
+        drop(groups_service);
+
+        info!("User {} granted role {} to group {}", caller_uuid, role, req.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::GrantGroupRole, &req.group_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = GrantGroupRoleResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to revoke a role from a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the caller's session token, the UUID of the group and
+    ///   the name of the role.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the updated group. Revoking a role the group does not hold succeeds
+    /// without changes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, a
+    /// `PermissionDenied` status if the caller cannot manage roles, an `InvalidArgument` status if
+    /// the role is unknown, a `NotFound` status if the group does not exist, or a
+    /// `FailedPrecondition` status if it would remove the last administrator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = RevokeGroupRoleRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     group_uuid: "example_group_uuid".to_string(),
+    ///     role: "auditor".to_string(),
+    /// };
+    /// let response = auth_service.revoke_group_role(Request::new(request)).await;
+    /// ```
+    async fn revoke_group_role(
+        &self,
+        request: Request<RevokeGroupRoleRequest>,
+    ) -> Result<Response<RevokeGroupRoleResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let caller_uuid = self.authorize(&req.session_token, Permission::ManageRoles)?;
+        let role: Role = req.role.parse()?;
+
+        let users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        self.ensure_admin_remains(&*users_service, |_, groups| {
+            for group in groups.iter_mut().filter(|group| group.group_uuid == req.group_uuid) {
+                group.roles.retain(|held| *held != role);
+            }
+        })?;
+
+        let mut groups_service = self.groups_service.lock().expect("lock should not be tampered");
+
+        let mut group = groups_service.get_group(&req.group_uuid).ok_or(GroupsError::GroupNotFound)?;
+
+        if group.roles.contains(&role) {
+            group.roles.retain(|held| *held != role);
+            groups_service.update_group(group.clone())?;
+        }
+
+        drop(groups_service);
+        drop(users_service);
+
+        info!("User {} revoked role {} from group {}", caller_uuid, role, req.group_uuid);
+        self.audit(
+            AuditEvent::new(AuditAction::RevokeGroupRole, &req.group_uuid, AuditOutcome::Success)
+                .with_actor(&caller_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = RevokeGroupRoleResponse {
+            status_code: StatusCode::Success.into(),
+            group: Some(group_info(group)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to choose a new password after an administrator forced a reset.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the reset token and the new password.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the status. The user can then sign in with the new password.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the reset token is unknown, already used
+    /// or expired, or a `NotFound` status if the user no longer exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ResetPasswordRequest {
+    ///     reset_token: "example_reset_token".to_string(),
+    ///     new_password: "example_password".to_string(),
+    /// };
+    /// let response = auth_service.reset_password(Request::new(request)).await;
+    /// ```
+    async fn reset_password(&self, request: Request<ResetPasswordRequest>) -> Result<Response<ResetPasswordResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.sessions_service.lock()
+                                             .expect("lock should not be tampered")
+                                             .take_password_reset(&req.reset_token)?;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        users_service.set_password(&user_uuid, req.new_password)?;
+
+        let mut user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+        user.password_reset_required = false;
+        users_service.update_user(user)?;
+
+        drop(users_service);
+
+        self.audit(
+            AuditEvent::new(AuditAction::ResetPassword, &user_uuid, AuditOutcome::Success)
+                .with_actor(&user_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = ResetPasswordResponse {
+            status_code: StatusCode::Success.into(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests for the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the user's UUID, username and profile.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, or a `NotFound`
+    /// status if the user no longer exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = GetProfileRequest {
+    ///     session_token: "example_session_token".to_string(),
+    /// };
+    /// let response = auth_service.get_profile(Request::new(request)).await;
+    /// ```
+    async fn get_profile(&self, request: Request<GetProfileRequest>) -> Result<Response<GetProfileResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user = self.authenticate(&req.session_token)?;
+
+        let reply = GetProfileResponse {
+            status_code: StatusCode::Success.into(),
+            user_uuid: user.user_uuid,
+            username: user.username,
+            profile: Some(api_profile(user.profile)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to replace the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token and the new profile. Fields left
+    ///   empty are cleared.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the stored profile.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid, an
+    /// `InvalidArgument` status if a field is malformed or too long, or a `NotFound` status if the
+    /// user no longer exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = UpdateProfileRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     profile: Some(Profile {
+    ///         display_name: "Jane Smith".to_string(),
+    ///         ..Profile::default()
+    ///     }),
+    /// };
+    /// let response = auth_service.update_profile(Request::new(request)).await;
+    /// ```
+    async fn update_profile(&self, request: Request<UpdateProfileRequest>) -> Result<Response<UpdateProfileResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let profile = profile(req.profile.unwrap_or_default());
+
+        self.users_service.lock()
+                          .expect("lock should not be tampered")
+                          .update_profile(&user_uuid, profile.clone())?;
+
+        let reply = UpdateProfileResponse {
+            status_code: StatusCode::Success.into(),
+            profile: Some(api_profile(profile)),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Handles requests to change the signed-in user's username.
+    ///
+    /// The caller must re-confirm their password. If the service reserves old usernames, the
+    /// previous username stays reserved for the user for the configured period.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A gRPC request containing the session token, the new username and the user's
+    ///   password.
+    ///
+    /// # Returns
+    ///
+    /// A gRPC response containing the new username.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `Unauthenticated` status if the session is invalid or the password is
+    /// incorrect, or an `AlreadyExists` status if the username is taken or reserved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `auth_service` is an instance of AuthService
+    /// let request = ChangeUsernameRequest {
+    ///     session_token: "example_session_token".to_string(),
+    ///     new_username: "example_username".to_string(),
+    ///     password: "example_password".to_string(),
+    /// };
+    /// let response = auth_service.change_username(Request::new(request)).await;
+    /// ```
+    async fn change_username(&self, request: Request<ChangeUsernameRequest>) -> Result<Response<ChangeUsernameResponse>, Status> {
+        debug!(request = ?Redacted(request.get_ref()), "Got a request");
+
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        let req = request.into_inner();
+
+        let user_uuid = self.authenticate(&req.session_token)?.user_uuid;
+
+        let mut users_service = self.users_service.lock().expect("lock should not be tampered");
+
+        let user = users_service.get_user(&user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if !confirm_password(&*users_service, &user, req.password) {
+            drop(users_service);
+            self.audit(
+                AuditEvent::new(AuditAction::ChangeUsername, &user_uuid, AuditOutcome::Failure)
+                    .with_actor(&user_uuid)
+                    .with_reason("invalid_credentials")
+                    .with_peer_ip(peer_ip),
+            );
+
+            return Err(UsersError::InvalidCredentials.into());
+        }
+
+        let username_reservation_period = *self.username_reservation_period.lock().expect("lock should not be tampered");
+        let reserve_until = (!username_reservation_period.is_zero())
+            .then(|| SystemTime::now() + username_reservation_period);
+
+        users_service.change_username(&user_uuid, req.new_username.clone(), reserve_until)?;
+        drop(users_service);
+
+        info!("User {} changed their username from {} to {}", user_uuid, user.username, req.new_username);
+        self.audit(
+            AuditEvent::new(AuditAction::ChangeUsername, &user_uuid, AuditOutcome::Success)
+                .with_actor(&user_uuid)
+                .with_peer_ip(peer_ip),
+        );
+
+        let reply = ChangeUsernameResponse {
+            status_code: StatusCode::Success.into(),
+            username: req.new_username,
+        };
+
+        Ok(Response::new(reply))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{collections::HashMap, net::SocketAddr};
+
+    use tonic::transport::server::TcpConnectInfo;
+
+    use crate::{
+        errors::error_reason,
+        sessions::SessionsImpl,
+        users::{AccountStatus, UsersImpl},
+        webauthn::software_authenticator::{Assertion, SoftwareAuthenticator},
+    };
+    use authentication::ErrorReason;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sign_in_should_fail_if_user_not_found() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_in(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_fail_if_incorrect_password() {
+        let mut users_service = UsersImpl::for_tests();
+
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "wrong password".to_owned(),
+        });
+
+        let status = auth_service.sign_in(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::bool_assert_comparison)]
+    async fn sign_in_should_succeed() {
+        let mut users_service = UsersImpl::for_tests();
+
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let result = auth_service.sign_in(request).await.unwrap().into_inner();
+
+        assert_eq!(result.status_code, i32::from(StatusCode::Success));
+        assert_eq!(result.user_uuid.is_empty(), false);
+        assert_eq!(result.session_token.is_empty(), false);
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_audit_attempts_with_peer() {
+        let mut users_service = UsersImpl::for_tests();
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        let user_uuid = users_service.get_user_uuid("123456".to_owned(), "654321".to_owned()).unwrap();
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let peer: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        let sign_in_request = |password: &str| {
+            let mut request = tonic::Request::new(SignInRequest {
+                username: "123456".to_owned(),
+                password: password.to_owned(),
+            });
+            request.extensions_mut().insert(TcpConnectInfo { local_addr: None, remote_addr: Some(peer) });
+            request
+        };
+
+        let _ = auth_service.sign_in(sign_in_request("wrong password")).await.unwrap_err();
+        let _ = auth_service.sign_in(sign_in_request("654321")).await.unwrap();
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0].action, AuditAction::SignIn);
+        assert_eq!(events[0].user_uuid, user_uuid);
+        assert_eq!(events[0].outcome, AuditOutcome::Failure);
+        assert_eq!(events[0].actor, "");
+        assert_eq!(events[0].reason.as_deref(), Some("invalid_credentials"));
+        assert_eq!(events[0].peer_ip, Some(peer.ip()));
+
+        assert_eq!(events[1].outcome, AuditOutcome::Success);
+        assert_eq!(events[1].actor, user_uuid);
+        assert_eq!(events[1].reason, None);
+    }
+
+    #[tokio::test]
+    async fn sign_up_and_sign_out_should_be_audited() {
+        let auth_service = AuthService::new(
+            Box::new(Mutex::new(UsersImpl::for_tests())),
+            Box::new(Mutex::new(SessionsImpl::default())),
+        );
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+        let _ = auth_service.sign_up(request).await.unwrap();
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+        let response = auth_service.sign_in(request).await.unwrap().into_inner();
+
+        let request = tonic::Request::new(SignOutRequest { session_token: response.session_token.clone() });
+        let _ = auth_service.sign_out(request).await.unwrap();
+
+        // Signing out again finds no session, so there is nothing to audit.
+        let request = tonic::Request::new(SignOutRequest { session_token: response.session_token });
+        let _ = auth_service.sign_out(request).await;
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        let actions: Vec<_> = events.iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![AuditAction::SignUp, AuditAction::SignIn, AuditAction::SignOut]);
+        assert!(events.iter().all(|event| event.user_uuid == response.user_uuid && event.actor == response.user_uuid));
+    }
+
+    #[tokio::test]
+    async fn sign_up_should_fail_if_username_exists() {
+        let mut users_service = UsersImpl::for_tests();
+
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_up(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+        assert_eq!(error_reason(&status), Some(ErrorReason::UsernameTaken));
+    }
+
+    #[tokio::test]
+    async fn sign_up_should_succeed() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let result = auth_service.sign_up(request).await.unwrap();
+
+        assert_eq!(result.into_inner().status_code, i32::from(StatusCode::Success));
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_be_rate_limited_per_username() {
+        let mut users_service = UsersImpl::for_tests();
+
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service)
+            .with_rate_limits(RateLimits { sign_in_per_minute: 2, sign_up_per_minute: 0 });
+
+        for _ in 0..2 {
+            let request = tonic::Request::new(SignInRequest {
+                username: "123456".to_owned(),
+                password: "wrong password".to_owned(),
+            });
+
+            let status = auth_service.sign_in(request).await.unwrap_err();
+            assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+        }
+
+        // The right password does not get around the limit.
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_in(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "other".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_in(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_only_limit_failures_from_the_same_address() {
+        let (auth_service, _, _) = service_with_signed_in_user();
+        let auth_service = auth_service.with_rate_limits(RateLimits { sign_in_per_minute: 1, sign_up_per_minute: 0 });
+
+        let sign_in_request = |password: &str, peer: &str| {
+            let mut request = tonic::Request::new(SignInRequest {
+                username: "123456".to_owned(),
+                password: password.to_owned(),
+            });
+            let peer: SocketAddr = peer.parse().unwrap();
+            request.extensions_mut().insert(TcpConnectInfo { local_addr: None, remote_addr: Some(peer) });
+            request
+        };
+
+        // Successful sign-ins do not use up the allowance.
+        for _ in 0..3 {
+            auth_service.sign_in(sign_in_request("654321", "192.0.2.1:5000")).await.unwrap();
+        }
+
+        let status = auth_service.sign_in(sign_in_request("wrong password", "192.0.2.1:5000")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+
+        let status = auth_service.sign_in(sign_in_request("654321", "192.0.2.1:5001")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+
+        // Guessing from one address does not lock the user out elsewhere.
+        auth_service.sign_in(sign_in_request("654321", "198.51.100.7:5000")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sign_up_should_be_rate_limited() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service)
+            .with_rate_limits(RateLimits { sign_in_per_minute: 0, sign_up_per_minute: 1 });
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "first".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        auth_service.sign_up(request).await.unwrap();
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "second".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_up(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn reconfigure_should_change_limits_of_running_service() {
+        let mut users_service = UsersImpl::for_tests();
+
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        auth_service.reconfigure(
+            Ttls::default(),
+            Duration::from_secs(60),
+            Duration::ZERO,
+            RateLimits { sign_in_per_minute: 1, sign_up_per_minute: 0 },
+        );
+
+        for expected in [tonic::Code::Unauthenticated, tonic::Code::ResourceExhausted] {
+            let request = tonic::Request::new(SignInRequest {
+                username: "123456".to_owned(),
+                password: "wrong password".to_owned(),
+            });
+
+            let code = auth_service.sign_in(request).await.map_or_else(|status| status.code(), |_| tonic::Code::Ok);
+            assert_eq!(code, expected);
+        }
+
+        let mut user = auth_service.users_service.lock().unwrap().get_user_by_username("123456").unwrap();
+        let deleted_at = SystemTime::now();
+        user.deleted_at = Some(deleted_at);
+
+        assert_eq!(auth_service.purge_time(&user), Some(deleted_at + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn storage_should_not_be_ready_once_poisoned() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        assert!(auth_service.storage_ready());
+
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _sessions_service = auth_service.sessions_service.lock().unwrap();
+            panic!("request panicked while holding the lock");
+        }));
+
+        assert!(!auth_service.storage_ready());
+    }
+
+    #[tokio::test]
+    async fn sign_out_should_succeed() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignOutRequest {
+            session_token: "".to_owned()
+        });
+
+        let result = auth_service.sign_out(request).await.unwrap();
+
+        assert_eq!(result.into_inner().status_code, i32::from(StatusCode::Success));
+    }
+
+    #[tokio::test]
+    async fn sign_out_should_revoke_session() {
+        let mut sessions_service = SessionsImpl::default();
+        let session_token = sessions_service.create_session("123456");
+
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(sessions_service));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(SignOutRequest {
+            session_token: session_token.clone(),
+        });
+
+        auth_service.sign_out(request).await.unwrap();
+
+        assert!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&session_token).is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_account_should_fail_if_session_invalid() {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token: "invalid".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.delete_account(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::SessionNotFound));
+    }
+
+    #[tokio::test]
+    async fn delete_account_should_fail_if_incorrect_password() {
+        let mut users_service = UsersImpl::for_tests();
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        let user_uuid = users_service.get_user_uuid("123456".to_owned(), "654321".to_owned()).unwrap();
+
+        let mut sessions_service = SessionsImpl::default();
+        let session_token = sessions_service.create_session(&user_uuid);
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(sessions_service));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token,
+            password: "wrong password".to_owned(),
+        });
+
+        let status = auth_service.delete_account(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+        assert!(auth_service.users_service.lock().unwrap().get_user(&user_uuid).is_some());
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].outcome, AuditOutcome::Failure);
+    }
+
+    #[tokio::test]
+    async fn delete_account_should_succeed() {
+        let mut users_service = UsersImpl::for_tests();
+        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        let user_uuid = users_service.get_user_uuid("123456".to_owned(), "654321".to_owned()).unwrap();
+
+        let mut sessions_service = SessionsImpl::default();
+        let session_token = sessions_service.create_session(&user_uuid);
+
+        let users_service = Box::new(Mutex::new(users_service));
+        let sessions_service = Box::new(Mutex::new(sessions_service));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token: session_token.clone(),
+            password: "654321".to_owned(),
+        });
+
+        let result = auth_service.delete_account(request).await.unwrap();
+
+        assert_eq!(result.into_inner().status_code, i32::from(StatusCode::Success));
+        assert!(auth_service.users_service.lock().unwrap().get_user(&user_uuid).is_none());
+        assert!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&session_token).is_err());
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, AuditAction::DeleteAccount);
+        assert_eq!(events[0].user_uuid, user_uuid);
+        assert_eq!(events[0].outcome, AuditOutcome::Success);
+    }
+
+    /// Creates a user and a session for them, returning the service, user UUID and session token.
+    fn service_with_signed_in_user() -> (AuthService, String, String) {
+        let users_service = Box::new(Mutex::new(UsersImpl::for_tests()));
+        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+
+        let auth_service = AuthService::new(users_service, sessions_service);
+        let (user_uuid, session_token) = sign_in_user(&auth_service, "123456", "654321");
+
+        (auth_service, user_uuid, session_token)
+    }
+
+    /// Creates a user without roles, unless one with the username exists, and signs them in,
+    /// returning their UUID and session token.
+    pub(crate) fn sign_in_user(auth_service: &AuthService, username: &str, password: &str) -> (String, String) {
+        let _ = auth_service.users_service.lock().unwrap().create_user(username.to_owned(), password.to_owned());
+        let user_uuid = auth_service.users_service.lock().unwrap().get_user_by_username(username).unwrap().user_uuid;
+        let session_token = auth_service.sessions_service.lock().unwrap().create_session(&user_uuid);
+
+        (user_uuid, session_token)
+    }
+
+    /// Computes the TOTP code for the current time from a base32 secret.
+    fn current_code(secret: &str) -> String {
+        let secret = base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret).unwrap();
+        format!("{:06}", mfa::hotp(&secret, mfa::totp_step(SystemTime::now())))
+    }
+
+    /// Enrolls the signed-in user in TOTP, returning the base32 secret.
+    async fn enroll_totp(auth_service: &AuthService, session_token: &str) -> String {
+        enroll_totp_with_recovery_codes(auth_service, session_token).await.0
+    }
+
+    /// Enrolls the signed-in user in TOTP, returning the base32 secret and the recovery codes.
+    async fn enroll_totp_with_recovery_codes(auth_service: &AuthService, session_token: &str) -> (String, Vec<String>) {
+        let request = tonic::Request::new(BeginTotpEnrollmentRequest {
+            session_token: session_token.to_owned(),
+        });
+
+        let secret = auth_service.begin_totp_enrollment(request).await.unwrap().into_inner().secret;
+
+        let request = tonic::Request::new(ConfirmTotpEnrollmentRequest {
+            session_token: session_token.to_owned(),
+            code: current_code(&secret),
+        });
+
+        let recovery_codes = auth_service.confirm_totp_enrollment(request).await.unwrap().into_inner().recovery_codes;
+
+        (secret, recovery_codes)
+    }
+
+    /// Signs in with the default test credentials and returns the MFA challenge token.
+    async fn mfa_challenge(auth_service: &AuthService) -> String {
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        auth_service.sign_in(request).await.unwrap().into_inner().mfa_challenge_token
+    }
+
+    #[tokio::test]
+    async fn begin_totp_enrollment_should_return_otpauth_uri() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(BeginTotpEnrollmentRequest { session_token });
+
+        let result = auth_service.begin_totp_enrollment(request).await.unwrap().into_inner();
+
+        assert_eq!(result.status_code, i32::from(StatusCode::Success));
+        assert!(result.otpauth_uri.starts_with("otpauth://totp/RustyAuth:123456?"));
+        assert!(result.otpauth_uri.contains(&result.secret));
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        assert!(user.totp_secret.is_none());
+        assert!(user.pending_totp_secret.is_some());
+        assert_ne!(user.pending_totp_secret.unwrap(), result.secret);
+    }
+
+    #[tokio::test]
+    async fn confirm_totp_enrollment_should_fail_if_not_started() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(ConfirmTotpEnrollmentRequest {
+            session_token,
+            code: "123456".to_owned(),
+        });
+
+        let status = auth_service.confirm_totp_enrollment(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(error_reason(&status), Some(ErrorReason::TotpNotEnrolled));
+    }
+
+    #[tokio::test]
+    async fn confirm_totp_enrollment_should_fail_with_incorrect_code() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(BeginTotpEnrollmentRequest {
+            session_token: session_token.clone(),
+        });
+
+        let secret = auth_service.begin_totp_enrollment(request).await.unwrap().into_inner().secret;
+        let wrong_code = format!("{:06}", (current_code(&secret).parse::<u32>().unwrap() + 1) % 1_000_000);
+
+        let request = tonic::Request::new(ConfirmTotpEnrollmentRequest {
+            session_token,
+            code: wrong_code,
+        });
+
+        let status = auth_service.confirm_totp_enrollment(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidMfaCode));
+        assert!(auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap().totp_secret.is_none());
+    }
+
+    #[tokio::test]
+    async fn begin_totp_enrollment_should_fail_if_already_enabled() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        enroll_totp(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(BeginTotpEnrollmentRequest { session_token });
+
+        let status = auth_service.begin_totp_enrollment(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::TotpAlreadyEnabled));
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_require_mfa_when_totp_enabled() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        enroll_totp(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let result = auth_service.sign_in(request).await.unwrap().into_inner();
+
+        assert_eq!(result.status_code, i32::from(StatusCode::MfaRequired));
+        assert!(result.user_uuid.is_empty());
+        assert!(result.session_token.is_empty());
+        assert!(!result.mfa_challenge_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn verify_mfa_should_succeed() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let secret = enroll_totp(&auth_service, &session_token).await;
+
+        // The enrollment code has been used, so the user has to wait for the next time step.
+        let mut user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        user.totp_last_used_step = None;
+        auth_service.users_service.lock().unwrap().update_user(user).unwrap();
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let challenge = auth_service.sign_in(request).await.unwrap().into_inner().mfa_challenge_token;
+
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: challenge.clone(),
+            code: current_code(&secret),
+            recovery_code: "".to_owned(),
+        });
+
+        let result = auth_service.verify_mfa(request).await.unwrap().into_inner();
+
+        assert_eq!(result.status_code, i32::from(StatusCode::Success));
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&result.session_token).unwrap(), user_uuid);
+
+        // The challenge cannot be used a second time.
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: challenge,
+            code: current_code(&secret),
+            recovery_code: "".to_owned(),
+        });
+
+        let status = auth_service.verify_mfa(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::MfaChallengeInvalid));
+    }
+
+    #[tokio::test]
+    async fn verify_mfa_should_reject_replayed_code() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let secret = enroll_totp(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let challenge = auth_service.sign_in(request).await.unwrap().into_inner().mfa_challenge_token;
+
+        // The code was just used to confirm enrollment.
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: challenge,
+            code: current_code(&secret),
+            recovery_code: "".to_owned(),
+        });
+
+        let status = auth_service.verify_mfa(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidMfaCode));
+    }
+
+    #[tokio::test]
+    async fn verify_mfa_should_limit_failed_attempts() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+        let auth_service = auth_service.with_rate_limits(RateLimits { sign_in_per_minute: 2, sign_up_per_minute: 0 });
+
+        let secret = enroll_totp(&auth_service, &session_token).await;
+        let challenges = [
+            mfa_challenge(&auth_service).await,
+            mfa_challenge(&auth_service).await,
+            mfa_challenge(&auth_service).await,
+        ];
+
+        for challenge in &challenges[..2] {
+            let request = tonic::Request::new(VerifyMfaRequest {
+                mfa_challenge_token: challenge.clone(),
+                code: "".to_owned(),
+                recovery_code: "wrong".to_owned(),
+            });
+
+            let status = auth_service.verify_mfa(request).await.unwrap_err();
+            assert_eq!(error_reason(&status), Some(ErrorReason::InvalidMfaCode));
+        }
+
+        // Challenges issued earlier are refused, and no new ones are issued.
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: challenges[2].clone(),
+            code: current_code(&secret),
+            recovery_code: "".to_owned(),
+        });
+
+        let status = auth_service.verify_mfa(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "123456".to_owned(),
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.sign_in(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn confirm_totp_enrollment_should_issue_recovery_codes() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let (_, recovery_codes) = enroll_totp_with_recovery_codes(&auth_service, &session_token).await;
+
+        assert_eq!(recovery_codes.len(), mfa::RECOVERY_CODE_COUNT);
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        assert_eq!(user.recovery_code_hashes.len(), mfa::RECOVERY_CODE_COUNT);
+        assert!(!user.recovery_code_hashes.contains(&recovery_codes[0]));
+    }
+
+    #[tokio::test]
+    async fn verify_mfa_should_accept_recovery_code_once() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let (_, recovery_codes) = enroll_totp_with_recovery_codes(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: mfa_challenge(&auth_service).await,
+            code: "".to_owned(),
+            recovery_code: recovery_codes[0].clone(),
+        });
+
+        let result = auth_service.verify_mfa(request).await.unwrap().into_inner();
+
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(result.remaining_recovery_codes as usize, mfa::RECOVERY_CODE_COUNT - 1);
+
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: mfa_challenge(&auth_service).await,
+            code: "".to_owned(),
+            recovery_code: recovery_codes[0].clone(),
+        });
+
+        let status = auth_service.verify_mfa(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidMfaCode));
+    }
+
+    #[tokio::test]
+    async fn regenerate_recovery_codes_should_invalidate_old_codes() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let (_, old_codes) = enroll_totp_with_recovery_codes(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(RegenerateRecoveryCodesRequest {
+            session_token,
+            password: "654321".to_owned(),
+        });
+
+        let new_codes = auth_service.regenerate_recovery_codes(request).await.unwrap().into_inner().recovery_codes;
+
+        assert_eq!(new_codes.len(), mfa::RECOVERY_CODE_COUNT);
+
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: mfa_challenge(&auth_service).await,
+            code: "".to_owned(),
+            recovery_code: old_codes[0].clone(),
+        });
+
+        assert!(auth_service.verify_mfa(request).await.is_err());
+
+        let request = tonic::Request::new(VerifyMfaRequest {
+            mfa_challenge_token: mfa_challenge(&auth_service).await,
+            code: "".to_owned(),
+            recovery_code: new_codes[0].clone(),
+        });
+
+        assert!(auth_service.verify_mfa(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn regenerate_recovery_codes_should_fail_with_incorrect_password() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        enroll_totp(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(RegenerateRecoveryCodesRequest {
+            session_token,
+            password: "wrong password".to_owned(),
+        });
+
+        let status = auth_service.regenerate_recovery_codes(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn regenerate_recovery_codes_should_fail_without_totp() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(RegenerateRecoveryCodesRequest {
+            session_token,
+            password: "654321".to_owned(),
+        });
+
+        let status = auth_service.regenerate_recovery_codes(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::TotpNotEnrolled));
+    }
+
+    /// Registers a software passkey for the signed-in user.
+    async fn register_passkey(auth_service: &AuthService, session_token: &str) -> SoftwareAuthenticator {
+        let authenticator = SoftwareAuthenticator::new();
+
+        let request = tonic::Request::new(BeginPasskeyRegistrationRequest {
+            session_token: session_token.to_owned(),
+        });
+
+        let options = auth_service.begin_passkey_registration(request).await.unwrap().into_inner();
+        let attestation = authenticator.register(&auth_service.webauthn_config, &options.challenge);
+
+        let request = tonic::Request::new(FinishPasskeyRegistrationRequest {
+            session_token: session_token.to_owned(),
+            credential_id: authenticator.credential_id.clone(),
+            client_data_json: attestation.client_data_json,
+            attestation_object: attestation.attestation_object,
+            name: "laptop".to_owned(),
+        });
+
+        auth_service.finish_passkey_registration(request).await.unwrap();
+
+        authenticator
+    }
+
+    /// Starts a passkey sign-in for the default test user and returns the challenge.
+    async fn passkey_login_challenge(auth_service: &AuthService) -> Vec<u8> {
+        let request = tonic::Request::new(BeginPasskeyLoginRequest {
+            username: "123456".to_owned(),
+        });
+
+        auth_service.begin_passkey_login(request).await.unwrap().into_inner().challenge
+    }
+
+    /// Builds a passkey sign-in request from an assertion.
+    fn finish_passkey_login_request(credential_id: &[u8], assertion: Assertion) -> tonic::Request<FinishPasskeyLoginRequest> {
+        tonic::Request::new(FinishPasskeyLoginRequest {
+            credential_id: credential_id.to_vec(),
+            client_data_json: assertion.client_data_json,
+            authenticator_data: assertion.authenticator_data,
+            signature: assertion.signature,
+        })
+    }
+
+    #[tokio::test]
+    async fn passkey_registration_and_login_should_succeed() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let mut authenticator = register_passkey(&auth_service, &session_token).await;
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        assert_eq!(user.passkeys.len(), 1);
+        assert_eq!(user.passkeys[0].name, "laptop");
+
+        let challenge = passkey_login_challenge(&auth_service).await;
+        let assertion = authenticator.authenticate(&auth_service.webauthn_config, &challenge);
+
+        let result = auth_service
+            .finish_passkey_login(finish_passkey_login_request(&authenticator.credential_id, assertion))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(result.status_code, i32::from(StatusCode::Success));
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(auth_service.sessions_service.lock().unwrap().get_user_uuid(&result.session_token).unwrap(), user_uuid);
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        assert_eq!(user.passkeys[0].sign_count, 1);
+    }
+
+    #[tokio::test]
+    async fn begin_passkey_registration_should_exclude_registered_passkeys() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let authenticator = register_passkey(&auth_service, &session_token).await;
+
+        let request = tonic::Request::new(BeginPasskeyRegistrationRequest { session_token });
+
+        let options = auth_service.begin_passkey_registration(request).await.unwrap().into_inner();
+
+        assert_eq!(options.exclude_credential_ids, vec![authenticator.credential_id]);
+        assert_eq!(options.rp_id, "localhost");
+    }
+
+    #[tokio::test]
+    async fn finish_passkey_registration_should_fail_with_other_users_challenge() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let _ = auth_service.users_service.lock().unwrap().create_user("other".to_owned(), "password".to_owned());
+        let other_uuid = auth_service.users_service.lock().unwrap().get_user_by_username("other").unwrap().user_uuid;
+        let other_session = auth_service.sessions_service.lock().unwrap().create_session(&other_uuid);
+
+        let request = tonic::Request::new(BeginPasskeyRegistrationRequest { session_token });
+        let options = auth_service.begin_passkey_registration(request).await.unwrap().into_inner();
+
+        let authenticator = SoftwareAuthenticator::new();
+        let attestation = authenticator.register(&auth_service.webauthn_config, &options.challenge);
+
+        let request = tonic::Request::new(FinishPasskeyRegistrationRequest {
+            session_token: other_session,
+            credential_id: authenticator.credential_id.clone(),
+            client_data_json: attestation.client_data_json,
+            attestation_object: attestation.attestation_object,
+            name: "stolen".to_owned(),
+        });
+
+        let status = auth_service.finish_passkey_registration(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::WebauthnChallengeInvalid));
+    }
+
+    #[tokio::test]
+    async fn begin_passkey_login_should_fail_without_passkeys() {
+        let (auth_service, _, _) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(BeginPasskeyLoginRequest {
+            username: "123456".to_owned(),
+        });
+
+        let status = auth_service.begin_passkey_login(request).await.unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasskeyNotFound));
+    }
+
+    #[tokio::test]
+    async fn finish_passkey_login_should_fail_with_unregistered_passkey() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        register_passkey(&auth_service, &session_token).await;
+
+        let challenge = passkey_login_challenge(&auth_service).await;
+        let mut stranger = SoftwareAuthenticator::new();
+        let assertion = stranger.authenticate(&auth_service.webauthn_config, &challenge);
+
+        let status = auth_service
+            .finish_passkey_login(finish_passkey_login_request(&stranger.credential_id, assertion))
+            .await
+            .unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasskeyNotFound));
+    }
+
+    #[tokio::test]
+    async fn finish_passkey_login_should_reject_replayed_assertion() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let mut authenticator = register_passkey(&auth_service, &session_token).await;
+
+        let challenge = passkey_login_challenge(&auth_service).await;
+        let assertion = authenticator.authenticate(&auth_service.webauthn_config, &challenge);
+        let replay = Assertion {
+            client_data_json: assertion.client_data_json.clone(),
+            authenticator_data: assertion.authenticator_data.clone(),
+            signature: assertion.signature.clone(),
+        };
+
+        auth_service
+            .finish_passkey_login(finish_passkey_login_request(&authenticator.credential_id, assertion))
+            .await
+            .unwrap();
+
+        let status = auth_service
+            .finish_passkey_login(finish_passkey_login_request(&authenticator.credential_id, replay))
+            .await
+            .unwrap_err();
+
+        assert_eq!(error_reason(&status), Some(ErrorReason::WebauthnChallengeInvalid));
+    }
+
+    /// Creates an API key for the signed-in user, returning the key and its details.
+    async fn create_api_key(auth_service: &AuthService, session_token: &str, expires_in_seconds: u64) -> (String, ApiKeyInfo) {
+        let request = tonic::Request::new(CreateApiKeyRequest {
+            session_token: session_token.to_owned(),
+            name: "batch".to_owned(),
+            scopes: vec!["users:read".to_owned()],
+            expires_in_seconds,
+        });
+
+        let response = auth_service.create_api_key(request).await.unwrap().into_inner();
+
+        (response.api_key, response.info.unwrap())
+    }
+
+    #[tokio::test]
+    async fn validate_session_should_accept_session_token() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: session_token });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(result.credential_type, i32::from(CredentialType::Session));
+        assert!(result.scopes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn validate_session_should_accept_api_key() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let (api_key, info) = create_api_key(&auth_service, &session_token, 0).await;
+
+        assert!(api_key.starts_with(&info.prefix));
+        assert_eq!(info.expires_at, 0);
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: api_key });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(result.credential_type, i32::from(CredentialType::ApiKey));
+        assert_eq!(result.scopes, vec!["users:read".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn validate_session_should_reject_unknown_token() {
+        let (auth_service, _, _) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: "rak_unknown_key".to_owned() });
+        let status = auth_service.validate_session(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidApiKey));
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: "unknown".to_owned() });
+        let status = auth_service.validate_session(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::SessionNotFound));
+    }
+
+    #[tokio::test]
+    async fn create_api_key_should_reject_unknown_scopes() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(CreateApiKeyRequest {
+            session_token,
+            name: "batch".to_owned(),
+            scopes: vec!["users:read".to_owned(), "users:reed".to_owned()],
+            expires_in_seconds: 0,
+        });
+
+        let status = auth_service.create_api_key(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(error_reason(&status), Some(ErrorReason::UnknownPermission));
+        assert!(auth_service.api_keys_service.lock().unwrap().list_api_keys(&user_uuid).is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_api_key_should_set_expiry() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let (_, info) = create_api_key(&auth_service, &session_token, 3600).await;
+
+        assert_eq!(info.expires_at - info.created_at, 3600);
+    }
+
+    #[tokio::test]
+    async fn list_api_keys_should_not_return_secrets() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let (api_key, info) = create_api_key(&auth_service, &session_token, 0).await;
+
+        let request = tonic::Request::new(ListApiKeysRequest { session_token });
+
+        let result = auth_service.list_api_keys(request).await.unwrap().into_inner();
+
+        assert_eq!(result.api_keys, vec![info]);
+        assert!(!format!("{:?}", result).contains(&api_key));
+    }
+
+    #[tokio::test]
+    async fn revoke_api_key_should_invalidate_key() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let (api_key, info) = create_api_key(&auth_service, &session_token, 0).await;
+
+        let request = tonic::Request::new(RevokeApiKeyRequest {
+            session_token: session_token.clone(),
+            key_id: info.key_id.clone(),
+        });
+
+        auth_service.revoke_api_key(request).await.unwrap();
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: api_key });
+        assert!(auth_service.validate_session(request).await.is_err());
+
+        let request = tonic::Request::new(RevokeApiKeyRequest { session_token, key_id: info.key_id });
+        let status = auth_service.revoke_api_key(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::ApiKeyNotFound));
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        let actions: Vec<AuditAction> = events.iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![AuditAction::CreateApiKey, AuditAction::RevokeApiKey]);
+        assert!(events.iter().all(|event| event.user_uuid == user_uuid && event.actor == user_uuid));
+    }
+
+    #[tokio::test]
+    async fn delete_account_should_revoke_api_keys() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let (api_key, _) = create_api_key(&auth_service, &session_token, 0).await;
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token,
+            password: "654321".to_owned(),
+        });
+
+        auth_service.delete_account(request).await.unwrap();
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: api_key });
+        assert!(auth_service.validate_session(request).await.is_err());
+    }
+
+    /// Grants a role to a user directly in the store.
+    pub(crate) fn grant(auth_service: &AuthService, user_uuid: &str, role: Role) {
+        let mut users_service = auth_service.users_service.lock().unwrap();
+        let mut user = users_service.get_user(user_uuid).unwrap();
+        user.roles.push(role);
+        users_service.update_user(user).unwrap();
+    }
+
+    /// Creates a second, signed-in user without roles, returning their UUID and session token.
+    fn sign_in_other_user(auth_service: &AuthService) -> (String, String) {
+        sign_in_user(auth_service, "other", "password")
+    }
+
+    #[tokio::test]
+    async fn sign_in_should_return_roles() {
+        let (auth_service, user_uuid, _) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Auditor);
 
         let request = tonic::Request::new(SignInRequest {
             username: "123456".to_owned(),
@@ -286,62 +4086,598 @@ mod tests {
 
         let result = auth_service.sign_in(request).await.unwrap().into_inner();
 
-        assert_eq!(result.status_code, StatusCode::Success.into());
-        assert_eq!(result.user_uuid.is_empty(), false);
-        assert_eq!(result.session_token.is_empty(), false);
+        assert_eq!(result.roles, vec!["auditor".to_owned()]);
     }
 
     #[tokio::test]
-    async fn sign_up_should_fail_if_username_exists() {
-        let mut users_service = UsersImpl::default();
+    async fn validate_session_should_return_roles_and_permissions() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::UserManager);
 
-        let _ = users_service.create_user("123456".to_owned(), "654321".to_owned());
+        let request = tonic::Request::new(ValidateSessionRequest { token: session_token.clone() });
 
-        let users_service = Box::new(Mutex::new(users_service));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+        assert_eq!(result.roles, vec!["user_manager".to_owned()]);
+        assert_eq!(result.permissions, vec!["users:read".to_owned(), "users:write".to_owned()]);
 
-        let request = tonic::Request::new(SignUpRequest {
-            username: "123456".to_owned(),
+        // The helper creates keys scoped to "users:read" only.
+        let (api_key, _) = create_api_key(&auth_service, &session_token, 0).await;
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: api_key });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+
+        assert!(result.roles.is_empty());
+        assert_eq!(result.permissions, vec!["users:read".to_owned()]);
+
+        // Keys without scopes carry the user's roles like a session does.
+        let request = tonic::Request::new(CreateApiKeyRequest {
+            session_token,
+            name: "unscoped".to_owned(),
+            scopes: Vec::new(),
+            expires_in_seconds: 0,
+        });
+        let api_key = auth_service.create_api_key(request).await.unwrap().into_inner().api_key;
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: api_key });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+
+        assert_eq!(result.roles, vec!["user_manager".to_owned()]);
+        assert_eq!(result.permissions, vec!["users:read".to_owned(), "users:write".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn grant_role_should_require_permission() {
+        let (auth_service, user_uuid, _) = service_with_signed_in_user();
+        let (_, other_session) = sign_in_other_user(&auth_service);
+
+        let request = tonic::Request::new(GrantRoleRequest {
+            session_token: other_session,
+            user_uuid,
+            role: "admin".to_owned(),
+        });
+
+        let status = auth_service.grant_role(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn grant_role_should_succeed_for_admin() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+        let (other_uuid, _) = sign_in_other_user(&auth_service);
+
+        let request = tonic::Request::new(GrantRoleRequest {
+            session_token: session_token.clone(),
+            user_uuid: other_uuid.clone(),
+            role: "auditor".to_owned(),
+        });
+
+        auth_service.grant_role(request).await.unwrap();
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&other_uuid).unwrap();
+        assert_eq!(user.roles, vec![Role::Auditor]);
+
+        let request = tonic::Request::new(GrantRoleRequest {
+            session_token,
+            user_uuid: other_uuid,
+            role: "superuser".to_owned(),
+        });
+
+        let status = auth_service.grant_role(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::UnknownRole));
+    }
+
+    #[tokio::test]
+    async fn revoke_role_should_keep_last_admin() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+
+        let request = tonic::Request::new(RevokeRoleRequest {
+            session_token: session_token.clone(),
+            user_uuid: user_uuid.clone(),
+            role: "admin".to_owned(),
+        });
+
+        let status = auth_service.revoke_role(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::LastAdmin));
+
+        let (other_uuid, _) = sign_in_other_user(&auth_service);
+        grant(&auth_service, &other_uuid, Role::Admin);
+
+        let request = tonic::Request::new(RevokeRoleRequest {
+            session_token,
+            user_uuid: other_uuid.clone(),
+            role: "admin".to_owned(),
+        });
+
+        auth_service.revoke_role(request).await.unwrap();
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&other_uuid).unwrap();
+        assert!(user.roles.is_empty());
+    }
+
+    async fn create_group(auth_service: &AuthService, session_token: &str, name: &str) -> GroupInfo {
+        let request = tonic::Request::new(CreateGroupRequest {
+            session_token: session_token.to_owned(),
+            name: name.to_owned(),
+            description: "".to_owned(),
+        });
+
+        auth_service.create_group(request).await.unwrap().into_inner().group.unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_group_should_require_permission() {
+        let (auth_service, user_uuid, _) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::UserManager);
+        let (_, other_session) = sign_in_other_user(&auth_service);
+        let manager_session = auth_service.sessions_service.lock().unwrap().create_session(&user_uuid);
+
+        for session_token in [other_session, manager_session] {
+            let request = tonic::Request::new(CreateGroupRequest {
+                session_token,
+                name: "platform".to_owned(),
+                description: "".to_owned(),
+            });
+
+            let status = auth_service.create_group(request).await.unwrap_err();
+            assert_eq!(error_reason(&status), Some(ErrorReason::PermissionDenied));
+        }
+    }
+
+    #[tokio::test]
+    async fn group_crud_should_succeed_for_admin() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+
+        let group = create_group(&auth_service, &session_token, "platform").await;
+        create_group(&auth_service, &session_token, "support").await;
+
+        let request = tonic::Request::new(CreateGroupRequest {
+            session_token: session_token.clone(),
+            name: "platform".to_owned(),
+            description: "".to_owned(),
+        });
+
+        let status = auth_service.create_group(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::GroupNameTaken));
+
+        let request = tonic::Request::new(UpdateGroupRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            name: "infrastructure".to_owned(),
+            description: "Infrastructure team".to_owned(),
+        });
+
+        auth_service.update_group(request).await.unwrap();
+
+        let request = tonic::Request::new(GetGroupRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+        });
+
+        let result = auth_service.get_group(request).await.unwrap().into_inner().group.unwrap();
+        assert_eq!(result.description, "Infrastructure team");
+
+        let request = tonic::Request::new(DeleteGroupRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+        });
+
+        auth_service.delete_group(request).await.unwrap();
+
+        let request = tonic::Request::new(ListGroupsRequest { session_token: session_token.clone() });
+
+        let result = auth_service.list_groups(request).await.unwrap().into_inner();
+        let names: Vec<String> = result.groups.into_iter().map(|group| group.name).collect();
+        assert_eq!(names, vec!["support".to_owned()]);
+
+        let request = tonic::Request::new(GetGroupRequest { session_token, group_uuid: group.group_uuid.clone() });
+
+        let status = auth_service.get_group(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(error_reason(&status), Some(ErrorReason::GroupNotFound));
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        let group_events: Vec<AuditAction> = events.iter()
+                                                   .filter(|event| event.user_uuid == group.group_uuid)
+                                                   .map(|event| event.action)
+                                                   .collect();
+        assert_eq!(group_events, vec![AuditAction::CreateGroup, AuditAction::UpdateGroup, AuditAction::DeleteGroup]);
+        assert!(events.iter().all(|event| event.actor == user_uuid));
+    }
+
+    #[tokio::test]
+    async fn add_group_member_should_fail_for_unknown_user() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+        let group = create_group(&auth_service, &session_token, "platform").await;
+
+        let request = tonic::Request::new(AddGroupMemberRequest {
+            session_token,
+            group_uuid: group.group_uuid,
+            user_uuid: "unknown".to_owned(),
+        });
+
+        let status = auth_service.add_group_member(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn group_roles_should_apply_to_members() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+        let (other_uuid, other_session) = sign_in_other_user(&auth_service);
+        let group = create_group(&auth_service, &session_token, "support").await;
+
+        let request = tonic::Request::new(GrantGroupRoleRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            role: "user_manager".to_owned(),
+        });
+
+        auth_service.grant_group_role(request).await.unwrap();
+
+        let request = tonic::Request::new(AddGroupMemberRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            user_uuid: other_uuid.clone(),
+        });
+
+        let result = auth_service.add_group_member(request).await.unwrap().into_inner().group.unwrap();
+        assert_eq!(result.member_uuids, vec![other_uuid.clone()]);
+        assert_eq!(result.roles, vec!["user_manager".to_owned()]);
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: other_session.clone() });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+        assert_eq!(result.roles, vec!["user_manager".to_owned()]);
+        assert_eq!(result.permissions, vec!["users:read".to_owned(), "users:write".to_owned()]);
+        assert_eq!(result.groups, vec!["support".to_owned()]);
+
+        // Group roles authorize requests too.
+        let request = tonic::Request::new(ListGroupsRequest { session_token: other_session.clone() });
+        assert!(auth_service.list_groups(request).await.is_ok());
+
+        let request = tonic::Request::new(RemoveGroupMemberRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            user_uuid: other_uuid,
+        });
+
+        auth_service.remove_group_member(request).await.unwrap();
+
+        let request = tonic::Request::new(ValidateSessionRequest { token: other_session });
+
+        let result = auth_service.validate_session(request).await.unwrap().into_inner();
+        assert!(result.roles.is_empty());
+        assert!(result.groups.is_empty());
+
+        let request = tonic::Request::new(RevokeGroupRoleRequest {
+            session_token,
+            group_uuid: group.group_uuid.clone(),
+            role: "user_manager".to_owned(),
+        });
+
+        auth_service.revoke_group_role(request).await.unwrap();
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        let group_events: Vec<AuditAction> = events.iter()
+                                                   .filter(|event| event.user_uuid == group.group_uuid)
+                                                   .map(|event| event.action)
+                                                   .collect();
+        assert_eq!(group_events, vec![AuditAction::CreateGroup, AuditAction::GrantGroupRole, AuditAction::RevokeGroupRole]);
+    }
+
+    #[tokio::test]
+    async fn group_changes_should_keep_last_admin() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        grant(&auth_service, &user_uuid, Role::Admin);
+        let group = create_group(&auth_service, &session_token, "admins").await;
+
+        let request = tonic::Request::new(GrantGroupRoleRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            role: "admin".to_owned(),
+        });
+
+        auth_service.grant_group_role(request).await.unwrap();
+
+        let request = tonic::Request::new(AddGroupMemberRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            user_uuid: user_uuid.clone(),
+        });
+
+        auth_service.add_group_member(request).await.unwrap();
+
+        // The user stays an administrator through the group.
+        let request = tonic::Request::new(RevokeRoleRequest {
+            session_token: session_token.clone(),
+            user_uuid: user_uuid.clone(),
+            role: "admin".to_owned(),
+        });
+
+        auth_service.revoke_role(request).await.unwrap();
+
+        let request = tonic::Request::new(RevokeGroupRoleRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            role: "admin".to_owned(),
+        });
+
+        let status = auth_service.revoke_group_role(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::LastAdmin));
+
+        let request = tonic::Request::new(RemoveGroupMemberRequest {
+            session_token: session_token.clone(),
+            group_uuid: group.group_uuid.clone(),
+            user_uuid,
+        });
+
+        let status = auth_service.remove_group_member(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::LastAdmin));
+
+        let request = tonic::Request::new(DeleteGroupRequest { session_token, group_uuid: group.group_uuid });
+
+        let status = auth_service.delete_group(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn purge_should_leave_groups() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        let group = auth_service.groups_service.lock().unwrap().create_group("platform", "").unwrap();
+        let mut stored = group.clone();
+        stored.member_uuids.push(user_uuid.clone());
+        auth_service.groups_service.lock().unwrap().update_group(stored).unwrap();
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token,
             password: "654321".to_owned(),
         });
 
-        let result = auth_service.sign_up(request).await.unwrap();
+        auth_service.delete_account(request).await.unwrap();
+
+        // Memberships are kept so that a restored account gets them back.
+        assert_eq!(auth_service.groups_service.lock().unwrap().user_groups(&user_uuid).len(), 1);
 
-        assert_eq!(result.into_inner().status_code, StatusCode::Failure.into());
+        auth_service.purge_deleted_users(SystemTime::now() + DEFAULT_DELETION_GRACE_PERIOD);
+
+        assert!(auth_service.groups_service.lock().unwrap().user_groups(&user_uuid).is_empty());
     }
 
     #[tokio::test]
-    async fn sign_up_should_succeed() {
-        let users_service = Box::new(Mutex::new(UsersImpl::default()));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+    async fn purge_should_wait_for_grace_period() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+        let auth_service = auth_service.with_deletion_grace_period(Duration::from_secs(60));
+        create_api_key(&auth_service, &session_token, 0).await;
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token,
+            password: "654321".to_owned(),
+        });
+
+        auth_service.delete_account(request).await.unwrap();
+
+        assert_eq!(auth_service.purge_deleted_users(SystemTime::now()), 0);
+        assert_eq!(auth_service.users_service.lock().unwrap().list_deleted_users().len(), 1);
+        assert_eq!(auth_service.api_keys_service.lock().unwrap().list_api_keys(&user_uuid).len(), 1);
+
+        let purged = auth_service.purge_deleted_users(SystemTime::now() + Duration::from_secs(60));
+
+        assert_eq!(purged, 1);
+        assert!(auth_service.users_service.lock().unwrap().list_deleted_users().is_empty());
+        assert!(auth_service.api_keys_service.lock().unwrap().list_api_keys(&user_uuid).is_empty());
+
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events.last().unwrap().action, AuditAction::PurgeAccount);
+    }
+
+    #[tokio::test]
+    async fn sign_up_should_fail_with_username_of_deleted_account() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(DeleteAccountRequest {
+            session_token,
+            password: "654321".to_owned(),
+        });
+
+        auth_service.delete_account(request).await.unwrap();
 
         let request = tonic::Request::new(SignUpRequest {
             username: "123456".to_owned(),
             password: "654321".to_owned(),
         });
 
-        let result = auth_service.sign_up(request).await.unwrap();
+        assert!(auth_service.sign_up(request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn self_service_should_reject_session_of_inactive_account() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        // Changing the status through the Admin service deletes the session; this covers one that
+        // outlives the change, such as a session created in between.
+        {
+            let mut users_service = auth_service.users_service.lock().unwrap();
+            let mut user = users_service.get_user(&user_uuid).unwrap();
+            user.status = AccountStatus::Locked;
+            users_service.update_user(user).unwrap();
+        }
+
+        let request = tonic::Request::new(GetProfileRequest { session_token: session_token.clone() });
+        let status = auth_service.get_profile(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
+
+        let request = tonic::Request::new(BeginTotpEnrollmentRequest { session_token: session_token.clone() });
+        let status = auth_service.begin_totp_enrollment(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
 
-        assert_eq!(result.into_inner().status_code, StatusCode::Success.into());
+        let request = tonic::Request::new(CreateApiKeyRequest {
+            session_token: session_token.clone(),
+            name: "batch".to_owned(),
+            scopes: Vec::new(),
+            expires_in_seconds: 0,
+        });
+        let status = auth_service.create_api_key(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
+
+        let request = tonic::Request::new(ChangeUsernameRequest {
+            session_token,
+            new_username: "jane".to_owned(),
+            password: "654321".to_owned(),
+        });
+        let status = auth_service.change_username(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::AccountLocked));
+        assert!(auth_service.api_keys_service.lock().unwrap().list_api_keys(&user_uuid).is_empty());
     }
 
     #[tokio::test]
-    async fn sign_out_should_succeed() {
-        let users_service = Box::new(Mutex::new(UsersImpl::default()));
-        let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+    async fn update_profile_should_store_profile() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
 
-        let auth_service = AuthService::new(users_service, sessions_service);
+        let profile = ApiProfile {
+            display_name: "Jane Smith".to_owned(),
+            email: "jane@example.com".to_owned(),
+            locale: "en-GB".to_owned(),
+            metadata: HashMap::from([("team".to_owned(), "platform".to_owned())]),
+        };
 
-        let request = tonic::Request::new(SignOutRequest {
-            session_token: "".to_owned()
+        let request = tonic::Request::new(UpdateProfileRequest {
+            session_token: session_token.clone(),
+            profile: Some(profile.clone()),
         });
 
-        let result = auth_service.sign_out(request).await.unwrap();
+        let result = auth_service.update_profile(request).await.unwrap().into_inner();
+        assert_eq!(result.profile, Some(profile.clone()));
+
+        let request = tonic::Request::new(GetProfileRequest { session_token });
+        let result = auth_service.get_profile(request).await.unwrap().into_inner();
+
+        assert_eq!(result.user_uuid, user_uuid);
+        assert_eq!(result.username, "123456");
+        assert_eq!(result.profile, Some(profile));
+    }
+
+    #[tokio::test]
+    async fn update_profile_should_clear_empty_fields() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(UpdateProfileRequest {
+            session_token: session_token.clone(),
+            profile: Some(ApiProfile {
+                display_name: "Jane Smith".to_owned(),
+                ..ApiProfile::default()
+            }),
+        });
+        auth_service.update_profile(request).await.unwrap();
+
+        let request = tonic::Request::new(UpdateProfileRequest {
+            session_token,
+            profile: Some(ApiProfile::default()),
+        });
+        auth_service.update_profile(request).await.unwrap();
+
+        let user = auth_service.users_service.lock().unwrap().get_user(&user_uuid).unwrap();
+        assert_eq!(user.profile, Profile::default());
+    }
+
+    #[tokio::test]
+    async fn update_profile_should_reject_invalid_email() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(UpdateProfileRequest {
+            session_token,
+            profile: Some(ApiProfile {
+                email: "not an email".to_owned(),
+                ..ApiProfile::default()
+            }),
+        });
+
+        let status = auth_service.update_profile(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidProfile));
+    }
+
+    #[tokio::test]
+    async fn get_profile_should_fail_if_session_invalid() {
+        let (auth_service, _, _) = service_with_signed_in_user();
+
+        let request = tonic::Request::new(GetProfileRequest { session_token: "invalid".to_owned() });
+
+        let status = auth_service.get_profile(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    fn change_username_request(session_token: &str, new_username: &str, password: &str) -> tonic::Request<ChangeUsernameRequest> {
+        tonic::Request::new(ChangeUsernameRequest {
+            session_token: session_token.to_owned(),
+            new_username: new_username.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+
+    #[tokio::test]
+    async fn change_username_should_succeed() {
+        let (auth_service, user_uuid, session_token) = service_with_signed_in_user();
+
+        let result = auth_service.change_username(change_username_request(&session_token, "renamed", "654321")).await.unwrap();
+        assert_eq!(result.into_inner().username, "renamed");
+
+        let request = tonic::Request::new(SignInRequest {
+            username: "renamed".to_owned(),
+            password: "654321".to_owned(),
+        });
+        assert_eq!(auth_service.sign_in(request).await.unwrap().into_inner().user_uuid, user_uuid);
+
+        // The sign-in after the rename is audited too.
+        let events = auth_service.audit_service.lock().unwrap().events();
+        assert_eq!(events[events.len() - 2].action, AuditAction::ChangeUsername);
+        assert_eq!(events[events.len() - 2].outcome, AuditOutcome::Success);
+        assert_eq!(events[events.len() - 1].action, AuditAction::SignIn);
+    }
+
+    #[tokio::test]
+    async fn change_username_should_fail_if_incorrect_password() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        let status = auth_service.change_username(change_username_request(&session_token, "renamed", "wrong")).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCredentials));
+
+        assert!(auth_service.users_service.lock().unwrap().get_user_by_username("123456").is_some());
+    }
+
+    #[tokio::test]
+    async fn change_username_should_reserve_old_username() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+        let auth_service = auth_service.with_username_reservation_period(Duration::from_secs(60));
+
+        auth_service.change_username(change_username_request(&session_token, "renamed", "654321")).await.unwrap();
+
+        let request = tonic::Request::new(SignUpRequest {
+            username: "123456".to_owned(),
+            password: "password".to_owned(),
+        });
+        let status = auth_service.sign_up(request).await.unwrap_err();
+        assert_eq!(error_reason(&status), Some(ErrorReason::UsernameTaken));
+    }
+
+    #[tokio::test]
+    async fn change_username_should_release_old_username_without_reservation() {
+        let (auth_service, _, session_token) = service_with_signed_in_user();
+
+        auth_service.change_username(change_username_request(&session_token, "renamed", "654321")).await.unwrap();
 
-        assert_eq!(result.into_inner().status_code, StatusCode::Success.into());
+        let request = tonic::Request::new(SignUpRequest {
+            username: "123456".to_owned(),
+            password: "password".to_owned(),
+        });
+        assert!(auth_service.sign_up(request).await.is_ok());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,398 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    rbac::{Permission, Role},
+    users::{AccountStatus, Profile, User, Users},
+};
+
+/// One user as a line of a JSON Lines import or export.
+///
+/// Only the account, its roles and its profile are carried over. MFA secrets and passkeys are
+/// bound to the service that issued them, so users set them up again after a migration.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserRecord {
+    /// The UUID of the user. A new one is generated when it is left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_uuid: Option<String>,
+
+    /// The username of the user.
+    username: String,
+
+    /// The PBKDF2 hash of the user's password in PHC string format.
+    password_hash: String,
+
+    /// The names of the roles granted to the user.
+    #[serde(default)]
+    roles: Vec<String>,
+
+    /// The status of the account, e.g. `active`. Accounts are active when it is left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+
+    /// Whether the user must reset their password before signing in.
+    #[serde(default)]
+    password_reset_required: bool,
+
+    /// The user's profile.
+    #[serde(default)]
+    profile: ProfileRecord,
+}
+
+/// The profile of a `UserRecord`.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileRecord {
+    /// The name shown for the user instead of their username.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+
+    /// The user's email address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+
+    /// The user's preferred locale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locale: Option<String>,
+
+    /// Sorted so that exports of the same users are identical.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+}
+
+/// `RejectedRecord` struct describes a line of an import that was not imported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RejectedRecord {
+    /// The number of the line, starting at 1.
+    pub line: usize,
+
+    /// The username on the line, or an empty string if the line could not be read.
+    pub username: String,
+
+    /// Why the line was rejected.
+    pub reason: String,
+}
+
+/// `ImportReport` struct summarises an import.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// The UUIDs of the users that were imported, or that would be imported in a dry run.
+    pub imported: Vec<String>,
+
+    /// The lines that were rejected.
+    pub rejected: Vec<RejectedRecord>,
+}
+
+/// Returns the name of an account status in import and export files.
+///
+/// # Arguments
+///
+/// * `status` - The account status.
+///
+/// # Returns
+///
+/// The name of the status, e.g. `pending_verification`.
+fn status_name(status: AccountStatus) -> &'static str {
+    match status {
+        AccountStatus::Active => "active",
+        AccountStatus::Disabled => "disabled",
+        AccountStatus::PendingVerification => "pending_verification",
+        AccountStatus::Locked => "locked",
+    }
+}
+
+/// Parses the name of an account status in an import file.
+///
+/// # Arguments
+///
+/// * `name` - The name of the status.
+///
+/// # Returns
+///
+/// The matching `AccountStatus`, or `None` if the name is unknown.
+fn parse_status(name: &str) -> Option<AccountStatus> {
+    match name {
+        "active" => Some(AccountStatus::Active),
+        "disabled" => Some(AccountStatus::Disabled),
+        "pending_verification" => Some(AccountStatus::PendingVerification),
+        "locked" => Some(AccountStatus::Locked),
+        _ => None,
+    }
+}
+
+/// Converts an imported record into a user.
+///
+/// # Arguments
+///
+/// * `record` - The record read from the import.
+///
+/// # Returns
+///
+/// An `Ok` result containing the user, otherwise a description of the problem with the record.
+fn record_user(record: UserRecord) -> Result<User, String> {
+    if record.username.is_empty() {
+        return Err("username must not be empty".to_owned());
+    }
+
+    let user_uuid = match record.user_uuid {
+        Some(user_uuid) => Uuid::parse_str(&user_uuid)
+            .map_err(|_| format!("user_uuid {} is not a UUID", user_uuid))?
+            .to_string(),
+        None => Uuid::new_v4().to_string(),
+    };
+
+    let roles = record.roles
+        .iter()
+        .map(|role| role.parse::<Role>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<Role>, String>>()?;
+
+    let status = match record.status {
+        Some(status) => parse_status(&status).ok_or_else(|| format!("unknown status {}", status))?,
+        None => AccountStatus::Active,
+    };
+
+    Ok(User {
+        user_uuid,
+        username: record.username,
+        password: record.password_hash,
+        totp_secret: None,
+        pending_totp_secret: None,
+        totp_last_used_step: None,
+        recovery_code_hashes: Vec::new(),
+        passkeys: Vec::new(),
+        roles,
+        status,
+        password_reset_required: record.password_reset_required,
+        deleted_at: None,
+        profile: Profile {
+            display_name: record.profile.display_name,
+            email: record.profile.email,
+            locale: record.profile.locale,
+            metadata: record.profile.metadata.into_iter().collect(),
+        },
+    })
+}
+
+/// Imports users from JSON Lines, one user per line.
+///
+/// Every line is checked on its own, so a bad line is reported and skipped without affecting the
+/// others. A line is rejected if it cannot be parsed, if its password hash or profile is invalid,
+/// if it grants roles and `allow_roles` is `false`, if its username or UUID is already used in the
+/// store, or if an earlier line of the import used the same username or UUID.
+///
+/// # Arguments
+///
+/// * `users_service` - The users store to import into.
+/// * `data` - The JSON Lines to import. Blank lines are ignored.
+/// * `dry_run` - If `true`, every line is checked but nothing is stored.
+/// * `allow_roles` - Whether lines may grant roles. Callers that cannot grant roles themselves must
+///   not be able to import them either.
+///
+/// # Returns
+///
+/// An `ImportReport` listing the users imported and the lines rejected.
+///
+/// # Example
+///
+/// ```
+/// // Assuming `users_service` implements `Users` trait
+/// let data = r#"{"username":"jane","password_hash":"$pbkdf2-sha256$i=600000,l=32$..."}"#;
+/// let report = bulk::import_users(&mut users_service, data, true, false);
+/// println!("{} users would be imported", report.imported.len());
+/// ```
+pub fn import_users(users_service: &mut dyn Users, data: &str, dry_run: bool, allow_roles: bool) -> ImportReport {
+    let mut report = ImportReport::default();
+    let mut seen_usernames = HashSet::new();
+    let mut seen_uuids = HashSet::new();
+
+    for (index, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: UserRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                report.rejected.push(RejectedRecord { line: index + 1, username: String::new(), reason: format!("invalid JSON: {}", e) });
+                continue;
+            }
+        };
+
+        let username = record.username.clone();
+
+        let result = record_user(record).and_then(|user| {
+            if !allow_roles && !user.roles.is_empty() {
+                return Err(format!("granting roles requires the {} permission", Permission::ManageRoles));
+            }
+
+            if !seen_usernames.insert(user.username.clone()) {
+                return Err("duplicate username in import".to_owned());
+            }
+
+            if !seen_uuids.insert(user.user_uuid.clone()) {
+                return Err("duplicate user_uuid in import".to_owned());
+            }
+
+            let user_uuid = user.user_uuid.clone();
+
+            match dry_run {
+                true => users_service.validate_import(&user),
+                false => users_service.import_user(user),
+            }
+            .map(|_| user_uuid)
+            .map_err(|e| e.to_string())
+        });
+
+        match result {
+            Ok(user_uuid) => report.imported.push(user_uuid),
+            Err(reason) => report.rejected.push(RejectedRecord { line: index + 1, username, reason }),
+        }
+    }
+
+    report
+}
+
+/// Exports users as JSON Lines, one user per line, sorted by username.
+///
+/// The output can be read back with `import_users`.
+///
+/// # Arguments
+///
+/// * `users` - The users to export.
+///
+/// # Returns
+///
+/// The JSON Lines, each ending with a newline.
+///
+/// # Example
+///
+/// ```
+/// // Assuming `users_service` implements `Users` trait
+/// let data = bulk::export_users(users_service.list_users());
+/// std::fs::write("users.jsonl", data)?;
+/// ```
+pub fn export_users(mut users: Vec<User>) -> String {
+    users.sort_by(|a, b| a.username.cmp(&b.username));
+
+    users
+        .into_iter()
+        .map(|user| {
+            let record = UserRecord {
+                user_uuid: Some(user.user_uuid),
+                username: user.username,
+                password_hash: user.password,
+                roles: user.roles.iter().map(|role| role.as_str().to_owned()).collect(),
+                status: Some(status_name(user.status).to_owned()),
+                password_reset_required: user.password_reset_required,
+                profile: ProfileRecord {
+                    display_name: user.profile.display_name,
+                    email: user.profile.email,
+                    locale: user.profile.locale,
+                    metadata: user.profile.metadata.into_iter().collect(),
+                },
+            };
+
+            // Serializing a struct of strings and booleans cannot fail.
+            serde_json::to_string(&record).expect("user record should serialize") + "\n"
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::users::{UsersImpl, MIN_PBKDF2_ROUNDS};
+
+    use super::*;
+
+    /// Creates a store holding one user with a known password and returns it with its export.
+    fn exported_store() -> (UsersImpl, String) {
+        let mut users_service = UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS);
+        users_service.create_user("jane".to_owned(), "password".to_owned()).unwrap();
+
+        let mut user = users_service.get_user_by_username("jane").unwrap();
+        user.roles.push(Role::Auditor);
+        user.status = AccountStatus::Locked;
+        user.profile.email = Some("jane@example.com".to_owned());
+        user.profile.metadata.insert("team".to_owned(), "platform".to_owned());
+        users_service.update_user(user).unwrap();
+
+        let data = export_users(users_service.list_users());
+
+        (users_service, data)
+    }
+
+    #[test]
+    fn should_round_trip_users() {
+        let (source, data) = exported_store();
+        let original = source.get_user_by_username("jane").unwrap();
+
+        let mut users_service = UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS);
+        let report = import_users(&mut users_service, &data, false, true);
+
+        assert_eq!(report.imported, vec![original.user_uuid.clone()]);
+        assert!(report.rejected.is_empty());
+
+        let imported = users_service.get_user(&original.user_uuid).unwrap();
+        assert_eq!(imported.roles, vec![Role::Auditor]);
+        assert_eq!(imported.status, AccountStatus::Locked);
+        assert_eq!(imported.profile, original.profile);
+        assert_eq!(
+            users_service.get_user_uuid("jane".to_owned(), "password".to_owned()),
+            Some(original.user_uuid)
+        );
+    }
+
+    #[test]
+    fn should_not_store_users_in_dry_run() {
+        let (_, data) = exported_store();
+
+        let mut users_service = UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS);
+        let report = import_users(&mut users_service, &data, true, true);
+
+        assert_eq!(report.imported.len(), 1);
+        assert!(users_service.list_users().is_empty());
+    }
+
+    #[test]
+    fn should_report_rejected_lines() {
+        let (mut users_service, data) = exported_store();
+        let hash = users_service.get_user_by_username("jane").unwrap().password;
+
+        let data = [
+            data.trim_end().to_owned(),
+            "not json".to_owned(),
+            format!(r#"{{"username":"bob","password_hash":"{}","roles":["owner"]}}"#, hash),
+            r#"{"username":"carol","password_hash":"plaintext"}"#.to_owned(),
+            String::new(),
+            format!(r#"{{"username":"dave","password_hash":"{}"}}"#, hash),
+            format!(r#"{{"username":"dave","password_hash":"{}"}}"#, hash),
+        ]
+        .join("\n");
+
+        let report = import_users(&mut users_service, &data, false, true);
+
+        let lines: Vec<usize> = report.rejected.iter().map(|rejected| rejected.line).collect();
+        assert_eq!(lines, vec![1, 2, 3, 4, 7]);
+        assert_eq!(report.rejected[0].reason, "Username already exists.");
+        assert_eq!(report.rejected[1].username, "");
+        assert_eq!(report.rejected[4].reason, "duplicate username in import");
+
+        assert_eq!(report.imported.len(), 1);
+        assert!(users_service.get_user_by_username("dave").is_some());
+    }
+
+    #[test]
+    fn should_reject_roles_unless_allowed() {
+        let (_, data) = exported_store();
+        let mut users_service = UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS);
+
+        let report = import_users(&mut users_service, &data, false, false);
+
+        assert!(report.imported.is_empty());
+        assert_eq!(report.rejected[0].reason, "granting roles requires the roles:manage permission");
+        assert!(users_service.list_users().is_empty());
+    }
+}
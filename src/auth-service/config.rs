@@ -0,0 +1,1155 @@
+use clap::Parser;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use std::{
+    fmt, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    auth::DEFAULT_DELETION_GRACE_PERIOD,
+    mfa::SecretCipher,
+    ratelimit::RateLimits,
+    sessions::{Ttls, MFA_CHALLENGE_TTL, PASSWORD_RESET_TTL, WEBAUTHN_CHALLENGE_TTL},
+    users::{MAX_PBKDF2_ROUNDS, MIN_PBKDF2_ROUNDS},
+    webauthn::WebauthnConfig,
+};
+
+/// What the `Debug` output of the configuration shows in place of a secret.
+const REDACTED: &str = "[REDACTED]";
+
+/// `ConfigError` enumerates the ways loading the configuration can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The configuration file could not be read. Carries the path and the cause.
+    Read(PathBuf, String),
+
+    /// The configuration file is not valid TOML or does not match the expected layout. Carries the
+    /// path and the cause.
+    Parse(PathBuf, String),
+
+    /// One or more settings are invalid. Carries a description of every problem found.
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, cause) => write!(f, "Could not read {}: {}", path.display(), cause),
+            ConfigError::Parse(path, cause) => write!(f, "Could not parse {}: {}", path.display(), cause),
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// `Args` struct holds the command-line flags of the authentication service. Flags take
+/// precedence over environment variables, which take precedence over the configuration file.
+#[derive(Parser, Debug, Default)]
+#[command(author, version, about = "The authentication service", long_about = None)]
+pub struct Args {
+    /// Path to a TOML configuration file. Defaults to AUTH_CONFIG if set.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// The address to listen on, e.g. [::0]:50051
+    #[arg(long)]
+    pub bind_address: Option<String>,
+
+    /// Where users and sessions are stored. Only `memory` is available.
+    #[arg(long)]
+    pub storage_backend: Option<String>,
+
+    /// How long a session lasts, in seconds
+    #[arg(long)]
+    pub session_ttl_seconds: Option<u64>,
+
+    /// How long an MFA challenge lasts, in seconds
+    #[arg(long)]
+    pub mfa_challenge_ttl_seconds: Option<u64>,
+
+    /// How long a WebAuthn challenge lasts, in seconds
+    #[arg(long)]
+    pub webauthn_challenge_ttl_seconds: Option<u64>,
+
+    /// How long a password reset token lasts, in seconds
+    #[arg(long)]
+    pub password_reset_ttl_seconds: Option<u64>,
+
+    /// How long a deleted account can be restored before it is purged, in seconds
+    #[arg(long)]
+    pub deletion_grace_period_seconds: Option<u64>,
+
+    /// How long a former username stays reserved after a rename, in seconds
+    #[arg(long)]
+    pub username_reservation_seconds: Option<u64>,
+
+    /// The number of PBKDF2 rounds new passwords are hashed with
+    #[arg(long)]
+    pub pbkdf2_rounds: Option<u32>,
+
+    /// How many failed sign-ins a username may receive from one client address per minute. 0
+    /// disables the limit.
+    #[arg(long)]
+    pub sign_in_per_minute: Option<u32>,
+
+    /// How many sign-ups a client address may send per minute. 0 disables the limit.
+    #[arg(long)]
+    pub sign_up_per_minute: Option<u32>,
+
+    /// Path to the PEM encoded TLS certificate chain
+    #[arg(long)]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Path to the PEM encoded TLS private key
+    #[arg(long)]
+    pub tls_key: Option<PathBuf>,
+
+    /// Path to the PEM encoded CA certificates that client certificates must chain to. Enables
+    /// mutual TLS.
+    #[arg(long)]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// How log lines are written: `text` or `json`
+    #[arg(long)]
+    pub log_format: Option<String>,
+
+    /// The address to serve Prometheus metrics on, e.g. [::0]:9090
+    #[arg(long)]
+    pub metrics_bind_address: Option<String>,
+
+    /// The OTLP gRPC endpoint to export traces to, e.g. http://localhost:4317. Enables tracing.
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Path to the hash-chained audit log file, which is created if missing
+    #[arg(long)]
+    pub audit_file: Option<PathBuf>,
+
+    /// The username of the administrator created at startup if missing
+    #[arg(long)]
+    pub bootstrap_admin_username: Option<String>,
+    // The bootstrap password and the encryption key have no flags, since other local users can
+    // read process arguments.
+}
+
+/// `StorageBackend` enumerates where users and sessions can be stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// In process memory. Everything is lost when the service stops.
+    #[default]
+    Memory,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err(format!("unknown storage backend {}, expected memory", s)),
+        }
+    }
+}
+
+/// `LogFormat` enumerates how log lines are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// `ServerConfig` struct holds where the service listens and how it stops.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address to listen on. All interfaces on port 50051, the recommended gRPC port, by
+    /// default so the service is reachable from outside its Docker container.
+    pub bind_address: SocketAddr,
+
+    /// How long requests in flight may take to finish once the service is asked to stop. Kept
+    /// below the 10 seconds `docker stop` waits before killing the service.
+    pub shutdown_timeout_seconds: u64,
+
+    /// Whether to serve gRPC reflection, which describes every RPC to anyone who can connect.
+    pub reflection: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0u16; 8], 50051)),
+            shutdown_timeout_seconds: 8,
+            reflection: false,
+        }
+    }
+}
+
+/// `StorageConfig` struct holds which storage backend is used.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// The backend holding users and sessions.
+    pub backend: StorageBackend,
+}
+
+/// `TtlConfig` struct holds how long tokens and deleted accounts last, in seconds.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtlConfig {
+    /// How long a session lasts. Unset keeps sessions until the user signs out.
+    pub session_seconds: Option<u64>,
+
+    /// How long an MFA challenge lasts.
+    pub mfa_challenge_seconds: u64,
+
+    /// How long a WebAuthn challenge lasts.
+    pub webauthn_challenge_seconds: u64,
+
+    /// How long a password reset token lasts.
+    pub password_reset_seconds: u64,
+
+    /// How long a deleted account can be restored before it is purged.
+    pub deletion_grace_period_seconds: u64,
+
+    /// How long a former username stays reserved after a rename. Zero releases it straight away.
+    pub username_reservation_seconds: u64,
+}
+
+impl Default for TtlConfig {
+    fn default() -> Self {
+        Self {
+            session_seconds: None,
+            mfa_challenge_seconds: MFA_CHALLENGE_TTL.as_secs(),
+            webauthn_challenge_seconds: WEBAUTHN_CHALLENGE_TTL.as_secs(),
+            password_reset_seconds: PASSWORD_RESET_TTL.as_secs(),
+            deletion_grace_period_seconds: DEFAULT_DELETION_GRACE_PERIOD.as_secs(),
+            username_reservation_seconds: 0,
+        }
+    }
+}
+
+/// `HashingConfig` struct holds the password hashing parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HashingConfig {
+    /// The number of PBKDF2-SHA256 rounds new password hashes use. Existing hashes keep theirs.
+    pub pbkdf2_rounds: u32,
+}
+
+impl Default for HashingConfig {
+    fn default() -> Self {
+        Self {
+            pbkdf2_rounds: pbkdf2::Params::RECOMMENDED_ROUNDS as u32,
+        }
+    }
+}
+
+/// `TlsConfig` struct holds the server's TLS identity and how clients are verified. TLS is off
+/// unless both `cert_path` and `key_path` are set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain.
+    pub cert_path: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key.
+    pub key_path: Option<PathBuf>,
+
+    /// Path to the PEM encoded CA certificates that client certificates must chain to. When set,
+    /// clients must present a certificate signed by one of them.
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// `RateLimitConfig` struct holds the request allowances of the rate limited RPCs. Zero disables
+/// a limit.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// How many failed `SignIn` attempts a username may receive from one client address per minute,
+    /// and how many failed `VerifyMfa` attempts a user may make from any address.
+    pub sign_in_per_minute: u32,
+
+    /// How many `SignUp` requests a client address may send per minute.
+    pub sign_up_per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            sign_in_per_minute: 10,
+            sign_up_per_minute: 60,
+        }
+    }
+}
+
+/// `MetricsConfig` struct holds where Prometheus metrics are served.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Whether to serve metrics.
+    pub enabled: bool,
+
+    /// The address to serve metrics on, over plain HTTP at `/metrics`.
+    pub bind_address: SocketAddr,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind_address: SocketAddr::from(([0u16; 8], 9090)),
+        }
+    }
+}
+
+/// `TracingConfig` struct holds where the spans of RPCs and storage calls are exported.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingConfig {
+    /// Whether to export spans.
+    pub enabled: bool,
+
+    /// The OTLP gRPC endpoint of the collector spans are exported to.
+    pub otlp_endpoint: String,
+
+    /// The `service.name` spans are exported under.
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            otlp_endpoint: "http://localhost:4317".to_owned(),
+            service_name: "auth".to_owned(),
+        }
+    }
+}
+
+/// `AuditConfig` struct holds where audit events are written besides the log.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Path to the file audit events are appended to, one hash-chained JSON object per line. Unset
+    /// writes audit events to the log only.
+    pub file_path: Option<PathBuf>,
+}
+
+/// `BootstrapConfig` struct holds the administrator created at startup, so that roles can be
+/// granted through the API from then on.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BootstrapConfig {
+    /// The username of the administrator. The account is created if missing and granted the
+    /// `admin` role.
+    pub admin_username: Option<String>,
+
+    /// The password the account is created with. An existing account keeps its password.
+    pub admin_password: Option<String>,
+}
+
+// Written by hand so the password never reaches logs or failed assertions.
+impl fmt::Debug for BootstrapConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BootstrapConfig")
+            .field("admin_username", &self.admin_username)
+            .field("admin_password", &self.admin_password.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+/// `EncryptionConfig` struct holds the key secrets are encrypted with at rest.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// The base64 encoded 32-byte key TOTP secrets are encrypted with. Unset uses a random key, so
+    /// secrets cannot be read after a restart.
+    pub key: Option<String>,
+}
+
+// Written by hand so the key never reaches logs or failed assertions.
+impl fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionConfig")
+            .field("key", &self.key.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+/// `LoggingConfig` struct holds how and what the service logs.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// How log lines are written.
+    pub format: LogFormat,
+
+    /// Which log lines are written, as a `tracing` filter such as `info` or
+    /// `info,auth=debug`. Requests are logged, redacted, at `debug`.
+    pub level: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: "info".to_owned(),
+        }
+    }
+}
+
+/// `Config` struct holds the settings of the authentication service.
+///
+/// Settings are read from an optional TOML file, then overridden by `AUTH_*` environment variables
+/// and finally by command-line flags.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where the service listens.
+    pub server: ServerConfig,
+
+    /// Where users and sessions are stored.
+    pub storage: StorageConfig,
+
+    /// How long tokens and deleted accounts last.
+    pub ttl: TtlConfig,
+
+    /// The password hashing parameters.
+    pub hashing: HashingConfig,
+
+    /// The server's TLS identity.
+    pub tls: TlsConfig,
+
+    /// The request allowances of the rate limited RPCs.
+    pub rate_limits: RateLimitConfig,
+
+    /// The relying party passkeys are registered with.
+    pub webauthn: WebauthnConfig,
+
+    /// How and what the service logs.
+    pub logging: LoggingConfig,
+
+    /// Where Prometheus metrics are served.
+    pub metrics: MetricsConfig,
+
+    /// Where traces are exported.
+    pub tracing: TracingConfig,
+
+    /// Where audit events are written.
+    pub audit: AuditConfig,
+
+    /// The administrator created at startup.
+    pub bootstrap: BootstrapConfig,
+
+    /// The key secrets are encrypted with.
+    pub encryption: EncryptionConfig,
+}
+
+/// Overrides a setting with an environment variable, if it is set.
+///
+/// # Arguments
+///
+/// * `lookup` - Returns the value of an environment variable.
+/// * `name` - The name of the variable.
+/// * `target` - The setting to override.
+/// * `problems` - Collects a description of the problem if the value cannot be parsed.
+fn override_from<T: FromStr>(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+    problems: &mut Vec<String>,
+) {
+    if let Some(value) = lookup(name) {
+        match value.parse() {
+            Ok(value) => *target = value,
+            Err(_) => problems.push(format!("{} has an invalid value: {}", name, value)),
+        }
+    }
+}
+
+impl Config {
+
+    /// Loads the configuration from the file, environment variables and command-line flags.
+    ///
+    /// The file is taken from `--config`, or `AUTH_CONFIG` if the flag is not given. Without
+    /// either the defaults are used. Every problem with the resulting settings is reported at
+    /// once, so they can all be fixed before the next start.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The parsed command-line flags.
+    /// * `lookup` - Returns the value of an environment variable, e.g. `|name| env::var(name).ok()`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the configuration, otherwise a `ConfigError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let config = Config::load(&Args::parse(), &|name| env::var(name).ok())?;
+    /// println!("Listening on {}", config.server.bind_address);
+    /// ```
+    pub fn load(args: &Args, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let path = args.config.clone().or_else(|| lookup("AUTH_CONFIG").map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+
+        let mut problems = Vec::new();
+
+        config.apply_env(lookup, &mut problems);
+        config.apply_args(args, &mut problems);
+        problems.extend(config.validate());
+
+        match problems.is_empty() {
+            true => Ok(config),
+            false => Err(ConfigError::Invalid(problems)),
+        }
+    }
+
+    /// Reads the configuration from a TOML file. Settings left out of the file keep their defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the configuration, otherwise a `ConfigError`.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Read(path.to_owned(), e.to_string()))?;
+
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_owned(), e.to_string()))
+    }
+
+    /// Overrides settings with the `AUTH_*` environment variables that are set.
+    ///
+    /// # Arguments
+    ///
+    /// * `lookup` - Returns the value of an environment variable.
+    /// * `problems` - Collects a description of every value that cannot be parsed.
+    fn apply_env(&mut self, lookup: &dyn Fn(&str) -> Option<String>, problems: &mut Vec<String>) {
+        override_from(lookup, "AUTH_BIND_ADDRESS", &mut self.server.bind_address, problems);
+        override_from(lookup, "AUTH_SHUTDOWN_TIMEOUT_SECONDS", &mut self.server.shutdown_timeout_seconds, problems);
+        override_from(lookup, "AUTH_REFLECTION", &mut self.server.reflection, problems);
+        override_from(lookup, "AUTH_STORAGE_BACKEND", &mut self.storage.backend, problems);
+
+        if let Some(value) = lookup("AUTH_SESSION_TTL_SECONDS") {
+            match value.parse() {
+                Ok(seconds) => self.ttl.session_seconds = Some(seconds),
+                Err(_) => problems.push(format!("AUTH_SESSION_TTL_SECONDS has an invalid value: {}", value)),
+            }
+        }
+
+        override_from(lookup, "AUTH_MFA_CHALLENGE_TTL_SECONDS", &mut self.ttl.mfa_challenge_seconds, problems);
+        override_from(lookup, "AUTH_WEBAUTHN_CHALLENGE_TTL_SECONDS", &mut self.ttl.webauthn_challenge_seconds, problems);
+        override_from(lookup, "AUTH_PASSWORD_RESET_TTL_SECONDS", &mut self.ttl.password_reset_seconds, problems);
+        override_from(lookup, "AUTH_DELETION_GRACE_PERIOD_SECONDS", &mut self.ttl.deletion_grace_period_seconds, problems);
+        override_from(lookup, "AUTH_USERNAME_RESERVATION_SECONDS", &mut self.ttl.username_reservation_seconds, problems);
+        override_from(lookup, "AUTH_PBKDF2_ROUNDS", &mut self.hashing.pbkdf2_rounds, problems);
+
+        if let Some(value) = lookup("AUTH_TLS_CERT_PATH") {
+            self.tls.cert_path = Some(PathBuf::from(value));
+        }
+
+        if let Some(value) = lookup("AUTH_TLS_KEY_PATH") {
+            self.tls.key_path = Some(PathBuf::from(value));
+        }
+
+        if let Some(value) = lookup("AUTH_TLS_CLIENT_CA_PATH") {
+            self.tls.client_ca_path = Some(PathBuf::from(value));
+        }
+
+        override_from(lookup, "AUTH_SIGN_IN_RATE_LIMIT", &mut self.rate_limits.sign_in_per_minute, problems);
+        override_from(lookup, "AUTH_SIGN_UP_RATE_LIMIT", &mut self.rate_limits.sign_up_per_minute, problems);
+        override_from(lookup, "AUTH_WEBAUTHN_RP_ID", &mut self.webauthn.rp_id, problems);
+        override_from(lookup, "AUTH_WEBAUTHN_RP_NAME", &mut self.webauthn.rp_name, problems);
+        override_from(lookup, "AUTH_WEBAUTHN_ORIGIN", &mut self.webauthn.origin, problems);
+        override_from(lookup, "AUTH_LOG_FORMAT", &mut self.logging.format, problems);
+        override_from(lookup, "AUTH_LOG_LEVEL", &mut self.logging.level, problems);
+        override_from(lookup, "AUTH_METRICS_ENABLED", &mut self.metrics.enabled, problems);
+        override_from(lookup, "AUTH_METRICS_BIND_ADDRESS", &mut self.metrics.bind_address, problems);
+        override_from(lookup, "AUTH_TRACING_ENABLED", &mut self.tracing.enabled, problems);
+        override_from(lookup, "AUTH_OTLP_ENDPOINT", &mut self.tracing.otlp_endpoint, problems);
+        override_from(lookup, "AUTH_TRACING_SERVICE_NAME", &mut self.tracing.service_name, problems);
+
+        if let Some(value) = lookup("AUTH_AUDIT_FILE_PATH") {
+            self.audit.file_path = Some(PathBuf::from(value));
+        }
+
+        if let Some(value) = lookup("AUTH_BOOTSTRAP_ADMIN_USERNAME") {
+            self.bootstrap.admin_username = Some(value);
+        }
+
+        if let Some(value) = lookup("AUTH_BOOTSTRAP_ADMIN_PASSWORD") {
+            self.bootstrap.admin_password = Some(value);
+        }
+
+        if let Some(value) = lookup("AUTH_ENCRYPTION_KEY") {
+            self.encryption.key = Some(value);
+        }
+    }
+
+    /// Overrides settings with the command-line flags that are given.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The parsed command-line flags.
+    /// * `problems` - Collects a description of every value that cannot be parsed.
+    fn apply_args(&mut self, args: &Args, problems: &mut Vec<String>) {
+        if let Some(value) = &args.bind_address {
+            match value.parse() {
+                Ok(bind_address) => self.server.bind_address = bind_address,
+                Err(_) => problems.push(format!("--bind-address has an invalid value: {}", value)),
+            }
+        }
+
+        if let Some(value) = &args.storage_backend {
+            match value.parse() {
+                Ok(backend) => self.storage.backend = backend,
+                Err(e) => problems.push(format!("--storage-backend: {}", e)),
+            }
+        }
+
+        if let Some(seconds) = args.session_ttl_seconds {
+            self.ttl.session_seconds = Some(seconds);
+        }
+
+        for (value, setting) in [
+            (args.mfa_challenge_ttl_seconds, &mut self.ttl.mfa_challenge_seconds),
+            (args.webauthn_challenge_ttl_seconds, &mut self.ttl.webauthn_challenge_seconds),
+            (args.password_reset_ttl_seconds, &mut self.ttl.password_reset_seconds),
+            (args.deletion_grace_period_seconds, &mut self.ttl.deletion_grace_period_seconds),
+            (args.username_reservation_seconds, &mut self.ttl.username_reservation_seconds),
+        ] {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+
+        for (value, setting) in [
+            (args.pbkdf2_rounds, &mut self.hashing.pbkdf2_rounds),
+            (args.sign_in_per_minute, &mut self.rate_limits.sign_in_per_minute),
+            (args.sign_up_per_minute, &mut self.rate_limits.sign_up_per_minute),
+        ] {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+
+        if let Some(path) = &args.tls_cert {
+            self.tls.cert_path = Some(path.clone());
+        }
+
+        if let Some(path) = &args.tls_key {
+            self.tls.key_path = Some(path.clone());
+        }
+
+        if let Some(path) = &args.tls_client_ca {
+            self.tls.client_ca_path = Some(path.clone());
+        }
+
+        if let Some(value) = &args.metrics_bind_address {
+            match value.parse() {
+                Ok(bind_address) => self.metrics.bind_address = bind_address,
+                Err(_) => problems.push(format!("--metrics-bind-address has an invalid value: {}", value)),
+            }
+        }
+
+        if let Some(value) = &args.otlp_endpoint {
+            self.tracing.enabled = true;
+            self.tracing.otlp_endpoint = value.clone();
+        }
+
+        if let Some(path) = &args.audit_file {
+            self.audit.file_path = Some(path.clone());
+        }
+
+        if let Some(value) = &args.bootstrap_admin_username {
+            self.bootstrap.admin_username = Some(value.clone());
+        }
+
+        if let Some(value) = &args.log_format {
+            match value.parse() {
+                Ok(format) => self.logging.format = format,
+                Err(e) => problems.push(format!("--log-format: {}", e)),
+            }
+        }
+    }
+
+    /// Checks the settings for values the service cannot run with.
+    ///
+    /// # Returns
+    ///
+    /// A description of every problem found, empty if the configuration is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.ttl.session_seconds == Some(0) {
+            problems.push("ttl.session_seconds must be greater than 0; leave it unset to keep sessions until sign-out".to_owned());
+        }
+
+        for (name, seconds) in [
+            ("ttl.mfa_challenge_seconds", self.ttl.mfa_challenge_seconds),
+            ("ttl.webauthn_challenge_seconds", self.ttl.webauthn_challenge_seconds),
+            ("ttl.password_reset_seconds", self.ttl.password_reset_seconds),
+        ] {
+            if seconds == 0 {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        // More rounds would make every sign-in a denial of service, and produce hashes that
+        // `ImportUsers` rejects.
+        if !(MIN_PBKDF2_ROUNDS..=MAX_PBKDF2_ROUNDS).contains(&self.hashing.pbkdf2_rounds) {
+            problems.push(format!("hashing.pbkdf2_rounds must be between {} and {}", MIN_PBKDF2_ROUNDS, MAX_PBKDF2_ROUNDS));
+        }
+
+        if self.tls.cert_path.is_some() != self.tls.key_path.is_some() {
+            problems.push("tls.cert_path and tls.key_path must be set together".to_owned());
+        }
+
+        if self.tls.client_ca_path.is_some() && self.tls.cert_path.is_none() {
+            problems.push("tls.client_ca_path requires tls.cert_path and tls.key_path".to_owned());
+        }
+
+        for (name, path) in [
+            ("tls.cert_path", &self.tls.cert_path),
+            ("tls.key_path", &self.tls.key_path),
+            ("tls.client_ca_path", &self.tls.client_ca_path),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    problems.push(format!("{} {} is not a file", name, path.display()));
+                }
+            }
+        }
+
+        if self.webauthn.rp_id.is_empty() {
+            problems.push("webauthn.rp_id must not be empty".to_owned());
+        }
+
+        if !self.webauthn.origin.starts_with("https://") && !self.webauthn.origin.starts_with("http://") {
+            problems.push("webauthn.origin must be an http:// or https:// origin".to_owned());
+        }
+
+        if self.metrics.enabled && self.metrics.bind_address == self.server.bind_address {
+            problems.push("metrics.bind_address must differ from server.bind_address".to_owned());
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.logging.level) {
+            problems.push(format!("logging.level is not a valid filter: {}", e));
+        }
+
+        if self.tracing.enabled {
+            if !self.tracing.otlp_endpoint.starts_with("https://") && !self.tracing.otlp_endpoint.starts_with("http://") {
+                problems.push("tracing.otlp_endpoint must be an http:// or https:// URL".to_owned());
+            }
+
+            if self.tracing.service_name.is_empty() {
+                problems.push("tracing.service_name must not be empty".to_owned());
+            }
+        }
+
+        match (&self.bootstrap.admin_username, &self.bootstrap.admin_password) {
+            (Some(username), Some(_)) if username.is_empty() => {
+                problems.push("bootstrap.admin_username must not be empty".to_owned());
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                problems.push("bootstrap.admin_username and bootstrap.admin_password must be set together".to_owned());
+            }
+            _ => {}
+        }
+
+        // The key itself is left out of the problem, as it is a secret.
+        if let Some(key) = &self.encryption.key {
+            if SecretCipher::from_base64(key).is_none() {
+                problems.push("encryption.key must be a base64 encoded 32-byte key".to_owned());
+            }
+        }
+
+        problems
+    }
+
+    /// Returns the lifetimes of the tokens issued by the sessions store.
+    ///
+    /// # Returns
+    ///
+    /// The configured `Ttls`.
+    pub fn ttls(&self) -> Ttls {
+        Ttls {
+            session: self.ttl.session_seconds.map(Duration::from_secs),
+            mfa_challenge: Duration::from_secs(self.ttl.mfa_challenge_seconds),
+            webauthn_challenge: Duration::from_secs(self.ttl.webauthn_challenge_seconds),
+            password_reset: Duration::from_secs(self.ttl.password_reset_seconds),
+        }
+    }
+
+    /// Returns the request allowances of the rate limited RPCs.
+    ///
+    /// # Returns
+    ///
+    /// The configured `RateLimits`.
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            sign_in_per_minute: self.rate_limits.sign_in_per_minute,
+            sign_up_per_minute: self.rate_limits.sign_up_per_minute,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Returns a lookup over the given environment variables.
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    /// Writes a configuration file and returns its path.
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.toml", name, uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn should_use_defaults_without_file() {
+        let config = Config::load(&Args::default(), &env(&[])).unwrap();
+
+        assert_eq!(config, Config::default());
+        assert_eq!(config.server.bind_address.to_string(), "[::]:50051");
+        assert_eq!(config.hashing.pbkdf2_rounds, 600_000);
+        assert_eq!(config.ttls(), Ttls::default());
+    }
+
+    #[test]
+    fn should_layer_file_env_and_args() {
+        let path = write_config("layered", r#"
+[server]
+bind_address = "127.0.0.1:6000"
+
+[ttl]
+session_seconds = 3600
+mfa_challenge_seconds = 60
+
+[rate_limits]
+sign_in_per_minute = 5
+"#);
+
+        let args = Args {
+            config: Some(path.clone()),
+            bind_address: Some("127.0.0.1:7000".to_owned()),
+            ..Args::default()
+        };
+
+        let config = Config::load(&args, &env(&[
+            ("AUTH_BIND_ADDRESS", "127.0.0.1:6500"),
+            ("AUTH_MFA_CHALLENGE_TTL_SECONDS", "120"),
+            ("AUTH_WEBAUTHN_RP_ID", "example.com"),
+            ("AUTH_REFLECTION", "true"),
+        ])).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.server.bind_address.to_string(), "127.0.0.1:7000");
+        assert_eq!(config.ttls().session, Some(Duration::from_secs(3600)));
+        assert_eq!(config.ttls().mfa_challenge, Duration::from_secs(120));
+        assert_eq!(config.rate_limits().sign_in_per_minute, 5);
+        assert_eq!(config.rate_limits().sign_up_per_minute, 60);
+        assert_eq!(config.webauthn.rp_id, "example.com");
+        assert!(config.server.reflection);
+    }
+
+    #[test]
+    fn should_override_ttls_hashing_and_rate_limits_with_flags() {
+        let args = Args {
+            session_ttl_seconds: Some(900),
+            password_reset_ttl_seconds: Some(300),
+            username_reservation_seconds: Some(86_400),
+            pbkdf2_rounds: Some(200_000),
+            sign_in_per_minute: Some(3),
+            ..Args::default()
+        };
+
+        let config = Config::load(&args, &env(&[
+            ("AUTH_PASSWORD_RESET_TTL_SECONDS", "600"),
+            ("AUTH_PBKDF2_ROUNDS", "700000"),
+            ("AUTH_SIGN_UP_RATE_LIMIT", "30"),
+        ])).unwrap();
+
+        assert_eq!(config.ttls().session, Some(Duration::from_secs(900)));
+        assert_eq!(config.ttls().password_reset, Duration::from_secs(300));
+        assert_eq!(config.ttl.username_reservation_seconds, 86_400);
+        assert_eq!(config.hashing.pbkdf2_rounds, 200_000);
+        assert_eq!(config.rate_limits().sign_in_per_minute, 3);
+        assert_eq!(config.rate_limits().sign_up_per_minute, 30);
+    }
+
+    #[test]
+    fn should_take_file_from_env() {
+        let path = write_config("from-env", "[hashing]\npbkdf2_rounds = 700000\n");
+
+        let config = Config::load(&Args::default(), &env(&[("AUTH_CONFIG", path.to_str().unwrap())])).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.hashing.pbkdf2_rounds, 700_000);
+    }
+
+    #[test]
+    fn should_reject_unknown_settings() {
+        let path = write_config("unknown", "[server]\nport = 50051\n");
+
+        let args = Args { config: Some(path.clone()), ..Args::default() };
+        let result = Config::load(&args, &env(&[]));
+
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(ConfigError::Parse(_, cause)) if cause.contains("port")));
+    }
+
+    #[test]
+    fn should_reject_unknown_storage_backend() {
+        let path = write_config("backend", "[storage]\nbackend = \"postgres\"\n");
+
+        let args = Args { config: Some(path.clone()), ..Args::default() };
+        let result = Config::load(&args, &env(&[]));
+
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(ConfigError::Parse(_, _))));
+
+        let args = Args { storage_backend: Some("postgres".to_owned()), ..Args::default() };
+
+        assert_eq!(
+            Config::load(&args, &env(&[])),
+            Err(ConfigError::Invalid(vec!["--storage-backend: unknown storage backend postgres, expected memory".to_owned()]))
+        );
+    }
+
+    #[test]
+    fn should_reject_metrics_on_server_address() {
+        let lookup = env(&[("AUTH_BIND_ADDRESS", "127.0.0.1:9090"), ("AUTH_METRICS_BIND_ADDRESS", "127.0.0.1:9090")]);
+
+        assert_eq!(
+            Config::load(&Args::default(), &lookup),
+            Err(ConfigError::Invalid(vec!["metrics.bind_address must differ from server.bind_address".to_owned()]))
+        );
+
+        let args = Args { metrics_bind_address: Some("127.0.0.1:9091".to_owned()), ..Args::default() };
+        assert_eq!(Config::load(&args, &lookup).unwrap().metrics.bind_address.port(), 9091);
+
+        let lookup = env(&[
+            ("AUTH_BIND_ADDRESS", "127.0.0.1:9090"),
+            ("AUTH_METRICS_BIND_ADDRESS", "127.0.0.1:9090"),
+            ("AUTH_METRICS_ENABLED", "false"),
+        ]);
+        assert!(Config::load(&Args::default(), &lookup).is_ok());
+    }
+
+    #[test]
+    fn should_validate_logging() {
+        let args = Args { log_format: Some("json".to_owned()), ..Args::default() };
+        let config = Config::load(&args, &env(&[("AUTH_LOG_LEVEL", "warn,auth=debug")])).unwrap();
+
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(config.logging.level, "warn,auth=debug");
+
+        let result = Config::load(&Args::default(), &env(&[("AUTH_LOG_FORMAT", "xml"), ("AUTH_LOG_LEVEL", "auth=loud")]));
+
+        let Err(ConfigError::Invalid(problems)) = result else {
+            panic!("expected invalid configuration, got {:?}", result);
+        };
+
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0], "AUTH_LOG_FORMAT has an invalid value: xml");
+        assert!(problems[1].starts_with("logging.level is not a valid filter"));
+    }
+
+    #[test]
+    fn should_validate_tracing() {
+        let config = Config::load(&Args::default(), &env(&[("AUTH_OTLP_ENDPOINT", "localhost:4317")])).unwrap();
+        assert!(!config.tracing.enabled);
+
+        assert_eq!(
+            Config::load(&Args::default(), &env(&[("AUTH_TRACING_ENABLED", "true"), ("AUTH_OTLP_ENDPOINT", "localhost:4317")])),
+            Err(ConfigError::Invalid(vec!["tracing.otlp_endpoint must be an http:// or https:// URL".to_owned()]))
+        );
+
+        let args = Args { otlp_endpoint: Some("http://collector:4317".to_owned()), ..Args::default() };
+        let config = Config::load(&args, &env(&[("AUTH_TRACING_SERVICE_NAME", "auth-eu")])).unwrap();
+
+        assert!(config.tracing.enabled);
+        assert_eq!(config.tracing.otlp_endpoint, "http://collector:4317");
+        assert_eq!(config.tracing.service_name, "auth-eu");
+    }
+
+    #[test]
+    fn should_override_audit_file() {
+        assert_eq!(Config::load(&Args::default(), &env(&[])).unwrap().audit.file_path, None);
+
+        let lookup = env(&[("AUTH_AUDIT_FILE_PATH", "/var/log/auth/audit.log")]);
+        let config = Config::load(&Args::default(), &lookup).unwrap();
+        assert_eq!(config.audit.file_path, Some(PathBuf::from("/var/log/auth/audit.log")));
+
+        let args = Args { audit_file: Some(PathBuf::from("audit.log")), ..Args::default() };
+        assert_eq!(Config::load(&args, &lookup).unwrap().audit.file_path, Some(PathBuf::from("audit.log")));
+    }
+
+    #[test]
+    fn should_override_secrets() {
+        let path = write_config("secrets", "[bootstrap]\nadmin_username = \"root\"\nadmin_password = \"from file\"\n");
+        let key = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+        let args = Args {
+            config: Some(path.clone()),
+            bootstrap_admin_username: Some("admin".to_owned()),
+            ..Args::default()
+        };
+        let config = Config::load(&args, &env(&[
+            ("AUTH_BOOTSTRAP_ADMIN_PASSWORD", "from env"),
+            ("AUTH_ENCRYPTION_KEY", key),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.bootstrap.admin_username.as_deref(), Some("admin"));
+        assert_eq!(config.bootstrap.admin_password.as_deref(), Some("from env"));
+        assert_eq!(config.encryption.key.as_deref(), Some(key));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_redact_secrets_in_debug_output() {
+        let key = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        let config = Config::load(&Args::default(), &env(&[
+            ("AUTH_BOOTSTRAP_ADMIN_USERNAME", "root"),
+            ("AUTH_BOOTSTRAP_ADMIN_PASSWORD", "hunter2"),
+            ("AUTH_ENCRYPTION_KEY", key),
+        ]))
+        .unwrap();
+
+        let debug = format!("{:?}", config);
+
+        assert!(debug.contains(r#"BootstrapConfig { admin_username: Some("root"), admin_password: Some("[REDACTED]") }"#));
+        assert!(debug.contains(r#"EncryptionConfig { key: Some("[REDACTED]") }"#));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains(key));
+    }
+
+    #[test]
+    fn should_reject_too_many_pbkdf2_rounds() {
+        let result = Config::load(&Args::default(), &env(&[("AUTH_PBKDF2_ROUNDS", "4000000000")]));
+
+        assert_eq!(result, Err(ConfigError::Invalid(vec![
+            "hashing.pbkdf2_rounds must be between 100000 and 10000000".to_owned(),
+        ])));
+    }
+
+    #[test]
+    fn should_validate_secrets() {
+        let result = Config::load(&Args::default(), &env(&[
+            ("AUTH_BOOTSTRAP_ADMIN_USERNAME", "root"),
+            ("AUTH_ENCRYPTION_KEY", "dG9vIHNob3J0"),
+        ]));
+
+        assert_eq!(result, Err(ConfigError::Invalid(vec![
+            "bootstrap.admin_username and bootstrap.admin_password must be set together".to_owned(),
+            "encryption.key must be a base64 encoded 32-byte key".to_owned(),
+        ])));
+    }
+
+    #[test]
+    fn should_report_every_problem() {
+        let args = Args {
+            tls_cert: Some(PathBuf::from("/nonexistent/cert.pem")),
+            ..Args::default()
+        };
+
+        let result = Config::load(&args, &env(&[
+            ("AUTH_BIND_ADDRESS", "not an address"),
+            ("AUTH_SESSION_TTL_SECONDS", "0"),
+            ("AUTH_PBKDF2_ROUNDS", "1000"),
+        ]));
+
+        let Err(ConfigError::Invalid(problems)) = result else {
+            panic!("expected invalid configuration, got {:?}", result);
+        };
+
+        assert_eq!(problems, vec![
+            "AUTH_BIND_ADDRESS has an invalid value: not an address".to_owned(),
+            "ttl.session_seconds must be greater than 0; leave it unset to keep sessions until sign-out".to_owned(),
+            "hashing.pbkdf2_rounds must be between 100000 and 10000000".to_owned(),
+            "tls.cert_path and tls.key_path must be set together".to_owned(),
+            "tls.cert_path /nonexistent/cert.pem is not a file".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn should_require_server_identity_for_client_ca() {
+        let path = write_config("client-ca", "");
+
+        let args = Args { tls_client_ca: Some(path.clone()), ..Args::default() };
+        let result = Config::load(&args, &env(&[]));
+
+        assert_eq!(
+            result,
+            Err(ConfigError::Invalid(vec!["tls.client_ca_path requires tls.cert_path and tls.key_path".to_owned()]))
+        );
+
+        let config = Config::load(&Args::default(), &env(&[
+            ("AUTH_TLS_CERT_PATH", path.to_str().unwrap()),
+            ("AUTH_TLS_KEY_PATH", path.to_str().unwrap()),
+            ("AUTH_TLS_CLIENT_CA_PATH", path.to_str().unwrap()),
+        ])).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.tls.client_ca_path, Some(path));
+    }
+
+    #[test]
+    fn should_report_missing_file() {
+        let args = Args { config: Some(PathBuf::from("/nonexistent/auth.toml")), ..Args::default() };
+
+        assert!(matches!(Config::load(&args, &env(&[])), Err(ConfigError::Read(_, _))));
+    }
+}
@@ -0,0 +1,373 @@
+use prost::Message;
+use tonic::{Code, Status};
+use tracing::error;
+
+use crate::{
+    api_keys::ApiKeysError,
+    audit::AuditQueryError,
+    auth::authentication::ErrorReason,
+    groups::GroupsError,
+    mfa::MfaError,
+    ratelimit::RateLimitError,
+    rbac::RbacError,
+    sessions::SessionsError,
+    users::UsersError,
+    webauthn::WebauthnError,
+};
+
+pub mod google {
+    pub mod rpc {
+        tonic::include_proto!("google.rpc");
+    }
+}
+
+use google::rpc::ErrorInfo;
+
+/// The `ErrorInfo.domain` attached to every error raised by the authentication service.
+pub const ERROR_DOMAIN: &str = "authentication";
+
+/// The type URL under which `ErrorInfo` details are packed.
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+/// Builds a gRPC `Status` carrying a `google.rpc.ErrorInfo` detail.
+///
+/// # Arguments
+///
+/// * `code` - The gRPC status code of the failure.
+/// * `reason` - The machine-readable reason placed in the error details.
+/// * `message` - A human-readable description of the failure.
+///
+/// # Returns
+///
+/// A `Status` whose details decode to a `google.rpc.Status` wrapping a single `ErrorInfo`.
+///
+/// # Example
+///
+/// ```
+/// let status = error_status(Code::AlreadyExists, ErrorReason::UsernameTaken, "Username already exists.");
+/// assert_eq!(status.code(), Code::AlreadyExists);
+/// ```
+pub fn error_status(code: Code, reason: ErrorReason, message: impl Into<String>) -> Status {
+    let message = message.into();
+
+    let info = ErrorInfo {
+        reason: reason.as_str_name().to_owned(),
+        domain: ERROR_DOMAIN.to_owned(),
+        ..Default::default()
+    };
+
+    let details = google::rpc::Status {
+        code: code as i32,
+        message: message.clone(),
+        details: vec![prost_types::Any {
+            type_url: ERROR_INFO_TYPE_URL.to_owned(),
+            value: info.encode_to_vec(),
+        }],
+    };
+
+    Status::with_details(code, message, details.encode_to_vec().into())
+}
+
+/// Extracts the `ErrorReason` from a `Status` built by `error_status`.
+///
+/// # Arguments
+///
+/// * `status` - The status to inspect.
+///
+/// # Returns
+///
+/// An `Option<ErrorReason>` containing the reason if the status carries an `ErrorInfo` detail from
+/// this service, otherwise `None`.
+pub fn error_reason(status: &Status) -> Option<ErrorReason> {
+    let details = google::rpc::Status::decode(status.details()).ok()?;
+
+    details
+        .details
+        .iter()
+        .filter(|any| any.type_url == ERROR_INFO_TYPE_URL)
+        .filter_map(|any| ErrorInfo::decode(any.value.as_slice()).ok())
+        .find(|info| info.domain == ERROR_DOMAIN)
+        .and_then(|info| ErrorReason::from_str_name(&info.reason))
+}
+
+impl From<UsersError> for Status {
+    fn from(error: UsersError) -> Self {
+        match error {
+            UsersError::UsernameTaken => {
+                error_status(Code::AlreadyExists, ErrorReason::UsernameTaken, error.to_string())
+            }
+            // The underlying hashing error stays in the logs rather than going back to the caller.
+            UsersError::HashingFailed(ref e) => {
+                error!("Password hashing failed: {}", e);
+                error_status(Code::Internal, ErrorReason::PasswordHashingFailed, "Failed to hash password.")
+            }
+            UsersError::InvalidCredentials => {
+                error_status(Code::Unauthenticated, ErrorReason::InvalidCredentials, error.to_string())
+            }
+            UsersError::UserNotFound => {
+                error_status(Code::NotFound, ErrorReason::UserNotFound, error.to_string())
+            }
+            UsersError::AccountDisabled => {
+                error_status(Code::PermissionDenied, ErrorReason::AccountDisabled, error.to_string())
+            }
+            UsersError::AccountLocked => {
+                error_status(Code::PermissionDenied, ErrorReason::AccountLocked, error.to_string())
+            }
+            UsersError::AccountNotVerified => {
+                error_status(Code::FailedPrecondition, ErrorReason::AccountNotVerified, error.to_string())
+            }
+            UsersError::PasswordResetRequired => {
+                error_status(Code::FailedPrecondition, ErrorReason::PasswordResetRequired, error.to_string())
+            }
+            UsersError::InvalidProfile(_) => {
+                error_status(Code::InvalidArgument, ErrorReason::InvalidProfile, error.to_string())
+            }
+            UsersError::UserUuidTaken => {
+                error_status(Code::AlreadyExists, ErrorReason::UserUuidTaken, error.to_string())
+            }
+            UsersError::InvalidPasswordHash => {
+                error_status(Code::InvalidArgument, ErrorReason::InvalidPasswordHash, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<SessionsError> for Status {
+    fn from(error: SessionsError) -> Self {
+        match error {
+            SessionsError::SessionNotFound => {
+                error_status(Code::Unauthenticated, ErrorReason::SessionNotFound, error.to_string())
+            }
+            SessionsError::MfaChallengeNotFound => {
+                error_status(Code::Unauthenticated, ErrorReason::MfaChallengeInvalid, error.to_string())
+            }
+            SessionsError::WebauthnChallengeNotFound => {
+                error_status(Code::Unauthenticated, ErrorReason::WebauthnChallengeInvalid, error.to_string())
+            }
+            SessionsError::PasswordResetNotFound => {
+                error_status(Code::Unauthenticated, ErrorReason::PasswordResetInvalid, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<MfaError> for Status {
+    fn from(error: MfaError) -> Self {
+        match error {
+            MfaError::InvalidCode => {
+                error_status(Code::Unauthenticated, ErrorReason::InvalidMfaCode, error.to_string())
+            }
+            MfaError::NotEnrolled => {
+                error_status(Code::FailedPrecondition, ErrorReason::TotpNotEnrolled, error.to_string())
+            }
+            MfaError::AlreadyEnabled => {
+                error_status(Code::FailedPrecondition, ErrorReason::TotpAlreadyEnabled, error.to_string())
+            }
+            MfaError::SecretUnavailable => {
+                error_status(Code::Internal, ErrorReason::SecretUnavailable, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<ApiKeysError> for Status {
+    fn from(error: ApiKeysError) -> Self {
+        match error {
+            ApiKeysError::KeyNotFound => {
+                error_status(Code::NotFound, ErrorReason::ApiKeyNotFound, error.to_string())
+            }
+            ApiKeysError::InvalidKey => {
+                error_status(Code::Unauthenticated, ErrorReason::InvalidApiKey, error.to_string())
+            }
+            ApiKeysError::Expired => {
+                error_status(Code::Unauthenticated, ErrorReason::ApiKeyExpired, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<GroupsError> for Status {
+    fn from(error: GroupsError) -> Self {
+        match error {
+            GroupsError::GroupNotFound => {
+                error_status(Code::NotFound, ErrorReason::GroupNotFound, error.to_string())
+            }
+            GroupsError::GroupNameTaken => {
+                error_status(Code::AlreadyExists, ErrorReason::GroupNameTaken, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<RbacError> for Status {
+    fn from(error: RbacError) -> Self {
+        match error {
+            RbacError::PermissionDenied(_) => {
+                error_status(Code::PermissionDenied, ErrorReason::PermissionDenied, error.to_string())
+            }
+            RbacError::UnknownRole(_) => {
+                error_status(Code::InvalidArgument, ErrorReason::UnknownRole, error.to_string())
+            }
+            RbacError::UnknownPermission(_) => {
+                error_status(Code::InvalidArgument, ErrorReason::UnknownPermission, error.to_string())
+            }
+            RbacError::LastAdmin => {
+                error_status(Code::FailedPrecondition, ErrorReason::LastAdmin, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<AuditQueryError> for Status {
+    fn from(error: AuditQueryError) -> Self {
+        match error {
+            AuditQueryError::InvalidCursor(_) => {
+                error_status(Code::InvalidArgument, ErrorReason::InvalidCursor, error.to_string())
+            }
+            AuditQueryError::Lagged(_) => {
+                error_status(Code::ResourceExhausted, ErrorReason::AuditTailLagged, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<RateLimitError> for Status {
+    fn from(error: RateLimitError) -> Self {
+        match error {
+            RateLimitError::TooManyRequests(_) => {
+                error_status(Code::ResourceExhausted, ErrorReason::RateLimited, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<WebauthnError> for Status {
+    fn from(error: WebauthnError) -> Self {
+        match error {
+            WebauthnError::CredentialNotFound => {
+                error_status(Code::Unauthenticated, ErrorReason::PasskeyNotFound, error.to_string())
+            }
+            WebauthnError::CredentialAlreadyRegistered => {
+                error_status(Code::AlreadyExists, ErrorReason::PasskeyAlreadyRegistered, error.to_string())
+            }
+            WebauthnError::InvalidSignature | WebauthnError::SignCountRegression | WebauthnError::UserNotPresent => {
+                error_status(Code::Unauthenticated, ErrorReason::PasskeyVerificationFailed, error.to_string())
+            }
+            _ => error_status(Code::InvalidArgument, ErrorReason::PasskeyVerificationFailed, error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn should_round_trip_error_reason() {
+        let status = error_status(Code::AlreadyExists, ErrorReason::UsernameTaken, "taken");
+
+        assert_eq!(status.code(), Code::AlreadyExists);
+        assert_eq!(status.message(), "taken");
+        assert_eq!(error_reason(&status), Some(ErrorReason::UsernameTaken));
+    }
+
+    #[test]
+    fn should_map_users_errors_to_status_codes() {
+        assert_eq!(Status::from(UsersError::UsernameTaken).code(), Code::AlreadyExists);
+        assert_eq!(Status::from(UsersError::HashingFailed("boom".to_owned())).code(), Code::Internal);
+        assert_eq!(Status::from(UsersError::InvalidCredentials).code(), Code::Unauthenticated);
+        assert_eq!(Status::from(UsersError::UserNotFound).code(), Code::NotFound);
+        assert_eq!(Status::from(UsersError::AccountDisabled).code(), Code::PermissionDenied);
+        assert_eq!(error_reason(&Status::from(UsersError::AccountLocked)), Some(ErrorReason::AccountLocked));
+        assert_eq!(Status::from(UsersError::AccountNotVerified).code(), Code::FailedPrecondition);
+        assert_eq!(error_reason(&Status::from(UsersError::PasswordResetRequired)), Some(ErrorReason::PasswordResetRequired));
+        assert_eq!(Status::from(UsersError::InvalidProfile("bad".to_owned())).code(), Code::InvalidArgument);
+        assert_eq!(Status::from(UsersError::UserUuidTaken).code(), Code::AlreadyExists);
+        assert_eq!(error_reason(&Status::from(UsersError::InvalidPasswordHash)), Some(ErrorReason::InvalidPasswordHash));
+    }
+
+    #[test]
+    fn should_map_rate_limit_errors_to_status_codes() {
+        let status = Status::from(RateLimitError::TooManyRequests(Duration::from_secs(5)));
+
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(error_reason(&status), Some(ErrorReason::RateLimited));
+        assert_eq!(status.message(), "Too many requests, retry in 5 seconds.");
+    }
+
+    #[test]
+    fn should_map_audit_query_errors_to_status_codes() {
+        let status = Status::from(AuditQueryError::InvalidCursor("abc".to_owned()));
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(error_reason(&status), Some(ErrorReason::InvalidCursor));
+
+        let status = Status::from(AuditQueryError::Lagged(3));
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(error_reason(&status), Some(ErrorReason::AuditTailLagged));
+        assert_eq!(status.message(), "Fell behind and missed 3 audit events.");
+    }
+
+    #[test]
+    fn should_not_leak_hashing_error_details() {
+        let status = Status::from(UsersError::HashingFailed("secret detail".to_owned()));
+
+        assert!(!status.message().contains("secret detail"));
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasswordHashingFailed));
+    }
+
+    #[test]
+    fn should_map_sessions_errors_to_status_codes() {
+        let status = Status::from(SessionsError::SessionNotFound);
+
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::SessionNotFound));
+        assert_eq!(error_reason(&Status::from(SessionsError::PasswordResetNotFound)), Some(ErrorReason::PasswordResetInvalid));
+    }
+
+    #[test]
+    fn should_map_mfa_errors_to_status_codes() {
+        assert_eq!(Status::from(MfaError::InvalidCode).code(), Code::Unauthenticated);
+        assert_eq!(Status::from(MfaError::NotEnrolled).code(), Code::FailedPrecondition);
+        assert_eq!(Status::from(MfaError::AlreadyEnabled).code(), Code::FailedPrecondition);
+        assert_eq!(Status::from(MfaError::SecretUnavailable).code(), Code::Internal);
+    }
+
+    #[test]
+    fn should_map_webauthn_errors_to_status_codes() {
+        let status = Status::from(WebauthnError::InvalidSignature);
+
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(error_reason(&status), Some(ErrorReason::PasskeyVerificationFailed));
+        assert_eq!(Status::from(WebauthnError::OriginMismatch).code(), Code::InvalidArgument);
+        assert_eq!(Status::from(WebauthnError::CredentialAlreadyRegistered).code(), Code::AlreadyExists);
+        assert_eq!(Status::from(SessionsError::WebauthnChallengeNotFound).code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn should_map_api_keys_errors_to_status_codes() {
+        assert_eq!(Status::from(ApiKeysError::KeyNotFound).code(), Code::NotFound);
+        assert_eq!(Status::from(ApiKeysError::InvalidKey).code(), Code::Unauthenticated);
+        assert_eq!(error_reason(&Status::from(ApiKeysError::Expired)), Some(ErrorReason::ApiKeyExpired));
+    }
+
+    #[test]
+    fn should_map_rbac_errors_to_status_codes() {
+        use crate::rbac::Permission;
+
+        let status = Status::from(RbacError::PermissionDenied(Permission::ManageRoles));
+
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert!(status.message().contains("roles:manage"));
+        assert_eq!(Status::from(RbacError::UnknownRole("root".to_owned())).code(), Code::InvalidArgument);
+        assert_eq!(error_reason(&Status::from(RbacError::UnknownPermission("users:reed".to_owned()))), Some(ErrorReason::UnknownPermission));
+        assert_eq!(Status::from(RbacError::LastAdmin).code(), Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn should_map_groups_errors_to_status_codes() {
+        assert_eq!(Status::from(GroupsError::GroupNotFound).code(), Code::NotFound);
+        assert_eq!(error_reason(&Status::from(GroupsError::GroupNameTaken)), Some(ErrorReason::GroupNameTaken));
+    }
+}
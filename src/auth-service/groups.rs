@@ -0,0 +1,504 @@
+use tracing::instrument;
+use uuid::Uuid;
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    rbac::Role,
+    users::{AccountStatus, User},
+};
+
+/// `GroupsError` enumerates the ways an operation on the `Groups` store can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GroupsError {
+    /// No group exists with the requested UUID.
+    GroupNotFound,
+
+    /// The requested group name is already taken by another group.
+    GroupNameTaken,
+}
+
+impl fmt::Display for GroupsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupsError::GroupNotFound => write!(f, "Group not found."),
+            GroupsError::GroupNameTaken => write!(f, "Group name already exists."),
+        }
+    }
+}
+
+impl std::error::Error for GroupsError {}
+
+/// `Group` struct represents a team of users that can be granted roles together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    /// A string representing the UUID of the group.
+    pub group_uuid: String,
+
+    /// A unique, human-readable name for the group.
+    pub name: String,
+
+    /// A free-form description of the group.
+    pub description: String,
+
+    /// The roles every member of the group holds.
+    pub roles: Vec<Role>,
+
+    /// The UUIDs of the group's members, in the order they were added.
+    pub member_uuids: Vec<String>,
+}
+
+/// `Groups` trait defines methods for managing groups and their membership.
+pub trait Groups {
+
+    /// Creates a new group without members or roles.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A unique name for the group.
+    /// * `description` - A description of the group.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the new group, otherwise `GroupsError::GroupNameTaken`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// let group = groups_service.create_group("platform", "Platform team")?;
+    /// ```
+    fn create_group(&mut self, name: &str, description: &str) -> Result<Group, GroupsError>;
+
+    /// Retrieves the group with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_uuid` - A string representing the UUID of the group to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Group>` containing the group if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// if let Some(group) = groups_service.get_group("group_uuid") {
+    ///     println!("Members: {}", group.member_uuids.len());
+    /// }
+    /// ```
+    fn get_group(&self, group_uuid: &str) -> Option<Group>;
+
+    /// Retrieves every group, ordered by name.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing all groups.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// for group in groups_service.list_groups() {
+    ///     println!("{}", group.name);
+    /// }
+    /// ```
+    fn list_groups(&self) -> Vec<Group>;
+
+    /// Replaces the stored record of an existing group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The updated group, identified by its `group_uuid`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the group was updated, otherwise `GroupsError::GroupNotFound`, or
+    /// `GroupsError::GroupNameTaken` if it was renamed to the name of another group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// let mut group = groups_service.get_group("group_uuid").unwrap();
+    /// group.member_uuids.push("user_uuid".to_string());
+    /// groups_service.update_group(group)?;
+    /// ```
+    fn update_group(&mut self, group: Group) -> Result<(), GroupsError>;
+
+    /// Deletes the group with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_uuid` - A string representing the UUID of the group to delete.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the group was deleted, otherwise `GroupsError::GroupNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// groups_service.delete_group("group_uuid")?;
+    /// ```
+    fn delete_group(&mut self, group_uuid: &str) -> Result<(), GroupsError>;
+
+    /// Retrieves the groups the specified user is a member of.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the user's groups, ordered by name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// let groups = groups_service.user_groups("user_uuid");
+    /// ```
+    fn user_groups(&self, user_uuid: &str) -> Vec<Group>;
+
+    /// Removes the specified user from every group, e.g. when their account is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_service` implements `Groups` trait
+    /// groups_service.remove_member_from_all("user_uuid");
+    /// ```
+    fn remove_member_from_all(&mut self, user_uuid: &str);
+}
+
+/// `GroupsImpl` represents an implementation of the `Groups` trait.
+///
+/// This implementation stores groups in memory using a HashMap keyed by group UUID.
+#[derive(Default)]
+pub struct GroupsImpl {
+
+    /// A HashMap that maps group UUIDs to groups.
+    uuid_to_group: HashMap<String, Group>,
+}
+
+impl GroupsImpl {
+
+    /// Tells whether a group other than the given one already uses a name.
+    fn name_taken(&self, name: &str, group_uuid: &str) -> bool {
+        self.uuid_to_group
+            .values()
+            .any(|group| group.name == name && group.group_uuid != group_uuid)
+    }
+}
+
+impl Groups for GroupsImpl {
+
+    /// Creates a new group without members or roles.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A unique name for the group.
+    /// * `description` - A description of the group.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the new group, otherwise `GroupsError::GroupNameTaken`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// let group = groups_impl.create_group("platform", "Platform team")?;
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn create_group(&mut self, name: &str, description: &str) -> Result<Group, GroupsError> {
+        if self.name_taken(name, "") {
+            return Err(GroupsError::GroupNameTaken);
+        }
+
+        let group = Group {
+            group_uuid: Uuid::new_v4().to_string(),
+            name: name.to_owned(),
+            description: description.to_owned(),
+            roles: Vec::new(),
+            member_uuids: Vec::new(),
+        };
+
+        self.uuid_to_group.insert(group.group_uuid.clone(), group.clone());
+
+        Ok(group)
+    }
+
+    /// Retrieves the group with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_uuid` - A string representing the UUID of the group to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Group>` containing the group if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// let group = groups_impl.get_group("group_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn get_group(&self, group_uuid: &str) -> Option<Group> {
+        self.uuid_to_group.get(group_uuid).cloned()
+    }
+
+    /// Retrieves every group, ordered by name.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing all groups.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// let groups = groups_impl.list_groups();
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn list_groups(&self) -> Vec<Group> {
+        let mut groups: Vec<Group> = self.uuid_to_group.values().cloned().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        groups
+    }
+
+    /// Replaces the stored record of an existing group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The updated group, identified by its `group_uuid`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the group was updated, otherwise a `GroupsError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// let mut group = groups_impl.get_group("group_uuid").unwrap();
+    /// group.description = "Platform and infrastructure".to_string();
+    /// groups_impl.update_group(group)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn update_group(&mut self, group: Group) -> Result<(), GroupsError> {
+        if !self.uuid_to_group.contains_key(&group.group_uuid) {
+            return Err(GroupsError::GroupNotFound);
+        }
+
+        if self.name_taken(&group.name, &group.group_uuid) {
+            return Err(GroupsError::GroupNameTaken);
+        }
+
+        self.uuid_to_group.insert(group.group_uuid.clone(), group);
+
+        Ok(())
+    }
+
+    /// Deletes the group with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_uuid` - A string representing the UUID of the group to delete.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the group was deleted, otherwise `GroupsError::GroupNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// groups_impl.delete_group("group_uuid")?;
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn delete_group(&mut self, group_uuid: &str) -> Result<(), GroupsError> {
+        self.uuid_to_group
+            .remove(group_uuid)
+            .map(|_| ())
+            .ok_or(GroupsError::GroupNotFound)
+    }
+
+    /// Retrieves the groups the specified user is a member of.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the user's groups, ordered by name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// let groups = groups_impl.user_groups("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn user_groups(&self, user_uuid: &str) -> Vec<Group> {
+        self.list_groups()
+            .into_iter()
+            .filter(|group| group.member_uuids.iter().any(|member| member == user_uuid))
+            .collect()
+    }
+
+    /// Removes the specified user from every group.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `groups_impl` is an instance of `GroupsImpl`
+    /// groups_impl.remove_member_from_all("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "groups"))]
+    fn remove_member_from_all(&mut self, user_uuid: &str) {
+        for group in self.uuid_to_group.values_mut() {
+            group.member_uuids.retain(|member| member != user_uuid);
+        }
+    }
+}
+
+/// Resolves the roles a user holds directly or through group membership.
+///
+/// # Arguments
+///
+/// * `user` - The user.
+/// * `groups` - The groups the user is a member of.
+///
+/// # Returns
+///
+/// The union of the user's own roles and their groups' roles, without duplicates and in a stable
+/// order.
+pub fn effective_roles(user: &User, groups: &[Group]) -> Vec<Role> {
+    let mut roles: Vec<Role> = user.roles
+        .iter()
+        .chain(groups.iter().flat_map(|group| group.roles.iter()))
+        .copied()
+        .collect();
+
+    roles.sort();
+    roles.dedup();
+    roles
+}
+
+/// Counts the users who hold the admin role, directly or through a group. Only active users are
+/// counted, since nobody else can use the role.
+///
+/// # Arguments
+///
+/// * `users` - Every user.
+/// * `groups` - Every group.
+///
+/// # Returns
+///
+/// The number of administrators.
+pub fn count_admins(users: &[User], groups: &[Group]) -> usize {
+    users
+        .iter()
+        .filter(|user| user.status == AccountStatus::Active)
+        .filter(|user| {
+            user.roles.contains(&Role::Admin)
+                || groups.iter().any(|group| {
+                    group.roles.contains(&Role::Admin) && group.member_uuids.contains(&user.user_uuid)
+                })
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::users::{Users, UsersImpl};
+
+    use super::*;
+
+    #[test]
+    fn should_create_group() {
+        let mut groups_service = GroupsImpl::default();
+        let group = groups_service.create_group("platform", "Platform team").unwrap();
+
+        assert_eq!(groups_service.get_group(&group.group_uuid), Some(group));
+        assert_eq!(groups_service.create_group("platform", ""), Err(GroupsError::GroupNameTaken));
+    }
+
+    #[test]
+    fn should_not_rename_group_to_taken_name() {
+        let mut groups_service = GroupsImpl::default();
+        groups_service.create_group("platform", "").unwrap();
+        let mut group = groups_service.create_group("support", "").unwrap();
+
+        group.name = "platform".to_owned();
+
+        assert_eq!(groups_service.update_group(group), Err(GroupsError::GroupNameTaken));
+    }
+
+    #[test]
+    fn should_delete_group() {
+        let mut groups_service = GroupsImpl::default();
+        let group = groups_service.create_group("platform", "").unwrap();
+
+        groups_service.delete_group(&group.group_uuid).unwrap();
+
+        assert!(groups_service.list_groups().is_empty());
+        assert_eq!(groups_service.delete_group(&group.group_uuid), Err(GroupsError::GroupNotFound));
+    }
+
+    #[test]
+    fn should_track_membership() {
+        let mut groups_service = GroupsImpl::default();
+        let mut platform = groups_service.create_group("platform", "").unwrap();
+        let mut support = groups_service.create_group("support", "").unwrap();
+
+        platform.member_uuids.push("123456".to_owned());
+        support.member_uuids.push("123456".to_owned());
+        groups_service.update_group(platform).unwrap();
+        groups_service.update_group(support).unwrap();
+
+        let names: Vec<String> = groups_service.user_groups("123456").into_iter().map(|group| group.name).collect();
+        assert_eq!(names, vec!["platform".to_owned(), "support".to_owned()]);
+
+        groups_service.remove_member_from_all("123456");
+        assert!(groups_service.user_groups("123456").is_empty());
+    }
+
+    #[test]
+    fn should_resolve_effective_roles_and_admins() {
+        let mut users_service = UsersImpl::for_tests();
+        users_service.create_user("123456".to_owned(), "654321".to_owned()).unwrap();
+        let mut user = users_service.get_user_by_username("123456").unwrap();
+        user.roles.push(Role::Auditor);
+
+        let group = Group {
+            group_uuid: "group".to_owned(),
+            name: "admins".to_owned(),
+            description: "".to_owned(),
+            roles: vec![Role::Admin, Role::Auditor],
+            member_uuids: vec![user.user_uuid.clone()],
+        };
+
+        assert_eq!(effective_roles(&user, std::slice::from_ref(&group)), vec![Role::Admin, Role::Auditor]);
+        assert_eq!(count_admins(&[user.clone()], std::slice::from_ref(&group)), 1);
+        assert_eq!(count_admins(&[user.clone()], &[]), 0);
+
+        user.status = AccountStatus::Disabled;
+        assert_eq!(count_admins(&[user], &[group]), 0);
+    }
+}
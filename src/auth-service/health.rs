@@ -0,0 +1,132 @@
+use tonic::server::NamedService;
+use tonic_health::{server::HealthReporter, ServingStatus};
+use tracing::info;
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    auth::{AdminServer, AuthServer, AuthService},
+    shutdown::Shutdown,
+};
+
+/// How often the readiness of the stores is checked.
+const READINESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The services whose health is reported. The empty name stands for the server as a whole.
+const SERVICES: [&str; 3] = [
+    "",
+    <AuthServer<AuthService> as NamedService>::NAME,
+    <AdminServer<AuthService> as NamedService>::NAME,
+];
+
+/// Reports the same status for every service.
+///
+/// # Arguments
+///
+/// * `reporter` - The reporter of the `grpc.health.v1.Health` service.
+/// * `status` - The status to report.
+async fn set_status(reporter: &mut HealthReporter, status: ServingStatus) {
+    for service in SERVICES {
+        reporter.set_service_status(service, status).await;
+    }
+}
+
+/// Keeps the `grpc.health.v1.Health` service up to date until the service shuts down.
+///
+/// Every service is `SERVING` while the stores are ready and `NOT_SERVING` otherwise. Once
+/// shutdown starts every service is reported `NOT_SERVING`, so load balancers stop sending
+/// requests while those in flight drain.
+///
+/// # Arguments
+///
+/// * `reporter` - The reporter of the `grpc.health.v1.Health` service.
+/// * `auth_service` - The service whose stores are checked.
+/// * `shutdown` - Stops the checks once the service shuts down.
+///
+/// # Example
+///
+/// ```
+/// let (reporter, health_service) = tonic_health::server::health_reporter();
+/// tokio::spawn(health::report(reporter, auth_service.clone(), shutdown.clone()));
+/// ```
+pub async fn report(mut reporter: HealthReporter, auth_service: Arc<AuthService>, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(READINESS_INTERVAL);
+    let mut reported = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let status = match auth_service.storage_ready() {
+                    true => ServingStatus::Serving,
+                    false => ServingStatus::NotServing,
+                };
+
+                if reported != Some(status) {
+                    info!("Health status: {:?}", status);
+                    set_status(&mut reporter, status).await;
+                    reported = Some(status);
+                }
+            }
+            _ = shutdown.wait() => {
+                set_status(&mut reporter, ServingStatus::NotServing).await;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, Server};
+    use tonic_health::pb::{
+        health_check_response::ServingStatus as ResponseStatus, health_client::HealthClient, HealthCheckRequest,
+    };
+
+    use std::sync::Mutex;
+
+    use crate::{sessions::SessionsImpl, shutdown, users::UsersImpl};
+
+    use super::*;
+
+    /// Asks the health service for the status of a service.
+    async fn check(client: &mut HealthClient<Channel>, service: &str) -> ResponseStatus {
+        let request = HealthCheckRequest { service: service.to_owned() };
+        let status = client.check(request).await.unwrap().into_inner().status;
+
+        ResponseStatus::try_from(status).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_report_not_serving_on_shutdown() {
+        let auth_service = Arc::new(AuthService::new(
+            Box::new(Mutex::new(UsersImpl::for_tests())),
+            Box::new(Mutex::new(SessionsImpl::default())),
+        ));
+
+        let (reporter, health_service) = tonic_health::server::health_reporter();
+        let (trigger, shutdown) = shutdown::channel();
+        let report_task = tokio::spawn(report(reporter, auth_service, shutdown));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Server::builder().add_service(health_service).serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+        let mut client = HealthClient::new(channel);
+
+        // The first check runs straight away.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for service in SERVICES {
+            assert_eq!(check(&mut client, service).await, ResponseStatus::Serving);
+        }
+
+        trigger.trigger();
+        report_task.await.unwrap();
+
+        assert_eq!(check(&mut client, "authentication.Auth").await, ResponseStatus::NotServing);
+        assert_eq!(check(&mut client, "").await, ResponseStatus::NotServing);
+    }
+}
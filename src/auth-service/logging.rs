@@ -0,0 +1,547 @@
+use http::HeaderValue;
+use http_body::Body;
+use tonic::Code;
+use tower::{Layer, Service};
+use opentelemetry_sdk::trace::Tracer;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer as _};
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Instant,
+};
+
+use crate::{
+    config::{LogFormat, LoggingConfig},
+    metrics::Metrics,
+    telemetry,
+};
+
+/// The header carrying the ID of a request. The client's ID is kept if it sent one, so a request
+/// can be followed across services; otherwise one is generated.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest request ID accepted from a client. Longer ones are replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Field name suffixes whose values are never logged, e.g. `password`, `new_password` and
+/// `session_token`.
+const SENSITIVE_SUFFIXES: [&str; 6] = ["password", "token", "secret", "api_key", "recovery_code", "recovery_codes"];
+
+/// Field names whose values are never logged but are too common to match as suffixes, e.g.
+/// `status_code`.
+const SENSITIVE_NAMES: [&str; 1] = ["code"];
+
+/// What redacted values are replaced with.
+const REDACTED: &str = "\"[REDACTED]\"";
+
+/// Installs the global logger, and the exporter of traces if a tracer is given.
+///
+/// # Arguments
+///
+/// * `logging` - The format and filter of the log lines. The filter must have been validated.
+/// * `tracer` - Exports the spans of RPCs and storage calls, if tracing is enabled.
+///
+/// # Example
+///
+/// ```
+/// let config = Config::load(&args, &|name| env::var(name).ok())?;
+/// logging::init(&config.logging, None);
+/// tracing::info!("Logging initialised");
+/// ```
+pub fn init(logging: &LoggingConfig, tracer: Option<Tracer>) {
+    let log_layer = match logging.format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer().json().flatten_event(true).with_span_list(false).boxed(),
+    };
+
+    // Spans are exported whatever the log level, so quiet logs do not leave gaps in traces.
+    let trace_layer = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer).with_filter(LevelFilter::INFO));
+
+    tracing_subscriber::registry()
+        .with(log_layer.with_filter(EnvFilter::new(&logging.level)))
+        .with(trace_layer)
+        .init();
+}
+
+/// Returns whether the value of a field must not be logged.
+fn is_sensitive(name: &str) -> bool {
+    SENSITIVE_NAMES.contains(&name) || SENSITIVE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Returns the length of the string literal at the start of `debug`, quotes included.
+fn string_length(debug: &str) -> usize {
+    let mut escaped = false;
+
+    for (index, c) in debug.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return index + 1,
+            _ => {}
+        }
+    }
+
+    debug.len()
+}
+
+/// Returns the length of the field value at the start of `debug`, which ends at the first comma or
+/// closing bracket outside any string or nested value.
+fn value_length(debug: &str) -> usize {
+    let mut depth = 0;
+    let mut index = 0;
+
+    while let Some(c) = debug[index..].chars().next() {
+        match c {
+            '"' => {
+                index += string_length(&debug[index..]);
+                continue;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => return debug[..index].trim_end().len(),
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => return index,
+            _ => {}
+        }
+
+        index += c.len_utf8();
+    }
+
+    index
+}
+
+/// Replaces the values of sensitive fields in the `Debug` output of a message.
+///
+/// # Arguments
+///
+/// * `debug` - The output of formatting a message with `{:?}`.
+///
+/// # Returns
+///
+/// The output with the value of every password, token, secret and code field replaced.
+fn redact(debug: &str) -> String {
+    let mut redacted = String::with_capacity(debug.len());
+    let mut rest = debug;
+
+    while let Some(c) = rest.chars().next() {
+        // String values are copied whole, so text inside them is never taken for a field name.
+        if c == '"' {
+            let (string, after) = rest.split_at(string_length(rest));
+            redacted.push_str(string);
+            rest = after;
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let (name, after) = rest.split_at(length);
+            redacted.push_str(name);
+            rest = after;
+
+            if is_sensitive(name) && rest.starts_with(": ") {
+                redacted.push_str(": ");
+                redacted.push_str(REDACTED);
+                rest = &rest[2..];
+                rest = &rest[value_length(rest)..];
+            }
+
+            continue;
+        }
+
+        redacted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    redacted
+}
+
+/// `Redacted` formats a message with `Debug`, leaving out the values of its password, token,
+/// secret and code fields, so requests can be logged without leaking credentials.
+///
+/// # Example
+///
+/// ```
+/// tracing::debug!(request = ?Redacted(request.get_ref()), "Got a request");
+/// ```
+pub struct Redacted<'a, T>(pub &'a T);
+
+impl<T: Debug> Debug for Redacted<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&redact(&format!("{:?}", self.0)))
+    }
+}
+
+/// Reads the gRPC status code from response headers or trailers.
+fn grpc_code(headers: &http::HeaderMap) -> Option<Code> {
+    let code = headers.get("grpc-status")?.to_str().ok()?.parse::<i32>().ok()?;
+
+    Some(Code::from(code))
+}
+
+/// Tracks a single RPC until its outcome is logged.
+struct Rpc {
+    /// The span of the RPC.
+    span: Span,
+
+    /// The path of the RPC, e.g. `/authentication.Auth/SignIn`.
+    method: String,
+
+    /// The metrics the latency of the RPC is recorded in.
+    metrics: Arc<Metrics>,
+
+    /// When the request was received.
+    started_at: Instant,
+
+    /// Whether the outcome has been logged.
+    finished: bool,
+}
+
+impl Rpc {
+
+    /// Logs the outcome of the RPC and records its latency, unless this has been done already.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The gRPC status the RPC finished with.
+    fn finish(&mut self, code: Code) {
+        if std::mem::replace(&mut self.finished, true) {
+            return;
+        }
+
+        let latency = self.started_at.elapsed();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+
+        self.metrics.record_rpc(&self.method, code, latency);
+        self.span.set_attribute("rpc.grpc.status_code", code as i64);
+
+        match code {
+            Code::Ok => tracing::info!(parent: &self.span, outcome = "ok", ?code, latency_ms, "RPC finished"),
+            // These mean the service is at fault rather than the request.
+            Code::Unknown | Code::Internal | Code::DataLoss => {
+                tracing::error!(parent: &self.span, outcome = "error", ?code, latency_ms, "RPC failed")
+            }
+            _ => tracing::info!(parent: &self.span, outcome = "error", ?code, latency_ms, "RPC failed"),
+        }
+    }
+}
+
+impl Drop for Rpc {
+    fn drop(&mut self) {
+        if !self.finished {
+            let latency = self.started_at.elapsed();
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+
+            self.metrics.record_rpc(&self.method, Code::Cancelled, latency);
+            self.span.set_attribute("rpc.grpc.status_code", Code::Cancelled as i64);
+            tracing::info!(parent: &self.span, outcome = "cancelled", latency_ms, "RPC cancelled");
+        }
+    }
+}
+
+/// `LoggedBody` wraps a response body to log the outcome of the RPC once the trailers holding its
+/// status have been sent.
+pub struct LoggedBody<B> {
+    /// The wrapped body.
+    inner: B,
+
+    /// The RPC the body answers.
+    rpc: Rpc,
+}
+
+impl<B: Body + Unpin> Body for LoggedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let result = ready!(Pin::new(&mut self.inner).poll_trailers(cx));
+
+        // A body that ends without a status is an error to gRPC clients.
+        let code = match &result {
+            Ok(Some(trailers)) => grpc_code(trailers).unwrap_or(Code::Unknown),
+            Ok(None) | Err(_) => Code::Unknown,
+        };
+        self.rpc.finish(code);
+
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// `RpcLogLayer` wraps every RPC in a span carrying its method and request ID, and logs its
+/// outcome and latency once it finishes. The request ID is echoed in the `x-request-id` response
+/// header, and the latency is recorded in the service's metrics.
+///
+/// # Example
+///
+/// ```
+/// Server::builder()
+///     .layer(RpcLogLayer::new(auth_service.metrics()))
+///     .add_service(AuthServer::from_arc(auth_service))
+///     .serve(addr)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct RpcLogLayer {
+    /// The metrics RPC latencies are recorded in.
+    metrics: Arc<Metrics>,
+}
+
+impl RpcLogLayer {
+
+    /// Constructs a new `RpcLogLayer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The metrics RPC latencies are recorded in.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `RpcLogLayer`.
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for RpcLogLayer {
+    type Service = RpcLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcLog {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// `RpcLog` is the service `RpcLogLayer` wraps around the gRPC services.
+#[derive(Clone)]
+pub struct RpcLog<S> {
+    /// The wrapped service.
+    inner: S,
+
+    /// The metrics RPC latencies are recorded in.
+    metrics: Arc<Metrics>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for RpcLog<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Body + Unpin + Send + 'static,
+{
+    type Response = http::Response<LoggedBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        let request_id = HeaderValue::from_str(&request_id).expect("request ID should be a valid header value");
+
+        // Handlers see the same ID as the logs and the client.
+        request.headers_mut().insert(REQUEST_ID_HEADER, request_id.clone());
+
+        // Traces name the span after the RPC, as OpenTelemetry does for gRPC servers.
+        let span = tracing::info_span!(
+            "rpc",
+            method = request.uri().path(),
+            request_id = request_id.to_str().unwrap_or_default(),
+            otel.name = request.uri().path().trim_start_matches('/'),
+            otel.kind = "server",
+        );
+        telemetry::continue_trace(&span, request.headers(), request.uri().path());
+
+        let mut rpc = Rpc {
+            span: span.clone(),
+            method: request.uri().path().to_owned(),
+            metrics: self.metrics.clone(),
+            started_at: Instant::now(),
+            finished: false,
+        };
+
+        let response = span.in_scope(|| self.inner.call(request));
+
+        Box::pin(
+            async move {
+                let mut response = response.await?;
+                response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+
+                // Errors raised before any message is sent carry their status in the headers.
+                if let Some(code) = grpc_code(response.headers()) {
+                    rpc.finish(code);
+                }
+
+                Ok(response.map(|inner| LoggedBody { inner, rpc }))
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use std::{
+        convert::Infallible,
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use opentelemetry::trace::{SpanId, SpanKind, TraceId, TracerProvider as _};
+    use opentelemetry_sdk::{testing::trace::InMemorySpanExporter, trace::TracerProvider};
+    use tracing_subscriber::Registry;
+
+    use crate::{auth::authentication::SignInRequest, users::{Users, UsersImpl}};
+
+    use super::*;
+
+    /// Collects log lines written by a test.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("lock should not be tampered").extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_redact_credentials() {
+        let request = SignInRequest {
+            username: "jane".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+
+        assert_eq!(
+            format!("{:?}", Redacted(&request)),
+            "SignInRequest { username: \"jane\", password: \"[REDACTED]\" }"
+        );
+    }
+
+    #[test]
+    fn should_redact_nested_values_only() {
+        let debug = r#"Request { note: "token: x, \"code\"", api_key: Some(Key { id: [1, 2] }), status_code: 3, new_password: "a\"b", code: "123" }"#;
+
+        assert_eq!(
+            redact(debug),
+            r#"Request { note: "token: x, \"code\"", api_key: "[REDACTED]", status_code: 3, new_password: "[REDACTED]", code: "[REDACTED]" }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn should_log_outcome_with_request_id() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let metrics = Arc::new(Metrics::default());
+        let service = RpcLogLayer::new(metrics.clone()).layer(tower::service_fn(|_request: http::Request<()>| async {
+            let response = http::Response::builder()
+                .header("grpc-status", "5")
+                .body(tonic::body::empty_body())
+                .unwrap();
+
+            Ok::<_, Infallible>(response)
+        }));
+
+        let request = http::Request::builder()
+            .uri("/authentication.Auth/SignIn")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(())
+            .unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+        drop(response);
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(logs.lines().count(), 1);
+        assert!(logs.contains("\"outcome\":\"error\""));
+        assert!(logs.contains("\"code\":\"NotFound\""));
+        assert!(logs.contains("\"request_id\":\"abc-123\""));
+        assert!(logs.contains("\"method\":\"/authentication.Auth/SignIn\""));
+
+        let encoded = metrics.encode(0, 0);
+        assert!(encoded.contains("auth_rpc_duration_seconds_count{code=\"NotFound\",method=\"/authentication.Auth/SignIn\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn should_continue_client_trace() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = Registry::default().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let service = RpcLogLayer::new(Arc::new(Metrics::default())).layer(tower::service_fn(|_request: http::Request<()>| async {
+            UsersImpl::for_tests().count_users();
+
+            let response = http::Response::builder()
+                .header("grpc-status", "0")
+                .body(tonic::body::empty_body())
+                .unwrap();
+
+            Ok::<_, Infallible>(response)
+        }));
+
+        let request = http::Request::builder()
+            .uri("/authentication.Auth/SignIn")
+            .header("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            .body(())
+            .unwrap();
+
+        drop(service.oneshot(request).await.unwrap());
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let storage_span = spans.iter().find(|span| span.name == "count_users").unwrap();
+        let rpc_span = spans.iter().find(|span| span.name == "authentication.Auth/SignIn").unwrap();
+
+        assert_eq!(rpc_span.span_kind, SpanKind::Server);
+        assert_eq!(rpc_span.span_context.trace_id(), TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap());
+        assert_eq!(rpc_span.parent_span_id, SpanId::from_hex("00f067aa0ba902b7").unwrap());
+        assert!(rpc_span.attributes.iter().any(|kv| kv.key.as_str() == "rpc.method" && kv.value.as_str() == "SignIn"));
+        assert!(rpc_span.attributes.iter().any(|kv| kv.key.as_str() == "rpc.grpc.status_code" && kv.value.as_str() == "0"));
+
+        assert_eq!(storage_span.span_context.trace_id(), rpc_span.span_context.trace_id());
+        assert_eq!(storage_span.parent_span_id, rpc_span.span_context.span_id());
+    }
+}
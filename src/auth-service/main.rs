@@ -1,13 +1,53 @@
-use std::sync::Mutex;
+use clap::Parser;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
 
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+mod admin;
+mod api_keys;
+mod audit;
 mod auth;
+mod bulk;
+mod config;
+mod errors;
+mod groups;
+mod health;
+mod logging;
+mod metrics;
+mod mfa;
+mod ratelimit;
+mod rbac;
+mod reflection;
+mod reload;
 mod sessions;
+mod shutdown;
+mod telemetry;
+mod tls;
 mod users;
+mod webauthn;
 
+use audit::{AuditImpl, AuditSink, FileSink, LogSink};
 use auth::*;
+use config::{Args, Config, StorageBackend};
+use logging::RpcLogLayer;
+use mfa::SecretCipher;
+use reload::Reloader;
 use sessions::SessionsImpl;
+use tls::ReloadableTlsAcceptor;
 use users::UsersImpl;
 
+/// The largest request the `Admin` service accepts, so that bulk imports of many users fit in one
+/// request.
+const MAX_ADMIN_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// How often deleted accounts whose grace period has ended are purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 /// The main function of the authentication service.
 ///
 /// # Returns
@@ -15,26 +55,207 @@ use users::UsersImpl;
 /// An `Ok(())` result if the service starts successfully, otherwise an error message.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting Auth Service...");
-    // Using IP 0.0.0.0 here so the service is listening on all the configured network interfaces.
-    // This is needed for Docker to work.
+    // Settings come from the --config file, then AUTH_* environment variables, then flags. Every
+    // problem is reported before exiting so they can all be fixed at once. The logger depends on
+    // the settings, so these go straight to stderr.
+    let args = Args::parse();
+    let config = match Config::load(&args, &|name| env::var(name).ok()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Spans of RPCs and storage calls are exported over OTLP when tracing is enabled.
+    let tracer = match config.tracing.enabled {
+        true => Some(telemetry::tracer(&config.tracing)?),
+        false => None,
+    };
+
+    logging::init(&config.logging, tracer);
+    info!("Starting Auth Service...");
+
+    // The default address listens on all network interfaces, which Docker needs.
     // See: https://stackoverflow.com/questions/39525820/docker-port-forwarding-not-working
-    // Port 50051 is the recommended gRPC port.
-    let addr = "[::0]:50051".parse()?;
-    // let addr = "127.0.0.1:50051".parse()?;
+    let addr = config.server.bind_address;
+
+    let (mut users_service, sessions_service) = match config.storage.backend {
+        StorageBackend::Memory => (
+            UsersImpl::with_pbkdf2_rounds(config.hashing.pbkdf2_rounds),
+            SessionsImpl::with_ttls(config.ttls()),
+        ),
+    };
+
+    // The first administrator is created from the bootstrap settings; further roles are granted
+    // through the API. Validation guarantees the username and password are set together.
+    if let (Some(username), Some(password)) = (&config.bootstrap.admin_username, &config.bootstrap.admin_password) {
+        rbac::bootstrap_admin(&mut users_service, username, password)?;
+        info!("Bootstrap administrator: {}", username);
+    }
+
+    let users_service = Box::new(Mutex::new(users_service));
+    let sessions_service = Box::new(Mutex::new(sessions_service));
+
+    // TOTP secrets are encrypted at rest. Validation guarantees the key is well formed.
+    let secret_cipher = match &config.encryption.key {
+        Some(key) => SecretCipher::from_base64(key).ok_or("encryption.key must be a base64 encoded 32-byte key")?,
+        None => {
+            warn!("encryption.key is not set, TOTP secrets will be encrypted with a random key.");
+            SecretCipher::default()
+        }
+    };
+
+    // Audit events always go to the log and, when a file is configured, to a hash-chained file. A
+    // file whose chain is broken is refused so that tampering is noticed. Event IDs continue after
+    // the events in the file, so cursors from before a restart do not point at new events.
+    let mut audit_sinks: Vec<Box<dyn AuditSink + Send + Sync>> = vec![Box::new(LogSink)];
+    let mut next_audit_id = 1;
+    if let Some(path) = &config.audit.file_path {
+        let file_sink = FileSink::open(path)?;
+        next_audit_id = file_sink.head().lines + 1;
+        audit_sinks.push(Box::new(file_sink));
+        info!("Writing audit events to {}", path.display());
+    }
+
+    // Background tasks, streaming responses and the server stop once SIGTERM or SIGINT is received.
+    let (shutdown_trigger, shutdown) = shutdown::channel();
+
+    // The Auth and Admin services share one instance so they see the same stores.
+    let auth_service = Arc::new(
+        AuthService::new(users_service, sessions_service)
+            .with_audit_service(Box::new(Mutex::new(AuditImpl::with_sinks(audit_sinks).with_next_id(next_audit_id))))
+            .with_secret_cipher(secret_cipher)
+            .with_webauthn_config(config.webauthn.clone())
+            .with_deletion_grace_period(Duration::from_secs(config.ttl.deletion_grace_period_seconds))
+            .with_username_reservation_period(Duration::from_secs(config.ttl.username_reservation_seconds))
+            .with_rate_limits(config.rate_limits())
+            .with_shutdown(shutdown.clone()),
+    );
+
+    // Kept to flush the audit sinks once the servers have stopped.
+    let flush_service = auth_service.clone();
+
+    // Permanently remove deleted accounts once they can no longer be restored.
+    let purge_service = auth_service.clone();
+    let purge_shutdown = shutdown.clone();
+    let purge_task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let purged = purge_service.purge_deleted_users(SystemTime::now());
+                    if purged > 0 {
+                        info!("Purged {} deleted accounts", purged);
+                    }
+                }
+                _ = purge_shutdown.wait() => return,
+            }
+        }
+    });
+
+    // Validation guarantees the certificate and key are either both set or both unset.
+    let tls_acceptor = match &config.tls.cert_path {
+        Some(cert_path) => {
+            let acceptor = ReloadableTlsAcceptor::new(tls::server_config(&config.tls)?);
+            info!("TLS enabled with certificate {}", cert_path.display());
+
+            if let Some(client_ca_path) = &config.tls.client_ca_path {
+                info!("Client certificates required, signed by {}", client_ca_path.display());
+            }
+
+            Some(Arc::new(acceptor))
+        }
+        None => None,
+    };
+
+    let reflection_enabled = config.server.reflection;
+    let metrics_address = config.metrics.enabled.then_some(config.metrics.bind_address);
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_seconds);
+
+    // Certificates, TTLs and rate limits are reloaded on SIGHUP or when their files change.
+    let reloader = Reloader::new(
+        args,
+        Box::new(|name| env::var(name).ok()),
+        config,
+        auth_service.clone(),
+        tls_acceptor.clone(),
+    );
+    let reload_task = tokio::spawn(reloader.run(shutdown.clone()));
+
+    // grpc.health.v1.Health reports whether the stores are ready, and NOT_SERVING during shutdown.
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    let health_task = tokio::spawn(health::report(health_reporter, auth_service.clone(), shutdown.clone()));
+
+    // Reflection describes every RPC, so it is only served when enabled.
+    let reflection_service = match reflection_enabled {
+        true => Some(reflection::service()?),
+        false => None,
+    };
+
+    // Prometheus metrics are served over plain HTTP on their own address.
+    let metrics_task = match metrics_address {
+        Some(metrics_address) => {
+            let metrics_server = metrics::serve(metrics_address, auth_service.clone(), shutdown.clone())?;
+            info!("Serving metrics at: http://{}/metrics", metrics_address);
+            Some(tokio::spawn(metrics_server))
+        }
+        None => None,
+    };
+
+    // Instantiate gRPC server. Every RPC is logged with its request ID, outcome and latency.
+    let router = Server::builder()
+        .layer(RpcLogLayer::new(auth_service.metrics()))
+        .add_service(health_service)
+        .add_optional_service(reflection_service)
+        .add_service(AuthServer::from_arc(auth_service.clone()))
+        .add_service(AdminServer::from_arc(auth_service).max_decoding_message_size(MAX_ADMIN_MESSAGE_SIZE));
+
+    // On shutdown the server stops accepting connections and waits for requests in flight.
+    let server_shutdown = shutdown.clone();
+    let mut server = match tls_acceptor {
+        Some(tls_acceptor) => {
+            let incoming = tls::incoming(TcpListener::bind(addr).await?, tls_acceptor);
+            tokio::spawn(router.serve_with_incoming_shutdown(incoming, async move { server_shutdown.wait().await }))
+        }
+        None => tokio::spawn(router.serve_with_shutdown(addr, async move { server_shutdown.wait().await })),
+    };
+
+    info!("Server started at: {}", addr);
+
+    let signal = tokio::select! {
+        result = &mut server => {
+            // The server failed, e.g. because the address is in use.
+            shutdown_trigger.trigger();
+            return Ok(result??);
+        }
+        signal = shutdown::signal_received() => signal?,
+    };
+
+    info!("Received {}, draining requests for up to {} seconds", signal, shutdown_timeout.as_secs());
+    shutdown_trigger.trigger();
+
+    match tokio::time::timeout(shutdown_timeout, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            warn!("Requests still in flight after {} seconds, stopping anyway", shutdown_timeout.as_secs());
+            server.abort();
+        }
+    }
 
-    let users_service = Box::new(Mutex::new(UsersImpl::default()));
-    let sessions_service = Box::new(Mutex::new(SessionsImpl::default()));
+    purge_task.await?;
+    reload_task.await?;
+    health_task.await?;
 
-    let auth_service = AuthService::new(users_service, sessions_service);
+    if let Some(metrics_task) = metrics_task {
+        metrics_task.await??;
+    }
 
-    println!("Server started at: {}", addr);
+    // The stores live in memory, but the audit files must reach the disk before exit.
+    flush_service.flush();
 
-    // Instantiate gRPC server
-    Server::builder()
-        .add_service(AuthServer::new(auth_service))
-        .serve(addr)
-        .await?;
+    info!("Auth Service stopped");
+    telemetry::shutdown();
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,332 @@
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use tonic::{Code, Status};
+
+use std::{convert::Infallible, future::Future, net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{auth::AuthService, errors::error_reason, shutdown::Shutdown};
+
+/// The prefix of every metric name.
+const NAMESPACE: &str = "auth";
+
+/// The path metrics are served on.
+const METRICS_PATH: &str = "/metrics";
+
+/// `Metrics` struct holds the Prometheus metrics of the authentication service.
+///
+/// Counters and histograms are updated as requests are handled. The session and user gauges are
+/// read from the stores when metrics are scraped.
+pub struct Metrics {
+    /// The registry every metric is registered with.
+    registry: Registry,
+
+    /// `SignUp` requests by outcome.
+    sign_ups: IntCounterVec,
+
+    /// `SignIn` requests by outcome.
+    sign_ins: IntCounterVec,
+
+    /// `SignOut` requests by outcome.
+    sign_outs: IntCounterVec,
+
+    /// How long RPCs take, by method and gRPC status code.
+    rpc_duration: HistogramVec,
+
+    /// Accounts locked by an administrator.
+    lockouts: IntCounter,
+
+    /// Requests rejected by a rate limit, by RPC.
+    rate_limit_rejections: IntCounterVec,
+
+    /// Sessions that have not expired or been signed out.
+    active_sessions: IntGauge,
+
+    /// Users that have not been deleted.
+    users: IntGauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some(NAMESPACE.to_owned()), None).expect("namespace should be valid");
+
+        let outcome_counter = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["outcome"]).expect("metric should be valid");
+            registry.register(Box::new(counter.clone())).expect("metric should be registered once");
+            counter
+        };
+
+        let sign_ups = outcome_counter("sign_ups_total", "SignUp requests by outcome.");
+        let sign_ins = outcome_counter("sign_ins_total", "SignIn requests by outcome.");
+        let sign_outs = outcome_counter("sign_outs_total", "SignOut requests by outcome.");
+
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new("rpc_duration_seconds", "How long RPCs take, by method and gRPC status code."),
+            &["method", "code"],
+        )
+        .expect("metric should be valid");
+
+        let lockouts = IntCounter::new("lockouts_total", "Accounts locked by an administrator.")
+            .expect("metric should be valid");
+
+        let rate_limit_rejections = IntCounterVec::new(
+            Opts::new("rate_limit_rejections_total", "Requests rejected by a rate limit, by RPC."),
+            &["rpc"],
+        )
+        .expect("metric should be valid");
+
+        let active_sessions = IntGauge::new("active_sessions", "Sessions that have not expired or been signed out.")
+            .expect("metric should be valid");
+
+        let users = IntGauge::new("users", "Users that have not been deleted.").expect("metric should be valid");
+
+        for collector in [
+            Box::new(rpc_duration.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(lockouts.clone()),
+            Box::new(rate_limit_rejections.clone()),
+            Box::new(active_sessions.clone()),
+            Box::new(users.clone()),
+        ] {
+            registry.register(collector).expect("metric should be registered once");
+        }
+
+        Self {
+            registry,
+            sign_ups,
+            sign_ins,
+            sign_outs,
+            rpc_duration,
+            lockouts,
+            rate_limit_rejections,
+            active_sessions,
+            users,
+        }
+    }
+}
+
+/// Returns the outcome label of an RPC result: `success`, or the lower case `ErrorReason` of the
+/// failure, e.g. `invalid_credentials`.
+///
+/// # Arguments
+///
+/// * `result` - The result returned by a handler.
+///
+/// # Returns
+///
+/// The outcome label.
+pub fn outcome<T>(result: &Result<T, Status>) -> String {
+    match result {
+        Ok(_) => "success".to_owned(),
+        Err(status) => match error_reason(status) {
+            Some(reason) => reason.as_str_name().to_lowercase(),
+            None => format!("{:?}", status.code()).to_lowercase(),
+        },
+    }
+}
+
+impl Metrics {
+
+    /// Counts a `SignUp` request.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The outcome of the request, see `outcome`.
+    pub fn record_sign_up(&self, outcome: &str) {
+        self.sign_ups.with_label_values(&[outcome]).inc();
+    }
+
+    /// Counts a `SignIn` request.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The outcome of the request, see `outcome`.
+    pub fn record_sign_in(&self, outcome: &str) {
+        self.sign_ins.with_label_values(&[outcome]).inc();
+    }
+
+    /// Counts a `SignOut` request.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The outcome of the request, see `outcome`.
+    pub fn record_sign_out(&self, outcome: &str) {
+        self.sign_outs.with_label_values(&[outcome]).inc();
+    }
+
+    /// Records how long an RPC took.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The path of the RPC, e.g. `/authentication.Auth/SignIn`.
+    /// * `code` - The gRPC status the RPC finished with.
+    /// * `duration` - How long the RPC took.
+    pub fn record_rpc(&self, method: &str, code: Code, duration: Duration) {
+        // Paths no service answers are up to the client, so they share one label.
+        let method = match code {
+            Code::Unimplemented => "unknown",
+            _ => method,
+        };
+
+        self.rpc_duration
+            .with_label_values(&[method, &format!("{:?}", code)])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Counts an account being locked.
+    pub fn record_lockout(&self) {
+        self.lockouts.inc();
+    }
+
+    /// Counts a request rejected by a rate limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `rpc` - The rate limited RPC, e.g. `sign_in`.
+    pub fn record_rate_limit_rejection(&self, rpc: &str) {
+        self.rate_limit_rejections.with_label_values(&[rpc]).inc();
+    }
+
+    /// Encodes every metric in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_sessions` - The number of sessions that have not expired or been signed out.
+    /// * `users` - The number of users that have not been deleted.
+    ///
+    /// # Returns
+    ///
+    /// The encoded metrics.
+    pub fn encode(&self, active_sessions: usize, users: usize) -> String {
+        self.active_sessions.set(active_sessions as i64);
+        self.users.set(users as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics should encode");
+
+        String::from_utf8(buffer).expect("metrics should be UTF-8")
+    }
+}
+
+/// Answers a single HTTP request to the metrics server.
+fn respond(request: Request<Body>, auth_service: &AuthService) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .expect("response should be valid");
+    }
+
+    Response::builder()
+        .header(CONTENT_TYPE, TextEncoder::new().format_type())
+        .body(Body::from(auth_service.encode_metrics()))
+        .expect("response should be valid")
+}
+
+/// Binds the metrics server, which serves the metrics of the authentication service over HTTP on
+/// `GET /metrics` until the service shuts down.
+///
+/// The address is bound straight away, so a clash is reported at startup rather than from the
+/// server task.
+///
+/// # Arguments
+///
+/// * `addr` - The address to listen on.
+/// * `auth_service` - The service whose metrics are served.
+/// * `shutdown` - Stops the server once the service shuts down.
+///
+/// # Returns
+///
+/// An `Ok` result containing the server to spawn, otherwise an error if the address cannot be
+/// bound.
+///
+/// # Example
+///
+/// ```
+/// tokio::spawn(metrics::serve("[::]:9090".parse()?, auth_service.clone(), shutdown.clone())?);
+/// ```
+pub fn serve(
+    addr: SocketAddr,
+    auth_service: Arc<AuthService>,
+    shutdown: Shutdown,
+) -> Result<impl Future<Output = Result<(), hyper::Error>>, hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let auth_service = auth_service.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = respond(request, &auth_service);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?
+        .serve(make_service)
+        .with_graceful_shutdown(async move { shutdown.wait().await });
+
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{auth::authentication::ErrorReason, errors::error_status, sessions::SessionsImpl, users::UsersImpl};
+
+    use super::*;
+
+    #[test]
+    fn should_label_outcomes() {
+        assert_eq!(outcome(&Ok::<_, Status>(())), "success");
+        assert_eq!(
+            outcome(&Err::<(), _>(error_status(Code::ResourceExhausted, ErrorReason::RateLimited, "Slow down."))),
+            "rate_limited"
+        );
+        assert_eq!(outcome(&Err::<(), _>(Status::internal("Failed."))), "internal");
+    }
+
+    #[test]
+    fn should_encode_metrics() {
+        let metrics = Metrics::default();
+
+        metrics.record_sign_in("success");
+        metrics.record_sign_in("invalid_credentials");
+        metrics.record_sign_in("invalid_credentials");
+        metrics.record_rate_limit_rejection("sign_up");
+        metrics.record_rpc("/authentication.Auth/SignIn", Code::Ok, Duration::from_millis(20));
+        metrics.record_rpc("/no.Such/Method", Code::Unimplemented, Duration::from_millis(1));
+
+        let encoded = metrics.encode(3, 7);
+
+        assert!(encoded.contains("auth_sign_ins_total{outcome=\"invalid_credentials\"} 2"));
+        assert!(encoded.contains("auth_sign_ins_total{outcome=\"success\"} 1"));
+        assert!(encoded.contains("auth_rate_limit_rejections_total{rpc=\"sign_up\"} 1"));
+        assert!(encoded.contains("auth_rpc_duration_seconds_count{code=\"Ok\",method=\"/authentication.Auth/SignIn\"} 1"));
+        assert!(encoded.contains("auth_rpc_duration_seconds_count{code=\"Unimplemented\",method=\"unknown\"} 1"));
+        assert!(encoded.contains("auth_active_sessions 3"));
+        assert!(encoded.contains("auth_users 7"));
+        assert!(encoded.contains("auth_lockouts_total 0"));
+    }
+
+    #[tokio::test]
+    async fn should_serve_metrics() {
+        let auth_service = Arc::new(AuthService::new(
+            Box::new(Mutex::new(UsersImpl::for_tests())),
+            Box::new(Mutex::new(SessionsImpl::default())),
+        ));
+
+        let response = respond(Request::get(METRICS_PATH).body(Body::empty()).unwrap(), &auth_service);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("auth_users 0"));
+
+        let response = respond(Request::get("/").body(Body::empty()).unwrap(), &auth_service);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
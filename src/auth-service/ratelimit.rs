@@ -0,0 +1,344 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The number of keys a `RateLimiter` tracks. Beyond it, the least recently used key is forgotten.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// `RateLimitError` enumerates the ways a rate limit check can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RateLimitError {
+    /// The caller has used up their allowance. Carries how long until another request is allowed.
+    TooManyRequests(Duration),
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::TooManyRequests(retry_after) => {
+                write!(f, "Too many requests, retry in {} seconds.", retry_after.as_secs().max(1))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RateLimitError {}
+
+/// `RateLimits` struct holds the request allowances of the rate limited RPCs. A limit of 0
+/// disables rate limiting for that RPC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    /// How many failed `SignIn` attempts a username may receive from one client address per minute,
+    /// and how many failed `VerifyMfa` attempts a user may make from any address.
+    pub sign_in_per_minute: u32,
+
+    /// How many `SignUp` requests a client address may send per minute.
+    pub sign_up_per_minute: u32,
+}
+
+/// The allowance left to a single key.
+struct Bucket {
+    /// The number of requests that can be made straight away.
+    tokens: f64,
+
+    /// When `tokens` was last brought up to date.
+    updated_at: Instant,
+
+    /// When the bucket was last used, as a position in `RateLimiter::recently_used`.
+    used_at: u64,
+}
+
+/// `RateLimiter` allows each key a number of requests per minute using a token bucket, so short
+/// bursts up to the full allowance are accepted while the long-term rate stays bounded.
+pub struct RateLimiter {
+    /// The allowance per minute. 0 disables the limiter.
+    per_minute: u32,
+
+    /// A HashMap that maps keys, such as usernames, to their remaining allowance.
+    buckets: HashMap<String, Bucket>,
+
+    /// The tracked keys, least recently used first.
+    recently_used: BTreeMap<u64, String>,
+
+    /// The `used_at` of the next bucket used.
+    next_use: u64,
+}
+
+impl RateLimiter {
+
+    /// Constructs a new `RateLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `per_minute` - How many requests each key may make per minute. 0 disables the limiter.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `RateLimiter`.
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: HashMap::new(),
+            recently_used: BTreeMap::new(),
+            next_use: 0,
+        }
+    }
+
+    /// Records a request for a key if its allowance permits it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the limit applies to, e.g. a username or a client address.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the request is allowed, otherwise `RateLimitError::TooManyRequests`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut limiter = RateLimiter::new(10);
+    /// limiter.check("username", Instant::now())?;
+    /// ```
+    pub fn check(&mut self, key: &str, now: Instant) -> Result<(), RateLimitError> {
+        self.ensure_allowed(key, now)?;
+        self.charge(key, now);
+
+        Ok(())
+    }
+
+    /// Checks that a key has allowance left, without using any of it. A key that was never charged
+    /// has its full allowance and is not tracked.
+    ///
+    /// Together with `charge` this limits only some of a key's requests, such as failed ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the limit applies to, e.g. a username or a client address.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if a request would be allowed, otherwise `RateLimitError::TooManyRequests`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut limiter = RateLimiter::new(10);
+    /// limiter.ensure_allowed("username", Instant::now())?;
+    /// ```
+    pub fn ensure_allowed(&mut self, key: &str, now: Instant) -> Result<(), RateLimitError> {
+        let per_second = f64::from(self.per_minute) / 60.0;
+
+        match self.refill(key, now) {
+            Some(bucket) if bucket.tokens < 1.0 => {
+                Err(RateLimitError::TooManyRequests(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Uses one request of a key's allowance, tracking the key if it is new. The allowance does not
+    /// go below zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the limit applies to, e.g. a username or a client address.
+    /// * `now` - The current instant.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut limiter = RateLimiter::new(10);
+    /// limiter.charge("username", Instant::now());
+    /// ```
+    pub fn charge(&mut self, key: &str, now: Instant) {
+        if self.per_minute == 0 {
+            return;
+        }
+
+        if !self.buckets.contains_key(key) {
+            // Evicting the least recently used key keeps the limiter bounded whatever the keys.
+            if self.buckets.len() >= MAX_TRACKED_KEYS {
+                if let Some((_, oldest)) = self.recently_used.pop_first() {
+                    self.buckets.remove(&oldest);
+                }
+            }
+
+            let bucket = Bucket { tokens: f64::from(self.per_minute), updated_at: now, used_at: self.next_use };
+            self.recently_used.insert(self.next_use, key.to_owned());
+            self.buckets.insert(key.to_owned(), bucket);
+            self.next_use += 1;
+        }
+
+        if let Some(bucket) = self.refill(key, now) {
+            bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+        }
+    }
+
+    /// Brings the allowance of a tracked key up to date and marks it as the most recently used.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the limit applies to.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// The key's bucket, or `None` if the limiter is disabled or does not track the key.
+    fn refill(&mut self, key: &str, now: Instant) -> Option<&mut Bucket> {
+        if self.per_minute == 0 {
+            return None;
+        }
+
+        let capacity = f64::from(self.per_minute);
+        let per_second = capacity / 60.0;
+
+        let bucket = self.buckets.get_mut(key)?;
+
+        self.recently_used.remove(&bucket.used_at);
+        bucket.used_at = self.next_use;
+        self.recently_used.insert(self.next_use, key.to_owned());
+        self.next_use += 1;
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated_at = now;
+
+        Some(bucket)
+    }
+
+    /// Changes the allowance per minute. Keys keep what is left of their allowance, up to the new
+    /// allowance.
+    ///
+    /// # Arguments
+    ///
+    /// * `per_minute` - How many requests each key may make per minute. 0 disables the limiter.
+    pub fn set_per_minute(&mut self, per_minute: u32) {
+        self.per_minute = per_minute;
+
+        if per_minute == 0 {
+            self.buckets.clear();
+            self.recently_used.clear();
+            return;
+        }
+
+        // Buckets filled under a larger allowance would otherwise allow a burst above the new one.
+        let capacity = f64::from(per_minute);
+        for bucket in self.buckets.values_mut() {
+            bucket.tokens = bucket.tokens.min(capacity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_allow_requests_up_to_limit() {
+        let mut limiter = RateLimiter::new(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check("username", now), Ok(()));
+        }
+
+        assert!(matches!(limiter.check("username", now), Err(RateLimitError::TooManyRequests(_))));
+        assert_eq!(limiter.check("other", now), Ok(()));
+    }
+
+    #[test]
+    fn should_refill_over_time() {
+        let mut limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        for _ in 0..60 {
+            limiter.check("username", now).unwrap();
+        }
+
+        assert_eq!(
+            limiter.check("username", now),
+            Err(RateLimitError::TooManyRequests(Duration::from_secs(1)))
+        );
+        assert_eq!(limiter.check("username", now + Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    fn should_apply_changed_limit() {
+        let mut limiter = RateLimiter::new(10);
+        let now = Instant::now();
+
+        limiter.check("username", now).unwrap();
+        limiter.set_per_minute(1);
+
+        assert_eq!(limiter.check("username", now), Ok(()));
+        assert!(matches!(limiter.check("username", now), Err(RateLimitError::TooManyRequests(_))));
+
+        limiter.set_per_minute(0);
+        assert_eq!(limiter.check("username", now), Ok(()));
+    }
+
+    #[test]
+    fn should_only_limit_charged_requests() {
+        let mut limiter = RateLimiter::new(2);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(limiter.ensure_allowed("username", now), Ok(()));
+        }
+
+        limiter.charge("username", now);
+        limiter.charge("username", now);
+        limiter.charge("username", now);
+
+        assert!(matches!(limiter.ensure_allowed("username", now), Err(RateLimitError::TooManyRequests(_))));
+        assert_eq!(limiter.ensure_allowed("username", now + Duration::from_secs(30)), Ok(()));
+    }
+
+    #[test]
+    fn should_only_track_charged_keys() {
+        let mut limiter = RateLimiter::new(5);
+        let now = Instant::now();
+
+        for index in 0..100 {
+            assert_eq!(limiter.ensure_allowed(&format!("username{}", index), now), Ok(()));
+        }
+
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn should_forget_least_recently_used_keys() {
+        let mut limiter = RateLimiter::new(1);
+        let now = Instant::now();
+
+        limiter.charge("username", now);
+        for index in 0..MAX_TRACKED_KEYS + 100 {
+            limiter.charge(&format!("other{}", index), now);
+
+            // Keeps the first key in use, so it outlives the others.
+            if index % 100 == 0 {
+                assert!(limiter.ensure_allowed("username", now).is_err());
+            }
+        }
+
+        assert_eq!(limiter.buckets.len(), MAX_TRACKED_KEYS);
+        assert_eq!(limiter.recently_used.len(), MAX_TRACKED_KEYS);
+        assert!(limiter.ensure_allowed("username", now).is_err());
+        assert_eq!(limiter.ensure_allowed("other0", now), Ok(()));
+    }
+
+    #[test]
+    fn should_not_limit_when_disabled() {
+        let mut limiter = RateLimiter::new(0);
+        let now = Instant::now();
+
+        for _ in 0..1000 {
+            assert_eq!(limiter.check("username", now), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use crate::users::{Users, UsersError};
+
+/// `RbacError` enumerates the ways an authorization check or role change can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RbacError {
+    /// The caller lacks the permission the operation requires.
+    PermissionDenied(Permission),
+
+    /// The role name is not one of the known roles.
+    UnknownRole(String),
+
+    /// The permission name is not one of the known permissions.
+    UnknownPermission(String),
+
+    /// The change would leave the service without any administrator.
+    LastAdmin,
+}
+
+impl fmt::Display for RbacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RbacError::PermissionDenied(permission) => write!(f, "Missing permission: {permission}."),
+            RbacError::UnknownRole(role) => write!(f, "Unknown role: {role}."),
+            RbacError::UnknownPermission(permission) => write!(f, "Unknown permission: {permission}."),
+            RbacError::LastAdmin => write!(f, "The last administrator cannot lose the admin role."),
+        }
+    }
+}
+
+impl std::error::Error for RbacError {}
+
+/// `Permission` enumerates the privileged operations that can be granted through roles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Look up other users' accounts.
+    ReadUsers,
+
+    /// Change or remove other users' accounts.
+    ManageUsers,
+
+    /// Grant and revoke roles, for users and for groups.
+    ManageRoles,
+
+    /// Create, change and delete groups and their membership.
+    ManageGroups,
+
+    /// Search and follow the audit trail.
+    ReadAudit,
+}
+
+impl Permission {
+
+    /// Returns the name used for the permission in API responses and API key scopes.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ReadUsers => "users:read",
+            Permission::ManageUsers => "users:write",
+            Permission::ManageRoles => "roles:manage",
+            Permission::ManageGroups => "groups:manage",
+            Permission::ReadAudit => "audit:read",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = RbacError;
+
+    fn from_str(permission: &str) -> Result<Self, Self::Err> {
+        match permission {
+            "users:read" => Ok(Permission::ReadUsers),
+            "users:write" => Ok(Permission::ManageUsers),
+            "roles:manage" => Ok(Permission::ManageRoles),
+            "groups:manage" => Ok(Permission::ManageGroups),
+            "audit:read" => Ok(Permission::ReadAudit),
+            _ => Err(RbacError::UnknownPermission(permission.to_owned())),
+        }
+    }
+}
+
+/// `Role` enumerates the roles a user can hold. Users without roles can only manage their own
+/// account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Full control over users, roles and groups.
+    Admin,
+
+    /// Support staff who can look up and change user accounts.
+    UserManager,
+
+    /// Read-only access to user accounts and the audit trail.
+    Auditor,
+}
+
+impl Role {
+
+    /// Returns the name used for the role in requests and responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::UserManager => "user_manager",
+            Role::Auditor => "auditor",
+        }
+    }
+
+    /// Returns the permissions granted by the role.
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            // Group members inherit the group's roles, so managing groups is as privileged as
+            // managing roles.
+            Role::Admin => &[
+                Permission::ReadUsers,
+                Permission::ManageUsers,
+                Permission::ManageRoles,
+                Permission::ManageGroups,
+                Permission::ReadAudit,
+            ],
+            Role::UserManager => &[Permission::ReadUsers, Permission::ManageUsers],
+            Role::Auditor => &[Permission::ReadUsers, Permission::ReadAudit],
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = RbacError;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "admin" => Ok(Role::Admin),
+            "user_manager" => Ok(Role::UserManager),
+            "auditor" => Ok(Role::Auditor),
+            _ => Err(RbacError::UnknownRole(role.to_owned())),
+        }
+    }
+}
+
+/// Resolves the permissions granted by a set of roles.
+///
+/// # Arguments
+///
+/// * `roles` - The roles held.
+///
+/// # Returns
+///
+/// The union of the roles' permissions, without duplicates and in a stable order.
+pub fn permissions<'a>(roles: impl IntoIterator<Item = &'a Role>) -> BTreeSet<Permission> {
+    roles.into_iter().flat_map(|role| role.permissions().iter().copied()).collect()
+}
+
+/// Checks that a set of roles grants a permission.
+///
+/// # Arguments
+///
+/// * `roles` - The roles held by the caller.
+/// * `permission` - The permission the operation requires.
+///
+/// # Returns
+///
+/// An `Ok(())` result if one of the roles grants the permission, otherwise
+/// `RbacError::PermissionDenied`.
+pub fn require<'a>(roles: impl IntoIterator<Item = &'a Role>, permission: Permission) -> Result<(), RbacError> {
+    if permissions(roles).contains(&permission) {
+        Ok(())
+    } else {
+        Err(RbacError::PermissionDenied(permission))
+    }
+}
+
+/// Checks that a caller's roles grant every permission another user's roles grant.
+///
+/// Operations that take over or lock out an account use this, so that a caller cannot act on
+/// someone more privileged than themselves.
+///
+/// # Arguments
+///
+/// * `caller_roles` - The roles held by the caller.
+/// * `target_roles` - The roles held by the user the operation acts on.
+///
+/// # Returns
+///
+/// An `Ok(())` result if the caller holds all of the target's permissions, otherwise
+/// `RbacError::PermissionDenied` naming the first permission the caller lacks.
+pub fn require_all<'a>(
+    caller_roles: impl IntoIterator<Item = &'a Role>,
+    target_roles: impl IntoIterator<Item = &'a Role>,
+) -> Result<(), RbacError> {
+    let granted = permissions(caller_roles);
+
+    match permissions(target_roles).into_iter().find(|permission| !granted.contains(permission)) {
+        Some(permission) => Err(RbacError::PermissionDenied(permission)),
+        None => Ok(()),
+    }
+}
+
+/// Makes sure an administrator account exists, creating it if needed.
+///
+/// This is how the first administrator is created; after that, administrators grant roles through
+/// the API.
+///
+/// # Arguments
+///
+/// * `users_service` - The users store.
+/// * `username` - The username of the administrator.
+/// * `password` - The password used if the account has to be created. An existing account keeps its
+///   password.
+///
+/// # Returns
+///
+/// An `Ok(())` result if the user exists and holds the admin role, otherwise a `UsersError`.
+///
+/// # Example
+///
+/// ```
+/// bootstrap_admin(&mut users_service, "root", "change-me")?;
+/// ```
+pub fn bootstrap_admin(users_service: &mut dyn Users, username: &str, password: &str) -> Result<(), UsersError> {
+    if users_service.get_user_by_username(username).is_none() {
+        users_service.create_user(username.to_owned(), password.to_owned())?;
+    }
+
+    let mut user = users_service.get_user_by_username(username).ok_or(UsersError::UserNotFound)?;
+
+    if !user.roles.contains(&Role::Admin) {
+        user.roles.push(Role::Admin);
+        users_service.update_user(user)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::users::UsersImpl;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_role_names() {
+        for role in [Role::Admin, Role::UserManager, Role::Auditor] {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+
+        assert_eq!("root".parse::<Role>(), Err(RbacError::UnknownRole("root".to_owned())));
+    }
+
+    #[test]
+    fn should_parse_permission_names() {
+        for permission in Role::Admin.permissions() {
+            assert_eq!(permission.as_str().parse::<Permission>(), Ok(*permission));
+        }
+
+        assert_eq!("users:reed".parse::<Permission>(), Err(RbacError::UnknownPermission("users:reed".to_owned())));
+    }
+
+    #[test]
+    fn should_union_role_permissions() {
+        let granted = permissions(&[Role::Auditor, Role::UserManager]);
+
+        assert_eq!(granted.into_iter().collect::<Vec<_>>(), vec![Permission::ReadUsers, Permission::ManageUsers, Permission::ReadAudit]);
+    }
+
+    #[test]
+    fn should_require_permission() {
+        assert!(require(&[Role::Admin], Permission::ManageRoles).is_ok());
+        assert_eq!(
+            require(&[Role::UserManager], Permission::ManageRoles),
+            Err(RbacError::PermissionDenied(Permission::ManageRoles))
+        );
+        assert!(require(&[], Permission::ReadUsers).is_err());
+    }
+
+    #[test]
+    fn should_require_all_target_permissions() {
+        assert!(require_all(&[Role::Admin], &[Role::UserManager, Role::Auditor]).is_ok());
+        assert!(require_all(&[Role::UserManager], &[]).is_ok());
+        assert_eq!(
+            require_all(&[Role::UserManager], &[Role::Auditor]),
+            Err(RbacError::PermissionDenied(Permission::ReadAudit))
+        );
+        assert_eq!(
+            require_all(&[Role::UserManager], &[Role::Admin]),
+            Err(RbacError::PermissionDenied(Permission::ManageRoles))
+        );
+    }
+
+    #[test]
+    fn should_bootstrap_admin_once() {
+        let mut users_service = UsersImpl::for_tests();
+
+        bootstrap_admin(&mut users_service, "root", "password").unwrap();
+        bootstrap_admin(&mut users_service, "root", "other password").unwrap();
+
+        let user = users_service.get_user_by_username("root").unwrap();
+        assert_eq!(user.roles, vec![Role::Admin]);
+        assert!(users_service.get_user_uuid("root".to_owned(), "password".to_owned()).is_some());
+    }
+}
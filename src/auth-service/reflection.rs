@@ -0,0 +1,52 @@
+use tonic_reflection::server::{Error, ServerReflection, ServerReflectionServer};
+
+/// The encoded descriptors of `authentication.proto` and the `google.rpc` messages it uses, written
+/// by `build.rs`.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("authentication_descriptor");
+
+/// Builds the gRPC reflection service, which lets tools such as `grpcurl` discover the `Auth`,
+/// `Admin` and `grpc.health.v1.Health` services without a copy of their proto files.
+///
+/// # Returns
+///
+/// An `Ok` result containing the reflection service, otherwise an error if a descriptor set cannot
+/// be decoded.
+///
+/// # Example
+///
+/// ```
+/// Server::builder()
+///     .add_service(reflection::service()?)
+///     .add_service(AuthServer::new(auth_service))
+///     .serve(addr)
+///     .await?;
+/// ```
+pub fn service() -> Result<ServerReflectionServer<impl ServerReflection>, Error> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use prost_types::FileDescriptorSet;
+
+    use super::*;
+
+    #[test]
+    fn should_describe_auth_and_admin_services() {
+        let descriptor_set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).unwrap();
+
+        let services: Vec<String> = descriptor_set
+            .file
+            .iter()
+            .filter(|file| file.package() == "authentication")
+            .flat_map(|file| file.service.iter().map(|service| service.name().to_owned()))
+            .collect();
+
+        assert_eq!(services, vec!["Auth", "Admin"]);
+        assert!(service().is_ok());
+    }
+}
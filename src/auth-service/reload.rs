@@ -0,0 +1,311 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    auth::AuthService,
+    config::{Args, Config},
+    shutdown::Shutdown,
+    tls::ReloadableTlsAcceptor,
+};
+
+/// How often the configuration file and TLS files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Returns the value of an environment variable.
+type Lookup = Box<dyn Fn(&str) -> Option<String> + Send>;
+
+/// `Reloader` applies changes to the configuration and TLS files while the service runs.
+///
+/// Only the TLS certificates, key and client CAs, the TTLs and the rate limits take effect without
+/// a restart. Changes to any other setting are reported and otherwise ignored.
+pub struct Reloader {
+    /// The command-line flags the service was started with. They still override the file.
+    args: Args,
+
+    /// Returns the value of an environment variable.
+    lookup: Lookup,
+
+    /// The configuration in effect.
+    config: Config,
+
+    /// The service whose TTLs and rate limits are updated.
+    auth_service: Arc<AuthService>,
+
+    /// The acceptor whose certificates are updated, if the service was started with TLS.
+    tls_acceptor: Option<Arc<ReloadableTlsAcceptor>>,
+}
+
+impl Reloader {
+
+    /// Constructs a new `Reloader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command-line flags the service was started with.
+    /// * `lookup` - Returns the value of an environment variable, e.g. `|name| env::var(name).ok()`.
+    /// * `config` - The configuration the service was started with.
+    /// * `auth_service` - The service whose TTLs and rate limits are updated.
+    /// * `tls_acceptor` - The acceptor whose certificates are updated, if TLS is enabled.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Reloader`.
+    pub fn new(
+        args: Args,
+        lookup: Lookup,
+        config: Config,
+        auth_service: Arc<AuthService>,
+        tls_acceptor: Option<Arc<ReloadableTlsAcceptor>>,
+    ) -> Self {
+        Self {
+            args,
+            lookup,
+            config,
+            auth_service,
+            tls_acceptor,
+        }
+    }
+
+    /// Returns the files whose changes trigger a reload.
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let config_path = self.args.config.clone().or_else(|| (self.lookup)("AUTH_CONFIG").map(PathBuf::from));
+
+        let tls = &self.config.tls;
+
+        [config_path, tls.cert_path.clone(), tls.key_path.clone(), tls.client_ca_path.clone()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Returns when each watched file was last modified, or `None` for files that cannot be read.
+    fn modification_times(&self) -> Vec<Option<SystemTime>> {
+        self.watched_files()
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+            .collect()
+    }
+
+    /// Loads the configuration again and applies the settings that can change without a restart.
+    ///
+    /// If the new configuration is invalid, or its TLS files cannot be used, nothing is changed.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result listing the changed settings that need a restart to take effect, otherwise a
+    /// description of why the configuration was not applied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// match reloader.reload() {
+    ///     Ok(ignored) => println!("Configuration reloaded, {} settings need a restart", ignored.len()),
+    ///     Err(e) => println!("Keeping the previous configuration: {}", e),
+    /// }
+    /// ```
+    pub fn reload(&mut self) -> Result<Vec<&'static str>, String> {
+        let config = Config::load(&self.args, &*self.lookup).map_err(|e| e.to_string())?;
+
+        let mut ignored = Vec::new();
+
+        if config.server != self.config.server {
+            ignored.push("server");
+        }
+
+        if config.storage != self.config.storage {
+            ignored.push("storage");
+        }
+
+        if config.hashing != self.config.hashing {
+            ignored.push("hashing");
+        }
+
+        if config.webauthn != self.config.webauthn {
+            ignored.push("webauthn");
+        }
+
+        if config.logging != self.config.logging {
+            ignored.push("logging");
+        }
+
+        if config.metrics != self.config.metrics {
+            ignored.push("metrics");
+        }
+
+        if config.tracing != self.config.tracing {
+            ignored.push("tracing");
+        }
+
+        if config.audit != self.config.audit {
+            ignored.push("audit");
+        }
+
+        if config.bootstrap != self.config.bootstrap {
+            ignored.push("bootstrap");
+        }
+
+        if config.encryption != self.config.encryption {
+            ignored.push("encryption");
+        }
+
+        // Switching between plaintext and TLS changes how connections are accepted.
+        let tls_switched = config.tls.cert_path.is_some() != self.tls_acceptor.is_some();
+
+        if tls_switched {
+            ignored.push("tls");
+        } else if let Some(tls_acceptor) = &self.tls_acceptor {
+            tls_acceptor.reload(&config.tls).map_err(|e| e.to_string())?;
+        }
+
+        self.auth_service.reconfigure(
+            config.ttls(),
+            Duration::from_secs(config.ttl.deletion_grace_period_seconds),
+            Duration::from_secs(config.ttl.username_reservation_seconds),
+            config.rate_limits(),
+        );
+
+        // Only the settings that were applied are kept, so the others still describe what is running
+        // and their changes are reported again until the service restarts.
+        if !tls_switched {
+            self.config.tls = config.tls;
+        }
+
+        self.config.ttl = config.ttl;
+        self.config.rate_limits = config.rate_limits;
+
+        Ok(ignored)
+    }
+
+    /// Reloads the configuration and reports the outcome.
+    fn reload_and_report(&mut self) {
+        match self.reload() {
+            Ok(ignored) if ignored.is_empty() => info!("Configuration reloaded"),
+            Ok(ignored) => warn!(
+                "Configuration reloaded; changes to [{}] need a restart to take effect",
+                ignored.join(", ")
+            ),
+            Err(e) => error!("Keeping the previous configuration: {}", e),
+        }
+    }
+
+    /// Reloads the configuration whenever the process receives SIGHUP or a watched file changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - Stops watching once the service shuts down.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// tokio::spawn(reloader.run(shutdown.clone()));
+    /// ```
+    pub async fn run(mut self, shutdown: Shutdown) {
+        let mut hangups = signal(SignalKind::hangup())
+            .map_err(|e| warn!("Reloading on SIGHUP is disabled: {}", e))
+            .ok();
+
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        let mut modification_times = self.modification_times();
+
+        loop {
+            tokio::select! {
+                Some(_) = async {
+                    match &mut hangups {
+                        Some(hangups) => hangups.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    info!("Received SIGHUP, reloading configuration");
+                    self.reload_and_report();
+                }
+                _ = interval.tick() => {
+                    if self.modification_times() == modification_times {
+                        continue;
+                    }
+
+                    info!("Configuration files changed, reloading configuration");
+                    self.reload_and_report();
+                }
+                _ = shutdown.wait() => return,
+            }
+
+            // The TLS paths may have changed with the configuration.
+            modification_times = self.modification_times();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{sessions::SessionsImpl, users::UsersImpl};
+
+    use super::*;
+
+    /// Writes a configuration file and returns a reloader watching it.
+    fn reloader(contents: &str) -> (Reloader, PathBuf) {
+        let path = std::env::temp_dir().join(format!("reload-{}.toml", uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+
+        let args = Args { config: Some(path.clone()), ..Args::default() };
+        let config = Config::load(&args, &|_| None).unwrap();
+
+        let auth_service = Arc::new(AuthService::new(
+            Box::new(Mutex::new(UsersImpl::for_tests())),
+            Box::new(Mutex::new(SessionsImpl::default())),
+        ));
+
+        (Reloader::new(args, Box::new(|_| None), config, auth_service, None), path)
+    }
+
+    #[test]
+    fn should_apply_changed_settings() {
+        let (mut reloader, path) = reloader("[rate_limits]\nsign_in_per_minute = 5\n");
+
+        assert_eq!(reloader.watched_files(), vec![path.clone()]);
+
+        fs::write(&path, "[rate_limits]\nsign_in_per_minute = 1\n\n[ttl]\nsession_seconds = 60\n").unwrap();
+        let ignored = reloader.reload().unwrap();
+
+        assert!(ignored.is_empty());
+        assert_eq!(reloader.config.rate_limits.sign_in_per_minute, 1);
+        assert_eq!(reloader.config.ttls().session, Some(Duration::from_secs(60)));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_report_settings_needing_restart() {
+        let (mut reloader, path) = reloader("");
+
+        fs::write(&path, "[server]\nbind_address = \"127.0.0.1:6000\"\n\n[hashing]\npbkdf2_rounds = 700000\n").unwrap();
+
+        assert_eq!(reloader.reload(), Ok(vec!["server", "hashing"]));
+
+        // The ignored settings keep describing the running service.
+        assert_eq!(reloader.config.server, Config::default().server);
+        assert_eq!(reloader.reload(), Ok(vec!["server", "hashing"]));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_keep_configuration_when_invalid() {
+        let (mut reloader, path) = reloader("[rate_limits]\nsign_in_per_minute = 5\n");
+
+        fs::write(&path, "[rate_limits]\nsign_in_per_minute = \"many\"\n").unwrap();
+
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.config.rate_limits.sign_in_per_minute, 5);
+
+        fs::remove_file(path).unwrap();
+    }
+}
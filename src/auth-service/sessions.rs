@@ -1,7 +1,80 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
 
+use tracing::instrument;
 use uuid::Uuid;
 
+use crate::webauthn::{self, WebauthnCeremony};
+
+/// How long a user has to complete the second sign-in step after entering their password.
+pub const MFA_CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a browser has to complete a WebAuthn ceremony after it was started.
+pub const WEBAUTHN_CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a user has to choose a new password after an administrator forced a reset.
+pub const PASSWORD_RESET_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `Ttls` struct holds how long each kind of token issued by `SessionsImpl` stays valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ttls {
+    /// How long a session lasts. `None` keeps sessions until the user signs out.
+    pub session: Option<Duration>,
+
+    /// How long an MFA challenge lasts, `MFA_CHALLENGE_TTL` by default.
+    pub mfa_challenge: Duration,
+
+    /// How long a WebAuthn challenge lasts, `WEBAUTHN_CHALLENGE_TTL` by default.
+    pub webauthn_challenge: Duration,
+
+    /// How long a password reset token lasts, `PASSWORD_RESET_TTL` by default.
+    pub password_reset: Duration,
+}
+
+impl Default for Ttls {
+    fn default() -> Self {
+        Self {
+            session: None,
+            mfa_challenge: MFA_CHALLENGE_TTL,
+            webauthn_challenge: WEBAUTHN_CHALLENGE_TTL,
+            password_reset: PASSWORD_RESET_TTL,
+        }
+    }
+}
+
+/// `SessionsError` enumerates the ways an operation on the `Sessions` store can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum SessionsError {
+    /// The session token does not belong to any active session.
+    SessionNotFound,
+
+    /// The MFA challenge token is unknown, already used or expired.
+    MfaChallengeNotFound,
+
+    /// The WebAuthn challenge is unknown, already used, expired or for the other ceremony.
+    WebauthnChallengeNotFound,
+
+    /// The password reset token is unknown, already used or expired.
+    PasswordResetNotFound,
+}
+
+impl fmt::Display for SessionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionsError::SessionNotFound => write!(f, "Session not found."),
+            SessionsError::MfaChallengeNotFound => write!(f, "MFA challenge not found or expired."),
+            SessionsError::WebauthnChallengeNotFound => write!(f, "WebAuthn challenge not found or expired."),
+            SessionsError::PasswordResetNotFound => write!(f, "Password reset token not found or expired."),
+        }
+    }
+}
+
+impl std::error::Error for SessionsError {}
+
 /// `Sessions` trait defines methods for managing user sessions.
 pub trait Sessions {
 
@@ -38,6 +111,204 @@ pub trait Sessions {
     /// println!("Session deleted successfully.");
     /// ```
     fn delete_session(&mut self, user_uuid: &str);
+
+    /// Retrieves the UUID of the user who owns the specified session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - A string representing the session token to look up.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user if the session exists and has not expired,
+    /// otherwise `SessionsError::SessionNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// match sessions_service.get_user_uuid("session_token") {
+    ///     Ok(uuid) => println!("Session belongs to: {}", uuid),
+    ///     Err(error) => println!("{}", error),
+    /// }
+    /// ```
+    fn get_user_uuid(&self, session_token: &str) -> Result<String, SessionsError>;
+
+    /// Creates a short-lived MFA challenge for a user who has passed the password step of sign-in.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user being challenged.
+    ///
+    /// # Returns
+    ///
+    /// A string representing the challenge token, valid for the configured MFA challenge TTL.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let challenge_token = sessions_service.create_mfa_challenge("user_uuid");
+    /// ```
+    fn create_mfa_challenge(&mut self, user_uuid: &str) -> String;
+
+    /// Consumes an MFA challenge. A challenge can only be taken once, whether or not the code that
+    /// accompanies it turns out to be valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge_token` - A string representing the challenge token returned by `create_mfa_challenge`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the challenged user, otherwise
+    /// `SessionsError::MfaChallengeNotFound` if the challenge is unknown or expired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let user_uuid = sessions_service.take_mfa_challenge("challenge_token")?;
+    /// ```
+    fn take_mfa_challenge(&mut self, challenge_token: &str) -> Result<String, SessionsError>;
+
+    /// Creates a challenge for a WebAuthn ceremony started on behalf of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user the ceremony is for.
+    /// * `ceremony` - Whether the challenge is for registering or authenticating with a passkey.
+    ///
+    /// # Returns
+    ///
+    /// The raw challenge bytes to hand to the browser, valid for the configured WebAuthn challenge
+    /// TTL.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let challenge = sessions_service.create_webauthn_challenge("user_uuid", WebauthnCeremony::Registration);
+    /// ```
+    fn create_webauthn_challenge(&mut self, user_uuid: &str, ceremony: WebauthnCeremony) -> Vec<u8>;
+
+    /// Consumes a WebAuthn challenge. A challenge can only be taken once, whether or not the
+    /// response that echoes it turns out to be valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge` - The base64url encoded challenge, as found in `clientDataJSON`.
+    /// * `ceremony` - The ceremony the challenge is expected to belong to.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user the ceremony is for, otherwise
+    /// `SessionsError::WebauthnChallengeNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let user_uuid = sessions_service.take_webauthn_challenge("challenge", WebauthnCeremony::Authentication)?;
+    /// ```
+    fn take_webauthn_challenge(&mut self, challenge: &str, ceremony: WebauthnCeremony) -> Result<String, SessionsError>;
+
+    /// Creates a single-use token a user can redeem to choose a new password.
+    ///
+    /// Any earlier reset token of the user stops working.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose password is to be reset.
+    ///
+    /// # Returns
+    ///
+    /// A string representing the reset token, valid for the configured password reset TTL.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let reset_token = sessions_service.create_password_reset("user_uuid");
+    /// ```
+    fn create_password_reset(&mut self, user_uuid: &str) -> String;
+
+    /// Consumes a password reset token.
+    ///
+    /// # Arguments
+    ///
+    /// * `reset_token` - A string representing the token returned by `create_password_reset`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user, otherwise
+    /// `SessionsError::PasswordResetNotFound` if the token is unknown or expired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let user_uuid = sessions_service.take_password_reset("reset_token")?;
+    /// ```
+    fn take_password_reset(&mut self, reset_token: &str) -> Result<String, SessionsError>;
+
+    /// Changes how long tokens stay valid. Tokens that were already issued keep their expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttls` - How long each kind of token stays valid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// sessions_service.set_ttls(Ttls { session: Some(Duration::from_secs(3600)), ..Ttls::default() });
+    /// ```
+    fn set_ttls(&mut self, ttls: Ttls);
+
+    /// Counts the sessions that have not expired or been signed out.
+    ///
+    /// # Returns
+    ///
+    /// The number of active sessions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_service` implements `Sessions` trait
+    /// let active_sessions = sessions_service.count_sessions();
+    /// ```
+    fn count_sessions(&self) -> usize;
+}
+
+/// `MfaChallenge` struct represents a pending second sign-in step.
+struct MfaChallenge {
+    /// The UUID of the challenged user.
+    user_uuid: String,
+
+    /// The instant after which the challenge can no longer be completed.
+    expires_at: Instant,
+}
+
+/// `WebauthnChallenge` struct represents a pending WebAuthn ceremony.
+struct WebauthnChallenge {
+    /// The UUID of the user the ceremony is for.
+    user_uuid: String,
+
+    /// The ceremony the challenge was issued for.
+    ceremony: WebauthnCeremony,
+
+    /// The instant after which the challenge can no longer be completed.
+    expires_at: Instant,
+}
+
+/// `PasswordReset` struct represents a password reset an administrator has forced on a user.
+struct PasswordReset {
+    /// The UUID of the user whose password is to be reset.
+    user_uuid: String,
+
+    /// The instant after which the token can no longer be redeemed.
+    expires_at: Instant,
 }
 
 /// `SessionsImpl` represents an implementation of the `Sessions` trait.
@@ -48,6 +319,52 @@ pub struct SessionsImpl {
 
     /// A HashMap that maps user UUIDs to session tokens.
     uuid_to_session: HashMap<String, String>,
+
+    /// A HashMap that maps session tokens back to user UUIDs.
+    session_to_uuid: HashMap<String, String>,
+
+    /// A HashMap that maps session tokens to the instant they expire, if sessions expire.
+    session_expiry: HashMap<String, Instant>,
+
+    /// A HashMap that maps MFA challenge tokens to pending challenges.
+    mfa_challenges: HashMap<String, MfaChallenge>,
+
+    /// A HashMap that maps base64url encoded WebAuthn challenges to pending ceremonies.
+    webauthn_challenges: HashMap<String, WebauthnChallenge>,
+
+    /// A HashMap that maps password reset tokens to pending resets.
+    password_resets: HashMap<String, PasswordReset>,
+
+    /// How long each kind of token stays valid.
+    ttls: Ttls,
+}
+
+impl SessionsImpl {
+
+    /// Constructs an empty `SessionsImpl` whose tokens last for the given times.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttls` - How long each kind of token stays valid.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `SessionsImpl`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let sessions_service = SessionsImpl::with_ttls(Ttls {
+    ///     session: Some(Duration::from_secs(12 * 60 * 60)),
+    ///     ..Ttls::default()
+    /// });
+    /// ```
+    pub fn with_ttls(ttls: Ttls) -> Self {
+        Self {
+            ttls,
+            ..Self::default()
+        }
+    }
 }
 
 impl Sessions for SessionsImpl {
@@ -69,9 +386,21 @@ impl Sessions for SessionsImpl {
     /// let session_token = sessions_impl.create_session("user_uuid");
     /// println!("Created session with token: {}", session_token);
     /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
     fn create_session(&mut self, user_uuid: &str) -> String {
         let session: String = Uuid::new_v4().to_string();
-        self.uuid_to_session.insert(user_uuid.to_owned(), session.clone());
+
+        // A user only holds one session at a time, so forget the token being replaced.
+        if let Some(previous) = self.uuid_to_session.insert(user_uuid.to_owned(), session.clone()) {
+            self.session_to_uuid.remove(&previous);
+            self.session_expiry.remove(&previous);
+        }
+        self.session_to_uuid.insert(session.clone(), user_uuid.to_owned());
+
+        if let Some(ttl) = self.ttls.session {
+            self.session_expiry.insert(session.clone(), Instant::now() + ttl);
+        }
+
         session
     }
 
@@ -88,10 +417,263 @@ impl Sessions for SessionsImpl {
     /// sessions_impl.delete_session("user_uuid");
     /// println!("Session deleted successfully.");
     /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
     fn delete_session(&mut self, user_uuid: &str) {
-        if self.uuid_to_session.contains_key(user_uuid) {
-            self.uuid_to_session.remove(user_uuid);
+        if let Some(session) = self.uuid_to_session.remove(user_uuid) {
+            self.session_to_uuid.remove(&session);
+            self.session_expiry.remove(&session);
+        }
+    }
+
+    /// Retrieves the UUID of the user who owns the specified session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - A string representing the session token to look up.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user if the session exists, otherwise
+    /// `SessionsError::SessionNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let user_uuid = sessions_impl.get_user_uuid("session_token");
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn get_user_uuid(&self, session_token: &str) -> Result<String, SessionsError> {
+        if self.session_expiry.get(session_token).is_some_and(|expires_at| *expires_at <= Instant::now()) {
+            return Err(SessionsError::SessionNotFound);
         }
+
+        self.session_to_uuid
+            .get(session_token)
+            .cloned()
+            .ok_or(SessionsError::SessionNotFound)
+    }
+
+    /// Creates a short-lived MFA challenge for the specified user UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user being challenged.
+    ///
+    /// # Returns
+    ///
+    /// A string representing the challenge token.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let challenge_token = sessions_impl.create_mfa_challenge("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn create_mfa_challenge(&mut self, user_uuid: &str) -> String {
+        let now = Instant::now();
+
+        // Abandoned challenges are swept here so they do not accumulate.
+        self.mfa_challenges.retain(|_, challenge| challenge.expires_at > now);
+
+        let token = Uuid::new_v4().to_string();
+        self.mfa_challenges.insert(
+            token.clone(),
+            MfaChallenge {
+                user_uuid: user_uuid.to_owned(),
+                expires_at: now + self.ttls.mfa_challenge,
+            },
+        );
+
+        token
+    }
+
+    /// Consumes an MFA challenge.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge_token` - A string representing the challenge token.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the challenged user, otherwise
+    /// `SessionsError::MfaChallengeNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let user_uuid = sessions_impl.take_mfa_challenge("challenge_token")?;
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn take_mfa_challenge(&mut self, challenge_token: &str) -> Result<String, SessionsError> {
+        match self.mfa_challenges.remove(challenge_token) {
+            Some(challenge) if challenge.expires_at > Instant::now() => Ok(challenge.user_uuid),
+            _ => Err(SessionsError::MfaChallengeNotFound),
+        }
+    }
+
+    /// Creates a challenge for a WebAuthn ceremony.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user the ceremony is for.
+    /// * `ceremony` - Whether the challenge is for registering or authenticating with a passkey.
+    ///
+    /// # Returns
+    ///
+    /// The raw challenge bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let challenge = sessions_impl.create_webauthn_challenge("user_uuid", WebauthnCeremony::Registration);
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn create_webauthn_challenge(&mut self, user_uuid: &str, ceremony: WebauthnCeremony) -> Vec<u8> {
+        let now = Instant::now();
+
+        // Abandoned ceremonies are swept here so they do not accumulate.
+        self.webauthn_challenges.retain(|_, challenge| challenge.expires_at > now);
+
+        let challenge = webauthn::generate_challenge();
+        self.webauthn_challenges.insert(
+            webauthn::encode(&challenge),
+            WebauthnChallenge {
+                user_uuid: user_uuid.to_owned(),
+                ceremony,
+                expires_at: now + self.ttls.webauthn_challenge,
+            },
+        );
+
+        challenge
+    }
+
+    /// Consumes a WebAuthn challenge.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge` - The base64url encoded challenge.
+    /// * `ceremony` - The ceremony the challenge is expected to belong to.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user the ceremony is for, otherwise
+    /// `SessionsError::WebauthnChallengeNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let user_uuid = sessions_impl.take_webauthn_challenge("challenge", WebauthnCeremony::Authentication)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn take_webauthn_challenge(&mut self, challenge: &str, ceremony: WebauthnCeremony) -> Result<String, SessionsError> {
+        match self.webauthn_challenges.remove(challenge) {
+            Some(pending) if pending.ceremony == ceremony && pending.expires_at > Instant::now() => Ok(pending.user_uuid),
+            _ => Err(SessionsError::WebauthnChallengeNotFound),
+        }
+    }
+
+    /// Creates a single-use password reset token.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user whose password is to be reset.
+    ///
+    /// # Returns
+    ///
+    /// A string representing the reset token.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let reset_token = sessions_impl.create_password_reset("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn create_password_reset(&mut self, user_uuid: &str) -> String {
+        let now = Instant::now();
+
+        // Expired tokens and the user's previous token are dropped.
+        self.password_resets.retain(|_, reset| reset.expires_at > now && reset.user_uuid != user_uuid);
+
+        let token = Uuid::new_v4().to_string();
+        self.password_resets.insert(
+            token.clone(),
+            PasswordReset {
+                user_uuid: user_uuid.to_owned(),
+                expires_at: now + self.ttls.password_reset,
+            },
+        );
+
+        token
+    }
+
+    /// Consumes a password reset token.
+    ///
+    /// # Arguments
+    ///
+    /// * `reset_token` - A string representing the reset token.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result containing the UUID of the user, otherwise
+    /// `SessionsError::PasswordResetNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let user_uuid = sessions_impl.take_password_reset("reset_token")?;
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn take_password_reset(&mut self, reset_token: &str) -> Result<String, SessionsError> {
+        match self.password_resets.remove(reset_token) {
+            Some(reset) if reset.expires_at > Instant::now() => Ok(reset.user_uuid),
+            _ => Err(SessionsError::PasswordResetNotFound),
+        }
+    }
+
+    /// Changes how long tokens stay valid. Tokens that were already issued keep their expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttls` - How long each kind of token stays valid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// sessions_impl.set_ttls(Ttls::default());
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn set_ttls(&mut self, ttls: Ttls) {
+        self.ttls = ttls;
+    }
+
+    /// Counts the sessions that have not expired or been signed out.
+    ///
+    /// # Returns
+    ///
+    /// The number of active sessions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `sessions_impl` is an instance of `SessionsImpl`
+    /// let active_sessions = sessions_impl.count_sessions();
+    /// ```
+    #[instrument(skip_all, fields(store = "sessions"))]
+    fn count_sessions(&self) -> usize {
+        let now = Instant::now();
+
+        self.session_to_uuid
+            .keys()
+            .filter(|session| self.session_expiry.get(*session).is_none_or(|expires_at| *expires_at > now))
+            .count()
     }
 }
 
@@ -114,5 +696,110 @@ mod tests {
         session_service.create_session("123456");
         session_service.delete_session("123456");
         assert_eq!(session_service.uuid_to_session.len(), 0);
+        assert_eq!(session_service.session_to_uuid.len(), 0);
+    }
+
+    #[test]
+    fn should_expire_sessions() {
+        let mut session_service = SessionsImpl::with_ttls(Ttls {
+            session: Some(Duration::from_secs(60)),
+            ..Ttls::default()
+        });
+        let session = session_service.create_session("123456");
+        assert_eq!(session_service.get_user_uuid(&session).unwrap(), "123456");
+        assert_eq!(session_service.count_sessions(), 1);
+
+        *session_service.session_expiry.get_mut(&session).unwrap() = Instant::now();
+        assert_eq!(session_service.get_user_uuid(&session), Err(SessionsError::SessionNotFound));
+        assert_eq!(session_service.count_sessions(), 0);
+
+        session_service.delete_session("123456");
+        assert!(session_service.session_expiry.is_empty());
+    }
+
+    #[test]
+    fn should_apply_changed_ttls_to_new_sessions() {
+        let mut session_service = SessionsImpl::default();
+        let unlimited = session_service.create_session("123456");
+
+        session_service.set_ttls(Ttls {
+            session: Some(Duration::from_secs(60)),
+            ..Ttls::default()
+        });
+        let limited = session_service.create_session("654321");
+
+        assert!(!session_service.session_expiry.contains_key(&unlimited));
+        assert!(session_service.session_expiry.contains_key(&limited));
+    }
+
+    #[test]
+    fn should_retrieve_user_uuid_from_session() {
+        let mut session_service = SessionsImpl::default();
+        let session = session_service.create_session("123456");
+        assert_eq!(session_service.get_user_uuid(&session).unwrap(), "123456");
+        assert_eq!(session_service.get_user_uuid("unknown"), Err(SessionsError::SessionNotFound));
+    }
+
+    #[test]
+    fn should_replace_previous_session() {
+        let mut session_service = SessionsImpl::default();
+        let first = session_service.create_session("123456");
+        let second = session_service.create_session("123456");
+        assert!(session_service.get_user_uuid(&first).is_err());
+        assert_eq!(session_service.get_user_uuid(&second).unwrap(), "123456");
+    }
+
+    #[test]
+    fn should_take_mfa_challenge_once() {
+        let mut session_service = SessionsImpl::default();
+        let challenge = session_service.create_mfa_challenge("123456");
+        assert_eq!(session_service.take_mfa_challenge(&challenge).unwrap(), "123456");
+        assert_eq!(session_service.take_mfa_challenge(&challenge), Err(SessionsError::MfaChallengeNotFound));
+    }
+
+    #[test]
+    fn should_reject_expired_mfa_challenge() {
+        let mut session_service = SessionsImpl::default();
+        let challenge = session_service.create_mfa_challenge("123456");
+        session_service.mfa_challenges.get_mut(&challenge).unwrap().expires_at = Instant::now();
+        assert_eq!(session_service.take_mfa_challenge(&challenge), Err(SessionsError::MfaChallengeNotFound));
+    }
+
+    #[test]
+    fn should_take_webauthn_challenge_once() {
+        let mut session_service = SessionsImpl::default();
+        let challenge = webauthn::encode(&session_service.create_webauthn_challenge("123456", WebauthnCeremony::Registration));
+        assert_eq!(session_service.take_webauthn_challenge(&challenge, WebauthnCeremony::Registration).unwrap(), "123456");
+        assert_eq!(
+            session_service.take_webauthn_challenge(&challenge, WebauthnCeremony::Registration),
+            Err(SessionsError::WebauthnChallengeNotFound)
+        );
+    }
+
+    #[test]
+    fn should_reject_webauthn_challenge_for_other_ceremony() {
+        let mut session_service = SessionsImpl::default();
+        let challenge = webauthn::encode(&session_service.create_webauthn_challenge("123456", WebauthnCeremony::Registration));
+        assert_eq!(
+            session_service.take_webauthn_challenge(&challenge, WebauthnCeremony::Authentication),
+            Err(SessionsError::WebauthnChallengeNotFound)
+        );
+    }
+
+    #[test]
+    fn should_take_password_reset_once() {
+        let mut session_service = SessionsImpl::default();
+        let reset_token = session_service.create_password_reset("123456");
+        assert_eq!(session_service.take_password_reset(&reset_token).unwrap(), "123456");
+        assert_eq!(session_service.take_password_reset(&reset_token), Err(SessionsError::PasswordResetNotFound));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_replace_previous_password_reset() {
+        let mut session_service = SessionsImpl::default();
+        let first = session_service.create_password_reset("123456");
+        let second = session_service.create_password_reset("123456");
+        assert_eq!(session_service.take_password_reset(&first), Err(SessionsError::PasswordResetNotFound));
+        assert_eq!(session_service.take_password_reset(&second).unwrap(), "123456");
+    }
+}
@@ -0,0 +1,124 @@
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+/// `Shutdown` lets a task find out that the service is shutting down. Clones all observe the same
+/// shutdown.
+#[derive(Clone)]
+pub struct Shutdown {
+    /// Holds `true` once shutdown has been triggered.
+    receiver: watch::Receiver<bool>,
+}
+
+/// `ShutdownTrigger` starts the shutdown observed by its `Shutdown` handles.
+pub struct ShutdownTrigger {
+    /// Set to `true` to start shutting down.
+    sender: watch::Sender<bool>,
+}
+
+/// Creates a shutdown trigger and a handle to observe it.
+///
+/// # Returns
+///
+/// The trigger and a `Shutdown` handle, which can be cloned for each task.
+///
+/// # Example
+///
+/// ```
+/// let (trigger, shutdown) = shutdown::channel();
+/// tokio::spawn(async move { shutdown.wait().await; println!("Stopping"); });
+/// trigger.trigger();
+/// ```
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+
+    (ShutdownTrigger { sender }, Shutdown { receiver })
+}
+
+impl ShutdownTrigger {
+
+    /// Starts shutting down, waking every task waiting on a `Shutdown` handle.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+}
+
+impl Shutdown {
+
+    /// Waits until shutdown is triggered. Returns straight away if it already has been, or if the
+    /// trigger was dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// tokio::select! {
+    ///     _ = interval.tick() => do_work(),
+    ///     _ = shutdown.wait() => return,
+    /// }
+    /// ```
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Waits until the process is asked to stop with SIGTERM, as sent by `docker stop`, or SIGINT, as
+/// sent by pressing `CTRL-C`.
+///
+/// # Returns
+///
+/// The name of the signal received.
+///
+/// # Errors
+///
+/// This function returns an error if the signal handlers cannot be installed.
+pub async fn signal_received() -> std::io::Result<&'static str> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = terminate.recv() => Ok("SIGTERM"),
+        _ = interrupt.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_wake_every_waiter() {
+        let (trigger, shutdown) = channel();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move { shutdown.wait().await })
+            })
+            .collect();
+
+        trigger.trigger();
+
+        for waiter in waiters {
+            tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        }
+
+        // Handles that start waiting afterwards return straight away.
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_wait_until_triggered() {
+        let (_trigger, shutdown) = channel();
+
+        assert!(tokio::time::timeout(Duration::from_millis(50), shutdown.wait()).await.is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use http::HeaderMap;
+use opentelemetry::{
+    global,
+    propagation::{Extractor, TextMapPropagator},
+    trace::TraceError,
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing::{warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::config::TracingConfig;
+
+/// `HeaderExtractor` reads the trace context a client propagated from the headers of its request.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Builds the tracer whose spans are exported over OTLP, and installs its provider globally so
+/// that `shutdown` can flush it.
+///
+/// Spans are batched and sent in the background, so a collector that is down only loses spans.
+/// Export failures are logged as warnings.
+///
+/// # Arguments
+///
+/// * `tracing` - Where spans are exported and the service name they are exported under.
+///
+/// # Returns
+///
+/// An `Ok` result containing the tracer, otherwise a `TraceError` if the exporter cannot be built.
+///
+/// # Example
+///
+/// ```
+/// let tracer = telemetry::tracer(&config.tracing)?;
+/// logging::init(&config.logging, Some(tracer));
+/// ```
+pub fn tracer(tracing: &TracingConfig) -> Result<trace::Tracer, TraceError> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(&tracing.otlp_endpoint);
+
+    // OpenTelemetry writes its errors to stderr unless told otherwise.
+    global::set_error_handler(|e| warn!("Tracing error: {}", e)).map_err(|e| TraceError::Other(Box::new(e)))?;
+
+    let resource = Resource::new([KeyValue::new("service.name", tracing.service_name.clone())]);
+
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace::config().with_resource(resource))
+        .install_batch(runtime::Tokio)
+}
+
+/// Exports the spans still waiting in the batch. Does nothing if no tracer was built.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Continues the trace of the client that sent a request: the span of the RPC becomes a child of
+/// the span the client propagated in the W3C `traceparent` and `tracestate` headers, if any, and
+/// carries the OpenTelemetry RPC attributes.
+///
+/// # Arguments
+///
+/// * `span` - The span of the RPC.
+/// * `headers` - The headers, i.e. the gRPC metadata, of the request.
+/// * `method` - The path of the RPC, e.g. `/authentication.Auth/SignIn`.
+pub fn continue_trace(span: &Span, headers: &HeaderMap, method: &str) {
+    span.set_parent(TraceContextPropagator::new().extract(&HeaderExtractor(headers)));
+
+    let (service, method) = method.trim_start_matches('/').split_once('/').unwrap_or(("", method));
+
+    span.set_attribute("rpc.system", "grpc");
+    span.set_attribute("rpc.service", service.to_owned());
+    span.set_attribute("rpc.method", method.to_owned());
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanId, TraceContextExt, TraceId};
+
+    use super::*;
+
+    #[test]
+    fn should_extract_trace_context() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap());
+
+        let context = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        let span_context = context.span().span_context().clone();
+
+        assert!(span_context.is_remote());
+        assert!(span_context.is_sampled());
+        assert_eq!(span_context.trace_id(), TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap());
+        assert_eq!(span_context.span_id(), SpanId::from_hex("00f067aa0ba902b7").unwrap());
+
+        let context = TraceContextPropagator::new().extract(&HeaderExtractor(&HeaderMap::new()));
+        assert!(!context.span().span_context().is_valid());
+    }
+}
@@ -0,0 +1,292 @@
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    rustls::{pki_types::CertificateDer, server::WebPkiClientVerifier, RootCertStore, ServerConfig},
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, warn};
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::config::TlsConfig;
+
+/// How long a client has to complete the TLS handshake before its connection is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many handshaken connections may wait for the server to pick them up.
+const PENDING_CONNECTIONS: usize = 128;
+
+/// `TlsError` enumerates the ways loading the server's TLS settings can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TlsError {
+    /// A certificate or key file could not be read. Carries the path and the cause.
+    Read(PathBuf, String),
+
+    /// A file that should hold certificates holds none. Carries the path.
+    NoCertificates(PathBuf),
+
+    /// The key file holds no private key. Carries the path.
+    NoPrivateKey(PathBuf),
+
+    /// The certificates and key cannot be used together, or a CA certificate is unusable. Carries
+    /// the cause.
+    Invalid(String),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Read(path, cause) => write!(f, "Could not read {}: {}", path.display(), cause),
+            TlsError::NoCertificates(path) => write!(f, "{} holds no PEM certificates.", path.display()),
+            TlsError::NoPrivateKey(path) => write!(f, "{} holds no PEM private key.", path.display()),
+            TlsError::Invalid(cause) => write!(f, "Invalid TLS settings: {}", cause),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+/// Reads the PEM encoded certificates in a file.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+///
+/// # Returns
+///
+/// An `Ok` result containing at least one certificate, otherwise a `TlsError`.
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem = fs::read(path).map_err(|e| TlsError::Read(path.to_owned(), e.to_string()))?;
+
+    let certificates = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Read(path.to_owned(), e.to_string()))?;
+
+    match certificates.is_empty() {
+        true => Err(TlsError::NoCertificates(path.to_owned())),
+        false => Ok(certificates),
+    }
+}
+
+/// Builds the rustls settings of the server from its TLS configuration.
+///
+/// # Arguments
+///
+/// * `tls` - The paths of the certificate chain, private key and, for mutual TLS, client CAs.
+///
+/// # Returns
+///
+/// An `Ok` result containing the settings, otherwise a `TlsError` if a file cannot be read or the
+/// files do not fit together.
+///
+/// # Example
+///
+/// ```
+/// let server_config = tls::server_config(&config.tls)?;
+/// let acceptor = ReloadableTlsAcceptor::new(server_config);
+/// ```
+pub fn server_config(tls: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let (Some(cert_path), Some(key_path)) = (&tls.cert_path, &tls.key_path) else {
+        return Err(TlsError::Invalid("tls.cert_path and tls.key_path must be set".to_owned()));
+    };
+
+    let certificates = read_certificates(cert_path)?;
+
+    let key_pem = fs::read(key_path).map_err(|e| TlsError::Read(key_path.to_owned(), e.to_string()))?;
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| TlsError::Read(key_path.to_owned(), e.to_string()))?
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.to_owned()))?;
+
+    let builder = ServerConfig::builder();
+
+    // With a client CA every connection must present a certificate it signed.
+    let builder = match &tls.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in read_certificates(client_ca_path)? {
+                roots.add(certificate).map_err(|e| TlsError::Invalid(e.to_string()))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|e| TlsError::Invalid(e.to_string()))?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certificates, key)
+        .map_err(|e| TlsError::Invalid(e.to_string()))?;
+
+    // gRPC runs over HTTP/2.
+    server_config.alpn_protocols = vec![b"h2".to_vec()];
+
+    Ok(Arc::new(server_config))
+}
+
+/// `ReloadableTlsAcceptor` accepts TLS connections with settings that can be replaced while the
+/// server runs. Each connection uses the settings current when it was accepted, so replacing them
+/// leaves established connections untouched.
+pub struct ReloadableTlsAcceptor {
+    /// The settings new connections are accepted with.
+    server_config: RwLock<Arc<ServerConfig>>,
+}
+
+impl ReloadableTlsAcceptor {
+
+    /// Constructs a new `ReloadableTlsAcceptor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_config` - The settings to accept connections with.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `ReloadableTlsAcceptor`.
+    pub fn new(server_config: Arc<ServerConfig>) -> Self {
+        Self {
+            server_config: RwLock::new(server_config),
+        }
+    }
+
+    /// Reads the certificates and key again and uses them for new connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `tls` - The paths of the certificate chain, private key and, for mutual TLS, client CAs.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the settings were replaced, otherwise a `TlsError`, in which case the
+    /// previous settings stay in use.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// if let Err(e) = acceptor.reload(&config.tls) {
+    ///     println!("Keeping the previous certificate: {}", e);
+    /// }
+    /// ```
+    pub fn reload(&self, tls: &TlsConfig) -> Result<(), TlsError> {
+        let server_config = server_config(tls)?;
+
+        *self.server_config.write().expect("lock should not be tampered") = server_config;
+
+        Ok(())
+    }
+
+    /// Returns an acceptor using the current settings.
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.server_config.read().expect("lock should not be tampered").clone())
+    }
+}
+
+/// Accepts TLS connections on a listener.
+///
+/// Handshakes run in their own tasks, so a slow or failing client does not hold up the others.
+/// Connections whose handshake fails are logged at `debug` and dropped. The listener is closed once the
+/// stream is dropped, which the server does when it shuts down.
+///
+/// # Arguments
+///
+/// * `listener` - The bound listener to accept connections on.
+/// * `acceptor` - The acceptor whose current settings each connection is accepted with.
+///
+/// # Returns
+///
+/// A stream of established connections to pass to `Router::serve_with_incoming`.
+///
+/// # Example
+///
+/// ```
+/// let listener = TcpListener::bind(addr).await?;
+/// Server::builder()
+///     .add_service(AuthServer::new(auth_service))
+///     .serve_with_incoming(tls::incoming(listener, acceptor))
+///     .await?;
+/// ```
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: Arc<ReloadableTlsAcceptor>,
+) -> ReceiverStream<Result<TlsStream<TcpStream>, io::Error>> {
+    let (sender, receiver) = mpsc::channel(PENDING_CONNECTIONS);
+
+    tokio::spawn(async move {
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = sender.closed() => return,
+            };
+
+            let (stream, peer) = match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    // Usually the process is out of file descriptors; give connections time to close.
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let _ = stream.set_nodelay(true);
+            let tls_acceptor = acceptor.acceptor();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls_acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = sender.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_report_missing_files() {
+        let tls = TlsConfig {
+            cert_path: Some(PathBuf::from("/nonexistent/cert.pem")),
+            key_path: Some(PathBuf::from("/nonexistent/key.pem")),
+            client_ca_path: None,
+        };
+
+        assert!(matches!(server_config(&tls), Err(TlsError::Read(path, _)) if path == Path::new("/nonexistent/cert.pem")));
+    }
+
+    #[test]
+    fn should_reject_files_without_certificates() {
+        let path = std::env::temp_dir().join(format!("{}.pem", uuid::Uuid::new_v4()));
+        fs::write(&path, "not a certificate").unwrap();
+
+        let tls = TlsConfig {
+            cert_path: Some(path.clone()),
+            key_path: Some(path.clone()),
+            client_ca_path: None,
+        };
+        let result = server_config(&tls);
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.err(), Some(TlsError::NoCertificates(path)));
+    }
+}
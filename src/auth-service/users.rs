@@ -1,11 +1,207 @@
 use pbkdf2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Pbkdf2,
+    Algorithm, Params, Pbkdf2,
 };
 use rand_core::OsRng;
+use tracing::instrument;
 use uuid::Uuid;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, time::SystemTime};
+
+use crate::{rbac::Role, webauthn::PasskeyCredential};
+
+/// `UsersError` enumerates the ways an operation on the `Users` store can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UsersError {
+    /// The requested username is already taken by another user.
+    UsernameTaken,
+
+    /// The password could not be hashed.
+    HashingFailed(String),
+
+    /// The supplied username and password do not match a user.
+    InvalidCredentials,
+
+    /// No user exists with the requested UUID.
+    UserNotFound,
+
+    /// The account has been disabled by an administrator.
+    AccountDisabled,
+
+    /// The account has been locked.
+    AccountLocked,
+
+    /// The account has not been verified yet.
+    AccountNotVerified,
+
+    /// An administrator has required the user to reset their password before signing in.
+    PasswordResetRequired,
+
+    /// A profile field is malformed or too long. Carries a description of the problem.
+    InvalidProfile(String),
+
+    /// Another user already has the requested UUID.
+    UserUuidTaken,
+
+    /// A supplied password hash is not a PBKDF2 hash in PHC string format.
+    InvalidPasswordHash,
+}
+
+impl fmt::Display for UsersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsersError::UsernameTaken => write!(f, "Username already exists."),
+            UsersError::HashingFailed(e) => write!(f, "Failed to hash password.\n{e}"),
+            UsersError::InvalidCredentials => write!(f, "Invalid username or password."),
+            UsersError::UserNotFound => write!(f, "User not found."),
+            UsersError::AccountDisabled => write!(f, "Account is disabled."),
+            UsersError::AccountLocked => write!(f, "Account is locked."),
+            UsersError::AccountNotVerified => write!(f, "Account has not been verified."),
+            UsersError::PasswordResetRequired => write!(f, "Password must be reset before signing in."),
+            UsersError::InvalidProfile(e) => write!(f, "Invalid profile: {e}"),
+            UsersError::UserUuidTaken => write!(f, "User UUID already exists."),
+            UsersError::InvalidPasswordHash => write!(f, "Password hash is not a PBKDF2 PHC string."),
+        }
+    }
+}
+
+impl std::error::Error for UsersError {}
+
+/// `AccountStatus` enumerates the states an account can be in. Only active accounts can sign in or
+/// use their sessions and API keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccountStatus {
+    /// The account can be used normally.
+    #[default]
+    Active,
+
+    /// An administrator has disabled the account.
+    Disabled,
+
+    /// The account has been created but not verified yet.
+    PendingVerification,
+
+    /// The account has been locked, for example after suspicious activity.
+    Locked,
+}
+
+impl AccountStatus {
+
+    /// Checks that an account in this status may be used.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result for active accounts, otherwise the `UsersError` describing why the account
+    /// cannot be used.
+    pub fn ensure_active(&self) -> Result<(), UsersError> {
+        match self {
+            AccountStatus::Active => Ok(()),
+            AccountStatus::Disabled => Err(UsersError::AccountDisabled),
+            AccountStatus::PendingVerification => Err(UsersError::AccountNotVerified),
+            AccountStatus::Locked => Err(UsersError::AccountLocked),
+        }
+    }
+}
+
+/// The fewest PBKDF2 rounds a password hash may use, whether configured or imported. Fewer rounds
+/// make stolen hashes cheap to crack.
+pub const MIN_PBKDF2_ROUNDS: u32 = 100_000;
+
+/// The most PBKDF2 rounds an imported password hash may use. Each sign-in verifies the hash, so a
+/// huge round count would let one account tie up the service.
+pub const MAX_PBKDF2_ROUNDS: u32 = 10_000_000;
+
+/// The PBKDF2 rounds `UsersImpl::for_tests` hashes with, far too few for real passwords.
+#[cfg(test)]
+pub const TEST_PBKDF2_ROUNDS: u32 = 1_000;
+
+/// The longest display name a profile may hold, in characters.
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+
+/// The longest email address a profile may hold, in characters.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// The largest number of metadata entries a profile may hold.
+pub const MAX_METADATA_ENTRIES: usize = 32;
+
+/// The longest metadata key, in characters.
+pub const MAX_METADATA_KEY_LENGTH: usize = 64;
+
+/// The longest metadata value, in characters.
+pub const MAX_METADATA_VALUE_LENGTH: usize = 1024;
+
+/// `Profile` struct holds the optional, user-editable details of an account.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The name shown for the user instead of their username.
+    pub display_name: Option<String>,
+
+    /// The user's email address.
+    pub email: Option<String>,
+
+    /// The user's preferred locale as a language tag, e.g. `en-GB`.
+    pub locale: Option<String>,
+
+    /// Arbitrary key-value pairs set by the user or by applications on their behalf.
+    pub metadata: HashMap<String, String>,
+}
+
+impl Profile {
+
+    /// Checks that every field of the profile is well formed and within its length limit.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the profile can be stored, otherwise `UsersError::InvalidProfile`.
+    pub fn validate(&self) -> Result<(), UsersError> {
+        let invalid = |message: &str| Err(UsersError::InvalidProfile(message.to_owned()));
+
+        if let Some(display_name) = &self.display_name {
+            if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+                return invalid("display name is too long");
+            }
+        }
+
+        if let Some(email) = &self.email {
+            let valid = email.chars().count() <= MAX_EMAIL_LENGTH
+                && !email.chars().any(char::is_whitespace)
+                && email.split_once('@').is_some_and(|(local, domain)| {
+                    !local.is_empty() && !domain.contains('@') && domain.contains('.')
+                        && !domain.starts_with('.') && !domain.ends_with('.')
+                });
+
+            if !valid {
+                return invalid("email is not a valid address");
+            }
+        }
+
+        if let Some(locale) = &self.locale {
+            let valid = locale.split('-').all(|part| {
+                (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+
+            if !valid {
+                return invalid("locale is not a valid language tag");
+            }
+        }
+
+        if self.metadata.len() > MAX_METADATA_ENTRIES {
+            return invalid("too many metadata entries");
+        }
+
+        for (key, value) in &self.metadata {
+            if key.is_empty() || key.chars().count() > MAX_METADATA_KEY_LENGTH {
+                return invalid("metadata keys must be between 1 and 64 characters");
+            }
+
+            if value.chars().count() > MAX_METADATA_VALUE_LENGTH {
+                return invalid("metadata value is too long");
+            }
+        }
+
+        Ok(())
+    }
+}
 
 /// `Users` trait defines methods for managing user data.
 pub trait Users {
@@ -19,7 +215,7 @@ pub trait Users {
     ///
     /// # Returns
     ///
-    /// An `Ok(())` result if the user is created successfully, otherwise an error message.
+    /// An `Ok(())` result if the user is created successfully, otherwise a `UsersError`.
     ///
     /// # Example
     ///
@@ -31,7 +227,7 @@ pub trait Users {
     ///     Err(error) => eprintln!("Failed to create user: {}", error),
     /// }
     /// ```
-    fn create_user(&mut self, username: String, password: String) -> Result<(), String>;
+    fn create_user(&mut self, username: String, password: String) -> Result<(), UsersError>;
 
     /// Retrieves the UUID of the user with the provided username and password.
     ///
@@ -56,8 +252,234 @@ pub trait Users {
     /// ```
     fn get_user_uuid(&self, username: String, password: String) -> Option<String>;
 
+    /// Retrieves the user with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<User>` containing the user if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// if let Some(user) = users_service.get_user("user_uuid") {
+    ///     println!("Username: {}", user.username);
+    /// }
+    /// ```
+    fn get_user(&self, user_uuid: &str) -> Option<User>;
+
+    /// Retrieves the user with the specified username, without checking a password.
+    ///
+    /// Used by sign-in flows that authenticate with something other than a password, such as a
+    /// passkey.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - A string representing the username of the user to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<User>` containing the user if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// if let Some(user) = users_service.get_user_by_username("username") {
+    ///     println!("Passkeys: {}", user.passkeys.len());
+    /// }
+    /// ```
+    fn get_user_by_username(&self, username: &str) -> Option<User>;
+
+    /// Retrieves every user.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing all users, in no particular order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let admins = users_service.list_users().into_iter().filter(|user| user.roles.contains(&Role::Admin)).count();
+    /// ```
+    fn list_users(&self) -> Vec<User>;
+
+    /// Counts the users that have not been deleted.
+    ///
+    /// # Returns
+    ///
+    /// The number of users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let users = users_service.count_users();
+    /// ```
+    fn count_users(&self) -> usize;
+
+    /// Retrieves a page of the users whose username contains a search term.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The term to search usernames for, ignoring case. An empty query matches every user.
+    /// * `offset` - The number of matching users to skip.
+    /// * `limit` - The maximum number of users to return.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the page of matching users, sorted by username, and the total number of
+    /// matching users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let (users, total) = users_service.search_users("smith", 0, 50);
+    /// ```
+    fn search_users(&self, query: &str, offset: usize, limit: usize) -> (Vec<User>, usize);
+
+    /// Replaces the stored record of an existing user.
+    ///
+    /// The username of the user must not change; only the other fields are updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The updated user, identified by its `user_uuid`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was updated, otherwise `UsersError::UserNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let mut user = users_service.get_user("user_uuid").unwrap();
+    /// user.totp_last_used_step = Some(1);
+    /// users_service.update_user(user)?;
+    /// ```
+    fn update_user(&mut self, user: User) -> Result<(), UsersError>;
+
+    /// Replaces the password of an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `password` - The new password, which is hashed before it is stored.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the password was changed, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// users_service.set_password("user_uuid", "new password".to_string())?;
+    /// ```
+    fn set_password(&mut self, user_uuid: &str, password: String) -> Result<(), UsersError>;
+
+    /// Replaces the profile of an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `profile` - The new profile, which replaces every field of the old one.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the profile was stored, otherwise `UsersError::InvalidProfile` or
+    /// `UsersError::UserNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let profile = Profile {
+    ///     display_name: Some("Jane Smith".to_string()),
+    ///     ..Profile::default()
+    /// };
+    /// users_service.update_profile("user_uuid", profile)?;
+    /// ```
+    fn update_profile(&mut self, user_uuid: &str, profile: Profile) -> Result<(), UsersError>;
+
+    /// Renames an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `new_username` - The new username, which must not be taken or reserved for another user.
+    /// * `reserve_until` - If `Some`, the old username stays reserved for the user until this time,
+    ///   so nobody else can sign up with it in the meantime.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was renamed, otherwise `UsersError::UsernameTaken` or
+    /// `UsersError::UserNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// let reserve_until = SystemTime::now() + Duration::from_secs(30 * 24 * 60 * 60);
+    /// users_service.change_username("user_uuid", "new_username".to_string(), Some(reserve_until))?;
+    /// ```
+    fn change_username(&mut self, user_uuid: &str, new_username: String, reserve_until: Option<SystemTime>) -> Result<(), UsersError>;
+
+    /// Checks that a fully formed user, such as one migrated from another system, could be added
+    /// with `import_user`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to check. Its `password` must already be a PHC string hash.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user could be imported, otherwise `UsersError::UsernameTaken`,
+    /// `UsersError::UserUuidTaken`, `UsersError::InvalidPasswordHash` or
+    /// `UsersError::InvalidProfile`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// if users_service.validate_import(&user).is_ok() {
+    ///     println!("{} can be imported", user.username);
+    /// }
+    /// ```
+    fn validate_import(&self, user: &User) -> Result<(), UsersError>;
+
+    /// Adds a fully formed user, keeping its UUID and password hash as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to add. Its `password` must already be a PHC string hash.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was added, otherwise the `UsersError` returned by
+    /// `validate_import`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// users_service.import_user(user)?;
+    /// ```
+    fn import_user(&mut self, user: User) -> Result<(), UsersError>;
+
     /// Deletes the user with the specified UUID.
     ///
+    /// The deletion is soft: the user is hidden from every other method except
+    /// `list_deleted_users`, but the record and its username are kept until `purge_user` is called,
+    /// so the user can still be restored with `restore_user`.
+    ///
     /// # Arguments
     ///
     /// * `user_uuid` - A string representing the UUID of the user to be deleted.
@@ -70,6 +492,55 @@ pub trait Users {
     /// println!("User deleted successfully.");
     /// ```
     fn delete_user(&mut self, user_uuid: String);
+
+    /// Retrieves every user that has been deleted but not yet purged.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the deleted users, in no particular order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// for user in users_service.list_deleted_users() {
+    ///     println!("{} was deleted at {:?}", user.username, user.deleted_at);
+    /// }
+    /// ```
+    fn list_deleted_users(&self) -> Vec<User>;
+
+    /// Restores a deleted user that has not been purged yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the deleted user.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was restored, otherwise `UsersError::UserNotFound` if no
+    /// deleted user has the UUID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// users_service.restore_user("user_uuid")?;
+    /// ```
+    fn restore_user(&mut self, user_uuid: &str) -> Result<(), UsersError>;
+
+    /// Permanently removes a user, freeing their username.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user to be purged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_service` implements `Users` trait
+    /// users_service.purge_user("user_uuid");
+    /// ```
+    fn purge_user(&mut self, user_uuid: &str);
 }
 
 /// `User` struct represents user data.
@@ -83,129 +554,666 @@ pub struct User {
 
     /// A string representing the password of the user.
     pub password: String,
-}
 
-/// `UsersImpl` represents an implementation of the `Users` trait.
-///
-/// This implementation stores user data in memory using two HashMaps: one mapping UUIDs to users
-/// and the other mapping usernames to users.
-#[derive(Default)]
-pub struct UsersImpl {
-    /// A HashMap that maps user UUIDs to user data.
-    pub uuid_to_user: HashMap<String, User>,
+    /// The user's TOTP secret, encrypted with the service's `SecretCipher`. `Some` once TOTP
+    /// enrollment has been confirmed.
+    pub totp_secret: Option<String>,
 
-    /// A HashMap that maps usernames to user data.
-    pub username_to_user: HashMap<String, User>,
+    /// A TOTP secret, encrypted with the service's `SecretCipher`, that has been issued but not yet
+    /// confirmed with a valid code.
+    pub pending_totp_secret: Option<String>,
+
+    /// The time step of the last TOTP code accepted for this user, used to reject replayed codes.
+    pub totp_last_used_step: Option<u64>,
+
+    /// SHA-256 hashes of the user's unused MFA recovery codes.
+    pub recovery_code_hashes: Vec<String>,
+
+    /// The WebAuthn credentials (passkeys) registered to the user.
+    pub passkeys: Vec<PasskeyCredential>,
+
+    /// The roles granted to the user. Users without roles can only manage their own account.
+    pub roles: Vec<Role>,
+
+    /// The status of the account. Only active accounts can sign in.
+    pub status: AccountStatus,
+
+    /// Whether the user must reset their password with a reset token before signing in again.
+    pub password_reset_required: bool,
+
+    /// When the user was deleted. `Some` while the deleted user awaits being purged.
+    pub deleted_at: Option<SystemTime>,
+
+    /// The user's display name, email, locale and metadata.
+    pub profile: Profile,
+}
+
+impl User {
+
+    /// Returns whether the user has been deleted.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+/// `UsernameReservation` struct holds a former username back for the user who gave it up.
+#[derive(Clone, Debug)]
+pub struct UsernameReservation {
+    /// A string representing the UUID of the user who held the username.
+    pub user_uuid: String,
+
+    /// The time at which the username becomes available to everyone.
+    pub expires_at: SystemTime,
+}
+
+/// `UsersImpl` represents an implementation of the `Users` trait.
+///
+/// This implementation stores user data in memory using two HashMaps: one mapping UUIDs to users
+/// and the other mapping usernames to users.
+#[derive(Default)]
+pub struct UsersImpl {
+    /// A HashMap that maps user UUIDs to user data.
+    pub uuid_to_user: HashMap<String, User>,
+
+    /// A HashMap that maps usernames to user data.
+    pub username_to_user: HashMap<String, User>,
+
+    /// A HashMap that maps former usernames to their reservations.
+    pub reserved_usernames: HashMap<String, UsernameReservation>,
+
+    /// The PBKDF2 parameters new passwords are hashed with. Existing hashes keep the parameters
+    /// they were created with.
+    pub pbkdf2_params: Params,
+}
+
+impl UsersImpl {
+
+    /// Constructs an empty `UsersImpl` that hashes passwords with the given number of PBKDF2
+    /// rounds.
+    ///
+    /// # Arguments
+    ///
+    /// * `rounds` - The number of PBKDF2-SHA256 rounds, clamped to between `MIN_PBKDF2_ROUNDS` and
+    ///   `MAX_PBKDF2_ROUNDS` so that the hashes pass `validate_password_hash`.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `UsersImpl`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let users_service = UsersImpl::with_pbkdf2_rounds(1_000_000);
+    /// ```
+    pub fn with_pbkdf2_rounds(rounds: u32) -> Self {
+        Self {
+            pbkdf2_params: Params { rounds: rounds.clamp(MIN_PBKDF2_ROUNDS, MAX_PBKDF2_ROUNDS), ..Params::default() },
+            ..Self::default()
+        }
+    }
+
+    /// Constructs an empty `UsersImpl` that hashes passwords with `TEST_PBKDF2_ROUNDS` rounds, so
+    /// that tests run quickly in unoptimized builds. `validate_password_hash` rejects its hashes, so
+    /// tests that import them use `with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS)` instead.
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        Self { pbkdf2_params: Params { rounds: TEST_PBKDF2_ROUNDS, ..Params::default() }, ..Self::default() }
+    }
+
+    /// Checks whether a username is free for a user to take.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - The username to check.
+    /// * `user_uuid` - The UUID of the user taking the username, if they already have an account.
+    ///   A user may take back a username reserved for them.
+    ///
+    /// # Returns
+    ///
+    /// `true` if no user has the username and it is not reserved for another user.
+    fn username_available(&self, username: &str, user_uuid: Option<&str>) -> bool {
+        if self.username_to_user.contains_key(username) {
+            return false;
+        }
+
+        let now = SystemTime::now();
+
+        self.reserved_usernames.get(username).is_none_or(|reservation| {
+            reservation.expires_at <= now || Some(reservation.user_uuid.as_str()) == user_uuid
+        })
+    }
 }
 
 impl Users for UsersImpl {
 
-    /// Creates a new user with the provided username and password.
+    /// Creates a new user with the provided username and password.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - A string representing the username of the user to be created.
+    /// * `password` - A string representing the password of the user to be created.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user is created successfully, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let result = users_impl.create_user("username".to_string(), "password".to_string());
+    /// match result {
+    ///     Ok(_) => println!("User created successfully."),
+    ///     Err(error) => eprintln!("Failed to create user: {}", error),
+    /// }
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn create_user(&mut self, username: String, password: String) -> Result<(), UsersError> {
+
+        // Check if username already exists or is reserved. If so return an error.
+        if !self.username_available(&username, None) {
+            return Err(UsersError::UsernameTaken);
+        }
+
+        let hashed_password = hash_password(&password, self.pbkdf2_params)?;
+
+        let user: User = User {
+            user_uuid: Uuid::new_v4().to_string(),
+            username: username.clone(),
+            password: hashed_password,
+            totp_secret: None,
+            pending_totp_secret: None,
+            totp_last_used_step: None,
+            recovery_code_hashes: Vec::new(),
+            passkeys: Vec::new(),
+            roles: Vec::new(),
+            status: AccountStatus::Active,
+            password_reset_required: false,
+            deleted_at: None,
+            profile: Profile::default(),
+        };
+
+        self.username_to_user.insert(username, user.clone());
+        self.uuid_to_user.insert(user.user_uuid.clone(), user);
+
+        Ok(())
+    }
+
+    /// Retrieves the UUID of the user with the provided username and password.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - A string representing the username of the user to retrieve.
+    /// * `password` - A string representing the password of the user to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` containing the UUID of the user if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let user_uuid = users_impl.get_user_uuid("username".to_string(), "password".to_string());
+    /// match user_uuid {
+    ///     Some(uuid) => println!("User UUID: {}", uuid),
+    ///     None => println!("User not found."),
+    /// }
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn get_user_uuid(&self, username: String, password: String) -> Option<String> {
+        let user = self.get_user_by_username(&username)?;
+
+        // Get user's password as `PasswordHash` instance. 
+        let hashed_password = user.password.clone();
+        let parsed_hash = PasswordHash::new(&hashed_password).ok()?;
+
+        // Verify passed in password matches user's password.
+        let result = Pbkdf2.verify_password(password.as_bytes(), &parsed_hash);
+
+        if user.username == username && result.is_ok() {
+            return Some(user.user_uuid.clone());
+        }
+
+        None
+    }
+
+    /// Retrieves the user with the specified UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<User>` containing the user if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let user = users_impl.get_user("user_uuid");
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn get_user(&self, user_uuid: &str) -> Option<User> {
+        self.uuid_to_user.get(user_uuid).filter(|user| !user.is_deleted()).cloned()
+    }
+
+    /// Retrieves the user with the specified username, without checking a password.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - A string representing the username of the user to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<User>` containing the user if found, otherwise `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let user = users_impl.get_user_by_username("username");
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn get_user_by_username(&self, username: &str) -> Option<User> {
+        self.username_to_user.get(username).filter(|user| !user.is_deleted()).cloned()
+    }
+
+    /// Retrieves every user.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing all users, in no particular order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let users = users_impl.list_users();
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn list_users(&self) -> Vec<User> {
+        self.uuid_to_user.values().filter(|user| !user.is_deleted()).cloned().collect()
+    }
+
+    /// Counts the users that have not been deleted.
+    ///
+    /// # Returns
+    ///
+    /// The number of users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let users = users_impl.count_users();
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn count_users(&self) -> usize {
+        self.uuid_to_user.values().filter(|user| !user.is_deleted()).count()
+    }
+
+    /// Retrieves a page of the users whose username contains a search term.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The term to search usernames for, ignoring case.
+    /// * `offset` - The number of matching users to skip.
+    /// * `limit` - The maximum number of users to return.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the page of matching users and the total number of matching users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let (users, total) = users_impl.search_users("smith", 0, 50);
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn search_users(&self, query: &str, offset: usize, limit: usize) -> (Vec<User>, usize) {
+        let query = query.to_lowercase();
+
+        let mut matches: Vec<&User> = self.username_to_user
+            .values()
+            .filter(|user| !user.is_deleted() && user.username.to_lowercase().contains(&query))
+            .collect();
+
+        // Sorting keeps pages stable between requests.
+        matches.sort_by(|a, b| a.username.cmp(&b.username));
+
+        let total = matches.len();
+        let page = matches.into_iter().skip(offset).take(limit).cloned().collect();
+
+        (page, total)
+    }
+
+    /// Replaces the stored record of an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The updated user, identified by its `user_uuid`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was updated, otherwise `UsersError::UserNotFound`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// let mut user = users_impl.get_user("user_uuid").unwrap();
+    /// user.totp_last_used_step = Some(1);
+    /// users_impl.update_user(user)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn update_user(&mut self, user: User) -> Result<(), UsersError> {
+        if !self.uuid_to_user.contains_key(&user.user_uuid) {
+            return Err(UsersError::UserNotFound);
+        }
+
+        // Both maps hold their own copy of the user, so both must be refreshed.
+        self.username_to_user.insert(user.username.clone(), user.clone());
+        self.uuid_to_user.insert(user.user_uuid.clone(), user);
+
+        Ok(())
+    }
+
+    /// Replaces the password of an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `password` - The new password.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the password was changed, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.set_password("user_uuid", "new password".to_string())?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn set_password(&mut self, user_uuid: &str, password: String) -> Result<(), UsersError> {
+        let mut user = self.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        user.password = hash_password(&password, self.pbkdf2_params)?;
+
+        self.update_user(user)
+    }
+
+    /// Replaces the profile of an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `profile` - The new profile.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the profile was stored, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.update_profile("user_uuid", Profile::default())?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn update_profile(&mut self, user_uuid: &str, profile: Profile) -> Result<(), UsersError> {
+        profile.validate()?;
+
+        let mut user = self.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        user.profile = profile;
+
+        self.update_user(user)
+    }
+
+    /// Renames an existing user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_uuid` - A string representing the UUID of the user.
+    /// * `new_username` - The new username.
+    /// * `reserve_until` - If `Some`, the time until which the old username stays reserved.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was renamed, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.change_username("user_uuid", "new_username".to_string(), None)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn change_username(&mut self, user_uuid: &str, new_username: String, reserve_until: Option<SystemTime>) -> Result<(), UsersError> {
+        let mut user = self.get_user(user_uuid).ok_or(UsersError::UserNotFound)?;
+
+        if user.username == new_username {
+            return Ok(());
+        }
+
+        if !self.username_available(&new_username, Some(user_uuid)) {
+            return Err(UsersError::UsernameTaken);
+        }
+
+        // Both indexes change together, so the user is always found under exactly one name.
+        let old_username = std::mem::replace(&mut user.username, new_username.clone());
+        self.username_to_user.remove(&old_username);
+
+        // Renames are rare, so this is a convenient point to forget reservations that have ended.
+        let now = SystemTime::now();
+        self.reserved_usernames.retain(|username, reservation| reservation.expires_at > now && *username != new_username);
+
+        if let Some(expires_at) = reserve_until {
+            let reservation = UsernameReservation { user_uuid: user_uuid.to_owned(), expires_at };
+            self.reserved_usernames.insert(old_username, reservation);
+        }
+
+        self.username_to_user.insert(new_username, user.clone());
+        self.uuid_to_user.insert(user.user_uuid.clone(), user);
+
+        Ok(())
+    }
+
+    /// Checks that a fully formed user could be added with `import_user`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to check.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user could be imported, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.validate_import(&user)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn validate_import(&self, user: &User) -> Result<(), UsersError> {
+        if !self.username_available(&user.username, None) {
+            return Err(UsersError::UsernameTaken);
+        }
+
+        if self.uuid_to_user.contains_key(&user.user_uuid) {
+            return Err(UsersError::UserUuidTaken);
+        }
+
+        validate_password_hash(&user.password)?;
+        user.profile.validate()
+    }
+
+    /// Adds a fully formed user, keeping its UUID and password hash as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to add.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok(())` result if the user was added, otherwise a `UsersError`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.import_user(user)?;
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn import_user(&mut self, user: User) -> Result<(), UsersError> {
+        self.validate_import(&user)?;
+
+        self.username_to_user.insert(user.username.clone(), user.clone());
+        self.uuid_to_user.insert(user.user_uuid.clone(), user);
+
+        Ok(())
+    }
+
+    /// Marks the user with the specified UUID as deleted.
     ///
     /// # Arguments
     ///
-    /// * `username` - A string representing the username of the user to be created.
-    /// * `password` - A string representing the password of the user to be created.
+    /// * `user_uuid` - A string representing the UUID of the user to be deleted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // Assuming `users_impl` is an instance of `UsersImpl`
+    /// users_impl.delete_user("user_uuid".to_string());
+    /// println!("User deleted successfully.");
+    /// ```
+    #[instrument(skip_all, fields(store = "users"))]
+    fn delete_user(&mut self, user_uuid: String) {
+        if let Some(mut user) = self.get_user(&user_uuid) {
+            user.deleted_at = Some(SystemTime::now());
+            let _ = self.update_user(user);
+        }
+    }
+
+    /// Retrieves every user that has been deleted but not yet purged.
     ///
     /// # Returns
     ///
-    /// An `Ok(())` result if the user is created successfully, otherwise an error message.
+    /// A vector containing the deleted users, in no particular order.
     ///
     /// # Example
     ///
     /// ```
     /// // Assuming `users_impl` is an instance of `UsersImpl`
-    /// let result = users_impl.create_user("username".to_string(), "password".to_string());
-    /// match result {
-    ///     Ok(_) => println!("User created successfully."),
-    ///     Err(error) => eprintln!("Failed to create user: {}", error),
-    /// }
+    /// let deleted = users_impl.list_deleted_users();
     /// ```
-    fn create_user(&mut self, username: String, password: String) -> Result<(), String> {
-
-        // Check if username already exists. If so return an error.
-        if self.username_to_user.contains_key(&username) {
-            return Err("Unable to create user. Username already exists.".to_owned());
-        }
-
-        let salt = SaltString::generate(&mut OsRng);
-
-        let hashed_password = Pbkdf2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| format!("Failed to hash password.\n{e:?}"))?
-            .to_string();
-
-        let user: User = User {
-            user_uuid: Uuid::new_v4().to_string(),
-            username: username.clone(),
-            password: hashed_password,
-        };
-
-        self.username_to_user.insert(username, user.clone());
-        self.uuid_to_user.insert(user.user_uuid.clone(), user);
-
-        Ok(())
+    #[instrument(skip_all, fields(store = "users"))]
+    fn list_deleted_users(&self) -> Vec<User> {
+        self.uuid_to_user.values().filter(|user| user.is_deleted()).cloned().collect()
     }
 
-    /// Retrieves the UUID of the user with the provided username and password.
+    /// Restores a deleted user that has not been purged yet.
     ///
     /// # Arguments
     ///
-    /// * `username` - A string representing the username of the user to retrieve.
-    /// * `password` - A string representing the password of the user to retrieve.
+    /// * `user_uuid` - A string representing the UUID of the deleted user.
     ///
     /// # Returns
     ///
-    /// An `Option<String>` containing the UUID of the user if found, otherwise `None`.
+    /// An `Ok(())` result if the user was restored, otherwise `UsersError::UserNotFound`.
     ///
     /// # Example
     ///
     /// ```
     /// // Assuming `users_impl` is an instance of `UsersImpl`
-    /// let user_uuid = users_impl.get_user_uuid("username".to_string(), "password".to_string());
-    /// match user_uuid {
-    ///     Some(uuid) => println!("User UUID: {}", uuid),
-    ///     None => println!("User not found."),
-    /// }
+    /// users_impl.restore_user("user_uuid")?;
     /// ```
-    fn get_user_uuid(&self, username: String, password: String) -> Option<String> {
-        let user = self.username_to_user.get(&username)?;
+    #[instrument(skip_all, fields(store = "users"))]
+    fn restore_user(&mut self, user_uuid: &str) -> Result<(), UsersError> {
+        let mut user = self.uuid_to_user
+            .get(user_uuid)
+            .filter(|user| user.is_deleted())
+            .cloned()
+            .ok_or(UsersError::UserNotFound)?;
 
-        // Get user's password as `PasswordHash` instance. 
-        let hashed_password = user.password.clone();
-        let parsed_hash = PasswordHash::new(&hashed_password).ok()?;
-
-        // Verify passed in password matches user's password.
-        let result = Pbkdf2.verify_password(password.as_bytes(), &parsed_hash);
-
-        if user.username == username && result.is_ok() {
-            return Some(user.user_uuid.clone());
-        }
+        user.deleted_at = None;
 
-        None
+        self.update_user(user)
     }
 
-    /// Deletes the user with the specified UUID.
+    /// Permanently removes a user, freeing their username.
     ///
     /// # Arguments
     ///
-    /// * `user_uuid` - A string representing the UUID of the user to be deleted.
+    /// * `user_uuid` - A string representing the UUID of the user to be purged.
     ///
     /// # Example
     ///
     /// ```
     /// // Assuming `users_impl` is an instance of `UsersImpl`
-    /// users_impl.delete_user("user_uuid".to_string());
-    /// println!("User deleted successfully.");
+    /// users_impl.purge_user("user_uuid");
     /// ```
-    fn delete_user(&mut self, user_uuid: String) {
-        if let Some(user) = self.uuid_to_user.get(&user_uuid) {
-            let user_uuid = user.user_uuid.clone();
+    #[instrument(skip_all, fields(store = "users"))]
+    fn purge_user(&mut self, user_uuid: &str) {
+        if let Some(user) = self.uuid_to_user.remove(user_uuid) {
             self.username_to_user.remove(&user.username);
-            self.uuid_to_user.remove(&user_uuid);
         }
+
+        self.reserved_usernames.retain(|_, reservation| reservation.user_uuid != user_uuid);
+    }
+}
+
+/// Checks that a password hash can be verified by `get_user_uuid`.
+///
+/// # Arguments
+///
+/// * `password_hash` - The hash, which must be a PHC string produced by PBKDF2 with SHA-256 or
+///   SHA-512, e.g. `$pbkdf2-sha256$i=600000,l=32$<salt>$<hash>`, with between
+///   `MIN_PBKDF2_ROUNDS` and `MAX_PBKDF2_ROUNDS` rounds.
+///
+/// # Returns
+///
+/// An `Ok(())` result if the hash is usable, otherwise `UsersError::InvalidPasswordHash`.
+pub fn validate_password_hash(password_hash: &str) -> Result<(), UsersError> {
+    let parsed_hash = PasswordHash::new(password_hash).map_err(|_| UsersError::InvalidPasswordHash)?;
+
+    let valid = Algorithm::try_from(parsed_hash.algorithm).is_ok()
+        && Params::try_from(&parsed_hash)
+            .is_ok_and(|params| (MIN_PBKDF2_ROUNDS..=MAX_PBKDF2_ROUNDS).contains(&params.rounds))
+        && parsed_hash.version.is_none()
+        && parsed_hash.salt.is_some()
+        && parsed_hash.hash.is_some();
+
+    if !valid {
+        return Err(UsersError::InvalidPasswordHash);
     }
-    
+
+    Ok(())
+}
+
+/// Hashes a password with PBKDF2 and a random salt.
+///
+/// # Arguments
+///
+/// * `password` - The plain text password.
+/// * `params` - The PBKDF2 parameters to hash with.
+///
+/// # Returns
+///
+/// An `Ok` result containing the PHC string of the hash, otherwise `UsersError::HashingFailed`.
+fn hash_password(password: &str, params: Params) -> Result<String, UsersError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Pbkdf2
+        .hash_password_customized(password.as_bytes(), None, None, params, &salt)
+        .map_err(|e| UsersError::HashingFailed(e.to_string()))?
+        .to_string())
 }
 
 #[cfg(test)]
@@ -214,7 +1222,7 @@ mod tests {
 
     #[test]
     fn should_create_user() {
-        let mut user_service = UsersImpl::default();
+        let mut user_service = UsersImpl::for_tests();
         user_service
             .create_user("username".to_owned(), "password".to_owned())
             .expect("should create user");
@@ -225,19 +1233,19 @@ mod tests {
 
     #[test]
     fn should_fail_creating_user_with_existing_username() {
-        let mut user_service = UsersImpl::default();
+        let mut user_service = UsersImpl::for_tests();
         user_service
             .create_user("username".to_owned(), "password".to_owned())
             .expect("should create user");
 
         let result = user_service.create_user("username".to_owned(), "password".to_owned());
 
-        assert!(result.is_err());
+        assert_eq!(result, Err(UsersError::UsernameTaken));
     }
 
     #[test]
     fn should_retrieve_user_uuid() {
-        let mut user_service = UsersImpl::default();
+        let mut user_service = UsersImpl::for_tests();
         user_service
             .create_user("username".to_owned(), "password".to_owned())
             .expect("should create user");
@@ -249,7 +1257,7 @@ mod tests {
 
     #[test]
     fn should_fail_to_retrieve_user_uuid_with_incorrect_password() {
-        let mut user_service = UsersImpl::default();
+        let mut user_service = UsersImpl::for_tests();
         user_service
             .create_user("username".to_owned(), "password".to_owned())
             .expect("should create user");
@@ -259,9 +1267,341 @@ mod tests {
             .is_none());
     }
 
+    #[test]
+    fn should_retrieve_user_by_uuid() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        let user_uuid = user_service
+            .get_user_uuid("username".to_owned(), "password".to_owned())
+            .unwrap();
+
+        assert_eq!(user_service.get_user(&user_uuid).unwrap().username, "username");
+        assert!(user_service.get_user("unknown").is_none());
+    }
+
+    #[test]
+    fn should_retrieve_user_by_username() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        assert_eq!(user_service.get_user_by_username("username").unwrap().username, "username");
+        assert!(user_service.get_user_by_username("unknown").is_none());
+    }
+
+    #[test]
+    fn should_only_accept_active_accounts() {
+        assert_eq!(AccountStatus::default().ensure_active(), Ok(()));
+        assert_eq!(AccountStatus::Disabled.ensure_active(), Err(UsersError::AccountDisabled));
+        assert_eq!(AccountStatus::PendingVerification.ensure_active(), Err(UsersError::AccountNotVerified));
+        assert_eq!(AccountStatus::Locked.ensure_active(), Err(UsersError::AccountLocked));
+    }
+
+    #[test]
+    fn should_list_users() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("first".to_owned(), "password".to_owned())
+            .expect("should create user");
+        user_service
+            .create_user("second".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        let mut usernames: Vec<String> = user_service.list_users().into_iter().map(|user| user.username).collect();
+        usernames.sort();
+
+        assert_eq!(usernames, vec!["first".to_owned(), "second".to_owned()]);
+        assert_eq!(user_service.count_users(), 2);
+    }
+
+    #[test]
+    fn should_search_users_by_page() {
+        let mut user_service = UsersImpl::for_tests();
+        for username in ["carol", "Alice", "bob", "alicia"] {
+            user_service
+                .create_user(username.to_owned(), "password".to_owned())
+                .expect("should create user");
+        }
+
+        let (users, total) = user_service.search_users("ALI", 0, 1);
+        let usernames: Vec<String> = users.into_iter().map(|user| user.username).collect();
+        assert_eq!((usernames, total), (vec!["Alice".to_owned()], 2));
+
+        let (users, _) = user_service.search_users("ali", 1, 1);
+        assert_eq!(users[0].username, "alicia");
+
+        let (users, total) = user_service.search_users("", 3, 10);
+        assert_eq!((users.len(), total), (1, 4));
+    }
+
+    #[test]
+    fn should_set_password() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+        user_service.set_password(&user_uuid, "new password".to_owned()).unwrap();
+
+        assert!(user_service.get_user_uuid("username".to_owned(), "password".to_owned()).is_none());
+        assert!(user_service.get_user_uuid("username".to_owned(), "new password".to_owned()).is_some());
+        assert_eq!(user_service.set_password("unknown", "password".to_owned()), Err(UsersError::UserNotFound));
+    }
+
+    #[test]
+    fn should_update_profile() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        let profile = Profile {
+            display_name: Some("Jane Smith".to_owned()),
+            email: Some("jane@example.com".to_owned()),
+            locale: Some("en-GB".to_owned()),
+            metadata: HashMap::from([("team".to_owned(), "platform".to_owned())]),
+        };
+
+        user_service.update_profile(&user_uuid, profile.clone()).expect("should update profile");
+
+        assert_eq!(user_service.get_user(&user_uuid).unwrap().profile, profile);
+        assert_eq!(user_service.username_to_user["username"].profile, profile);
+        assert_eq!(user_service.update_profile("unknown", Profile::default()), Err(UsersError::UserNotFound));
+    }
+
+    #[test]
+    fn should_reject_invalid_profiles() {
+        let invalid = [
+            Profile { display_name: Some("x".repeat(MAX_DISPLAY_NAME_LENGTH + 1)), ..Profile::default() },
+            Profile { email: Some("jane".to_owned()), ..Profile::default() },
+            Profile { email: Some("jane@localhost".to_owned()), ..Profile::default() },
+            Profile { email: Some("jane smith@example.com".to_owned()), ..Profile::default() },
+            Profile { locale: Some("en_GB".to_owned()), ..Profile::default() },
+            Profile { metadata: HashMap::from([(String::new(), "value".to_owned())]), ..Profile::default() },
+            Profile {
+                metadata: (0..=MAX_METADATA_ENTRIES).map(|i| (i.to_string(), String::new())).collect(),
+                ..Profile::default()
+            },
+        ];
+
+        for profile in invalid {
+            assert!(matches!(profile.validate(), Err(UsersError::InvalidProfile(_))), "{:?}", profile);
+        }
+
+        assert_eq!(Profile::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn should_change_username() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        user_service.change_username(&user_uuid, "renamed".to_owned(), None).expect("should change username");
+
+        assert!(user_service.get_user_by_username("username").is_none());
+        assert_eq!(user_service.get_user(&user_uuid).unwrap().username, "renamed");
+        assert_eq!(user_service.get_user_uuid("renamed".to_owned(), "password".to_owned()), Some(user_uuid));
+
+        // Without a reservation the old name is free straight away.
+        assert!(user_service.create_user("username".to_owned(), "password".to_owned()).is_ok());
+    }
+
+    #[test]
+    fn should_fail_changing_to_taken_username() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service.create_user("first".to_owned(), "password".to_owned()).unwrap();
+        user_service.create_user("second".to_owned(), "password".to_owned()).unwrap();
+        let user_uuid = user_service.username_to_user["first"].user_uuid.clone();
+
+        assert_eq!(
+            user_service.change_username(&user_uuid, "second".to_owned(), None),
+            Err(UsersError::UsernameTaken)
+        );
+        assert_eq!(user_service.get_user(&user_uuid).unwrap().username, "first");
+    }
+
+    #[test]
+    fn should_reserve_old_username() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        let reserve_until = SystemTime::now() + std::time::Duration::from_secs(60);
+        user_service.change_username(&user_uuid, "renamed".to_owned(), Some(reserve_until)).unwrap();
+
+        assert_eq!(
+            user_service.create_user("username".to_owned(), "password".to_owned()),
+            Err(UsersError::UsernameTaken)
+        );
+
+        // The user who gave the name up can take it back.
+        user_service.change_username(&user_uuid, "username".to_owned(), None).unwrap();
+        assert_eq!(user_service.get_user(&user_uuid).unwrap().username, "username");
+        assert!(user_service.reserved_usernames.is_empty());
+    }
+
+    #[test]
+    fn should_release_expired_reservation() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        user_service.change_username(&user_uuid, "renamed".to_owned(), Some(SystemTime::now())).unwrap();
+
+        assert!(user_service.create_user("username".to_owned(), "password".to_owned()).is_ok());
+    }
+
+    /// Builds a user as an import would, with the given username and a hash of `password`.
+    fn imported_user(username: &str) -> User {
+        User {
+            user_uuid: Uuid::new_v4().to_string(),
+            username: username.to_owned(),
+            password: hash_password("password", Params { rounds: MIN_PBKDF2_ROUNDS, ..Params::default() }).unwrap(),
+            totp_secret: None,
+            pending_totp_secret: None,
+            totp_last_used_step: None,
+            recovery_code_hashes: Vec::new(),
+            passkeys: Vec::new(),
+            roles: vec![Role::Auditor],
+            status: AccountStatus::Active,
+            password_reset_required: false,
+            deleted_at: None,
+            profile: Profile::default(),
+        }
+    }
+
+    #[test]
+    fn should_import_user_with_hashed_password() {
+        let mut user_service = UsersImpl::for_tests();
+        let user = imported_user("username");
+
+        user_service.import_user(user.clone()).expect("should import user");
+
+        assert_eq!(user_service.get_user_uuid("username".to_owned(), "password".to_owned()), Some(user.user_uuid.clone()));
+        assert_eq!(user_service.get_user(&user.user_uuid).unwrap().roles, vec![Role::Auditor]);
+    }
+
+    #[test]
+    fn should_reject_conflicting_imports() {
+        let mut user_service = UsersImpl::for_tests();
+        let user = imported_user("username");
+        user_service.import_user(user.clone()).unwrap();
+
+        assert_eq!(user_service.validate_import(&imported_user("username")), Err(UsersError::UsernameTaken));
+
+        let mut same_uuid = imported_user("other");
+        same_uuid.user_uuid = user.user_uuid;
+        assert_eq!(user_service.validate_import(&same_uuid), Err(UsersError::UserUuidTaken));
+
+        let mut plain_password = imported_user("other");
+        plain_password.password = "password".to_owned();
+        assert_eq!(user_service.import_user(plain_password), Err(UsersError::InvalidPasswordHash));
+    }
+
+    #[test]
+    fn should_validate_password_hashes() {
+        let params = Params { rounds: MIN_PBKDF2_ROUNDS, ..Params::default() };
+        assert_eq!(validate_password_hash(&hash_password("password", params).unwrap()), Ok(()));
+        assert_eq!(
+            validate_password_hash("$argon2id$v=19$m=65536,t=3,p=4$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA"),
+            Err(UsersError::InvalidPasswordHash)
+        );
+        assert_eq!(validate_password_hash("$pbkdf2-sha256$i=1000,l=32$c2FsdHNhbHQ"), Err(UsersError::InvalidPasswordHash));
+    }
+
+    /// Rewrites the round count of a PBKDF2 PHC string. The hash no longer matches the password,
+    /// which validation does not check.
+    fn with_rounds(password_hash: &str, rounds: u32) -> String {
+        password_hash.replacen(&format!("i={}", TEST_PBKDF2_ROUNDS), &format!("i={}", rounds), 1)
+    }
+
+    #[test]
+    fn should_reject_password_hashes_with_too_few_rounds() {
+        let password_hash = hash_password("password", Params { rounds: TEST_PBKDF2_ROUNDS, ..Params::default() }).unwrap();
+
+        assert_eq!(validate_password_hash(&with_rounds(&password_hash, MIN_PBKDF2_ROUNDS)), Ok(()));
+        assert_eq!(
+            validate_password_hash(&with_rounds(&password_hash, MIN_PBKDF2_ROUNDS - 1)),
+            Err(UsersError::InvalidPasswordHash)
+        );
+    }
+
+    #[test]
+    fn should_reject_password_hashes_with_too_many_rounds() {
+        let password_hash = hash_password("password", Params { rounds: TEST_PBKDF2_ROUNDS, ..Params::default() }).unwrap();
+
+        assert_eq!(validate_password_hash(&with_rounds(&password_hash, MAX_PBKDF2_ROUNDS)), Ok(()));
+        assert_eq!(
+            validate_password_hash(&with_rounds(&password_hash, MAX_PBKDF2_ROUNDS + 1)),
+            Err(UsersError::InvalidPasswordHash)
+        );
+    }
+
+    #[test]
+    fn should_hash_with_configured_rounds() {
+        let mut user_service = UsersImpl::with_pbkdf2_rounds(MIN_PBKDF2_ROUNDS);
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        assert!(user_service.username_to_user["username"].password.starts_with("$pbkdf2-sha256$i=100000,"));
+        assert!(user_service.get_user_uuid("username".to_owned(), "password".to_owned()).is_some());
+    }
+
+    #[test]
+    fn should_clamp_configured_rounds() {
+        assert_eq!(UsersImpl::with_pbkdf2_rounds(20_000).pbkdf2_params.rounds, MIN_PBKDF2_ROUNDS);
+        assert_eq!(UsersImpl::with_pbkdf2_rounds(4_000_000_000).pbkdf2_params.rounds, MAX_PBKDF2_ROUNDS);
+    }
+
+    #[test]
+    fn should_update_user() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        let user_uuid = user_service
+            .get_user_uuid("username".to_owned(), "password".to_owned())
+            .unwrap();
+
+        let mut user = user_service.get_user(&user_uuid).unwrap();
+        user.totp_secret = Some("secret".to_owned());
+        user_service.update_user(user).expect("should update user");
+
+        assert_eq!(user_service.uuid_to_user[&user_uuid].totp_secret.as_deref(), Some("secret"));
+        assert_eq!(user_service.username_to_user["username"].totp_secret.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn should_fail_updating_unknown_user() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+
+        let mut user = user_service.username_to_user["username"].clone();
+        user.user_uuid = "unknown".to_owned();
+
+        assert_eq!(user_service.update_user(user), Err(UsersError::UserNotFound));
+    }
+
     #[test]
     fn should_delete_user() {
-        let mut user_service = UsersImpl::default();
+        let mut user_service = UsersImpl::for_tests();
         user_service
             .create_user("username".to_owned(), "password".to_owned())
             .expect("should create user");
@@ -270,9 +1610,62 @@ mod tests {
             .get_user_uuid("username".to_owned(), "password".to_owned())
             .unwrap();
 
+        user_service.delete_user(user_uuid.clone());
+
+        assert!(user_service.get_user(&user_uuid).is_none());
+        assert!(user_service.get_user_by_username("username").is_none());
+        assert!(user_service.get_user_uuid("username".to_owned(), "password".to_owned()).is_none());
+        assert!(user_service.list_users().is_empty());
+        assert_eq!(user_service.search_users("", 0, 10).1, 0);
+        assert_eq!(user_service.list_deleted_users().len(), 1);
+    }
+
+    #[test]
+    fn should_keep_username_of_deleted_user() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
         user_service.delete_user(user_uuid);
 
+        assert_eq!(
+            user_service.create_user("username".to_owned(), "password".to_owned()),
+            Err(UsersError::UsernameTaken)
+        );
+    }
+
+    #[test]
+    fn should_restore_deleted_user() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        assert_eq!(user_service.restore_user(&user_uuid), Err(UsersError::UserNotFound));
+
+        user_service.delete_user(user_uuid.clone());
+        user_service.restore_user(&user_uuid).expect("should restore user");
+
+        assert!(user_service.get_user(&user_uuid).is_some());
+        assert!(user_service.list_deleted_users().is_empty());
+    }
+
+    #[test]
+    fn should_purge_user() {
+        let mut user_service = UsersImpl::for_tests();
+        user_service
+            .create_user("username".to_owned(), "password".to_owned())
+            .expect("should create user");
+        let user_uuid = user_service.username_to_user["username"].user_uuid.clone();
+
+        user_service.delete_user(user_uuid.clone());
+        user_service.purge_user(&user_uuid);
+
         assert_eq!(user_service.uuid_to_user.len(), 0);
         assert_eq!(user_service.username_to_user.len(), 0);
+        assert_eq!(user_service.restore_user(&user_uuid), Err(UsersError::UserNotFound));
     }
 }
\ No newline at end of file
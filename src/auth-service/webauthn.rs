@@ -0,0 +1,695 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
+use ciborium::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+
+/// The size in bytes of WebAuthn challenges.
+const CHALLENGE_LEN: usize = 32;
+
+/// Authenticator data flag: the user was present (touched the authenticator).
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// Authenticator data flag: attested credential data is included.
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// The length of the fixed part of authenticator data: RP ID hash, flags and sign counter.
+const AUTH_DATA_MIN_LEN: usize = 32 + 1 + 4;
+
+/// The length of the AAGUID that starts attested credential data.
+const AAGUID_LEN: usize = 16;
+
+/// COSE key type for elliptic curve keys with x and y coordinates.
+const COSE_KTY_EC2: i128 = 2;
+
+/// COSE algorithm identifier for ECDSA with SHA-256.
+const COSE_ALG_ES256: i128 = -7;
+
+/// COSE elliptic curve identifier for P-256.
+const COSE_CRV_P256: i128 = 1;
+
+/// `WebauthnCeremony` distinguishes the two WebAuthn flows so a challenge issued for one cannot be
+/// used for the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebauthnCeremony {
+    /// Creating a new credential (`navigator.credentials.create`).
+    Registration,
+
+    /// Signing in with an existing credential (`navigator.credentials.get`).
+    Authentication,
+}
+
+impl WebauthnCeremony {
+
+    /// Returns the `type` the browser puts in `clientDataJSON` for this ceremony.
+    fn client_data_type(&self) -> &'static str {
+        match self {
+            WebauthnCeremony::Registration => "webauthn.create",
+            WebauthnCeremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+/// `WebauthnError` enumerates the ways a WebAuthn ceremony can fail verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebauthnError {
+    /// `clientDataJSON`, the attestation object or the authenticator data could not be parsed.
+    MalformedData(String),
+
+    /// `clientDataJSON` was produced for the other ceremony.
+    WrongCeremony,
+
+    /// `clientDataJSON` was produced for a different origin.
+    OriginMismatch,
+
+    /// The authenticator data was produced for a different relying party.
+    RpIdMismatch,
+
+    /// The authenticator did not report user presence.
+    UserNotPresent,
+
+    /// Only the `none` attestation format is accepted.
+    UnsupportedAttestation(String),
+
+    /// Only ES256 (ECDSA P-256 with SHA-256) credentials are accepted.
+    UnsupportedAlgorithm,
+
+    /// The assertion signature does not verify against the stored public key.
+    InvalidSignature,
+
+    /// The signature counter did not increase, which suggests a cloned authenticator.
+    SignCountRegression,
+
+    /// The credential is not registered to the user.
+    CredentialNotFound,
+
+    /// The credential is already registered to the user.
+    CredentialAlreadyRegistered,
+}
+
+impl fmt::Display for WebauthnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebauthnError::MalformedData(what) => write!(f, "Malformed WebAuthn data: {what}."),
+            WebauthnError::WrongCeremony => write!(f, "Client data is for a different WebAuthn ceremony."),
+            WebauthnError::OriginMismatch => write!(f, "Client data origin does not match."),
+            WebauthnError::RpIdMismatch => write!(f, "Authenticator data relying party does not match."),
+            WebauthnError::UserNotPresent => write!(f, "Authenticator did not confirm user presence."),
+            WebauthnError::UnsupportedAttestation(format) => write!(f, "Unsupported attestation format: {format}."),
+            WebauthnError::UnsupportedAlgorithm => write!(f, "Only ES256 passkeys are supported."),
+            WebauthnError::InvalidSignature => write!(f, "Invalid assertion signature."),
+            WebauthnError::SignCountRegression => write!(f, "Signature counter did not increase."),
+            WebauthnError::CredentialNotFound => write!(f, "Passkey not found."),
+            WebauthnError::CredentialAlreadyRegistered => write!(f, "Passkey already registered."),
+        }
+    }
+}
+
+impl std::error::Error for WebauthnError {}
+
+/// `WebauthnConfig` describes the relying party that credentials are scoped to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebauthnConfig {
+    /// The relying party ID, usually the registrable domain of the web app.
+    pub rp_id: String,
+
+    /// The human-readable relying party name shown by authenticators.
+    pub rp_name: String,
+
+    /// The origin browsers report in `clientDataJSON`, e.g. `https://example.com`.
+    pub origin: String,
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: "localhost".to_owned(),
+            rp_name: "RustyAuth".to_owned(),
+            origin: "http://localhost".to_owned(),
+        }
+    }
+}
+
+/// `PasskeyCredential` struct represents a WebAuthn credential registered to a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PasskeyCredential {
+    /// The base64url encoded credential ID chosen by the authenticator.
+    pub credential_id: String,
+
+    /// The SEC1 encoded (uncompressed) P-256 public key.
+    pub public_key: Vec<u8>,
+
+    /// The last signature counter reported by the authenticator.
+    pub sign_count: u32,
+
+    /// A user-chosen label for the credential.
+    pub name: String,
+}
+
+/// The parts of `clientDataJSON` checked by the server.
+#[derive(Deserialize)]
+struct ClientData {
+    /// Either `webauthn.create` or `webauthn.get`.
+    #[serde(rename = "type")]
+    kind: String,
+
+    /// The base64url encoded challenge.
+    challenge: String,
+
+    /// The origin of the page that invoked the ceremony.
+    origin: String,
+}
+
+/// The fixed part of authenticator data.
+struct AuthenticatorData<'a> {
+    /// The flags byte.
+    flags: u8,
+
+    /// The signature counter.
+    sign_count: u32,
+
+    /// Attested credential data and extensions, if any.
+    rest: &'a [u8],
+}
+
+/// Generates a random challenge for a WebAuthn ceremony.
+///
+/// # Returns
+///
+/// The raw challenge bytes. Browsers echo them back base64url encoded in `clientDataJSON`.
+pub fn generate_challenge() -> Vec<u8> {
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    OsRng.fill_bytes(&mut challenge);
+    challenge
+}
+
+/// Encodes bytes the way WebAuthn encodes challenges and credential IDs.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to encode.
+///
+/// # Returns
+///
+/// The unpadded base64url encoding of the bytes.
+pub fn encode(bytes: &[u8]) -> String {
+    BASE64URL.encode(bytes)
+}
+
+/// Decodes a base64url encoded credential ID.
+///
+/// # Arguments
+///
+/// * `value` - The unpadded base64url string.
+///
+/// # Returns
+///
+/// The decoded bytes, or an empty vector if the value is not valid base64url.
+pub fn decode(value: &str) -> Vec<u8> {
+    BASE64URL.decode(value).unwrap_or_default()
+}
+
+/// Reads the challenge out of `clientDataJSON` so the pending ceremony can be looked up.
+///
+/// # Arguments
+///
+/// * `client_data_json` - The raw `clientDataJSON` bytes from the browser.
+///
+/// # Returns
+///
+/// A `Result` containing the base64url encoded challenge, otherwise `WebauthnError::MalformedData`.
+pub fn client_data_challenge(client_data_json: &[u8]) -> Result<String, WebauthnError> {
+    Ok(parse_client_data(client_data_json)?.challenge)
+}
+
+/// Verifies the response to a registration ceremony.
+///
+/// The challenge must already have been matched against a pending ceremony by the caller.
+///
+/// # Arguments
+///
+/// * `config` - The relying party configuration.
+/// * `credential_id` - The raw credential ID reported by the browser.
+/// * `client_data_json` - The raw `clientDataJSON` bytes.
+/// * `attestation_object` - The CBOR encoded attestation object.
+/// * `name` - A user-chosen label for the credential.
+///
+/// # Returns
+///
+/// A `Result` containing the credential to store, otherwise a `WebauthnError` describing why the
+/// response was rejected.
+pub fn verify_registration(
+    config: &WebauthnConfig,
+    credential_id: &[u8],
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+    name: &str,
+) -> Result<PasskeyCredential, WebauthnError> {
+    verify_client_data(config, client_data_json, WebauthnCeremony::Registration)?;
+
+    let attestation: Value = ciborium::de::from_reader(attestation_object)
+        .map_err(|_| WebauthnError::MalformedData("attestation object".to_owned()))?;
+
+    let format = map_get(&attestation, &Value::Text("fmt".to_owned()))
+        .and_then(Value::as_text)
+        .ok_or_else(|| WebauthnError::MalformedData("attestation format".to_owned()))?;
+
+    // Attestation is not required to sign in, so only self-declared "none" attestation is accepted
+    // rather than trusting authenticator vendor certificates.
+    if format != "none" {
+        return Err(WebauthnError::UnsupportedAttestation(format.to_owned()));
+    }
+
+    let auth_data = map_get(&attestation, &Value::Text("authData".to_owned()))
+        .and_then(Value::as_bytes)
+        .ok_or_else(|| WebauthnError::MalformedData("authenticator data".to_owned()))?;
+
+    let auth_data = parse_authenticator_data(config, auth_data)?;
+
+    if auth_data.flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 || auth_data.rest.len() < AAGUID_LEN + 2 {
+        return Err(WebauthnError::MalformedData("attested credential data".to_owned()));
+    }
+
+    let rest = &auth_data.rest[AAGUID_LEN..];
+    let id_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+    let rest = &rest[2..];
+
+    if rest.len() < id_len || &rest[..id_len] != credential_id {
+        return Err(WebauthnError::MalformedData("credential ID".to_owned()));
+    }
+
+    let public_key = parse_cose_key(&rest[id_len..])?;
+
+    Ok(PasskeyCredential {
+        credential_id: encode(credential_id),
+        public_key,
+        sign_count: auth_data.sign_count,
+        name: name.to_owned(),
+    })
+}
+
+/// Verifies the response to an authentication ceremony.
+///
+/// The challenge must already have been matched against a pending ceremony by the caller.
+///
+/// # Arguments
+///
+/// * `config` - The relying party configuration.
+/// * `credential` - The stored credential the assertion claims to be from.
+/// * `client_data_json` - The raw `clientDataJSON` bytes.
+/// * `authenticator_data` - The raw authenticator data.
+/// * `signature` - The DER encoded ECDSA signature over the authenticator data and client data hash.
+///
+/// # Returns
+///
+/// A `Result` containing the new signature counter to store, otherwise a `WebauthnError` describing
+/// why the assertion was rejected.
+pub fn verify_assertion(
+    config: &WebauthnConfig,
+    credential: &PasskeyCredential,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> Result<u32, WebauthnError> {
+    verify_client_data(config, client_data_json, WebauthnCeremony::Authentication)?;
+
+    let auth_data = parse_authenticator_data(config, authenticator_data)?;
+
+    let key = VerifyingKey::from_sec1_bytes(&credential.public_key)
+        .map_err(|_| WebauthnError::MalformedData("stored public key".to_owned()))?;
+    let signature = Signature::from_der(signature).map_err(|_| WebauthnError::InvalidSignature)?;
+
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+
+    key.verify(&signed, &signature).map_err(|_| WebauthnError::InvalidSignature)?;
+
+    // Authenticators that do not implement counters always report zero.
+    if (auth_data.sign_count != 0 || credential.sign_count != 0) && auth_data.sign_count <= credential.sign_count {
+        return Err(WebauthnError::SignCountRegression);
+    }
+
+    Ok(auth_data.sign_count)
+}
+
+/// Parses `clientDataJSON` and checks its type and origin.
+fn verify_client_data(
+    config: &WebauthnConfig,
+    client_data_json: &[u8],
+    ceremony: WebauthnCeremony,
+) -> Result<ClientData, WebauthnError> {
+    let client_data = parse_client_data(client_data_json)?;
+
+    if client_data.kind != ceremony.client_data_type() {
+        return Err(WebauthnError::WrongCeremony);
+    }
+
+    if client_data.origin != config.origin {
+        return Err(WebauthnError::OriginMismatch);
+    }
+
+    Ok(client_data)
+}
+
+/// Parses `clientDataJSON`.
+fn parse_client_data(client_data_json: &[u8]) -> Result<ClientData, WebauthnError> {
+    serde_json::from_slice(client_data_json).map_err(|_| WebauthnError::MalformedData("client data".to_owned()))
+}
+
+/// Parses the fixed part of authenticator data and checks the RP ID hash and user presence.
+fn parse_authenticator_data<'a>(
+    config: &WebauthnConfig,
+    auth_data: &'a [u8],
+) -> Result<AuthenticatorData<'a>, WebauthnError> {
+    if auth_data.len() < AUTH_DATA_MIN_LEN {
+        return Err(WebauthnError::MalformedData("authenticator data".to_owned()));
+    }
+
+    if auth_data[..32] != Sha256::digest(config.rp_id.as_bytes())[..] {
+        return Err(WebauthnError::RpIdMismatch);
+    }
+
+    let flags = auth_data[32];
+
+    if flags & FLAG_USER_PRESENT == 0 {
+        return Err(WebauthnError::UserNotPresent);
+    }
+
+    Ok(AuthenticatorData {
+        flags,
+        sign_count: u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]]),
+        rest: &auth_data[AUTH_DATA_MIN_LEN..],
+    })
+}
+
+/// Parses a COSE_Key holding an ES256 public key into SEC1 form.
+fn parse_cose_key(bytes: &[u8]) -> Result<Vec<u8>, WebauthnError> {
+    let key: Value = ciborium::de::from_reader(bytes)
+        .map_err(|_| WebauthnError::MalformedData("credential public key".to_owned()))?;
+
+    let int = |label: i64| map_get(&key, &Value::Integer(label.into())).and_then(Value::as_integer).map(i128::from);
+    let coordinate = |label: i64| {
+        map_get(&key, &Value::Integer(label.into()))
+            .and_then(Value::as_bytes)
+            .filter(|bytes| bytes.len() == 32)
+    };
+
+    if int(1) != Some(COSE_KTY_EC2) || int(3) != Some(COSE_ALG_ES256) || int(-1) != Some(COSE_CRV_P256) {
+        return Err(WebauthnError::UnsupportedAlgorithm);
+    }
+
+    let (x, y) = coordinate(-2)
+        .zip(coordinate(-3))
+        .ok_or_else(|| WebauthnError::MalformedData("credential public key".to_owned()))?;
+
+    let mut sec1 = vec![0x04];
+    sec1.extend_from_slice(x);
+    sec1.extend_from_slice(y);
+
+    // Rejects points that are not on the curve.
+    VerifyingKey::from_sec1_bytes(&sec1).map_err(|_| WebauthnError::MalformedData("credential public key".to_owned()))?;
+
+    Ok(sec1)
+}
+
+/// Looks up a key in a CBOR map.
+fn map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    map.as_map()?.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// A software WebAuthn authenticator used to exercise the ceremonies in tests.
+#[cfg(test)]
+pub mod software_authenticator {
+    use p256::ecdsa::{signature::Signer, DerSignature, SigningKey};
+
+    use super::*;
+
+    /// `SoftwareAuthenticator` holds a single ES256 credential, like a security key with one slot.
+    pub struct SoftwareAuthenticator {
+        /// The credential ID.
+        pub credential_id: Vec<u8>,
+
+        /// The credential's private key.
+        signing_key: SigningKey,
+
+        /// The signature counter, incremented on every assertion.
+        sign_count: u32,
+    }
+
+    /// A registration response as the browser would hand it to the server.
+    pub struct Attestation {
+        pub client_data_json: Vec<u8>,
+        pub attestation_object: Vec<u8>,
+    }
+
+    /// An authentication response as the browser would hand it to the server.
+    pub struct Assertion {
+        pub client_data_json: Vec<u8>,
+        pub authenticator_data: Vec<u8>,
+        pub signature: Vec<u8>,
+    }
+
+    impl SoftwareAuthenticator {
+        pub fn new() -> Self {
+            let mut credential_id = vec![0u8; 16];
+            OsRng.fill_bytes(&mut credential_id);
+
+            Self {
+                credential_id,
+                signing_key: SigningKey::random(&mut OsRng),
+                sign_count: 0,
+            }
+        }
+
+        /// Builds `clientDataJSON` for a ceremony.
+        pub fn client_data(kind: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+            format!(r#"{{"type":"{}","challenge":"{}","origin":"{}","crossOrigin":false}}"#, kind, encode(challenge), origin)
+                .into_bytes()
+        }
+
+        /// Builds authenticator data for the relying party.
+        fn authenticator_data(&self, rp_id: &str, flags: u8, attested: &[u8]) -> Vec<u8> {
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data.extend_from_slice(attested);
+            data
+        }
+
+        /// Responds to a registration ceremony.
+        pub fn register(&self, config: &WebauthnConfig, challenge: &[u8]) -> Attestation {
+            let point = self.signing_key.verifying_key().to_encoded_point(false);
+
+            let cose_key = Value::Map(vec![
+                (Value::Integer(1.into()), Value::Integer(2.into())),
+                (Value::Integer(3.into()), Value::Integer((-7).into())),
+                (Value::Integer((-1).into()), Value::Integer(1.into())),
+                (Value::Integer((-2).into()), Value::Bytes(point.x().unwrap().to_vec())),
+                (Value::Integer((-3).into()), Value::Bytes(point.y().unwrap().to_vec())),
+            ]);
+
+            let mut attested = vec![0u8; AAGUID_LEN];
+            attested.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            attested.extend_from_slice(&self.credential_id);
+            ciborium::ser::into_writer(&cose_key, &mut attested).unwrap();
+
+            let auth_data = self.authenticator_data(
+                &config.rp_id,
+                FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA,
+                &attested,
+            );
+
+            let attestation = Value::Map(vec![
+                (Value::Text("fmt".to_owned()), Value::Text("none".to_owned())),
+                (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+                (Value::Text("authData".to_owned()), Value::Bytes(auth_data)),
+            ]);
+
+            let mut attestation_object = Vec::new();
+            ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+
+            Attestation {
+                client_data_json: Self::client_data("webauthn.create", challenge, &config.origin),
+                attestation_object,
+            }
+        }
+
+        /// Responds to an authentication ceremony, bumping the signature counter.
+        pub fn authenticate(&mut self, config: &WebauthnConfig, challenge: &[u8]) -> Assertion {
+            self.sign_count += 1;
+
+            let client_data_json = Self::client_data("webauthn.get", challenge, &config.origin);
+            let authenticator_data = self.authenticator_data(&config.rp_id, FLAG_USER_PRESENT, &[]);
+
+            let mut signed = authenticator_data.clone();
+            signed.extend_from_slice(&Sha256::digest(&client_data_json));
+            let signature: DerSignature = self.signing_key.sign(&signed);
+
+            Assertion {
+                client_data_json,
+                authenticator_data,
+                signature: signature.as_bytes().to_vec(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::software_authenticator::SoftwareAuthenticator;
+    use super::*;
+
+    fn registered(config: &WebauthnConfig, authenticator: &SoftwareAuthenticator) -> PasskeyCredential {
+        let attestation = authenticator.register(config, &generate_challenge());
+
+        verify_registration(
+            config,
+            &authenticator.credential_id,
+            &attestation.client_data_json,
+            &attestation.attestation_object,
+            "laptop",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_verify_registration() {
+        let config = WebauthnConfig::default();
+        let authenticator = SoftwareAuthenticator::new();
+
+        let credential = registered(&config, &authenticator);
+
+        assert_eq!(credential.credential_id, encode(&authenticator.credential_id));
+        assert_eq!(credential.public_key.len(), 65);
+        assert_eq!(credential.sign_count, 0);
+        assert_eq!(credential.name, "laptop");
+    }
+
+    #[test]
+    fn should_reject_registration_for_other_origin() {
+        let config = WebauthnConfig::default();
+        let authenticator = SoftwareAuthenticator::new();
+
+        let other = WebauthnConfig {
+            origin: "https://evil.example".to_owned(),
+            ..WebauthnConfig::default()
+        };
+        let attestation = authenticator.register(&other, &generate_challenge());
+
+        let result = verify_registration(
+            &config,
+            &authenticator.credential_id,
+            &attestation.client_data_json,
+            &attestation.attestation_object,
+            "laptop",
+        );
+
+        assert_eq!(result, Err(WebauthnError::OriginMismatch));
+    }
+
+    #[test]
+    fn should_reject_registration_for_other_rp_id() {
+        let config = WebauthnConfig::default();
+        let authenticator = SoftwareAuthenticator::new();
+
+        let other = WebauthnConfig {
+            rp_id: "evil.example".to_owned(),
+            ..WebauthnConfig::default()
+        };
+        let attestation = authenticator.register(&other, &generate_challenge());
+
+        let result = verify_registration(
+            &config,
+            &authenticator.credential_id,
+            &attestation.client_data_json,
+            &attestation.attestation_object,
+            "laptop",
+        );
+
+        assert_eq!(result, Err(WebauthnError::RpIdMismatch));
+    }
+
+    #[test]
+    fn should_verify_assertion_and_advance_counter() {
+        let config = WebauthnConfig::default();
+        let mut authenticator = SoftwareAuthenticator::new();
+        let mut credential = registered(&config, &authenticator);
+
+        let assertion = authenticator.authenticate(&config, &generate_challenge());
+
+        let sign_count = verify_assertion(
+            &config,
+            &credential,
+            &assertion.client_data_json,
+            &assertion.authenticator_data,
+            &assertion.signature,
+        )
+        .unwrap();
+
+        assert_eq!(sign_count, 1);
+
+        // Replaying the same assertion once the counter has been stored is rejected.
+        credential.sign_count = sign_count;
+
+        let result = verify_assertion(
+            &config,
+            &credential,
+            &assertion.client_data_json,
+            &assertion.authenticator_data,
+            &assertion.signature,
+        );
+
+        assert_eq!(result, Err(WebauthnError::SignCountRegression));
+    }
+
+    #[test]
+    fn should_reject_assertion_from_other_key() {
+        let config = WebauthnConfig::default();
+        let credential = registered(&config, &SoftwareAuthenticator::new());
+
+        let assertion = SoftwareAuthenticator::new().authenticate(&config, &generate_challenge());
+
+        let result = verify_assertion(
+            &config,
+            &credential,
+            &assertion.client_data_json,
+            &assertion.authenticator_data,
+            &assertion.signature,
+        );
+
+        assert_eq!(result, Err(WebauthnError::InvalidSignature));
+    }
+
+    #[test]
+    fn should_reject_assertion_with_registration_client_data() {
+        let config = WebauthnConfig::default();
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&config, &authenticator);
+
+        let mut assertion = authenticator.authenticate(&config, &generate_challenge());
+        assertion.client_data_json = SoftwareAuthenticator::client_data("webauthn.create", &generate_challenge(), &config.origin);
+
+        let result = verify_assertion(
+            &config,
+            &credential,
+            &assertion.client_data_json,
+            &assertion.authenticator_data,
+            &assertion.signature,
+        );
+
+        assert_eq!(result, Err(WebauthnError::WrongCeremony));
+    }
+
+    #[test]
+    fn should_read_client_data_challenge() {
+        let challenge = generate_challenge();
+        let client_data = SoftwareAuthenticator::client_data("webauthn.get", &challenge, "http://localhost");
+
+        assert_eq!(client_data_challenge(&client_data).unwrap(), encode(&challenge));
+        assert!(client_data_challenge(b"not json").is_err());
+    }
+}
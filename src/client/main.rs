@@ -1,8 +1,21 @@
-use std::env;
+use std::{env, fs, path::PathBuf};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
 use clap::{Parser, Subcommand};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
 
+use authentication::admin_client::AdminClient;
 use authentication::auth_client::AuthClient;
-use authentication::{SignInRequest, SignOutRequest, SignUpRequest};
+use authentication::{
+    AccountStatus, AddGroupMemberRequest, AuditAction, AuditOutcome, DeleteUserRequest, ExportUsersRequest, ImportUsersRequest, SetUserStatusRequest, DisableUserRequest, EnableUserRequest,
+    ForcePasswordResetRequest, GetUserRequest, ListAuditEventsRequest, ListDeletedUsersRequest, ListUsersRequest,
+    ResetPasswordRequest, RestoreUserRequest, RevokeUserSessionsRequest, BeginPasskeyLoginRequest, BeginPasskeyRegistrationRequest,
+    BeginTotpEnrollmentRequest, ChangeUsernameRequest, ConfirmTotpEnrollmentRequest, CreateApiKeyRequest,
+    CreateGroupRequest, DeleteAccountRequest, DeleteGroupRequest, FinishPasskeyLoginRequest,
+    FinishPasskeyRegistrationRequest, GetGroupRequest, GetProfileRequest, Profile, UpdateProfileRequest, GrantGroupRoleRequest, GrantRoleRequest,
+    ListApiKeysRequest, ListGroupsRequest, RegenerateRecoveryCodesRequest, RemoveGroupMemberRequest,
+    RevokeApiKeyRequest, RevokeGroupRoleRequest, RevokeRoleRequest, UpdateGroupRequest,
+    ValidateSessionRequest, SignInRequest, SignOutRequest, SignUpRequest, TailAuditEventsRequest, VerifyMfaRequest,
+};
 
 
 pub mod authentication {
@@ -18,12 +31,30 @@ struct Cli {
     /// Represents the subcommand to be executed.
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Connect over TLS, trusting the PEM encoded CA certificates in this file.
+    #[arg(long, global = true)]
+    tls_ca: Option<PathBuf>,
+
+    /// The name the server certificate must be issued to, if it differs from AUTH_SERVICE_IP.
+    #[arg(long, global = true, requires = "tls_ca")]
+    tls_domain: Option<String>,
+
+    /// PEM encoded client certificate to present when the server requires mutual TLS.
+    #[arg(long, global = true, requires_all = ["tls_ca", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the client certificate.
+    #[arg(long, global = true, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 }
 
 /// Enum representing the available commands for the CLI.
 ///
-/// This enum defines subcommands for signing in, signing up, and signing out.
+/// This enum defines subcommands for signing in, signing up, signing out, deleting an account,
+/// managing two-factor authentication, using passkeys, managing API keys and managing roles.
 #[derive(Subcommand)]
+#[allow(clippy::enum_variant_names)]
 enum Commands {
     /// Sign-in subcommand.
     ///
@@ -59,61 +90,1255 @@ enum Commands {
         #[arg(short, long)]
         session_token: String,
     },
-}
 
-/// The main function of the authentication client.
-///
-/// This function establishes a connection with the authentication service and executes the specified command.
-///
-/// # Returns
-///
-/// An `Ok(())` result if the client runs successfully, otherwise an error message.
-///
-/// # Errors
-///
-/// This function returns an error if there are issues with establishing connections or performing gRPC requests.
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    
-    // AUTH_SERVICE_IP is to ne set to the droplet's IP address once deployed
-    let auth_ip = env::var("AUTH_SERVICE_IP").unwrap_or("[::0]".to_owned());
-    let mut client = AuthClient::connect(format!("http://{}:50051", auth_ip)).await?;
+    /// Delete-account subcommand.
+    ///
+    /// Allows users to delete their account by providing their session token and password.
+    DeleteAccount {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
 
-    let cli = Cli::parse();
+        /// Password of the user.
+        #[arg(short, long)]
+        password: String,
+    },
 
-    match &cli.command {
-        Some(Commands::SignIn { username, password }) => {
-            let request = tonic::Request::new(SignInRequest {
-                username: username.clone(),
-                password: password.clone(),
-            });
-        
-            // Make a sign in request
-            let response = client.sign_in(request).await?.into_inner();
-        
-            println!("{:?}", response);
-        }
-        Some(Commands::SignUp { username, password }) => {
-            let request = tonic::Request::new(SignUpRequest {
-                username: username.clone(),
-                password: password.clone(),
-            });
-        
-            let response = client.sign_up(request).await?;
-        
-            println!("{:?}", response.into_inner());
-        }
-        Some(Commands::SignOut { session_token }) => {
-            let request = tonic::Request::new(SignOutRequest {
-                session_token: session_token.clone(),
-            });
-        
-            let response = client.sign_out(request).await?;
-        
-            println!("{:?}", response.into_inner());
-        }
-        None => {}
-    }
+    /// Begin-TOTP-enrollment subcommand.
+    ///
+    /// Generates a TOTP secret for the signed-in user and prints the `otpauth://` URI to scan.
+    BeginTotpEnrollment {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+    },
 
-    Ok(())
-}
\ No newline at end of file
+    /// Confirm-TOTP-enrollment subcommand.
+    ///
+    /// Enables TOTP by providing a code from the authenticator app.
+    ConfirmTotpEnrollment {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Code shown by the authenticator app.
+        #[arg(short, long)]
+        code: String,
+    },
+
+    /// Verify-MFA subcommand.
+    ///
+    /// Completes a sign-in that returned `MfaRequired` by providing the challenge token and either a
+    /// code or a recovery code.
+    VerifyMfa {
+        /// Challenge token returned by sign-in.
+        #[arg(short, long)]
+        mfa_challenge_token: String,
+
+        /// Code shown by the authenticator app.
+        #[arg(short, long, required_unless_present = "recovery_code")]
+        code: Option<String>,
+
+        /// One of the recovery codes issued at enrollment.
+        #[arg(short, long, conflicts_with = "code")]
+        recovery_code: Option<String>,
+    },
+
+    /// Regenerate-recovery-codes subcommand.
+    ///
+    /// Replaces the user's recovery codes, invalidating the old ones.
+    RegenerateRecoveryCodes {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Password of the user.
+        #[arg(short, long)]
+        password: String,
+    },
+
+    /// Begin-passkey-registration subcommand.
+    ///
+    /// Prints the options to pass to `navigator.credentials.create` in the browser.
+    BeginPasskeyRegistration {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+    },
+
+    /// Finish-passkey-registration subcommand.
+    ///
+    /// Registers the passkey created by the browser. Binary values are base64url encoded.
+    FinishPasskeyRegistration {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// The credential's raw ID.
+        #[arg(long)]
+        credential_id: String,
+
+        /// The `clientDataJSON` returned by the browser.
+        #[arg(long)]
+        client_data_json: String,
+
+        /// The attestation object returned by the browser.
+        #[arg(long)]
+        attestation_object: String,
+
+        /// A label for the passkey.
+        #[arg(short, long, default_value = "")]
+        name: String,
+    },
+
+    /// Begin-passkey-login subcommand.
+    ///
+    /// Prints the options to pass to `navigator.credentials.get` in the browser.
+    BeginPasskeyLogin {
+        /// Username of the user.
+        #[arg(short, long)]
+        username: String,
+    },
+
+    /// Finish-passkey-login subcommand.
+    ///
+    /// Signs in with the assertion returned by the browser. Binary values are base64url encoded.
+    FinishPasskeyLogin {
+        /// The credential's raw ID.
+        #[arg(long)]
+        credential_id: String,
+
+        /// The `clientDataJSON` returned by the browser.
+        #[arg(long)]
+        client_data_json: String,
+
+        /// The authenticator data returned by the browser.
+        #[arg(long)]
+        authenticator_data: String,
+
+        /// The signature returned by the browser.
+        #[arg(long)]
+        signature: String,
+    },
+
+    /// Create-API-key subcommand.
+    ///
+    /// Creates an API key for the signed-in user. The key is only printed once.
+    CreateApiKey {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// A label for the key.
+        #[arg(short, long)]
+        name: String,
+
+        /// A scope to limit the key to. May be repeated.
+        #[arg(long = "scope")]
+        scopes: Vec<String>,
+
+        /// How long the key is valid for, in seconds. Keys without a lifetime never expire.
+        #[arg(short, long)]
+        expires_in_seconds: Option<u64>,
+    },
+
+    /// List-API-keys subcommand.
+    ///
+    /// Lists the signed-in user's API keys.
+    ListApiKeys {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+    },
+
+    /// Revoke-API-key subcommand.
+    ///
+    /// Revokes one of the signed-in user's API keys.
+    RevokeApiKey {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// ID of the key to revoke.
+        #[arg(short, long)]
+        key_id: String,
+    },
+
+    /// Validate-session subcommand.
+    ///
+    /// Checks a session token or API key and prints who it belongs to.
+    ValidateSession {
+        /// Session token or API key.
+        #[arg(short, long)]
+        token: String,
+    },
+
+    /// Grant-role subcommand.
+    ///
+    /// Grants a role to a user. Requires the `roles:manage` permission.
+    GrantRole {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user receiving the role.
+        #[arg(short, long)]
+        user_uuid: String,
+
+        /// Role to grant: admin, user_manager or auditor.
+        #[arg(short, long)]
+        role: String,
+    },
+
+    /// Revoke-role subcommand.
+    ///
+    /// Revokes a role from a user. Requires the `roles:manage` permission.
+    RevokeRole {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user losing the role.
+        #[arg(short, long)]
+        user_uuid: String,
+
+        /// Role to revoke: admin, user_manager or auditor.
+        #[arg(short, long)]
+        role: String,
+    },
+
+    /// Create-group subcommand.
+    ///
+    /// Creates a group. Requires the `groups:manage` permission.
+    CreateGroup {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Name of the group.
+        #[arg(short, long)]
+        name: String,
+
+        /// Description of the group.
+        #[arg(short, long)]
+        description: String,
+    },
+
+    /// Get-group subcommand.
+    ///
+    /// Prints a group with its roles and members. Requires the `users:read` permission.
+    GetGroup {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+    },
+
+    /// List-groups subcommand.
+    ///
+    /// Lists every group. Requires the `users:read` permission.
+    ListGroups {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+    },
+
+    /// Update-group subcommand.
+    ///
+    /// Renames a group or changes its description. Requires the `groups:manage` permission.
+    UpdateGroup {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+
+        /// New name of the group.
+        #[arg(short, long)]
+        name: String,
+
+        /// New description of the group.
+        #[arg(short, long)]
+        description: String,
+    },
+
+    /// Delete-group subcommand.
+    ///
+    /// Deletes a group. Requires the `groups:manage` permission.
+    DeleteGroup {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+    },
+
+    /// Add-group-member subcommand.
+    ///
+    /// Adds a user to a group. Requires the `groups:manage` permission.
+    AddGroupMember {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+
+        /// UUID of the user joining the group.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Remove-group-member subcommand.
+    ///
+    /// Removes a user from a group. Requires the `groups:manage` permission.
+    RemoveGroupMember {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+
+        /// UUID of the user leaving the group.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Grant-group-role subcommand.
+    ///
+    /// Grants a role to every member of a group. Requires the `roles:manage` permission.
+    GrantGroupRole {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+
+        /// Role to grant: admin, user_manager or auditor.
+        #[arg(short, long)]
+        role: String,
+    },
+
+    /// Revoke-group-role subcommand.
+    ///
+    /// Revokes a role from a group. Requires the `roles:manage` permission.
+    RevokeGroupRole {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the group.
+        #[arg(short, long)]
+        group_uuid: String,
+
+        /// Role to revoke: admin, user_manager or auditor.
+        #[arg(short, long)]
+        role: String,
+    },
+
+    /// Reset-password subcommand.
+    ///
+    /// Chooses a new password with a reset token issued by an administrator.
+    ResetPassword {
+        /// Reset token from the administrator.
+        #[arg(short, long)]
+        reset_token: String,
+
+        /// New password.
+        #[arg(short, long)]
+        new_password: String,
+    },
+
+    /// Get-profile subcommand.
+    ///
+    /// Shows the signed-in user's profile.
+    GetProfile {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+    },
+
+    /// Update-profile subcommand.
+    ///
+    /// Replaces the signed-in user's profile. Fields that are not given are cleared.
+    UpdateProfile {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Name to show instead of the username.
+        #[arg(short, long)]
+        display_name: Option<String>,
+
+        /// Email address.
+        #[arg(short, long)]
+        email: Option<String>,
+
+        /// Preferred locale, e.g. `en-GB`.
+        #[arg(short, long)]
+        locale: Option<String>,
+
+        /// A metadata entry of the form `key=value`. May be repeated.
+        #[arg(long = "metadata", value_parser = parse_key_value)]
+        metadata: Vec<(String, String)>,
+    },
+
+    /// Change-username subcommand.
+    ///
+    /// Changes the signed-in user's username. Requires the account password.
+    ChangeUsername {
+        /// Session token of the user.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// New username.
+        #[arg(short, long)]
+        new_username: String,
+
+        /// Password of the account.
+        #[arg(short, long)]
+        password: String,
+    },
+
+    /// List-users subcommand.
+    ///
+    /// Lists users whose username contains a search term. Requires the `users:read` permission.
+    ListUsers {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Term to search usernames for.
+        #[arg(short, long, default_value = "")]
+        query: String,
+
+        /// Number of matching users to skip.
+        #[arg(short, long, default_value_t = 0)]
+        offset: u32,
+
+        /// Maximum number of users to print.
+        #[arg(short, long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Get-user subcommand.
+    ///
+    /// Prints a user's account. Requires the `users:read` permission.
+    GetUser {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Disable-user subcommand.
+    ///
+    /// Disables an account and signs the user out. Requires the `users:write` permission.
+    DisableUser {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Enable-user subcommand.
+    ///
+    /// Makes an account active again, whatever its status. Requires the `users:write` permission.
+    EnableUser {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Set-user-status subcommand.
+    ///
+    /// Sets the status of an account. Requires the `users:write` permission.
+    SetUserStatus {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+
+        /// New status: active, disabled, pending_verification or locked.
+        #[arg(long)]
+        status: String,
+    },
+
+    /// Force-password-reset subcommand.
+    ///
+    /// Signs a user out and prints a token they must use to choose a new password. Requires the `users:write` permission.
+    ForcePasswordReset {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Delete-user subcommand.
+    ///
+    /// Deletes a user's account, which can be restored until its grace period ends. Requires the
+    /// `users:write` permission.
+    DeleteUser {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Revoke-user-sessions subcommand.
+    ///
+    /// Signs a user out. Requires the `users:write` permission.
+    RevokeUserSessions {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// List-deleted-users subcommand.
+    ///
+    /// Lists deleted accounts that can still be restored. Requires the `users:read` permission.
+    ListDeletedUsers {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+    },
+
+    /// Restore-user subcommand.
+    ///
+    /// Restores a deleted account. Requires the `users:write` permission.
+    RestoreUser {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// UUID of the user.
+        #[arg(short, long)]
+        user_uuid: String,
+    },
+
+    /// Import-users subcommand.
+    ///
+    /// Imports users from a JSON Lines file. Requires the `users:write` permission.
+    ImportUsers {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Path of the JSON Lines file to import.
+        #[arg(short, long)]
+        file: String,
+
+        /// Check every line without importing anything.
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+
+    /// Export-users subcommand.
+    ///
+    /// Exports every user as JSON Lines. Requires the `users:write` permission.
+    ExportUsers {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Path of the file to write. The users are printed when it is left out.
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+
+    /// List-audit-events subcommand.
+    ///
+    /// Lists audit events, newest first. Requires the `audit:read` permission.
+    ListAuditEvents {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Only events the user performed or that were performed on them.
+        #[arg(short, long, default_value = "")]
+        user_uuid: String,
+
+        /// Only events of this action, e.g. sign_in or change_account_status.
+        #[arg(short, long)]
+        action: Option<String>,
+
+        /// Only events with this outcome: success, mfa_required or failure.
+        #[arg(short, long)]
+        outcome: Option<String>,
+
+        /// Only events recorded at or after this Unix time.
+        #[arg(long, default_value_t = 0)]
+        since: i64,
+
+        /// Only events recorded before this Unix time.
+        #[arg(long, default_value_t = 0)]
+        until: i64,
+
+        /// Cursor printed with the previous page.
+        #[arg(short, long, default_value = "")]
+        cursor: String,
+
+        /// Maximum number of events to print.
+        #[arg(short, long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Tail-audit-events subcommand.
+    ///
+    /// Prints audit events as they are recorded until interrupted. Requires the `audit:read`
+    /// permission.
+    TailAuditEvents {
+        /// Session token of the caller.
+        #[arg(short, long)]
+        session_token: String,
+
+        /// Only events the user performed or that were performed on them.
+        #[arg(short, long, default_value = "")]
+        user_uuid: String,
+
+        /// Only events of this action, e.g. sign_in or change_account_status.
+        #[arg(short, long)]
+        action: Option<String>,
+
+        /// Only events with this outcome: success, mfa_required or failure.
+        #[arg(short, long)]
+        outcome: Option<String>,
+    },
+}
+
+/// The largest admin message the client accepts, matching the limit of the auth service.
+const MAX_ADMIN_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Builds the TLS settings of the connection from the command-line flags.
+///
+/// # Arguments
+///
+/// * `cli` - The parsed command-line flags.
+///
+/// # Returns
+///
+/// An `Ok` result containing the TLS settings, or `None` to connect in plaintext, otherwise an error
+/// if a certificate or key cannot be read.
+fn tls_config(cli: &Cli) -> Result<Option<ClientTlsConfig>, Box<dyn std::error::Error>> {
+    let Some(ca_path) = &cli.tls_ca else {
+        return Ok(None);
+    };
+
+    let mut tls_config = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(fs::read(ca_path)?));
+
+    if let Some(domain) = &cli.tls_domain {
+        tls_config = tls_config.domain_name(domain.clone());
+    }
+
+    if let (Some(cert_path), Some(key_path)) = (&cli.tls_cert, &cli.tls_key) {
+        tls_config = tls_config.identity(Identity::from_pem(fs::read(cert_path)?, fs::read(key_path)?));
+    }
+
+    Ok(Some(tls_config))
+}
+
+/// The main function of the authentication client.
+///
+/// This function establishes a connection with the authentication service and executes the specified command.
+///
+/// # Returns
+///
+/// An `Ok(())` result if the client runs successfully, otherwise an error message.
+///
+/// # Errors
+///
+/// This function returns an error if there are issues with establishing connections or performing gRPC requests.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // AUTH_SERVICE_IP is to ne set to the droplet's IP address once deployed
+    let auth_ip = env::var("AUTH_SERVICE_IP").unwrap_or("[::0]".to_owned());
+    let channel = match tls_config(&cli)? {
+        Some(tls_config) => Endpoint::from_shared(format!("https://{}:50051", auth_ip))?.tls_config(tls_config)?,
+        None => Endpoint::from_shared(format!("http://{}:50051", auth_ip))?,
+    }
+    .connect()
+    .await?;
+    let mut client = AuthClient::new(channel.clone());
+    // Exports of many users are larger than tonic's default 4 MiB message limit.
+    let mut admin_client = AdminClient::new(channel).max_decoding_message_size(MAX_ADMIN_MESSAGE_SIZE);
+
+    match &cli.command {
+        Some(Commands::SignIn { username, password }) => {
+            let request = tonic::Request::new(SignInRequest {
+                username: username.clone(),
+                password: password.clone(),
+            });
+        
+            // Make a sign in request
+            let response = client.sign_in(request).await?.into_inner();
+        
+            println!("{:?}", response);
+        }
+        Some(Commands::SignUp { username, password }) => {
+            let request = tonic::Request::new(SignUpRequest {
+                username: username.clone(),
+                password: password.clone(),
+            });
+        
+            let response = client.sign_up(request).await?;
+        
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::SignOut { session_token }) => {
+            let request = tonic::Request::new(SignOutRequest {
+                session_token: session_token.clone(),
+            });
+        
+            let response = client.sign_out(request).await?;
+        
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::DeleteAccount { session_token, password }) => {
+            let request = tonic::Request::new(DeleteAccountRequest {
+                session_token: session_token.clone(),
+                password: password.clone(),
+            });
+
+            let response = client.delete_account(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::BeginTotpEnrollment { session_token }) => {
+            let request = tonic::Request::new(BeginTotpEnrollmentRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = client.begin_totp_enrollment(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ConfirmTotpEnrollment { session_token, code }) => {
+            let request = tonic::Request::new(ConfirmTotpEnrollmentRequest {
+                session_token: session_token.clone(),
+                code: code.clone(),
+            });
+
+            let response = client.confirm_totp_enrollment(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::VerifyMfa { mfa_challenge_token, code, recovery_code }) => {
+            let request = tonic::Request::new(VerifyMfaRequest {
+                mfa_challenge_token: mfa_challenge_token.clone(),
+                code: code.clone().unwrap_or_default(),
+                recovery_code: recovery_code.clone().unwrap_or_default(),
+            });
+
+            let response = client.verify_mfa(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RegenerateRecoveryCodes { session_token, password }) => {
+            let request = tonic::Request::new(RegenerateRecoveryCodesRequest {
+                session_token: session_token.clone(),
+                password: password.clone(),
+            });
+
+            let response = client.regenerate_recovery_codes(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::BeginPasskeyRegistration { session_token }) => {
+            let request = tonic::Request::new(BeginPasskeyRegistrationRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = client.begin_passkey_registration(request).await?.into_inner();
+
+            println!("{:?}", response);
+            println!("challenge (base64url): {}", BASE64URL.encode(&response.challenge));
+        }
+        Some(Commands::FinishPasskeyRegistration { session_token, credential_id, client_data_json, attestation_object, name }) => {
+            let request = tonic::Request::new(FinishPasskeyRegistrationRequest {
+                session_token: session_token.clone(),
+                credential_id: BASE64URL.decode(credential_id)?,
+                client_data_json: BASE64URL.decode(client_data_json)?,
+                attestation_object: BASE64URL.decode(attestation_object)?,
+                name: name.clone(),
+            });
+
+            let response = client.finish_passkey_registration(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::BeginPasskeyLogin { username }) => {
+            let request = tonic::Request::new(BeginPasskeyLoginRequest {
+                username: username.clone(),
+            });
+
+            let response = client.begin_passkey_login(request).await?.into_inner();
+
+            println!("{:?}", response);
+            println!("challenge (base64url): {}", BASE64URL.encode(&response.challenge));
+        }
+        Some(Commands::FinishPasskeyLogin { credential_id, client_data_json, authenticator_data, signature }) => {
+            let request = tonic::Request::new(FinishPasskeyLoginRequest {
+                credential_id: BASE64URL.decode(credential_id)?,
+                client_data_json: BASE64URL.decode(client_data_json)?,
+                authenticator_data: BASE64URL.decode(authenticator_data)?,
+                signature: BASE64URL.decode(signature)?,
+            });
+
+            let response = client.finish_passkey_login(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::CreateApiKey { session_token, name, scopes, expires_in_seconds }) => {
+            let request = tonic::Request::new(CreateApiKeyRequest {
+                session_token: session_token.clone(),
+                name: name.clone(),
+                scopes: scopes.clone(),
+                expires_in_seconds: expires_in_seconds.unwrap_or_default(),
+            });
+
+            let response = client.create_api_key(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ListApiKeys { session_token }) => {
+            let request = tonic::Request::new(ListApiKeysRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = client.list_api_keys(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RevokeApiKey { session_token, key_id }) => {
+            let request = tonic::Request::new(RevokeApiKeyRequest {
+                session_token: session_token.clone(),
+                key_id: key_id.clone(),
+            });
+
+            let response = client.revoke_api_key(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ValidateSession { token }) => {
+            let request = tonic::Request::new(ValidateSessionRequest {
+                token: token.clone(),
+            });
+
+            let response = client.validate_session(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::GrantRole { session_token, user_uuid, role }) => {
+            let request = tonic::Request::new(GrantRoleRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+                role: role.clone(),
+            });
+
+            let response = client.grant_role(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RevokeRole { session_token, user_uuid, role }) => {
+            let request = tonic::Request::new(RevokeRoleRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+                role: role.clone(),
+            });
+
+            let response = client.revoke_role(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::CreateGroup { session_token, name, description }) => {
+            let request = tonic::Request::new(CreateGroupRequest {
+                session_token: session_token.clone(),
+                name: name.clone(),
+                description: description.clone(),
+            });
+
+            let response = client.create_group(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::GetGroup { session_token, group_uuid }) => {
+            let request = tonic::Request::new(GetGroupRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+            });
+
+            let response = client.get_group(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ListGroups { session_token }) => {
+            let request = tonic::Request::new(ListGroupsRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = client.list_groups(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::UpdateGroup { session_token, group_uuid, name, description }) => {
+            let request = tonic::Request::new(UpdateGroupRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+                name: name.clone(),
+                description: description.clone(),
+            });
+
+            let response = client.update_group(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::DeleteGroup { session_token, group_uuid }) => {
+            let request = tonic::Request::new(DeleteGroupRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+            });
+
+            let response = client.delete_group(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::AddGroupMember { session_token, group_uuid, user_uuid }) => {
+            let request = tonic::Request::new(AddGroupMemberRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = client.add_group_member(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RemoveGroupMember { session_token, group_uuid, user_uuid }) => {
+            let request = tonic::Request::new(RemoveGroupMemberRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = client.remove_group_member(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::GrantGroupRole { session_token, group_uuid, role }) => {
+            let request = tonic::Request::new(GrantGroupRoleRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+                role: role.clone(),
+            });
+
+            let response = client.grant_group_role(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RevokeGroupRole { session_token, group_uuid, role }) => {
+            let request = tonic::Request::new(RevokeGroupRoleRequest {
+                session_token: session_token.clone(),
+                group_uuid: group_uuid.clone(),
+                role: role.clone(),
+            });
+
+            let response = client.revoke_group_role(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ResetPassword { reset_token, new_password }) => {
+            let request = tonic::Request::new(ResetPasswordRequest {
+                reset_token: reset_token.clone(),
+                new_password: new_password.clone(),
+            });
+
+            let response = client.reset_password(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::GetProfile { session_token }) => {
+            let request = tonic::Request::new(GetProfileRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = client.get_profile(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::UpdateProfile { session_token, display_name, email, locale, metadata }) => {
+            let request = tonic::Request::new(UpdateProfileRequest {
+                session_token: session_token.clone(),
+                profile: Some(Profile {
+                    display_name: display_name.clone().unwrap_or_default(),
+                    email: email.clone().unwrap_or_default(),
+                    locale: locale.clone().unwrap_or_default(),
+                    metadata: metadata.iter().cloned().collect(),
+                }),
+            });
+
+            let response = client.update_profile(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ChangeUsername { session_token, new_username, password }) => {
+            let request = tonic::Request::new(ChangeUsernameRequest {
+                session_token: session_token.clone(),
+                new_username: new_username.clone(),
+                password: password.clone(),
+            });
+
+            let response = client.change_username(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ListUsers { session_token, query, offset, limit }) => {
+            let request = tonic::Request::new(ListUsersRequest {
+                session_token: session_token.clone(),
+                query: query.clone(),
+                offset: *offset,
+                limit: *limit,
+            });
+
+            let response = admin_client.list_users(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::GetUser { session_token, user_uuid }) => {
+            let request = tonic::Request::new(GetUserRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.get_user(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::DisableUser { session_token, user_uuid }) => {
+            let request = tonic::Request::new(DisableUserRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.disable_user(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::SetUserStatus { session_token, user_uuid, status }) => {
+            let status = AccountStatus::from_str_name(&status.to_uppercase())
+                .ok_or(format!("Unknown account status: {}", status))?;
+
+            let request = tonic::Request::new(SetUserStatusRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+                status: status.into(),
+            });
+
+            let response = admin_client.set_user_status(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::EnableUser { session_token, user_uuid }) => {
+            let request = tonic::Request::new(EnableUserRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.enable_user(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ForcePasswordReset { session_token, user_uuid }) => {
+            let request = tonic::Request::new(ForcePasswordResetRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.force_password_reset(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::DeleteUser { session_token, user_uuid }) => {
+            let request = tonic::Request::new(DeleteUserRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.delete_user(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RevokeUserSessions { session_token, user_uuid }) => {
+            let request = tonic::Request::new(RevokeUserSessionsRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.revoke_user_sessions(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ListDeletedUsers { session_token }) => {
+            let request = tonic::Request::new(ListDeletedUsersRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = admin_client.list_deleted_users(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::RestoreUser { session_token, user_uuid }) => {
+            let request = tonic::Request::new(RestoreUserRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+            });
+
+            let response = admin_client.restore_user(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ImportUsers { session_token, file, dry_run }) => {
+            let request = tonic::Request::new(ImportUsersRequest {
+                session_token: session_token.clone(),
+                users: fs::read_to_string(file)?,
+                dry_run: *dry_run,
+            });
+
+            let response = admin_client.import_users(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::ExportUsers { session_token, file }) => {
+            let request = tonic::Request::new(ExportUsersRequest {
+                session_token: session_token.clone(),
+            });
+
+            let response = admin_client.export_users(request).await?.into_inner();
+
+            match file {
+                Some(file) => fs::write(file, response.users)?,
+                None => print!("{}", response.users),
+            }
+        }
+        Some(Commands::ListAuditEvents { session_token, user_uuid, action, outcome, since, until, cursor, limit }) => {
+            let request = tonic::Request::new(ListAuditEventsRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+                action: parse_audit_action(action.as_deref())?.into(),
+                outcome: parse_audit_outcome(outcome.as_deref())?.into(),
+                since: *since,
+                until: *until,
+                cursor: cursor.clone(),
+                limit: *limit,
+            });
+
+            let response = admin_client.list_audit_events(request).await?;
+
+            println!("{:?}", response.into_inner());
+        }
+        Some(Commands::TailAuditEvents { session_token, user_uuid, action, outcome }) => {
+            let request = tonic::Request::new(TailAuditEventsRequest {
+                session_token: session_token.clone(),
+                user_uuid: user_uuid.clone(),
+                action: parse_audit_action(action.as_deref())?.into(),
+                outcome: parse_audit_outcome(outcome.as_deref())?.into(),
+            });
+
+            let mut stream = admin_client.tail_audit_events(request).await?.into_inner();
+
+            while let Some(response) = stream.message().await? {
+                println!("{:?}", response);
+            }
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+/// Parses the audit action to filter by.
+///
+/// # Arguments
+///
+/// * `action` - The action in lower case, e.g. `sign_in`, if given.
+///
+/// # Returns
+///
+/// An `Ok` result containing the action, `Unspecified` to match every action, otherwise an error
+/// message if the action is unknown.
+fn parse_audit_action(action: Option<&str>) -> Result<AuditAction, String> {
+    match action {
+        Some(action) => AuditAction::from_str_name(&format!("AUDIT_ACTION_{}", action.to_uppercase()))
+            .ok_or(format!("Unknown audit action: {}", action)),
+        None => Ok(AuditAction::Unspecified),
+    }
+}
+
+/// Parses the audit outcome to filter by.
+///
+/// # Arguments
+///
+/// * `outcome` - The outcome in lower case, e.g. `failure`, if given.
+///
+/// # Returns
+///
+/// An `Ok` result containing the outcome, `Unspecified` to match every outcome, otherwise an error
+/// message if the outcome is unknown.
+fn parse_audit_outcome(outcome: Option<&str>) -> Result<AuditOutcome, String> {
+    match outcome {
+        Some(outcome) => AuditOutcome::from_str_name(&format!("AUDIT_OUTCOME_{}", outcome.to_uppercase()))
+            .ok_or(format!("Unknown audit outcome: {}", outcome)),
+        None => Ok(AuditOutcome::Unspecified),
+    }
+}
+
+/// Parses a `key=value` command line argument.
+///
+/// # Arguments
+///
+/// * `argument` - The argument as given on the command line.
+///
+/// # Returns
+///
+/// An `Ok` result containing the key and value, otherwise an error message for clap to show.
+fn parse_key_value(argument: &str) -> Result<(String, String), String> {
+    argument
+        .split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected key=value, got `{}`", argument))
+}
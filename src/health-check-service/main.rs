@@ -1,8 +1,9 @@
-use std::env;
+use std::{env, fs};
 
 use authentication::auth_client::AuthClient;
 use authentication::{SignInRequest, SignOutRequest, SignUpRequest};
 use tokio::time::{sleep, Duration};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
 use uuid::Uuid;
 
 use crate::authentication::StatusCode;
@@ -11,6 +12,39 @@ pub mod authentication {
     tonic::include_proto!("authentication");
 }
 
+/// Builds the TLS settings of the connection from the environment.
+///
+/// TLS is used when `AUTH_TLS_CA_PATH` names the PEM encoded CA certificates to trust.
+/// `AUTH_TLS_DOMAIN` overrides the name the server certificate must be issued to, and
+/// `AUTH_TLS_CLIENT_CERT_PATH` with `AUTH_TLS_CLIENT_KEY_PATH` give the client certificate to present
+/// when the server requires mutual TLS.
+///
+/// # Returns
+///
+/// An `Ok` result containing the TLS settings, or `None` to connect in plaintext, otherwise an error
+/// if a certificate or key cannot be read.
+fn tls_config() -> Result<Option<ClientTlsConfig>, Box<dyn std::error::Error>> {
+    let Ok(ca_path) = env::var("AUTH_TLS_CA_PATH") else {
+        return Ok(None);
+    };
+
+    let mut tls_config = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(fs::read(ca_path)?));
+
+    if let Ok(domain) = env::var("AUTH_TLS_DOMAIN") {
+        tls_config = tls_config.domain_name(domain);
+    }
+
+    match (env::var("AUTH_TLS_CLIENT_CERT_PATH"), env::var("AUTH_TLS_CLIENT_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => {
+            tls_config = tls_config.identity(Identity::from_pem(fs::read(cert_path)?, fs::read(key_path)?));
+        }
+        (Err(_), Err(_)) => {}
+        _ => return Err("AUTH_TLS_CLIENT_CERT_PATH and AUTH_TLS_CLIENT_KEY_PATH must be set together".into()),
+    }
+
+    Ok(Some(tls_config))
+}
+
 /// The main function of the health check service.
 ///
 /// This function continuously performs sign-up, sign-in, and sign-out operations with the authentication service.
@@ -32,7 +66,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Connecting to: {}", auth_hostname);
 
     // Establish connection with auth service
-    let mut client = AuthClient::connect(format!("http://{}:50051", auth_hostname)).await?;
+    let channel = match tls_config()? {
+        Some(tls_config) => Endpoint::from_shared(format!("https://{}:50051", auth_hostname))?.tls_config(tls_config)?,
+        None => Endpoint::from_shared(format!("http://{}:50051", auth_hostname))?,
+    }
+    .connect()
+    .await?;
+    let mut client = AuthClient::new(channel);
 
     loop {
         let username = Uuid::new_v4().to_string();
@@ -46,13 +86,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             password: password.clone(),
         });
 
-        let response = client.sign_up(request).await?;
-
-        // Log the response
-        println!(
-            "SIGN UP RESPONSE STATUS: {:?}",
-            StatusCode::from_i32(response.into_inner().status_code)
-        );
+        // Log the response. Failures come back as a gRPC status rather than a FAILURE status code.
+        match client.sign_up(request).await {
+            Ok(response) => println!(
+                "SIGN UP RESPONSE STATUS: {:?}",
+                StatusCode::try_from(response.into_inner().status_code)
+            ),
+            Err(status) => println!("SIGN UP FAILED: {:?} {}", status.code(), status.message()),
+        }
 
         // SIGN IN
         // ---------------------------------------------
@@ -63,11 +104,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
 
         // Make a sign in request
-        let response = client.sign_in(request).await?.into_inner();
+        let response = match client.sign_in(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                println!("SIGN IN FAILED: {:?} {}", status.code(), status.message());
+                println!("--------------------------------------");
+                sleep(Duration::from_secs(3)).await;
+                continue;
+            }
+        };
 
         println!(
             "SIGN IN RESPONSE STATUS: {:?}",
-            StatusCode::from_i32(response.status_code)
+            StatusCode::try_from(response.status_code)
         );
 
         // SIGN OUT
@@ -77,12 +126,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             session_token: response.session_token,
         });
 
-        let response = client.sign_out(request).await?;
-
-        println!(
-            "SIGN OUT RESPONSE STATUS: {:?}",
-            StatusCode::from_i32(response.into_inner().status_code)
-        );
+        match client.sign_out(request).await {
+            Ok(response) => println!(
+                "SIGN OUT RESPONSE STATUS: {:?}",
+                StatusCode::try_from(response.into_inner().status_code)
+            ),
+            Err(status) => println!("SIGN OUT FAILED: {:?} {}", status.code(), status.message()),
+        }
 
         println!("--------------------------------------");
 